@@ -8,7 +8,7 @@ use proto::fuzzing::{
     ConnectionState, ResetStream, Retransmits, SendStream, Streams, StreamsState,
     TransportParameters,
 };
-use proto::{Dir, Side, StreamId, VarInt};
+use proto::{CreditPolicy, Dir, Side, StreamId, VarInt};
 
 #[derive(Arbitrary, Debug)]
 struct StreamParams {
@@ -18,6 +18,7 @@ struct StreamParams {
     send_window: u16,
     receive_window: u16,
     stream_receive_window: u16,
+    uni_stream_receive_window: u16,
     dir: Dir,
     transport_params: TransportParameters,
 }
@@ -42,6 +43,9 @@ fuzz_target!(|input: (StreamParams, Vec<Operation>)| {
         params.send_window.into(),
         params.receive_window.into(),
         params.stream_receive_window.into(),
+        params.uni_stream_receive_window.into(),
+        CreditPolicy::Threshold(8),
+        CreditPolicy::Threshold(8),
     );
 
     for operation in operations {
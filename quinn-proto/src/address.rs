@@ -0,0 +1,39 @@
+use std::{
+    fmt,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+};
+
+use bytes::BufMut;
+
+/// Identifies a peer on the network underlying an endpoint
+///
+/// Implemented for [`SocketAddr`], for QUIC over UDP/IP. Transports with their own addressing,
+/// such as overlay networks, may implement it for their own address type instead of mapping their
+/// addresses onto IP. Features tied to IP, such as NAT rebinding detection and passing the remote
+/// address to an [`AdmissionController`](crate::AdmissionController), are then unavailable.
+pub trait Address: Copy + Eq + Hash + fmt::Debug + fmt::Display + Send + Sync + 'static {
+    /// Append an encoding of the address to `buf` which differs from that of every other address
+    ///
+    /// Used to bind address validation tokens to the address they were issued to.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// The IP address and port this address corresponds to, if any
+    fn socket_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+impl Address for SocketAddr {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self.ip() {
+            IpAddr::V4(x) => buf.put_slice(&x.octets()),
+            IpAddr::V6(x) => buf.put_slice(&x.octets()),
+        }
+        buf.put_u16(self.port());
+    }
+
+    fn socket_addr(&self) -> Option<SocketAddr> {
+        Some(*self)
+    }
+}
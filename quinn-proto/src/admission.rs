@@ -0,0 +1,96 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Decides whether incoming connection attempts are admitted
+///
+/// Consulted by the `Endpoint` for every Initial packet which would create a new connection,
+/// allowing servers to plug in their own load-shedding logic.
+pub trait AdmissionController: Send + Sync {
+    /// Decide what to do with a connection attempt given the current load of the endpoint
+    fn admit(&self, load: &EndpointLoad) -> Admission;
+}
+
+/// Outcome of an [`AdmissionController`] decision
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Admission {
+    /// Proceed with the handshake
+    Accept,
+    /// Refuse the connection with a `CONNECTION_REFUSED` error
+    Reject,
+    /// Require the client to prove ownership of its address before committing any resources
+    ///
+    /// Treated like `Accept` if the client has already done so.
+    Retry,
+}
+
+/// Snapshot of an endpoint's load at the time a connection attempt is received
+#[derive(Debug, Copy, Clone)]
+#[non_exhaustive]
+pub struct EndpointLoad {
+    /// Address the connection attempt originates from, if the endpoint communicates over IP
+    pub remote: Option<SocketAddr>,
+    /// Whether the client has proven ownership of `remote` by echoing a Retry token
+    pub validated: bool,
+    /// Number of connections currently tracked by the endpoint, including those still handshaking
+    pub connections: usize,
+    /// Number of connection attempts received during the last second
+    pub handshake_rate: u64,
+    /// Memory usage in bytes most recently reported by the application, if any
+    ///
+    /// See `Endpoint::set_memory_usage`.
+    pub memory_usage: Option<u64>,
+}
+
+/// Counts connection attempts over a sliding window of `WINDOW`
+#[derive(Debug, Default)]
+pub(crate) struct HandshakeRate {
+    window_start: Option<Instant>,
+    current: u64,
+    previous: u64,
+}
+
+impl HandshakeRate {
+    /// Record a connection attempt and return the approximate number of attempts in the last
+    /// `WINDOW`
+    pub(crate) fn record(&mut self, now: Instant) -> u64 {
+        let mut start = *self.window_start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed >= 2 * WINDOW {
+            self.previous = 0;
+            self.current = 0;
+            start = now;
+        } else if elapsed >= WINDOW {
+            self.previous = self.current;
+            self.current = 0;
+            start += WINDOW;
+        }
+        self.window_start = Some(start);
+        self.current += 1;
+
+        // Weight the previous window by how much of it still overlaps the sliding window
+        let elapsed = now.saturating_duration_since(start);
+        let overlap = 1.0 - elapsed.as_secs_f64() / WINDOW.as_secs_f64();
+        self.current + (self.previous as f64 * overlap) as u64
+    }
+}
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_rate() {
+        let start = Instant::now();
+        let mut rate = HandshakeRate::default();
+        for _ in 0..9 {
+            rate.record(start);
+        }
+        assert_eq!(rate.record(start), 10);
+        assert_eq!(rate.record(start + WINDOW + WINDOW / 2), 6);
+        assert_eq!(rate.record(start + 5 * WINDOW), 1);
+    }
+}
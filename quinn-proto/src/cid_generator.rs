@@ -1,6 +1,6 @@
 use std::time::Duration;
 
-use rand::RngCore;
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
 
 use crate::shared::ConnectionId;
 use crate::MAX_CID_SIZE;
@@ -20,6 +20,12 @@ pub trait ConnectionIdGenerator: Send {
     ///
     /// Connection IDs will be retired after the returned `Duration`, if any. Assumed to be constant.
     fn cid_lifetime(&self) -> Option<Duration>;
+    /// Generate the same sequence of CIDs in every run starting from `seed`
+    ///
+    /// Called once before any CIDs are generated if the endpoint was configured with
+    /// `EndpointConfig::rng_seed()`, provided by the `rng-seed` feature. Generators that don't use
+    /// randomness can ignore it.
+    fn set_seed(&mut self, _seed: [u8; 32]) {}
 }
 
 /// Generates purely random connection IDs of a certain length
@@ -27,6 +33,10 @@ pub trait ConnectionIdGenerator: Send {
 pub struct RandomConnectionIdGenerator {
     cid_len: usize,
     lifetime: Option<Duration>,
+    /// Seed for the next CID, if set through `set_seed`
+    ///
+    /// Otherwise, randomness is drawn from the thread's RNG.
+    seed: Option<[u8; 32]>,
 }
 
 impl Default for RandomConnectionIdGenerator {
@@ -34,6 +44,7 @@ impl Default for RandomConnectionIdGenerator {
         Self {
             cid_len: 8,
             lifetime: None,
+            seed: None,
         }
     }
 }
@@ -60,7 +71,14 @@ impl RandomConnectionIdGenerator {
 impl ConnectionIdGenerator for RandomConnectionIdGenerator {
     fn generate_cid(&mut self) -> ConnectionId {
         let mut bytes_arr = [0; MAX_CID_SIZE];
-        rand::thread_rng().fill_bytes(&mut bytes_arr[..self.cid_len]);
+        match self.seed {
+            Some(ref mut seed) => {
+                let mut rng = StdRng::from_seed(*seed);
+                rng.fill_bytes(&mut bytes_arr[..self.cid_len]);
+                *seed = rng.gen();
+            }
+            None => rand::thread_rng().fill_bytes(&mut bytes_arr[..self.cid_len]),
+        }
 
         ConnectionId::new(&bytes_arr[..self.cid_len])
     }
@@ -73,4 +91,8 @@ impl ConnectionIdGenerator for RandomConnectionIdGenerator {
     fn cid_lifetime(&self) -> Option<Duration> {
         self.lifetime
     }
+
+    fn set_seed(&mut self, seed: [u8; 32]) {
+        self.seed = Some(seed);
+    }
 }
@@ -1,15 +1,20 @@
-use std::{convert::TryInto, fmt, num::TryFromIntError, sync::Arc, time::Duration};
+use std::{cmp, convert::TryInto, fmt, num::TryFromIntError, sync::Arc, time::Duration};
 
+use bytes::Bytes;
 use rand::RngCore;
 use thiserror::Error;
 
 #[cfg(feature = "rustls")]
 use crate::crypto::types::{Certificate, CertificateChain, PrivateKey};
 use crate::{
+    admission::AdmissionController,
     cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator},
     congestion,
+    connection::PacerConfig,
     crypto::{self, ClientConfig as _, HandshakeTokenKey as _, HmacKey as _, ServerConfig as _},
-    VarInt, VarIntBoundsExceeded, DEFAULT_SUPPORTED_VERSIONS,
+    rtt_cache::RttCache,
+    Side, VarInt, VarIntBoundsExceeded, DEFAULT_SUPPORTED_VERSIONS, MAX_AUTH_TOKEN_SIZE,
+    MIN_INITIAL_SIZE, MIN_MTU,
 };
 
 /// Parameters governing the core QUIC state machine
@@ -24,33 +29,57 @@ use crate::{
 /// for higher bandwidths and latencies increases worst-case memory consumption, but does not impair
 /// performance at lower bandwidths and latencies. The default configuration is tuned for a 100Mbps
 /// link with a 100ms round trip time.
+#[derive(Clone)]
 pub struct TransportConfig {
     pub(crate) max_concurrent_bidi_streams: VarInt,
     pub(crate) max_concurrent_uni_streams: VarInt,
     pub(crate) max_idle_timeout: Option<Duration>,
     pub(crate) stream_receive_window: VarInt,
+    pub(crate) uni_stream_receive_window: Option<VarInt>,
     pub(crate) receive_window: VarInt,
+    pub(crate) credit_policy: CreditPolicy,
+    pub(crate) connection_credit_policy: CreditPolicy,
     pub(crate) send_window: u64,
 
     pub(crate) max_tlps: u32,
     pub(crate) packet_threshold: u32,
     pub(crate) time_threshold: f32,
+    pub(crate) max_pto_backoff: u32,
     pub(crate) initial_rtt: Duration,
+    pub(crate) udp_blocked_probes: Option<u32>,
+    pub(crate) initial_duplicates: u32,
+    pub(crate) initial_duplicate_spacing: Duration,
 
     pub(crate) persistent_congestion_threshold: u32,
     pub(crate) keep_alive_interval: Option<Duration>,
+    pub(crate) idle_timeout_warning: Option<Duration>,
     pub(crate) crypto_buffer_size: usize,
     pub(crate) allow_spin: bool,
     pub(crate) datagram_receive_buffer_size: Option<usize>,
     pub(crate) datagram_send_buffer_size: usize,
-
-    pub(crate) congestion_controller_factory: Box<dyn congestion::ControllerFactory + Send + Sync>,
+    pub(crate) stream_initiator: Option<Side>,
+    pub(crate) initial_max_udp_payload_size: u16,
+    pub(crate) max_egress_udp_payload_size: Option<u16>,
+    pub(crate) auth_token: Option<Bytes>,
+    pub(crate) header_compression: bool,
+    pub(crate) pacer: PacerConfig,
+    pub(crate) low_latency: bool,
+    pub(crate) max_ack_delay: Duration,
+    pub(crate) ack_delay_exponent: u8,
+    pub(crate) padding: PaddingPolicy,
+    pub(crate) mtu_discovery: Option<MtuDiscoveryConfig>,
+    pub(crate) quota: Option<ConnectionQuota>,
+    pub(crate) rtt_cache: Option<Arc<dyn RttCache>>,
+
+    pub(crate) congestion_controller_factory: Arc<dyn congestion::ControllerFactory + Send + Sync>,
 }
 
 impl TransportConfig {
     /// Maximum number of bidirectional streams that may be open concurrently
     ///
-    /// Must be nonzero for the peer to open any bidirectional streams.
+    /// Must be nonzero for the peer to open any bidirectional streams. As streams close, the peer
+    /// is permitted to open new ones in batches of an eighth of this limit, or sooner if it's about
+    /// to run out.
     ///
     /// Worst-case memory use is directly proportional to `max_concurrent_bidi_streams *
     /// stream_receive_window`, with an upper bound proportional to `receive_window`.
@@ -93,6 +122,17 @@ impl TransportConfig {
         Ok(self)
     }
 
+    /// Variant of `stream_receive_window` affecting unidirectional streams
+    ///
+    /// `None`, the default, applies `stream_receive_window` to streams of both directionalities.
+    pub fn uni_stream_receive_window(
+        &mut self,
+        value: Option<u64>,
+    ) -> Result<&mut Self, ConfigError> {
+        self.uni_stream_receive_window = value.map(VarInt::from_u64).transpose()?;
+        Ok(self)
+    }
+
     /// Maximum number of bytes the peer may transmit across all streams of a connection before
     /// becoming blocked.
     ///
@@ -104,6 +144,33 @@ impl TransportConfig {
         Ok(self)
     }
 
+    /// When stream-level flow control credit is returned to the peer as data is read
+    ///
+    /// Defaults to `CreditPolicy::Threshold(8)`, returning credit in increments of an eighth of
+    /// the stream receive window. Fails if a threshold of zero is supplied.
+    pub fn credit_policy(&mut self, value: CreditPolicy) -> Result<&mut Self, ConfigError> {
+        if value == CreditPolicy::Threshold(0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.credit_policy = value;
+        Ok(self)
+    }
+
+    /// Variant of `credit_policy` affecting connection-level flow control credit
+    ///
+    /// Combined with `CreditPolicy::Manual` for streams, allows an application to bound exactly how
+    /// much data the peer can make it buffer.
+    pub fn connection_credit_policy(
+        &mut self,
+        value: CreditPolicy,
+    ) -> Result<&mut Self, ConfigError> {
+        if value == CreditPolicy::Threshold(0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.connection_credit_policy = value;
+        Ok(self)
+    }
+
     /// Maximum number of bytes to transmit to a peer without acknowledgment
     ///
     /// Provides an upper bound on memory when communicating with peers that issue large amounts of
@@ -135,12 +202,49 @@ impl TransportConfig {
         self
     }
 
+    /// Maximum number of times the probe timeout doubles while consecutive probes go unanswered
+    ///
+    /// Lower values make retransmissions after prolonged loss more aggressive, which suits
+    /// real-time applications that would rather waste bandwidth than stall. Capped at 16, which is
+    /// the default.
+    pub fn max_pto_backoff(&mut self, value: u32) -> &mut Self {
+        self.max_pto_backoff = value;
+        self
+    }
+
     /// The RTT used before an RTT sample is taken
     pub fn initial_rtt(&mut self, value: Duration) -> &mut Self {
         self.initial_rtt = value;
         self
     }
 
+    /// Number of unanswered probe timeouts after which a client concludes that UDP traffic to the
+    /// server is blocked, or `None` to wait for the handshake to time out
+    ///
+    /// Only applies while nothing at all has been received from the server. Failing early with
+    /// `ConnectionError::UdpBlocked` lets applications fall back to another transport, such as
+    /// TCP, well before the idle timeout expires. Defaults to `None`. Must be nonzero.
+    pub fn udp_blocked_probes(&mut self, value: Option<u32>) -> Result<&mut Self, ConfigError> {
+        if value == Some(0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.udp_blocked_probes = value;
+        Ok(self)
+    }
+
+    /// Number of extra copies of a client's first flight to send, and the delay between them
+    ///
+    /// On extremely lossy links, such as satellite or the edge of cellular coverage, a lost first
+    /// flight is otherwise only retransmitted after a probe timeout, which starts at several times
+    /// the initial RTT. Each copy resends the client's handshake data in a new Initial packet,
+    /// `spacing` after the previous one, until anything is received from the server. Defaults to
+    /// 0, sending no copies.
+    pub fn initial_duplicates(&mut self, count: u32, spacing: Duration) -> &mut Self {
+        self.initial_duplicates = count;
+        self.initial_duplicate_spacing = spacing;
+        self
+    }
+
     /// Number of consecutive PTOs after which network is considered to be experiencing persistent congestion.
     pub fn persistent_congestion_threshold(&mut self, value: u32) -> &mut Self {
         self.persistent_congestion_threshold = value;
@@ -159,6 +263,19 @@ impl TransportConfig {
         self
     }
 
+    /// How long before the idle timeout expires to emit a warning
+    ///
+    /// Lets applications that would rather not see a connection time out send a keep-alive or
+    /// close it gracefully, e.g. once they know whether any more work is pending. The warning is
+    /// emitted at most once per period of inactivity, and only for established connections.
+    ///
+    /// `None` to disable, which is the default. Has no effect if it's no shorter than the idle
+    /// timeout in effect.
+    pub fn idle_timeout_warning(&mut self, value: Option<Duration>) -> &mut Self {
+        self.idle_timeout_warning = value;
+        self
+    }
+
     /// Maximum quantity of out-of-order crypto layer data to buffer
     pub fn crypto_buffer_size(&mut self, value: usize) -> &mut Self {
         self.crypto_buffer_size = value;
@@ -196,6 +313,218 @@ impl TransportConfig {
         self
     }
 
+    /// Restrict which side of the connection may initiate streams, or `None` to allow both
+    ///
+    /// Application protocols which define a fixed stream direction contract, e.g. where only the
+    /// client issues requests, can use this to have violations enforced by the transport. If the
+    /// peer refers to a stream it initiated although it isn't permitted to, the connection is
+    /// closed with a `PROTOCOL_VIOLATION` error.
+    ///
+    /// A client configured with `Some(Side::Client)` rejects server-initiated streams, while a
+    /// server configured with `Some(Side::Server)` rejects client-initiated streams.
+    pub fn stream_initiator(&mut self, value: Option<Side>) -> &mut Self {
+        self.stream_initiator = value;
+        self
+    }
+
+    /// Opaque token for the server to authenticate the client with, or `None` to send none
+    ///
+    /// Carried in a transport parameter in the client's first flight, so that the server can
+    /// inspect it through the handshake data before committing resources to the connection, e.g.
+    /// to check an API key. Like the rest of the ClientHello, it's sent before the server is
+    /// authenticated and isn't protected against replay. Must be at most 1024 bytes. Only used by
+    /// clients.
+    pub fn auth_token(&mut self, value: Option<Bytes>) -> Result<&mut Self, ConfigError> {
+        if value
+            .as_ref()
+            .map_or(false, |x| x.len() > MAX_AUTH_TOKEN_SIZE)
+        {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.auth_token = value;
+        Ok(self)
+    }
+
+    /// Whether to compress the headers of 1-RTT packets, if the peer agrees
+    ///
+    /// A nonstandard extension for private carriers where both ends run quinn, e.g. tunnels over
+    /// expensive links. Support is advertised in a transport parameter, and once both peers have
+    /// done so, each replaces the destination connection ID of the packets it sends with that
+    /// connection ID's much shorter sequence number, signaled by clearing the QUIC bit. The peer
+    /// restores the full header before decrypting, so packet protection is unaffected.
+    ///
+    /// Compressed packets are attributed to connections by their source address, so only the
+    /// first of several connections with the same remote address compresses headers, and
+    /// compression suits carriers whose addresses don't change: a peer whose address is rebound
+    /// cannot be reached until the connection times out. Only datagrams carrying a single packet
+    /// which needn't be padded are compressed, so `Transmit`s with a `segment_size` are sent as-is.
+    /// Defaults to false.
+    pub fn header_compression(&mut self, value: bool) -> &mut Self {
+        self.header_compression = value;
+        self
+    }
+
+    /// Size of the UDP payloads sent on a new path
+    ///
+    /// Must be at least 1200, the smallest size all QUIC paths are required to support. The default
+    /// of 1232 is suitable for any IPv6 path with a standard MTU.
+    pub fn initial_max_udp_payload_size(&mut self, value: u16) -> Result<&mut Self, ConfigError> {
+        if value < MIN_INITIAL_SIZE {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.initial_max_udp_payload_size = value;
+        Ok(self)
+    }
+
+    /// Upper bound on the size of outgoing UDP payloads, or `None` for no limit beyond that
+    /// advertised by the peer
+    ///
+    /// Useful on paths known to have a reduced MTU, e.g. through tunnels. Takes precedence over
+    /// `initial_max_udp_payload_size`. Must be at least 1200.
+    pub fn max_egress_udp_payload_size(
+        &mut self,
+        value: Option<u16>,
+    ) -> Result<&mut Self, ConfigError> {
+        if value.map_or(false, |x| x < MIN_INITIAL_SIZE) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.max_egress_udp_payload_size = value;
+        Ok(self)
+    }
+
+    /// Interval at which the pacer releases bursts of packets
+    ///
+    /// Each burst carries as much of the congestion window as is due over this interval, so longer
+    /// intervals mean fewer timer wakeups at the cost of burstier sends. Should not be shorter than
+    /// the precision of the timers driving the connection. Defaults to 2ms. Must be nonzero.
+    pub fn pacer_burst_interval(&mut self, value: Duration) -> Result<&mut Self, ConfigError> {
+        if value == Duration::from_secs(0) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.pacer.burst_interval = value;
+        Ok(self)
+    }
+
+    /// Bounds on the size of the pacer's bursts, in packets
+    ///
+    /// Bursts computed from the burst interval are clamped to this range. Larger bursts make better
+    /// use of segmentation offload on fast links, while smaller ones spread packets out further on
+    /// slow links. Defaults to 10 to 256 packets. `min` must be nonzero and no greater than `max`.
+    pub fn pacer_burst_size(&mut self, min: u64, max: u64) -> Result<&mut Self, ConfigError> {
+        if min == 0 || min > max {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.pacer.min_burst_size = min;
+        self.pacer.max_burst_size = max;
+        Ok(self)
+    }
+
+    /// Send packets as soon as the congestion window allows and never batch acknowledgements
+    ///
+    /// Disables pacing, and prevents `PowerMode::PowerSaving` from holding back ACK-only packets.
+    /// Suits links where the path never queues much, such as within a datacenter, where pacing
+    /// and ACK batching cost more latency than they save in loss. Defaults to false. May be changed
+    /// for a live connection with `Connection::set_low_latency`.
+    pub fn low_latency(&mut self, value: bool) -> &mut Self {
+        self.low_latency = value;
+        self
+    }
+
+    /// Longest we may hold back acknowledgements, so that one ACK frame covers several packets
+    ///
+    /// Acknowledging every other packet can saturate the return path of links whose capacity is
+    /// highly asymmetric, such as DOCSIS or satellite uplinks, throttling downloads. A nonzero
+    /// delay thins out ACK-only packets while the peer allows for the delay in its loss detection.
+    /// Acknowledgements still ride along with any other data sent meanwhile. Also bounds how long
+    /// `PowerMode::PowerSaving` holds back acknowledgements. Ignored while `low_latency` is set.
+    ///
+    /// Defaults to zero, acknowledging immediately. Rounded down to whole milliseconds, and must be
+    /// less than 2^14 milliseconds, the largest value peers accept.
+    pub fn max_ack_delay(&mut self, value: Duration) -> Result<&mut Self, ConfigError> {
+        if value.as_millis() >= 1 << 14 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.max_ack_delay = value;
+        Ok(self)
+    }
+
+    /// Scale of the delays reported in our ACK frames, as a power of two microseconds
+    ///
+    /// Larger exponents save a byte or so per ACK frame at the cost of precision in the peer's RTT
+    /// estimate. Defaults to 3. Must be at most 20, the largest value peers accept.
+    pub fn ack_delay_exponent(&mut self, value: u8) -> Result<&mut Self, ConfigError> {
+        if value > 20 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.ack_delay_exponent = value;
+        Ok(self)
+    }
+
+    /// How much to pad 1-RTT packets, trading bandwidth for resistance to traffic analysis
+    ///
+    /// Defaults to `PaddingPolicy::Minimal`.
+    pub fn padding(&mut self, value: PaddingPolicy) -> &mut Self {
+        self.padding = value;
+        self
+    }
+
+    /// Apply a preset bundle of settings suited to a kind of network
+    ///
+    /// See [`TransportProfile`] for the settings affected. Other settings are left alone, and any
+    /// of the affected ones may be overridden afterwards.
+    pub fn profile(&mut self, profile: TransportProfile) -> &mut Self {
+        let defaults = TransportConfig::default();
+        let mut congestion = congestion::NewRenoConfig::default();
+        match profile {
+            TransportProfile::Internet => {
+                self.low_latency = defaults.low_latency;
+                self.initial_rtt = defaults.initial_rtt;
+                self.max_ack_delay = defaults.max_ack_delay;
+            }
+            TransportProfile::LowLatencyLan => {
+                self.low_latency = true;
+                self.initial_rtt = Duration::from_millis(1);
+                self.max_ack_delay = defaults.max_ack_delay;
+                congestion.initial_window(LAN_INITIAL_WINDOW);
+            }
+            TransportProfile::AsymmetricUplink => {
+                self.low_latency = defaults.low_latency;
+                self.initial_rtt = defaults.initial_rtt;
+                self.max_ack_delay = UPLINK_MAX_ACK_DELAY;
+            }
+        }
+        self.ack_delay_exponent = defaults.ack_delay_exponent;
+        self.congestion_controller_factory(congestion)
+    }
+
+    /// Probe for UDP payloads larger than the initial size once connected, or `None` to only change
+    /// the size in response to manual probes and path MTU reports
+    ///
+    /// Disabled by default. See [`MtuDiscoveryConfig`] for details.
+    pub fn mtu_discovery_config(&mut self, value: Option<MtuDiscoveryConfig>) -> &mut Self {
+        self.mtu_discovery = value;
+        self
+    }
+
+    /// Limits on what the peer may use over the connection's lifetime, or `None` for no limits
+    ///
+    /// See [`ConnectionQuota`] for details.
+    pub fn quota(&mut self, value: Option<ConnectionQuota>) -> &mut Self {
+        self.quota = value;
+        self
+    }
+
+    /// Where clients look up and record the RTT to each server, or `None` to always start from
+    /// `initial_rtt`
+    ///
+    /// On long or slow paths, such as satellite links, the default `initial_rtt` is far too
+    /// short, so the first retransmissions after connecting are spurious. With a cache, a client
+    /// starts from the RTT last observed to the same server name instead. Defaults to `None`.
+    pub fn rtt_cache(&mut self, value: Option<Arc<dyn RttCache>>) -> &mut Self {
+        self.rtt_cache = value;
+        self
+    }
+
     /// How to construct new `congestion::Controller`s
     ///
     /// Typically the refcounted configuration of a `congestion::Controller`,
@@ -211,9 +540,17 @@ impl TransportConfig {
         &mut self,
         factory: impl congestion::ControllerFactory + Send + Sync + 'static,
     ) -> &mut Self {
-        self.congestion_controller_factory = Box::new(factory);
+        self.congestion_controller_factory = Arc::new(factory);
         self
     }
+
+    /// Size of the UDP payloads to send on a new path, before accounting for the peer's limit
+    pub(crate) fn initial_mtu(&self) -> u16 {
+        cmp::min(
+            self.initial_max_udp_payload_size,
+            self.max_egress_udp_payload_size.unwrap_or(u16::MAX),
+        )
+    }
 }
 
 impl Default for TransportConfig {
@@ -229,22 +566,43 @@ impl Default for TransportConfig {
             max_concurrent_uni_streams: 100u32.into(),
             max_idle_timeout: Some(Duration::from_millis(10_000)),
             stream_receive_window: STREAM_RWND.into(),
+            uni_stream_receive_window: None,
             receive_window: VarInt::MAX,
+            credit_policy: CreditPolicy::Threshold(8),
+            connection_credit_policy: CreditPolicy::Threshold(8),
             send_window: (8 * STREAM_RWND).into(),
 
             max_tlps: 2,
             packet_threshold: 3,
             time_threshold: 9.0 / 8.0,
+            max_pto_backoff: 16,
             initial_rtt: Duration::from_millis(333), // per spec, intentionally distinct from EXPECTED_RTT
+            udp_blocked_probes: None,
+            initial_duplicates: 0,
+            initial_duplicate_spacing: Duration::from_millis(50),
 
             persistent_congestion_threshold: 3,
             keep_alive_interval: None,
+            idle_timeout_warning: None,
             crypto_buffer_size: 16 * 1024,
             allow_spin: true,
             datagram_receive_buffer_size: Some(STREAM_RWND as usize),
             datagram_send_buffer_size: 1024 * 1024,
-
-            congestion_controller_factory: Box::new(Arc::new(congestion::NewRenoConfig::default())),
+            stream_initiator: None,
+            initial_max_udp_payload_size: MIN_MTU,
+            max_egress_udp_payload_size: None,
+            auth_token: None,
+            header_compression: false,
+            pacer: PacerConfig::default(),
+            low_latency: false,
+            max_ack_delay: Duration::from_millis(0),
+            ack_delay_exponent: 3,
+            padding: PaddingPolicy::Minimal,
+            mtu_discovery: None,
+            quota: None,
+            rtt_cache: None,
+
+            congestion_controller_factory: Arc::new(congestion::NewRenoConfig::default()),
         }
     }
 }
@@ -262,17 +620,25 @@ impl fmt::Debug for TransportConfig {
             )
             .field("max_idle_timeout", &self.max_idle_timeout)
             .field("stream_receive_window", &self.stream_receive_window)
+            .field("uni_stream_receive_window", &self.uni_stream_receive_window)
             .field("receive_window", &self.receive_window)
+            .field("credit_policy", &self.credit_policy)
+            .field("connection_credit_policy", &self.connection_credit_policy)
             .field("send_window", &self.send_window)
             .field("max_tlps", &self.max_tlps)
             .field("packet_threshold", &self.packet_threshold)
             .field("time_threshold", &self.time_threshold)
+            .field("max_pto_backoff", &self.max_pto_backoff)
             .field("initial_rtt", &self.initial_rtt)
+            .field("udp_blocked_probes", &self.udp_blocked_probes)
+            .field("initial_duplicates", &self.initial_duplicates)
+            .field("initial_duplicate_spacing", &self.initial_duplicate_spacing)
             .field(
                 "persistent_congestion_threshold",
                 &self.persistent_congestion_threshold,
             )
             .field("keep_alive_interval", &self.keep_alive_interval)
+            .field("idle_timeout_warning", &self.idle_timeout_warning)
             .field("crypto_buffer_size", &self.crypto_buffer_size)
             .field("allow_spin", &self.allow_spin)
             .field(
@@ -280,11 +646,209 @@ impl fmt::Debug for TransportConfig {
                 &self.datagram_receive_buffer_size,
             )
             .field("datagram_send_buffer_size", &self.datagram_send_buffer_size)
+            .field("stream_initiator", &self.stream_initiator)
+            .field(
+                "initial_max_udp_payload_size",
+                &self.initial_max_udp_payload_size,
+            )
+            .field(
+                "max_egress_udp_payload_size",
+                &self.max_egress_udp_payload_size,
+            )
+            .field(
+                "auth_token",
+                &self.auth_token.as_ref().map(|_| "[ redacted ]"),
+            )
+            .field("header_compression", &self.header_compression)
+            .field("pacer", &self.pacer)
+            .field("low_latency", &self.low_latency)
+            .field("max_ack_delay", &self.max_ack_delay)
+            .field("ack_delay_exponent", &self.ack_delay_exponent)
+            .field("padding", &self.padding)
+            .field("mtu_discovery", &self.mtu_discovery)
+            .field("quota", &self.quota)
+            .field("rtt_cache", &self.rtt_cache.as_ref().map(|_| "[ opaque ]"))
             .field("congestion_controller_factory", &"[ opaque ]")
             .finish()
     }
 }
 
+/// Preset bundles of transport settings, applied with [`TransportConfig::profile()`]
+///
+/// Each profile sets [`TransportConfig::low_latency()`], [`TransportConfig::initial_rtt()`],
+/// [`TransportConfig::max_ack_delay()`], [`TransportConfig::ack_delay_exponent()`] and the
+/// congestion controller, which is a `congestion::NewRenoConfig` with a suitable initial window.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TransportProfile {
+    /// Settings for arbitrary internet paths, as in `TransportConfig::default()`
+    Internet,
+    /// Settings for RPC between hosts in a datacenter, with sub-millisecond round trips
+    ///
+    /// Disables pacing and ACK batching, assumes an initial RTT of 1ms, and starts with a
+    /// congestion window of 64KiB so that typical requests and responses fit in the first flight.
+    LowLatencyLan,
+    /// Settings for downloads over paths with a slow return direction, such as cable or satellite
+    ///
+    /// As `Internet`, but acknowledgements are held back for up to 25ms so that fewer ACK-only
+    /// packets compete for the uplink.
+    AsymmetricUplink,
+}
+
+/// Initial congestion window of `TransportProfile::LowLatencyLan`
+const LAN_INITIAL_WINDOW: u64 = 64 * 1024;
+
+/// `max_ack_delay` of `TransportProfile::AsymmetricUplink`
+const UPLINK_MAX_ACK_DELAY: Duration = Duration::from_millis(25);
+
+/// Parameters governing the search for the largest UDP payload a path can carry
+///
+/// Once a connection is established, probes are sent for ever larger payloads, `step` bytes at a
+/// time, until a probe is lost repeatedly or `upper_bound` is reached. The search is repeated
+/// every `interval`, in case the path changed. Probing never exceeds the largest payload the peer
+/// is willing to receive, nor `TransportConfig::max_egress_udp_payload_size`.
+///
+/// Probes are only meaningful if the socket prevents fragmentation of outgoing datagrams, as
+/// quinn's UDP socket does on Linux.
+#[derive(Debug, Copy, Clone)]
+pub struct MtuDiscoveryConfig {
+    pub(crate) interval: Duration,
+    pub(crate) upper_bound: u16,
+    pub(crate) step: u16,
+}
+
+impl MtuDiscoveryConfig {
+    /// Time after which to search again, once a search has ended
+    ///
+    /// Defaults to 10 minutes.
+    pub fn interval(&mut self, value: Duration) -> &mut Self {
+        self.interval = value;
+        self
+    }
+
+    /// Largest UDP payload size to probe for
+    ///
+    /// Defaults to 1452, suitable for a standard 1500-byte Ethernet MTU. Must be at least 1200.
+    pub fn upper_bound(&mut self, value: u16) -> Result<&mut Self, ConfigError> {
+        if value < MIN_INITIAL_SIZE {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.upper_bound = value;
+        Ok(self)
+    }
+
+    /// Increase in payload size from one probe to the next
+    ///
+    /// Larger steps find large MTUs in fewer round trips, at the cost of precision. Defaults to
+    /// 64. Must be nonzero.
+    pub fn step(&mut self, value: u16) -> Result<&mut Self, ConfigError> {
+        if value == 0 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.step = value;
+        Ok(self)
+    }
+}
+
+impl Default for MtuDiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            upper_bound: 1452,
+            step: 64,
+        }
+    }
+}
+
+/// Limits on what a peer may use over a connection's lifetime
+///
+/// Unlike flow control, which bounds how much the peer may send at once, quotas bound the total,
+/// e.g. for services offering a limited amount of free usage. Once the peer exceeds any of them,
+/// the connection is closed with `error_code`, and locally reported as lost with
+/// [`ConnectionError::QuotaExceeded`](crate::ConnectionError::QuotaExceeded).
+#[derive(Debug, Copy, Clone)]
+pub struct ConnectionQuota {
+    pub(crate) max_received_bytes: Option<u64>,
+    pub(crate) max_streams: Option<u64>,
+    pub(crate) error_code: VarInt,
+}
+
+impl ConnectionQuota {
+    /// Create a quota without any limits, closing connections that exceed it with `error_code`
+    pub fn new(error_code: VarInt) -> Self {
+        Self {
+            max_received_bytes: None,
+            max_streams: None,
+            error_code,
+        }
+    }
+
+    /// Maximum number of bytes of stream data and unreliable datagrams the peer may send
+    pub fn max_received_bytes(&mut self, value: Option<u64>) -> &mut Self {
+        self.max_received_bytes = value;
+        self
+    }
+
+    /// Maximum number of streams the peer may open
+    pub fn max_streams(&mut self, value: Option<u64>) -> &mut Self {
+        self.max_streams = value;
+        self
+    }
+}
+
+/// When a receiver returns flow control credit to the sender
+///
+/// Credit is returned by sending `MAX_STREAM_DATA` and `MAX_DATA` frames, which allow the peer to
+/// send more data on a stream and on the connection as a whole respectively. See
+/// `TransportConfig::credit_policy` and `TransportConfig::connection_credit_policy`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CreditPolicy {
+    /// Return credit for data as soon as it's read
+    ///
+    /// Minimizes the risk of the sender stalling on a full window, at the cost of sending more
+    /// frames.
+    Immediate,
+    /// Return credit once the credit for data read amounts to at least `1/n` of the receive window
+    Threshold(u32),
+    /// Only return credit explicitly granted by the application
+    ///
+    /// Credit is granted with `RecvStream::credit` for streams and `Connection::credit` for the
+    /// connection. Until then, the peer can only send a receive window's worth of data, regardless
+    /// of how much data has been read, allowing memory-constrained receivers to apply backpressure
+    /// deliberately.
+    Manual,
+}
+
+impl CreditPolicy {
+    /// Minimum amount of credit worth announcing to the peer
+    pub(crate) fn threshold(self, window: u64) -> u64 {
+        match self {
+            CreditPolicy::Threshold(n) => window / u64::from(n),
+            CreditPolicy::Immediate | CreditPolicy::Manual => 1,
+        }
+    }
+}
+
+/// How much to pad 1-RTT packets, set with `TransportConfig::padding`
+///
+/// Packet protection conceals the contents of packets but not their sizes, from which an observer
+/// may infer e.g. the sizes of messages exchanged, or which web page is being loaded. Handshake
+/// packets are padded as the protocol requires regardless of the policy.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PaddingPolicy {
+    /// Only pad where the protocol requires it, or to batch datagrams of equal size for
+    /// segmentation offload
+    ///
+    /// Wastes no bandwidth, but leaves the size of each datagram revealing how much data it
+    /// carries.
+    Minimal,
+    /// Pad every datagram carrying 1-RTT packets to the path MTU
+    ///
+    /// All such datagrams look alike to an observer, at the cost of sending a full-sized datagram
+    /// even for an acknowledgement. Suits tunnels and other applications where traffic analysis
+    /// is a concern.
+    Full,
+}
+
 /// Global configuration for the endpoint, affecting all connections
 ///
 /// Default values should be suitable for most internet applications.
@@ -301,6 +865,8 @@ where
         Arc<dyn Fn() -> Box<dyn ConnectionIdGenerator> + Send + Sync>,
     pub(crate) supported_versions: Vec<u32>,
     pub(crate) initial_version: u32,
+    pub(crate) rng_seed: Option<[u8; 32]>,
+    pub(crate) single_connection: bool,
 }
 
 impl<S> EndpointConfig<S>
@@ -317,6 +883,8 @@ where
             connection_id_generator_factory: Arc::new(cid_factory),
             initial_version: DEFAULT_SUPPORTED_VERSIONS[0],
             supported_versions: DEFAULT_SUPPORTED_VERSIONS.to_vec(),
+            rng_seed: None,
+            single_connection: false,
         }
     }
 
@@ -380,6 +948,52 @@ where
         self.initial_version = initial_version;
         Ok(self)
     }
+
+    /// Derive all randomness used by endpoints from `seed`, for reproducible tests
+    ///
+    /// Makes connection IDs, packet number skips, path challenges, stateless reset tokens and the
+    /// like identical across runs that receive the same packets at the same times, so that
+    /// failures found in randomized tests can be replayed. Custom connection ID generators are
+    /// seeded through [`ConnectionIdGenerator::set_seed()`], and the `reset_key` is replaced by one
+    /// derived from `seed`. Randomness inside the cryptographic session, such as that of TLS
+    /// handshakes, and keys in the `ServerConfig` are unaffected.
+    ///
+    /// Makes the endpoint's connection IDs and reset tokens predictable, so only available with the
+    /// `rng-seed` feature, which must never be enabled in production. Defaults to `None`, drawing
+    /// randomness from the operating system.
+    #[cfg(any(test, feature = "rng-seed"))]
+    pub fn rng_seed(&mut self, seed: Option<[u8; 32]>) -> &mut Self {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        self.rng_seed = seed.map(|seed| {
+            let mut rng = StdRng::from_seed(seed);
+            let mut reset_key = vec![0; S::HmacKey::KEY_LEN];
+            rng.fill_bytes(&mut reset_key);
+            self.reset_key = Arc::new(
+                S::HmacKey::new(&reset_key)
+                    .expect("HMAC key rejected random bytes; use reset_key instead"),
+            );
+            rng.gen()
+        });
+        self
+    }
+
+    /// Whether endpoints carry at most one connection at a time, using zero-length connection IDs
+    ///
+    /// Packets sent to an endpoint which uses zero-length connection IDs can only be attributed to a
+    /// connection by their source address, which is unambiguous when the endpoint has a single
+    /// connection. Omitting the connection ID saves 8 bytes, with the default generator, in every
+    /// packet the peer sends, which matters on constrained links, e.g. for a client which owns its
+    /// socket and talks to a single server.
+    ///
+    /// When enabled, overrides the connection ID generator set by `cid_generator`, and endpoints
+    /// refuse to initiate or accept a connection while another exists, with
+    /// `ConnectError::TooManyConnections` and `CONNECTION_REFUSED` respectively. Defaults to
+    /// `false`.
+    pub fn single_connection(&mut self, value: bool) -> &mut Self {
+        self.single_connection = value;
+        self
+    }
 }
 
 impl<S: crypto::Session> fmt::Debug for EndpointConfig<S> {
@@ -390,6 +1004,8 @@ impl<S: crypto::Session> fmt::Debug for EndpointConfig<S> {
             .field("cid_generator_factory", &"[ elided ]")
             .field("supported_versions", &self.supported_versions)
             .field("initial_version", &self.initial_version)
+            .field("rng_seed", &self.rng_seed)
+            .field("single_connection", &self.single_connection)
             .finish()
     }
 }
@@ -413,6 +1029,8 @@ impl<S: crypto::Session> Clone for EndpointConfig<S> {
             connection_id_generator_factory: self.connection_id_generator_factory.clone(),
             supported_versions: self.supported_versions.clone(),
             initial_version: self.initial_version,
+            rng_seed: self.rng_seed,
+            single_connection: self.single_connection,
         }
     }
 }
@@ -450,6 +1068,18 @@ where
     /// Improves behavior for clients that move between different internet connections or suffer NAT
     /// rebinding. Enabled by default.
     pub(crate) migration: bool,
+
+    /// Consulted on each connection attempt to decide whether to proceed
+    pub(crate) admission_controller: Option<Arc<dyn AdmissionController>>,
+
+    /// Whether to leave processing of a connection's first packet to the `Connection`
+    pub(crate) defer_handshake: bool,
+
+    /// Whether to withhold streams carrying 0-RTT data until the handshake is confirmed
+    pub(crate) defer_0rtt_data: bool,
+
+    /// Whether to resume sessions only from tickets issued for the same server name and protocol
+    pub(crate) pin_session_tickets: bool,
 }
 
 impl<S> ServerConfig<S>
@@ -469,6 +1099,12 @@ where
             concurrent_connections: 100_000,
 
             migration: true,
+
+            admission_controller: None,
+
+            defer_handshake: false,
+            defer_0rtt_data: false,
+            pin_session_tickets: false,
         }
     }
 
@@ -508,10 +1144,74 @@ where
         self.migration = value;
         self
     }
+
+    /// Install a hook deciding whether to accept, reject, or retry each connection attempt
+    ///
+    /// Applied in addition to `concurrent_connections`, which is checked first. Connection attempts
+    /// which the controller asks to retry are handled as if `use_stateless_retry` was enabled.
+    pub fn admission_controller(
+        &mut self,
+        controller: impl AdmissionController + 'static,
+    ) -> &mut Self {
+        self.admission_controller = Some(Arc::new(controller));
+        self
+    }
+
+    /// Whether to defer processing of the client's first flight to the new `Connection`
+    ///
+    /// By default, the handshake data carried by a connection attempt is processed by
+    /// `Endpoint::handle` before the connection is returned, which includes the potentially costly
+    /// cryptography required to respond to the client. When enabled, that work is instead done the
+    /// first time the `Connection` is driven, allowing it to be performed outside of the context
+    /// driving the endpoint. Connections whose first packet turns out to be invalid are then closed
+    /// like any other rather than being dropped silently. Disabled by default.
+    pub fn defer_handshake(&mut self, value: bool) -> &mut Self {
+        self.defer_handshake = value;
+        self
+    }
+
+    /// Whether to withhold streams opened by clients until the handshake is confirmed
+    ///
+    /// 0-RTT data may be replayed by an attacker, so it should only carry requests which are safe
+    /// to process more than once. When enabled, streams opened by the client, and thus any 0-RTT
+    /// data sent on them, are only reported once the handshake completes, which proves that the
+    /// client is live. 0-RTT still saves the data's round trip, but the application can't act on
+    /// it any earlier. Useful when the application can't judge the replay safety of individual
+    /// requests. Disabled by default.
+    pub fn defer_0rtt_data(&mut self, value: bool) -> &mut Self {
+        self.defer_0rtt_data = value;
+        self
+    }
+
+    /// Whether to resume sessions only from tickets issued for the same server name and application
+    /// protocol
+    ///
+    /// Session tickets don't record the server name or ALPN protocol they were issued for, so on a
+    /// server hosting several names or protocols, a ticket issued for one can resume a session for
+    /// another. When enabled, such tickets are ignored in favor of a full handshake without 0-RTT,
+    /// as reported by `Connection::ticket_mismatch`. Tickets issued while this was disabled are
+    /// ignored too. Disabled by default.
+    pub fn pin_session_tickets(&mut self, value: bool) -> &mut Self {
+        self.pin_session_tickets = value;
+        self
+    }
 }
 
 #[cfg(feature = "rustls")]
 impl ServerConfig<crypto::rustls::TlsSession> {
+    /// Create a configuration using `crypto` for TLS
+    ///
+    /// Allows using rustls features not otherwise exposed, such as client authentication. Only
+    /// TLS 1.3 is offered, without any cipher suites unsuitable for it, and 0-RTT is accepted
+    /// only if `crypto` sets a nonzero `max_early_data_size`. Everything else is left as is.
+    pub fn with_rustls_config(mut crypto: rustls::ServerConfig) -> Self {
+        crypto::rustls::quic_server_config(&mut crypto);
+        Self {
+            crypto: Arc::new(crypto),
+            ..Self::default()
+        }
+    }
+
     /// Set the certificate chain that will be presented to clients
     pub fn certificate(
         &mut self,
@@ -521,6 +1221,16 @@ impl ServerConfig<crypto::rustls::TlsSession> {
         Arc::make_mut(&mut self.crypto).set_single_cert(cert_chain.certs, key.inner)?;
         Ok(self)
     }
+
+    /// Select the certificate chain to present to each client from its ClientHello
+    ///
+    /// Returning `None` from `resolver` aborts the handshake with an `unrecognized_name` alert
+    /// before any key exchange takes place, which is the natural way to turn away clients asking
+    /// for server names this endpoint doesn't serve. Replaces any chain set with `certificate`.
+    pub fn cert_resolver(&mut self, resolver: Arc<dyn rustls::ResolvesServerCert>) -> &mut Self {
+        Arc::make_mut(&mut self.crypto).cert_resolver = resolver;
+        self
+    }
 }
 
 impl<S> fmt::Debug for ServerConfig<S>
@@ -536,6 +1246,13 @@ where
             .field("retry_token_lifetime", &self.retry_token_lifetime)
             .field("concurrent_connections", &self.concurrent_connections)
             .field("migration", &self.migration)
+            .field(
+                "admission_controller",
+                &self.admission_controller.as_ref().map(|_| "[ opaque ]"),
+            )
+            .field("defer_handshake", &self.defer_handshake)
+            .field("defer_0rtt_data", &self.defer_0rtt_data)
+            .field("pin_session_tickets", &self.pin_session_tickets)
             .finish()
     }
 }
@@ -568,6 +1285,10 @@ where
             retry_token_lifetime: self.retry_token_lifetime,
             concurrent_connections: self.concurrent_connections,
             migration: self.migration,
+            admission_controller: self.admission_controller.clone(),
+            defer_handshake: self.defer_handshake,
+            defer_0rtt_data: self.defer_0rtt_data,
+            pin_session_tickets: self.pin_session_tickets,
         }
     }
 }
@@ -588,6 +1309,20 @@ where
 
 #[cfg(feature = "rustls")]
 impl ClientConfig<crypto::rustls::TlsSession> {
+    /// Create a configuration using `crypto` for TLS
+    ///
+    /// Allows using rustls features not otherwise exposed, such as client certificates or custom
+    /// certificate verifiers. Only TLS 1.3 is offered, without any cipher suites unsuitable for
+    /// it, and 0-RTT is attempted only if `crypto` enables early data. Unlike the default
+    /// configuration, no certificate authorities are trusted unless `crypto` trusts them.
+    pub fn with_rustls_config(mut crypto: rustls::ClientConfig) -> Self {
+        crypto::rustls::quic_client_config(&mut crypto);
+        Self {
+            transport: Default::default(),
+            crypto: Arc::new(crypto),
+        }
+    }
+
     /// Add a trusted certificate authority
     pub fn add_certificate_authority(
         &mut self,
@@ -599,6 +1334,36 @@ impl ClientConfig<crypto::rustls::TlsSession> {
             .add_server_trust_anchors(&webpki::TLSServerTrustAnchors(&[anchor]));
         Ok(self)
     }
+
+    /// Reject servers that don't staple an OCSP response to their certificate
+    ///
+    /// A presence check only: the response isn't parsed, so neither its signature nor the
+    /// revocation status it reports is verified, and a server stapling an arbitrary response is
+    /// accepted. It merely catches servers which stopped stapling, e.g. because their
+    /// `StapledCertificate` wasn't set up. The certificate chain is verified as usual. Replaces any
+    /// other certificate verifier, such as the one installed by `danger_accept_invalid_certs`.
+    #[cfg(feature = "ocsp")]
+    pub fn require_any_ocsp_staple(&mut self) -> &mut Self {
+        Arc::make_mut(&mut self.crypto)
+            .dangerous()
+            .set_certificate_verifier(Arc::new(crypto::rustls::RequireAnyOcspStaple(
+                rustls::WebPKIVerifier::new(),
+            )));
+        self
+    }
+
+    /// Accept any certificate the server presents, whatever its issuer or the names it covers
+    ///
+    /// Only affects connections made with this configuration, so a pinned-key or development
+    /// peer can be reached with a modified copy of an endpoint's default configuration passed to
+    /// `connect_with`. Connections made this way are open to interception by anyone on the path.
+    #[cfg(feature = "dangerous_configuration")]
+    pub fn danger_accept_invalid_certs(&mut self) -> &mut Self {
+        Arc::make_mut(&mut self.crypto)
+            .dangerous()
+            .set_certificate_verifier(Arc::new(crypto::rustls::SkipServerVerification));
+        self
+    }
 }
 
 impl<S> Default for ClientConfig<S>
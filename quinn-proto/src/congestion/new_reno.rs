@@ -153,3 +153,9 @@ impl ControllerFactory for Arc<NewRenoConfig> {
         Box::new(NewReno::new(self.clone(), now))
     }
 }
+
+impl ControllerFactory for NewRenoConfig {
+    fn build(&self, now: Instant) -> Box<dyn Controller> {
+        Box::new(NewReno::new(Arc::new(self.clone()), now))
+    }
+}
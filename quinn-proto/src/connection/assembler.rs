@@ -29,6 +29,16 @@ impl Assembler {
         Self::default()
     }
 
+    /// Reset to the state of a new assembler, keeping allocated storage
+    pub(crate) fn reuse(&mut self) {
+        let mut data = mem::take(&mut self.data);
+        data.clear();
+        *self = Self {
+            data,
+            ..Self::default()
+        };
+    }
+
     pub(crate) fn ensure_ordering(&mut self, ordered: bool) -> Result<(), IllegalOrderedRead> {
         if ordered && !self.state.is_ordered() {
             return Err(IllegalOrderedRead);
@@ -93,6 +103,32 @@ impl Assembler {
         }
     }
 
+    /// Whether an ordered read would yield a chunk, or fail for unordered reads having been made
+    pub(crate) fn is_readable(&self) -> bool {
+        !self.state.is_ordered()
+            || self.data.iter().any(|chunk| {
+                chunk.offset <= self.bytes_read
+                    && chunk.offset + chunk.bytes.len() as u64 > self.bytes_read
+            })
+    }
+
+    /// Number of distinct bytes received but not yet read
+    pub(crate) fn unread(&self) -> u64 {
+        if !self.state.is_ordered() {
+            // Unordered mode discards duplicates on insertion
+            return self.buffered as u64;
+        }
+        let mut ranges = RangeSet::new();
+        for chunk in self.data.iter() {
+            let start = chunk.offset.max(self.bytes_read);
+            let end = chunk.offset + chunk.bytes.len() as u64;
+            if start < end {
+                ranges.insert(start..end);
+            }
+        }
+        ranges.iter().map(|x| x.end - x.start).sum()
+    }
+
     /// Copy fragmented chunk data to new chunks backed by a single buffer
     ///
     /// This makes sure we're not unnecessarily holding on to many larger allocations.
@@ -380,6 +416,17 @@ mod test {
         assert_matches!(next(&mut x, 32), None);
     }
 
+    #[test]
+    fn unread() {
+        let mut x = Assembler::new();
+        x.insert(0, Bytes::from_static(b"123"), 3);
+        x.insert(2, Bytes::from_static(b"34"), 2);
+        x.insert(8, Bytes::from_static(b"9"), 1);
+        assert_eq!(x.unread(), 5);
+        assert_matches!(next(&mut x, 2), Some(ref y) if &y[..] == b"12");
+        assert_eq!(x.unread(), 3);
+    }
+
     #[test]
     fn assemble_duplicate_compact() {
         let mut x = Assembler::new();
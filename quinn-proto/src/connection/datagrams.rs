@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, net::SocketAddr, sync::Arc};
 
 use bytes::Bytes;
 use thiserror::Error;
@@ -9,15 +9,15 @@ use crate::{
     crypto::{PacketKey, Session},
     frame::{Datagram, FrameStruct},
     packet::SpaceId,
-    TransportError,
+    Address, TapDirection, TransportError,
 };
 
 /// API to control datagram traffic
-pub struct Datagrams<'a, S: Session> {
-    pub(super) conn: &'a mut Connection<S>,
+pub struct Datagrams<'a, S: Session, A: Address = SocketAddr> {
+    pub(super) conn: &'a mut Connection<S, A>,
 }
 
-impl<'a, S: Session> Datagrams<'a, S> {
+impl<'a, S: Session, A: Address> Datagrams<'a, S, A> {
     /// Queue an unreliable, unordered datagram for immediate transmission
     ///
     /// Returns `Err` iff a `len`-byte datagram cannot currently be sent
@@ -41,6 +41,13 @@ impl<'a, S: Session> Datagrams<'a, S> {
         if data.len() > max {
             return Err(SendDatagramError::TooLarge);
         }
+        if let Some(ref tap) = self.conn.streams.tap {
+            tap.datagram(TapDirection::Sent, &data);
+        }
+        let data = match self.conn.datagrams.transform {
+            Some(ref transform) => transform.outgoing(data),
+            None => data,
+        };
         self.conn.datagrams.outgoing_total += data.len();
         self.conn.datagrams.outgoing.push_back(Datagram { data });
         Ok(())
@@ -54,7 +61,8 @@ impl<'a, S: Session> Datagrams<'a, S> {
     /// estimate. The peer can also enforce an arbitrarily small fixed limit, but if the peer's
     /// limit is large this is guaranteed to be a little over a kilobyte at minimum.
     ///
-    /// Not necessarily the maximum size of received datagrams.
+    /// Not necessarily the maximum size of received datagrams. Accounts for the overhead of the
+    /// connection's `DatagramTransform`, if any.
     pub fn max_size(&self) -> Option<usize> {
         // This is usually 1182 bytes, but we shouldn't document that without a doctest.
         let max_size = self.conn.path.mtu as usize
@@ -64,12 +72,68 @@ impl<'a, S: Session> Datagrams<'a, S> {
             - self.conn.spaces[SpaceId::Data].crypto.as_ref().map_or_else(|| &self.conn.zero_rtt_crypto.as_ref().unwrap().packet, |x| &x.packet.local).tag_len()
             - Datagram::SIZE_BOUND;
         let limit = self.conn.peer_params.max_datagram_frame_size?.into_inner();
-        Some(limit.min(max_size as u64) as usize)
+        let overhead = self
+            .conn
+            .datagrams
+            .transform
+            .as_ref()
+            .map_or(0, |x| x.overhead());
+        Some((limit.min(max_size as u64) as usize).saturating_sub(overhead))
     }
 
     /// Receive an unreliable, unordered datagram
     pub fn recv(&mut self) -> Option<Bytes> {
-        self.conn.datagrams.recv()
+        loop {
+            let data = self.conn.datagrams.recv()?;
+            let data = match self.conn.datagrams.transform {
+                Some(ref transform) => match transform.incoming(data) {
+                    Some(x) => x,
+                    None => {
+                        debug!("discarding datagram rejected by transform");
+                        continue;
+                    }
+                },
+                None => data,
+            };
+            if let Some(ref tap) = self.conn.streams.tap {
+                tap.datagram(TapDirection::Received, &data);
+            }
+            return Some(data);
+        }
+    }
+
+    /// Transform the payloads of datagrams subsequently sent and read, or stop doing so
+    ///
+    /// Both peers must agree on the transformation in use, e.g. through the application protocol.
+    /// Outgoing datagrams are transformed as they're queued by [`send()`](Self::send), so those
+    /// already queued are unaffected. Incoming datagrams are only transformed as they're read by
+    /// [`recv()`](Self::recv), so those already received but not yet read are passed through the
+    /// new transform.
+    pub fn set_transform(&mut self, transform: Option<Arc<dyn DatagramTransform>>) {
+        self.conn.datagrams.transform = transform;
+    }
+}
+
+/// Transforms the payloads of application datagrams as they're sent and received
+///
+/// Allows applications layering their own end-to-end encryption, authentication, or compression
+/// on top of datagrams to do so as datagrams are queued and delivered, rather than in a separate
+/// pass over each payload. Like a [`Tap`](crate::Tap), a transform is invoked synchronously while
+/// the connection's state is being accessed.
+pub trait DatagramTransform: Send + Sync {
+    /// Transform the payload of an outgoing datagram
+    ///
+    /// The result must be at most [`overhead()`](Self::overhead) bytes larger than `data`.
+    fn outgoing(&self, data: Bytes) -> Bytes;
+
+    /// Transform the payload of an incoming datagram
+    ///
+    /// Returns `None` to discard the datagram, e.g. if it failed authentication.
+    fn incoming(&self, data: Bytes) -> Option<Bytes>;
+
+    /// Maximum number of bytes `outgoing` adds to a payload
+    fn overhead(&self) -> usize {
+        0
     }
 }
 
@@ -81,6 +145,9 @@ pub(super) struct DatagramState {
     pub(super) incoming: VecDeque<Datagram>,
     pub(super) outgoing: VecDeque<Datagram>,
     pub(super) outgoing_total: usize,
+    pub(super) transform: Option<Arc<dyn DatagramTransform>>,
+    /// Total size of all datagrams received, including those dropped
+    pub(super) recvd_bytes: u64,
 }
 
 impl DatagramState {
@@ -101,6 +168,7 @@ impl DatagramState {
         if datagram.data.len() > window {
             return Err(TransportError::PROTOCOL_VIOLATION("oversized datagram"));
         }
+        self.recvd_bytes = self.recvd_bytes.saturating_add(datagram.data.len() as u64);
 
         let was_empty = self.recv_buffered == 0;
         while datagram.data.len() + self.recv_buffered > window {
@@ -113,6 +181,19 @@ impl DatagramState {
         Ok(was_empty)
     }
 
+    /// Discard queued datagrams larger than `max_size`, e.g. after the path MTU was reduced
+    pub fn drop_oversized(&mut self, max_size: usize) {
+        let total = &mut self.outgoing_total;
+        self.outgoing.retain(|x| {
+            if x.data.len() <= max_size {
+                return true;
+            }
+            trace!(len = x.data.len(), "dropping oversized outgoing datagram");
+            *total -= x.data.len();
+            false
+        });
+    }
+
     pub fn write(&mut self, buf: &mut Vec<u8>, max_size: usize) -> bool {
         let datagram = match self.outgoing.pop_front() {
             Some(x) => x,
@@ -10,7 +10,7 @@ use std::{
 
 use bytes::{Bytes, BytesMut};
 use frame::StreamMetaVec;
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand::{rngs::StdRng, Rng};
 use thiserror::Error;
 use tracing::{debug, error, trace, trace_span, warn};
 
@@ -18,18 +18,19 @@ use crate::{
     cid_generator::ConnectionIdGenerator,
     cid_queue::CidQueue,
     coding::BufMutExt,
-    config::{ServerConfig, TransportConfig},
+    config::{PaddingPolicy, ServerConfig, TransportConfig},
     crypto::{self, KeyPair, Keys, PacketKey},
     frame,
     frame::{Close, Datagram, FrameStruct},
-    packet::{Header, LongType, Packet, PartialDecode, SpaceId},
+    listener::ListenerId,
+    packet::{Header, LongType, Packet, PartialDecode, SpaceId, FIXED_BIT, LONG_HEADER_FORM},
     range_set::ArrayRangeSet,
     shared::{
         ConnectionEvent, ConnectionEventInner, ConnectionId, EcnCodepoint, EndpointEvent,
         EndpointEventInner, IssuedCid,
     },
     transport_parameters::TransportParameters,
-    Dir, Frame, Side, StreamId, Transmit, TransportError, TransportErrorCode, VarInt,
+    Address, Dir, Frame, Side, StreamId, Tap, Transmit, TransportError, TransportErrorCode, VarInt,
     MAX_STREAM_COUNT, MIN_INITIAL_SIZE, RESET_TOKEN_SIZE, TIMER_GRANULARITY,
 };
 
@@ -41,9 +42,12 @@ use cid_state::CidState;
 
 mod datagrams;
 use datagrams::DatagramState;
-pub use datagrams::{Datagrams, SendDatagramError};
+pub use datagrams::{DatagramTransform, Datagrams, SendDatagramError};
+
+mod mtud;
 
 mod pacing;
+pub(crate) use pacing::PacerConfig;
 
 mod packet_builder;
 use packet_builder::PacketBuilder;
@@ -61,7 +65,9 @@ use spaces::Retransmits;
 use spaces::{PacketSpace, SentPacket, ThinRetransmits};
 
 mod stats;
-pub use stats::ConnectionStats;
+pub use stats::{
+    ConnectionStats, LatencyHistogram, RecvStreamStats, SendStreamStats, StreamLatencyStats,
+};
 
 mod streams;
 #[cfg(fuzzing)]
@@ -69,8 +75,9 @@ pub use streams::StreamsState;
 #[cfg(not(fuzzing))]
 use streams::StreamsState;
 pub use streams::{
-    ByteSlice, BytesArray, BytesSource, Chunks, FinishError, ReadError, ReadableError, RecvStream,
-    SendStream, ShouldTransmit, StreamEvent, Streams, UnknownStream, WriteError, Written,
+    ByteSlice, BytesArray, BytesSource, Chunks, Coalescing, DrainedStream, FinishError, ReadError,
+    ReadableError, RecvStream, Reliability, SendStream, ShouldTransmit, StreamEvent, Streams,
+    UnknownStream, WriteError, Written,
 };
 
 mod timer;
@@ -116,11 +123,14 @@ use timer::{Timer, TimerTable};
 /// with events of the same [`Instant`] may be interleaved in any order with a
 /// call to [`handle_event`](self::handle_event) at that same instant; however
 /// events or timeouts with different instants must not be interleaved.
-pub struct Connection<S>
+pub struct Connection<S, A = SocketAddr>
 where
     S: crypto::Session,
+    A: Address,
 {
     server_config: Option<Arc<ServerConfig<S>>>,
+    /// The listener whose server configuration is in use, if any
+    pub(crate) listener: Option<ListenerId>,
     config: Arc<TransportConfig>,
     rng: StdRng,
     crypto: S,
@@ -132,8 +142,24 @@ where
     /// This is only populated for the server case, and if known
     local_ip: Option<IpAddr>,
 
-    path: PathData,
-    prev_path: Option<PathData>,
+    path: PathData<A>,
+    prev_path: Option<PathData<A>>,
+    /// Whether the next 1-RTT packet should be a padded PING, probing a new local address
+    path_probe_pending: bool,
+    /// When timers were frozen by `pause`, if they still are
+    paused_at: Option<Instant>,
+    /// The timers as they were when `pause` was called
+    paused_timers: TimerTable,
+    power_mode: PowerMode,
+    /// Whether pacing and ACK batching are disabled
+    low_latency: bool,
+    /// Copies of the first flight still to be sent by a client
+    initial_duplicates: u32,
+    /// Whether both peers agreed to compress the headers of 1-RTT packets
+    header_compression: bool,
+    /// Number of ack-eliciting 1-RTT packets received since we last sent an ACK, while
+    /// acknowledgements are batched
+    deferred_acks: u32,
     state: State,
     side: Side,
     /// Whether or not 0-RTT was enabled during the handshake. Does not imply acceptance.
@@ -153,7 +179,7 @@ where
     /// Total number of outgoing packets that have been deemed lost
     lost_packets: u64,
     events: VecDeque<Event>,
-    endpoint_events: VecDeque<EndpointEventInner>,
+    endpoint_events: VecDeque<EndpointEventInner<A>>,
     /// Whether the spin bit is in use for this connection
     spin_enabled: bool,
     /// Outgoing spin bit state
@@ -172,13 +198,27 @@ where
     accepted_0rtt: bool,
     /// Whether the idle timer should be reset the next time an ack-eliciting packet is transmitted.
     permit_idle_reset: bool,
-    /// Negotiated idle timeout
+    /// Idle timeout in effect, the negotiated one limited by `idle_timeout_limit`
     idle_timeout: Option<Duration>,
+    /// Negotiated idle timeout, or the configured one until the peer's transport parameters arrive
+    negotiated_idle_timeout: Option<Duration>,
+    /// Set through `set_max_idle_timeout`
+    idle_timeout_limit: Option<Duration>,
     timers: TimerTable,
     /// Number of packets received which could not be authenticated
     authentication_failures: u64,
     /// Why the connection was lost, if it has been
     error: Option<ConnectionError>,
+    /// The stateless reset that ended the connection, if any
+    stateless_reset: Option<StatelessReset<A>>,
+    /// First packet of an incoming connection, if processing it was deferred
+    deferred_packet: Option<DeferredPacket<A>>,
+    /// Whether the endpoint has been told whether the handshake succeeded
+    handshake_reported: bool,
+    /// Differentiated services code point to mark outgoing packets with
+    dscp: Option<u8>,
+    /// IPv6 flow label to mark outgoing packets with
+    flow_label: Option<u32>,
 
     //
     // Queued non-retransmittable 1-RTT data
@@ -191,6 +231,12 @@ where
     //
     /// The number of times a PTO has been sent without receiving an ack.
     pto_count: u32,
+    /// Reordering in packet number space tolerated before a packet is deemed lost
+    packet_threshold: u32,
+    /// Reordering in time tolerated before a packet is deemed lost, as a factor of RTT
+    time_threshold: f32,
+    /// Maximum exponent of the PTO backoff
+    max_pto_backoff: u32,
 
     //
     // Congestion Control
@@ -218,9 +264,10 @@ where
     version: u32,
 }
 
-impl<S> Connection<S>
+impl<S, A> Connection<S, A>
 where
     S: crypto::Session,
+    A: Address,
 {
     pub(crate) fn new(
         server_config: Option<Arc<ServerConfig<S>>>,
@@ -228,12 +275,13 @@ where
         init_cid: ConnectionId,
         loc_cid: ConnectionId,
         rem_cid: ConnectionId,
-        remote: SocketAddr,
+        remote: A,
         local_ip: Option<IpAddr>,
         crypto: S,
         cid_gen: &dyn ConnectionIdGenerator,
         now: Instant,
         version: u32,
+        mut rng: StdRng,
     ) -> Self {
         let side = if server_config.is_some() {
             Side::Server
@@ -249,10 +297,15 @@ where
             token: None,
             client_hello: None,
         });
-        let mut rng = StdRng::from_entropy();
         let path_validated = server_config
             .as_ref()
             .map_or(true, |c| c.use_stateless_retry);
+        let initial_rtt = match (&config.rtt_cache, crypto.server_name()) {
+            (Some(cache), Some(server_name)) if side.is_client() => {
+                cache.get(server_name).unwrap_or(config.initial_rtt)
+            }
+            _ => config.initial_rtt,
+        };
         let mut this = Self {
             server_config,
             crypto,
@@ -261,13 +314,27 @@ where
             local_cid_state: CidState::new(cid_gen.cid_len(), cid_gen.cid_lifetime(), now),
             path: PathData::new(
                 remote,
-                config.initial_rtt,
+                initial_rtt,
                 config.congestion_controller_factory.build(now),
                 now,
                 path_validated,
+                config.initial_mtu(),
+                config.pacer,
             ),
             local_ip,
             prev_path: None,
+            path_probe_pending: false,
+            paused_at: None,
+            paused_timers: TimerTable::default(),
+            power_mode: PowerMode::default(),
+            low_latency: config.low_latency,
+            initial_duplicates: match side {
+                Side::Client => config.initial_duplicates,
+                Side::Server => 0,
+            },
+            header_compression: false,
+            deferred_acks: 0,
+            listener: None,
             side,
             state,
             zero_rtt_enabled: false,
@@ -289,14 +356,24 @@ where
             accepted_0rtt: false,
             permit_idle_reset: true,
             idle_timeout: config.max_idle_timeout,
+            negotiated_idle_timeout: config.max_idle_timeout,
+            idle_timeout_limit: None,
             timers: TimerTable::default(),
             authentication_failures: 0,
             error: None,
+            stateless_reset: None,
+            deferred_packet: None,
+            handshake_reported: false,
+            dscp: None,
+            flow_label: None,
 
             path_response: None,
             close: false,
 
             pto_count: 0,
+            packet_threshold: config.packet_threshold,
+            time_threshold: config.time_threshold,
+            max_pto_backoff: config.max_pto_backoff,
 
             app_limited: false,
             in_flight: InFlight::new(),
@@ -310,6 +387,11 @@ where
                 config.send_window,
                 config.receive_window,
                 config.stream_receive_window,
+                config
+                    .uni_stream_receive_window
+                    .unwrap_or(config.stream_receive_window),
+                config.credit_policy,
+                config.connection_credit_policy,
             ),
             datagrams: DatagramState::default(),
             config,
@@ -318,10 +400,23 @@ where
             stats: ConnectionStats::default(),
             version,
         };
+        if this
+            .server_config
+            .as_ref()
+            .map_or(false, |c| c.defer_0rtt_data)
+        {
+            this.streams.defer_remote = true;
+        }
         if side.is_client() {
             // Kick off the connection
             this.write_crypto();
             this.init_0rtt();
+            if this.initial_duplicates > 0 {
+                this.timers.set(
+                    Timer::InitialDuplicate,
+                    now + this.config.initial_duplicate_spacing,
+                );
+            }
         }
         this
     }
@@ -335,7 +430,15 @@ where
     /// - a call was made to `handle_timeout`
     #[must_use]
     pub fn poll_timeout(&mut self) -> Option<Instant> {
-        self.timers.next_timeout()
+        if self.paused_at.is_some() {
+            return None;
+        }
+        let timeout = self.timers.next_timeout()?;
+        Some(match self.power_mode {
+            PowerMode::Performance => timeout,
+            // Let timers expiring shortly after one another be handled in a single wakeup
+            PowerMode::PowerSaving => timeout + POWER_SAVING_TIMER_SLACK,
+        })
     }
 
     /// Returns application-facing events
@@ -354,6 +457,11 @@ where
         }
 
         if let Some(err) = self.error.take() {
+            if !self.handshake_reported {
+                self.handshake_reported = true;
+                self.endpoint_events
+                    .push_back(EndpointEventInner::HandshakeFailed(err.handshake_failure()));
+            }
             return Some(Event::ConnectionLost { reason: err });
         }
 
@@ -362,7 +470,7 @@ where
 
     /// Return endpoint-facing events
     #[must_use]
-    pub fn poll_endpoint_events(&mut self) -> Option<EndpointEvent> {
+    pub fn poll_endpoint_events(&mut self) -> Option<EndpointEvent<A>> {
         self.endpoint_events.pop_front().map(EndpointEvent)
     }
 
@@ -408,8 +516,27 @@ where
     /// `max_datagrams` specifies how many datagrams can be returned inside a
     /// single Transmit using GSO. This must be at least 1.
     #[must_use]
-    pub fn poll_transmit(&mut self, now: Instant, max_datagrams: usize) -> Option<Transmit> {
+    pub fn poll_transmit(&mut self, now: Instant, max_datagrams: usize) -> Option<Transmit<A>> {
+        self.poll_transmit_into(now, max_datagrams, &mut Vec::new())
+    }
+
+    /// Variant of `poll_transmit` which assembles the transmit's contents in `buf`
+    ///
+    /// Lets callers reuse the buffers of transmits they've finished sending, rather than
+    /// allocating a new one for every transmit. `buf` is cleared first, and left in place if there
+    /// is nothing to send.
+    #[must_use]
+    pub fn poll_transmit_into(
+        &mut self,
+        now: Instant,
+        max_datagrams: usize,
+        buf: &mut Vec<u8>,
+    ) -> Option<Transmit<A>> {
         assert!(max_datagrams != 0);
+        self.process_deferred_packet();
+        self.expire_stream_data(now);
+        self.streams.stamp_writes(now);
+        self.release_held_streams(now);
         let max_datagrams = max_datagrams.min(MAX_TRANSMIT_SEGMENTS);
 
         let mut num_datagrams = 0;
@@ -461,10 +588,16 @@ where
                     ecn: None,
                     segment_size: None,
                     src_ip: self.local_ip,
+                    dscp: self.dscp,
+                    flow_label: self.flow_label,
                 });
             }
         }
 
+        if let Some(transmit) = self.poll_mtu_probe(now) {
+            return Some(transmit);
+        }
+
         // If we need to send a probe, make sure we have something to send.
         for space in SpaceId::iter() {
             self.spaces[space].maybe_queue_probe();
@@ -488,7 +621,7 @@ where
             _ => false,
         };
 
-        let mut buf = Vec::new();
+        buf.clear();
         // Reserving capacity can provide more capacity than we asked for.
         // However we are not allowed to write more than MTU size. Therefore
         // the maximum capacity is tracked separately.
@@ -578,13 +711,18 @@ where
 
                     // Check whether the next datagram is blocked by pacing
                     let smoothed_rtt = self.path.rtt.get();
-                    if let Some(delay) = self.path.pacing.delay(
-                        smoothed_rtt,
-                        bytes_to_send,
-                        self.path.mtu,
-                        self.path.congestion.window(),
-                        now,
-                    ) {
+                    let delay = if self.low_latency {
+                        None
+                    } else {
+                        self.path.pacing.delay(
+                            smoothed_rtt,
+                            bytes_to_send,
+                            self.path.mtu,
+                            self.path.congestion.window(),
+                            now,
+                        )
+                    };
+                    if let Some(delay) = delay {
                         self.timers.set(Timer::Pacing, delay);
                         congestion_blocked = true;
                         // Loss probes should be subject to pacing, even though
@@ -599,7 +737,7 @@ where
                     // which will always send the maximum PDU.
                     builder.pad_to(self.path.mtu);
 
-                    builder.finish_and_track(now, self, sent_frames.take(), &mut buf);
+                    builder.finish_and_track(now, self, sent_frames.take(), buf);
 
                     debug_assert_eq!(buf.len(), buf_capacity, "Packet must be padded");
                 }
@@ -625,7 +763,7 @@ where
                 // datagram.
                 // Finish current packet without adding extra padding
                 if let Some(builder) = builder.take() {
-                    builder.finish_and_track(now, self, sent_frames.take(), &mut buf);
+                    builder.finish_and_track(now, self, sent_frames.take(), buf);
                 }
             }
 
@@ -658,7 +796,7 @@ where
             let builder = builder.get_or_insert(PacketBuilder::new(
                 now,
                 space_id,
-                &mut buf,
+                buf,
                 buf_capacity,
                 (num_datagrams - 1) * (self.path.mtu as usize),
                 ack_eliciting,
@@ -676,14 +814,14 @@ where
                 match self.state {
                     State::Closed(state::Closed { ref reason }) => {
                         if space_id == SpaceId::Data {
-                            reason.encode(&mut buf, builder.max_size)
+                            reason.encode(buf, builder.max_size)
                         } else {
                             frame::ConnectionClose {
                                 error_code: TransportErrorCode::APPLICATION_ERROR,
                                 frame_type: None,
                                 reason: Bytes::new(),
                             }
-                            .encode(&mut buf, builder.max_size)
+                            .encode(buf, builder.max_size)
                         }
                     }
                     State::Draining => frame::ConnectionClose {
@@ -691,7 +829,7 @@ where
                         frame_type: None,
                         reason: Bytes::new(),
                     }
-                    .encode(&mut buf, builder.max_size),
+                    .encode(buf, builder.max_size),
                     _ => unreachable!(
                         "tried to make a close packet when the connection wasn't closed"
                     ),
@@ -702,7 +840,7 @@ where
                 break;
             }
 
-            let sent = self.populate_packet(space_id, &mut buf, buf_capacity - builder.tag_len);
+            let sent = self.populate_packet(now, space_id, buf, buf_capacity - builder.tag_len);
             pad_datagram |= sent.requires_padding;
 
             // If we sent any acks, don't immediately resend them. Setting this even if ack_only is
@@ -712,6 +850,10 @@ where
             // is available in this space - because otherwise it would return
             // `true` purely due to the ACKs.
             self.spaces[space_id].permit_ack_only &= sent.acks.is_empty();
+            if space_id == SpaceId::Data && !sent.acks.is_empty() {
+                self.deferred_acks = 0;
+                self.timers.stop(Timer::AckDelay);
+            }
 
             // Keep information about the packet around until it gets finalized
             sent_frames = Some(sent);
@@ -725,7 +867,10 @@ where
             if pad_datagram {
                 builder.pad_to(MIN_INITIAL_SIZE);
             }
-            builder.finish_and_track(now, self, sent_frames, &mut buf);
+            if builder.short_header && self.config.padding == PaddingPolicy::Full {
+                builder.pad_to(self.path.mtu);
+            }
+            builder.finish_and_track(now, self, sent_frames, buf);
         }
 
         self.app_limited = buf.is_empty() && !congestion_blocked;
@@ -734,6 +879,15 @@ where
             return None;
         }
 
+        if self.header_compression
+            && num_datagrams == 1
+            && !pad_datagram
+            && buf[0] & LONG_HEADER_FORM == 0
+            && self.state.is_established()
+        {
+            self.compress_header(buf);
+        }
+
         trace!("sending {} bytes in {} datagrams", buf.len(), num_datagrams);
         self.path.total_sent = self.path.total_sent.saturating_add(buf.len() as u64);
 
@@ -743,7 +897,7 @@ where
 
         Some(Transmit {
             destination: self.path.remote,
-            contents: buf,
+            contents: mem::take(buf),
             ecn: if self.path.sending_ecn {
                 Some(EcnCodepoint::Ect0)
             } else {
@@ -754,9 +908,26 @@ where
                 _ => Some(self.path.mtu as usize),
             },
             src_ip: self.local_ip,
+            dscp: self.dscp,
+            flow_label: self.flow_label,
         })
     }
 
+    /// Replace the destination CID of the short header packet in `buf` with its sequence number
+    ///
+    /// See `TransportConfig::header_compression`.
+    fn compress_header(&mut self, buf: &mut Vec<u8>) {
+        let dst_cid_len = self.rem_cids.active().len();
+        let seq = VarInt::from_u64(self.rem_cids.active_seq()).unwrap();
+        if seq.size() >= dst_cid_len {
+            return;
+        }
+        let mut compressed = Vec::with_capacity(seq.size());
+        compressed.write(seq);
+        buf[0] &= !FIXED_BIT;
+        buf.splice(1..1 + dst_cid_len, compressed);
+    }
+
     /// Returns `true` if a space has outgoing data to send
     fn space_can_send(&self, space_id: SpaceId) -> bool {
         if self.spaces[space_id].crypto.is_some() && self.spaces[space_id].can_send() {
@@ -781,8 +952,9 @@ where
     /// Will execute protocol logic upon receipt of a connection event, in turn preparing signals
     /// (including application `Event`s, `EndpointEvent`s and outgoing datagrams) that should be
     /// extracted through the relevant methods.
-    pub fn handle_event(&mut self, event: ConnectionEvent) {
+    pub fn handle_event(&mut self, event: ConnectionEvent<A>) {
         use self::ConnectionEventInner::*;
+        self.process_deferred_packet();
         match event.0 {
             Datagram {
                 now,
@@ -804,14 +976,18 @@ where
                 let was_anti_amplification_blocked =
                     self.path.anti_amplification_blocked(self.path.mtu as u64);
 
+                let len = first_decode.len() as u64;
                 self.stats.udp_rx.datagrams += 1;
-                self.stats.udp_rx.bytes += first_decode.len() as u64;
-                self.path.total_recvd = self
-                    .path
-                    .total_recvd
-                    .saturating_add(first_decode.len() as u64);
+                self.stats.udp_rx.bytes += len;
+                self.path.total_recvd = self.path.total_recvd.saturating_add(len);
 
+                let prev_remote = self.path.remote;
                 self.handle_decode(now, remote, ecn, first_decode);
+                if self.path.remote != prev_remote && self.path.remote == remote {
+                    // This datagram initiated a migration, so it counts towards the new path's
+                    // anti-amplification limit too
+                    self.path.total_recvd = self.path.total_recvd.saturating_add(len);
+                }
                 if let Some(data) = remaining {
                     self.stats.udp_rx.bytes += data.len() as u64;
                     self.handle_coalesced(now, remote, ecn, data);
@@ -851,6 +1027,10 @@ where
     /// `Instant` that was output by `poll_timeout`; however spurious extra calls will simply
     /// no-op and therefore are safe.
     pub fn handle_timeout(&mut self, now: Instant) {
+        self.process_deferred_packet();
+        if self.paused_at.is_some() {
+            return;
+        }
         for &timer in &Timer::VALUES {
             if !self.timers.is_expired(timer, now) {
                 continue;
@@ -863,12 +1043,43 @@ where
                     self.endpoint_events.push_back(EndpointEventInner::Drained);
                 }
                 Timer::Idle => {
-                    self.kill(ConnectionError::TimedOut);
+                    let reason = if self.state.is_handshake() {
+                        ConnectionError::HandshakeTimedOut(self.handshake_phase())
+                    } else {
+                        ConnectionError::TimedOut
+                    };
+                    self.kill(reason);
+                }
+                Timer::HandshakeDeadline => {
+                    debug!("handshake deadline passed");
+                    let reason = ConnectionError::HandshakeTimedOut(self.handshake_phase());
+                    self.close_inner(
+                        now,
+                        Close::Application(frame::ApplicationClose {
+                            error_code: VarInt(0),
+                            reason: Bytes::new(),
+                        }),
+                    );
+                    self.error = Some(reason);
+                }
+                Timer::StreamDeadline => {
+                    self.expire_stream_data(now);
+                }
+                Timer::Coalescing => self.release_held_streams(now),
+                Timer::InitialDuplicate => self.duplicate_initial(now),
+                Timer::IdleWarning => {
+                    debug!("idle timeout approaching");
+                    self.events.push_back(Event::IdleTimeoutWarning);
                 }
                 Timer::KeepAlive => {
                     trace!("sending keep-alive");
                     self.ping();
                 }
+                Timer::MtuDiscovery => self.search_mtu(now),
+                Timer::AckDelay => {
+                    trace!("sending deferred acks");
+                    self.release_acks();
+                }
                 Timer::LossDetection => {
                     self.on_loss_detection_timeout(now);
                 }
@@ -930,7 +1141,7 @@ where
     }
 
     /// Control datagrams
-    pub fn datagrams(&mut self) -> Datagrams<'_, S> {
+    pub fn datagrams(&mut self) -> Datagrams<'_, S, A> {
         Datagrams { conn: self }
     }
 
@@ -939,10 +1150,205 @@ where
         let mut stats = self.stats;
         stats.path.rtt = self.path.rtt.get();
         stats.path.cwnd = self.path.congestion.window();
+        stats.stream_latency = self.streams.latency;
 
         stats
     }
 
+    /// Streams whose data hasn't been fully delivered, with how much is outstanding
+    ///
+    /// Includes streams with written data the peer hasn't acknowledged, and streams with received
+    /// data the application hasn't read, whether or not they've been accepted. Meant for use
+    /// around [`close()`](Self::close), to log what's being lost or to recover from it at the
+    /// application layer.
+    pub fn drain_streams(&self) -> Vec<DrainedStream> {
+        self.streams.drained()
+    }
+
+    /// Current upper bound on the size of UDP payloads sent on this connection
+    ///
+    /// Accounts for the local configuration as well as the limit advertised by the peer, once
+    /// known.
+    pub fn max_udp_payload_size(&self) -> u16 {
+        self.path.mtu
+    }
+
+    /// Process a report that a datagram sent to `remote` was dropped for exceeding the path MTU
+    ///
+    /// `max_udp_payload_size` is the largest UDP payload the path is reported to support, e.g. as
+    /// derived from an ICMP Packet Too Big message. Reports for paths other than the one currently
+    /// in use are ignored. Because such reports are unauthenticated, the size of outgoing packets
+    /// is never reduced below the minimum which every QUIC path must support.
+    pub fn handle_packet_too_big(&mut self, remote: A, max_udp_payload_size: u16) {
+        if remote != self.path.remote {
+            return;
+        }
+        let mtu = cmp::max(max_udp_payload_size, MIN_INITIAL_SIZE);
+        if mtu < self.path.mtu {
+            debug!(mtu, "path MTU reduced by packet too big report");
+            self.set_mtu(mtu);
+        }
+    }
+
+    /// Mark outgoing packets with a differentiated services code point, or `None` to leave the
+    /// platform default in place
+    ///
+    /// Allows network equipment to classify the connection's traffic for quality of service
+    /// purposes.
+    ///
+    /// # Panics
+    ///
+    /// If `dscp` does not fit in 6 bits.
+    pub fn set_dscp(&mut self, dscp: Option<u8>) {
+        assert!(dscp.map_or(true, |x| x < 64), "DSCP must fit in 6 bits");
+        self.dscp = dscp;
+    }
+
+    /// Mark outgoing IPv6 packets with a flow label, or `None` to leave the platform default in
+    /// place
+    ///
+    /// Has no effect on packets sent over IPv4, or on platforms which don't support setting flow
+    /// labels.
+    ///
+    /// # Panics
+    ///
+    /// If `label` does not fit in 20 bits.
+    pub fn set_flow_label(&mut self, label: Option<u32>) {
+        assert!(
+            label.map_or(true, |x| x < 1 << 20),
+            "flow label must fit in 20 bits"
+        );
+        self.flow_label = label;
+    }
+
+    /// Attach a [`Tap`] to receive copies of application data, or detach the current one
+    ///
+    /// Only data written or read after the tap is attached is reported.
+    pub fn set_tap(&mut self, tap: Option<Arc<dyn Tap>>) {
+        self.streams.tap = tap;
+    }
+
+    /// The power mode currently in effect
+    pub fn power_mode(&self) -> PowerMode {
+        self.power_mode
+    }
+
+    /// Allow the peer to send `bytes` more data across all streams
+    ///
+    /// Only has an effect if the connection-level `CreditPolicy` is `CreditPolicy::Manual`, where
+    /// this is the only way for connection-level flow control credit to be returned to the peer.
+    pub fn credit(&mut self, bytes: u64) {
+        if self.streams.grant_credits(bytes).should_transmit() {
+            self.spaces[SpaceId::Data].pending.max_data = true;
+        }
+    }
+
+    /// Trade latency for fewer wakeups, e.g. to save battery on mobile devices
+    ///
+    /// May be changed at any time. See [`PowerMode`] for details.
+    pub fn set_power_mode(&mut self, now: Instant, mode: PowerMode) {
+        if mode == self.power_mode {
+            return;
+        }
+        self.power_mode = mode;
+        if mode == PowerMode::Performance {
+            self.release_acks();
+        }
+        if self.timers.get(Timer::KeepAlive).is_some() {
+            self.reset_keep_alive(now);
+        }
+    }
+
+    /// Whether pacing and ACK batching are disabled
+    pub fn low_latency(&self) -> bool {
+        self.low_latency
+    }
+
+    /// Disable or re-enable pacing and ACK batching
+    ///
+    /// Overrides [`TransportConfig::low_latency()`] for this connection.
+    pub fn set_low_latency(&mut self, value: bool) {
+        self.low_latency = value;
+        if value {
+            self.release_acks();
+            self.timers.stop(Timer::Pacing);
+        }
+    }
+
+    /// Set the reordering in packet number space tolerated before a packet is deemed lost
+    ///
+    /// Overrides [`TransportConfig::packet_threshold()`] for this connection. Takes effect the next
+    /// time loss detection runs.
+    pub fn set_packet_threshold(&mut self, value: u32) {
+        self.packet_threshold = value;
+    }
+
+    /// Set the reordering in time tolerated before a packet is deemed lost, as a factor of RTT
+    ///
+    /// Overrides [`TransportConfig::time_threshold()`] for this connection. Takes effect the next
+    /// time loss detection runs.
+    pub fn set_time_threshold(&mut self, value: f32) {
+        self.time_threshold = value;
+    }
+
+    /// Set the maximum number of times the probe timeout doubles while probes go unanswered
+    ///
+    /// Overrides [`TransportConfig::max_pto_backoff()`] for this connection. Takes effect the next
+    /// time the loss detection timer is armed.
+    pub fn set_max_pto_backoff(&mut self, value: u32) {
+        self.max_pto_backoff = value;
+    }
+
+    /// Limit the idle timeout in effect on this connection
+    ///
+    /// The negotiated idle timeout, which is used if `value` is `None`, can only be shortened, e.g.
+    /// to abandon peers quickly until they've authenticated and be more lenient afterwards. Only
+    /// affects when this endpoint considers the connection timed out; the peer keeps using the
+    /// negotiated timeout. Restarts the idle timer.
+    pub fn set_max_idle_timeout(&mut self, now: Instant, value: Option<Duration>) {
+        self.idle_timeout_limit = value;
+        self.update_idle_timeout();
+        if self.idle_timeout.is_none() {
+            self.timers.stop(Timer::Idle);
+            self.timers.stop(Timer::IdleWarning);
+        }
+        self.reset_idle_timeout(now);
+    }
+
+    /// The idle timeout in effect, if any
+    ///
+    /// See [`set_max_idle_timeout()`](Self::set_max_idle_timeout).
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    fn update_idle_timeout(&mut self) {
+        self.idle_timeout = match (self.negotiated_idle_timeout, self.idle_timeout_limit) {
+            (Some(x), Some(y)) => Some(cmp::min(x, y)),
+            (x, y) => x.or(y),
+        };
+    }
+
+    /// The current probe timeout, including backoff due to unanswered probes
+    ///
+    /// Once this much time passes without an acknowledgement for outstanding data, a probe packet
+    /// is sent.
+    pub fn current_pto(&self) -> Duration {
+        self.pto() * self.pto_backoff()
+    }
+
+    /// Abandon the handshake if it hasn't completed by `deadline`
+    ///
+    /// Unlike the idle timeout, which only fires once the peer stops responding, this bounds the
+    /// total duration of the handshake. When the deadline passes, the connection is closed, so the
+    /// peer is told to discard its state, and lost with [`ConnectionError::HandshakeTimedOut`].
+    /// Has no effect once the handshake has completed.
+    pub fn set_handshake_deadline(&mut self, deadline: Instant) {
+        if self.state.is_handshake() {
+            self.timers.set(Timer::HandshakeDeadline, deadline);
+        }
+    }
+
     /// Ping the remote endpoint
     ///
     /// Causes an ACK-eliciting packet to be transmitted.
@@ -950,6 +1356,61 @@ where
         self.spaces[self.highest_space].ping_pending = true;
     }
 
+    /// Freeze all timers, e.g. before the host goes to sleep
+    ///
+    /// While paused, [`poll_timeout()`](Self::poll_timeout) returns `None` and timeouts are
+    /// ignored, so time spent asleep can't cause the connection to time out or declare packets lost.
+    /// Datagrams are still handled as usual.
+    pub fn pause(&mut self, now: Instant) {
+        if self.paused_at.is_none() {
+            trace!("pausing timers");
+            self.paused_at = Some(now);
+            self.paused_timers = self.timers;
+        }
+    }
+
+    /// Unfreeze timers stopped by [`pause()`](Self::pause)
+    ///
+    /// Timers armed before the pause are postponed by the time spent paused, while those armed
+    /// since then keep their deadlines. If the handshake is complete, the current
+    /// path is revalidated with an immediate PING and PATH_CHALLENGE, since the network may have
+    /// changed while the host was asleep.
+    pub fn resume(&mut self, now: Instant) {
+        let paused_at = match self.paused_at.take() {
+            Some(x) => x,
+            None => return,
+        };
+        trace!("resuming timers");
+        self.timers.delay_unchanged(
+            &self.paused_timers,
+            now.saturating_duration_since(paused_at),
+        );
+        if self.highest_space != SpaceId::Data || self.state.is_closed() {
+            return;
+        }
+        if self.path.challenge.is_none() {
+            self.path.challenge = Some(self.rng.gen());
+            self.path.challenge_pending = true;
+            self.timers.set(Timer::PathValidation, now + 3 * self.pto());
+        }
+        self.spaces[SpaceId::Data].ping_pending = true;
+    }
+
+    /// Notify the connection that its datagrams are now sent from a different local address
+    ///
+    /// Switches to a fresh remote connection ID, if one is available, and sends a PING padded to
+    /// the minimum datagram size, large enough for the peer's anti-amplification limit to permit
+    /// validating the new path. Has no effect before the handshake completes.
+    pub fn local_address_changed(&mut self) {
+        if self.highest_space != SpaceId::Data || self.state.is_closed() {
+            return;
+        }
+        trace!("local address changed");
+        let _ = self.update_rem_cid();
+        self.spaces[SpaceId::Data].ping_pending = true;
+        self.path_probe_pending = true;
+    }
+
     #[doc(hidden)]
     pub fn initiate_key_update(&mut self) {
         self.update_keys(None, false);
@@ -960,6 +1421,15 @@ where
         &self.crypto
     }
 
+    /// Value binding application-layer credentials to this connection
+    ///
+    /// See [`ChannelBinding`](crypto::ChannelBinding) for details.
+    pub fn channel_binding(
+        &self,
+    ) -> Result<crypto::ChannelBinding, crypto::ExportKeyingMaterialError> {
+        crypto::ChannelBinding::new(&self.crypto)
+    }
+
     /// Whether the connection is in the process of being established
     ///
     /// If this returns `false`, the connection may be either established or closed, signaled by the
@@ -999,21 +1469,94 @@ where
         self.zero_rtt_enabled
     }
 
+    /// Identifier shared by the client's attempt at 0-RTT and any replays of it
+    ///
+    /// Only available on servers which accepted 0-RTT. Servers checking whether they've seen the
+    /// identifier before, e.g. in a store shared between instances, can tell replays apart and
+    /// [`release_0rtt_data()`](Self::release_0rtt_data) on first use only.
+    pub fn zero_rtt_id(&self) -> Option<ConnectionId> {
+        if self.side.is_server() && self.zero_rtt_enabled {
+            Some(self.initial_dst_cid)
+        } else {
+            None
+        }
+    }
+
+    /// Report streams withheld by [`ServerConfig::defer_0rtt_data()`] without waiting for the
+    /// handshake to complete
+    ///
+    /// For use once the client's 0-RTT data is known not to be a replay.
+    pub fn release_0rtt_data(&mut self) {
+        self.streams.defer_remote = false;
+    }
+
+    /// The stateless reset from the peer that ended the connection with
+    /// [`ConnectionError::Reset`], if it was
+    pub fn stateless_reset(&self) -> Option<&StatelessReset<A>> {
+        self.stateless_reset.as_ref()
+    }
+
+    /// How the cryptographic handshake was completed
+    ///
+    /// Returns `None` until the handshake has completed.
+    pub fn handshake_summary(&self) -> Option<HandshakeSummary> {
+        if self.crypto.is_handshaking() {
+            return None;
+        }
+        let early_data = if !self.zero_rtt_enabled {
+            EarlyData::NotAttempted
+        } else if self.side.is_server() || self.accepted_0rtt {
+            EarlyData::Accepted
+        } else {
+            EarlyData::Rejected
+        };
+        Some(HandshakeSummary {
+            resumed: self.crypto.is_resumed(),
+            early_data,
+        })
+    }
+
     /// Whether there are any pending retransmits
     pub fn has_pending_retransmits(&self) -> bool {
         !self.spaces[SpaceId::Data].pending.is_empty()
     }
 
+    /// The listener which accepted this connection
+    ///
+    /// `None` for clients and connections using the endpoint's default server configuration.
+    pub fn listener(&self) -> Option<ListenerId> {
+        self.listener
+    }
+
     /// Look up whether we're the client or server of this Connection
     pub fn side(&self) -> Side {
         self.side
     }
 
     /// The latest socket address for this connection's peer
-    pub fn remote_address(&self) -> SocketAddr {
+    pub fn remote_address(&self) -> A {
         self.path.remote
     }
 
+    /// Whether the client offered a session ticket issued for another server name or application
+    /// protocol
+    ///
+    /// Such tickets are ignored in favor of a full handshake by servers with
+    /// `ServerConfig::pin_session_tickets` enabled. Known once the handshake data has been
+    /// received; always `false` for clients.
+    pub fn ticket_mismatch(&self) -> bool {
+        self.crypto.ticket_mismatch()
+    }
+
+    /// The server name this connection is for
+    ///
+    /// For clients, this is the name passed to `connect`. For servers, it is the name the client
+    /// indicated (e.g. via TLS SNI), which is available once the handshake data has been received
+    /// and may be absent if the client didn't send one.
+    pub fn server_name(&self) -> Option<&str> {
+        self.crypto.server_name()
+    }
+
     /// The local IP address which was used when the peer established
     /// the connection
     ///
@@ -1046,6 +1589,13 @@ where
         if ack.largest >= self.spaces[space].next_packet_number {
             return Err(TransportError::PROTOCOL_VIOLATION("unsent packet acked"));
         }
+        if space == SpaceId::Initial
+            && self.stats.path.initial_duplicates > 0
+            && self.spaces[space].largest_acked_packet.is_none()
+        {
+            // Our first Initial packet had number 0
+            self.stats.path.initial_duplicate_needed = !ack.iter().any(|range| range.contains(&0));
+        }
         let new_largest = {
             let space = &mut self.spaces[space];
             if space
@@ -1078,13 +1628,25 @@ where
         }
 
         let mut ack_eliciting_acked = false;
+        let mut mtu_confirmed = None;
         for packet in newly_acked.elts() {
             if let Some(info) = self.spaces[space].sent_packets.remove(&packet) {
                 self.spaces[space].pending_acks.subtract(&info.acks);
                 ack_eliciting_acked |= info.ack_eliciting;
+                if space == SpaceId::Data {
+                    self.path.black_hole.on_acked(packet, info.size);
+                    mtu_confirmed = mtu_confirmed.or_else(|| self.path.mtud.on_acked(packet));
+                }
                 self.on_packet_acked(now, space, info);
             }
         }
+        if let Some(mtu) = mtu_confirmed {
+            if mtu > self.path.mtu {
+                debug!(mtu, "path MTU raised by probe");
+                self.set_mtu(mtu);
+            }
+            self.search_mtu(now);
+        }
 
         if new_largest && ack_eliciting_acked {
             let ack_delay = if space != SpaceId::Data {
@@ -1175,7 +1737,7 @@ where
         }
 
         for frame in info.stream_frames {
-            self.streams.received_ack_of(frame);
+            self.streams.received_ack_of(frame, now);
         }
     }
 
@@ -1194,6 +1756,28 @@ where
         self.timers.set(Timer::KeyDiscard, start + self.pto() * 3);
     }
 
+    /// Queue another copy of a client's first flight, unless the server has already responded
+    fn duplicate_initial(&mut self, now: Instant) {
+        if self.stats.udp_rx.datagrams > 0 || self.spaces[SpaceId::Initial].crypto.is_none() {
+            self.initial_duplicates = 0;
+            return;
+        }
+        trace!(
+            remaining = self.initial_duplicates,
+            "duplicating first flight"
+        );
+        let space = &mut self.spaces[SpaceId::Initial];
+        space.loss_probes = space.loss_probes.saturating_add(1);
+        self.stats.path.initial_duplicates += 1;
+        self.initial_duplicates -= 1;
+        if self.initial_duplicates > 0 {
+            self.timers.set(
+                Timer::InitialDuplicate,
+                now + self.config.initial_duplicate_spacing,
+            );
+        }
+    }
+
     fn on_loss_detection_timeout(&mut self, now: Instant) {
         if let Some((_, pn_space)) = self.loss_time_and_space() {
             // Time threshold loss Detection
@@ -1228,18 +1812,44 @@ where
         };
         self.spaces[space].loss_probes = self.spaces[space].loss_probes.saturating_add(count);
         self.pto_count = self.pto_count.saturating_add(1);
+
+        if self.side.is_client()
+            && self.stats.udp_rx.datagrams == 0
+            && self
+                .config
+                .udp_blocked_probes
+                .map_or(false, |n| self.pto_count >= n)
+        {
+            debug!("no response from server, UDP may be blocked");
+            self.kill(ConnectionError::UdpBlocked);
+            return;
+        }
+
+        // If large packets are dropped while no other traffic is exchanged, no ACKs will arrive
+        // from which to declare packets lost, so treat the outstanding packets as a loss burst.
+        if space == SpaceId::Data {
+            let probe = self.path.mtud.in_flight_packet();
+            let outstanding = self.spaces[space]
+                .sent_packets
+                .iter()
+                .filter(|&(&packet, info)| info.ack_eliciting && Some(packet) != probe)
+                .map(|(&packet, info)| (packet, info.size));
+            if self.path.black_hole.on_loss_burst(outstanding) {
+                self.on_black_hole(now);
+            }
+        }
         self.set_loss_detection_timer(now);
     }
 
     fn detect_lost_packets(&mut self, now: Instant, pn_space: SpaceId) {
         let mut lost_packets = Vec::<u64>::new();
         let rtt = self.path.rtt.conservative();
-        let loss_delay = cmp::max(rtt.mul_f32(self.config.time_threshold), TIMER_GRANULARITY);
+        let loss_delay = cmp::max(rtt.mul_f32(self.time_threshold), TIMER_GRANULARITY);
 
         // Packets sent before this time are deemed lost.
         let lost_send_time = now - loss_delay;
         let largest_acked_packet = self.spaces[pn_space].largest_acked_packet.unwrap();
-        let packet_threshold = self.config.packet_threshold as u64;
+        let packet_threshold = self.packet_threshold as u64;
 
         let space = &mut self.spaces[pn_space];
         space.loss_time = None;
@@ -1257,14 +1867,31 @@ where
             }
         }
 
+        // Lost MTU probes only tell us about the path's MTU, not about congestion
+        if pn_space == SpaceId::Data {
+            let probe = self.path.mtud.in_flight_packet();
+            if let Some(i) = lost_packets.iter().position(|&x| Some(x) == probe) {
+                let packet = lost_packets.remove(i);
+                let info = self.spaces[pn_space].sent_packets.remove(&packet).unwrap();
+                self.remove_in_flight(pn_space, &info);
+                self.path.mtud.on_lost(packet);
+                trace!(size = info.size, "MTU probe lost");
+                if !self.path.mtud.is_probing() {
+                    self.defer_mtu_search(now);
+                }
+            }
+        }
+
         // OnPacketsLost
         if let Some(largest_lost) = lost_packets.last().cloned() {
             let old_bytes_in_flight = self.in_flight.bytes;
             let largest_lost_sent = self.spaces[pn_space].sent_packets[&largest_lost].time_sent;
             self.lost_packets += lost_packets.len() as u64;
             trace!("packets lost: {:?}", lost_packets);
+            let mut lost_sizes = Vec::with_capacity(lost_packets.len());
             for packet in &lost_packets {
                 let info = self.spaces[pn_space].sent_packets.remove(&packet).unwrap(); // safe: lost_packets is populated just above
+                lost_sizes.push((*packet, info.size));
                 self.remove_in_flight(pn_space, &info);
                 for frame in info.stream_frames {
                     self.streams.retransmit(frame);
@@ -1288,9 +1915,129 @@ where
                     in_persistent_congestion,
                 );
             }
+
+            if pn_space == SpaceId::Data && self.path.black_hole.on_loss_burst(lost_sizes) {
+                self.on_black_hole(now);
+            }
+        }
+    }
+
+    /// Fall back to the minimum MTU after large packets were found to be dropped by the path
+    fn on_black_hole(&mut self, now: Instant) {
+        if self.path.mtu <= MIN_INITIAL_SIZE {
+            return;
+        }
+        debug!(
+            mtu = self.path.mtu,
+            "black hole detected, falling back to minimum MTU"
+        );
+        self.events.push_back(Event::BlackHoleDetected);
+        self.set_mtu(MIN_INITIAL_SIZE);
+        // Don't immediately probe for the sizes that were just found not to get through
+        self.path.mtud.pending = None;
+        self.defer_mtu_search(now);
+    }
+
+    /// Probe whether the path supports UDP payloads of `size` bytes
+    ///
+    /// If the probe is acknowledged, payloads of that size are sent from then on. Probes are sent
+    /// at most once at a time, and retried a few times if lost. Has no effect if `size` doesn't
+    /// exceed the current [`max_udp_payload_size()`](Self::max_udp_payload_size); sizes beyond the
+    /// peer's limit or `TransportConfig::max_egress_udp_payload_size` are reduced accordingly.
+    /// Works whether or not [`TransportConfig::mtu_discovery_config()`] is enabled.
+    ///
+    /// [`TransportConfig::mtu_discovery_config()`]: crate::TransportConfig::mtu_discovery_config
+    pub fn probe_mtu(&mut self, size: u16) {
+        let size = cmp::min(size, self.max_probe_size());
+        if size > self.path.mtu {
+            self.path.mtud.queue(size);
+        }
+    }
+
+    /// Queue a probe for the next size to try, or end the search if there's none
+    fn search_mtu(&mut self, now: Instant) {
+        let config = match self.config.mtu_discovery {
+            Some(x) => x,
+            None => return,
+        };
+        if self.path.mtud.is_probing() {
+            return;
+        }
+        let target = cmp::min(
+            self.path.mtu.saturating_add(config.step),
+            cmp::min(config.upper_bound, self.max_probe_size()),
+        );
+        if target > self.path.mtu {
+            self.timers.stop(Timer::MtuDiscovery);
+            self.path.mtud.queue(target);
+        } else {
+            self.defer_mtu_search(now);
         }
     }
 
+    /// Search for a larger MTU again after the configured interval
+    fn defer_mtu_search(&mut self, now: Instant) {
+        if let Some(ref config) = self.config.mtu_discovery {
+            self.timers.set(Timer::MtuDiscovery, now + config.interval);
+        }
+    }
+
+    /// Largest UDP payload worth probing for
+    fn max_probe_size(&self) -> u16 {
+        let peer_max = self.peer_params.max_udp_payload_size.into_inner();
+        cmp::min(
+            peer_max,
+            self.config
+                .max_egress_udp_payload_size
+                .unwrap_or(u16::MAX)
+                .into(),
+        ) as u16
+    }
+
+    /// Build a datagram carrying the pending MTU probe, if it can be sent now
+    fn poll_mtu_probe(&mut self, now: Instant) -> Option<Transmit<A>> {
+        let size = self.path.mtud.next_probe()?;
+        if !self.state.is_established()
+            || self.path.anti_amplification_blocked(size.into())
+            || self.in_flight.bytes + u64::from(size) > self.path.congestion.window()
+        {
+            return None;
+        }
+
+        let mut buf = Vec::with_capacity(size as usize);
+        let mut builder = PacketBuilder::new(
+            now,
+            SpaceId::Data,
+            &mut buf,
+            size as usize,
+            0,
+            true,
+            self,
+            self.version,
+        )?;
+        trace!(size, "sending MTU probe");
+        let packet = builder.exact_number;
+        buf.write(frame::Type::PING);
+        self.stats.frame_tx.ping += 1;
+        builder.pad_to(size);
+        builder.finish_and_track(now, self, Some(SentFrames::default()), &mut buf);
+        self.path.mtud.on_sent(packet);
+
+        self.path.total_sent = self.path.total_sent.saturating_add(buf.len() as u64);
+        self.stats.udp_tx.datagrams += 1;
+        self.stats.udp_tx.transmits += 1;
+        self.stats.udp_tx.bytes += buf.len() as u64;
+        Some(Transmit {
+            destination: self.path.remote,
+            contents: buf,
+            ecn: None,
+            segment_size: None,
+            src_ip: self.local_ip,
+            dscp: self.dscp,
+            flow_label: self.flow_label,
+        })
+    }
+
     fn loss_time_and_space(&self) -> Option<(Instant, SpaceId)> {
         SpaceId::iter()
             .filter_map(|id| Some((self.spaces[id].loss_time?, id)))
@@ -1298,7 +2045,7 @@ where
     }
 
     fn pto_time_and_space(&self, now: Instant) -> Option<(Instant, SpaceId)> {
-        let backoff = 2u32.pow(self.pto_count.min(MAX_BACKOFF_EXPONENT));
+        let backoff = self.pto_backoff();
         let mut duration = self.path.rtt.pto_base() * backoff;
 
         if self.in_flight.ack_eliciting == 0 {
@@ -1379,11 +2126,42 @@ where
         }
     }
 
+    /// Reset partially reliable streams with expired data, and schedule the next expiry
+    fn expire_stream_data(&mut self, now: Instant) {
+        if self.state.is_closed() {
+            return;
+        }
+        match self
+            .streams
+            .expire_data(now, &mut self.spaces[SpaceId::Data].pending)
+        {
+            Some(deadline) => self.timers.set(Timer::StreamDeadline, deadline),
+            None => self.timers.stop(Timer::StreamDeadline),
+        }
+    }
+
+    /// Queue held back stream data that's due to be sent, and schedule the next release
+    fn release_held_streams(&mut self, now: Instant) {
+        match self.streams.release_held(now) {
+            Some(release) => self.timers.set(Timer::Coalescing, release),
+            None => self.timers.stop(Timer::Coalescing),
+        }
+    }
+
     /// Probe Timeout
     fn pto(&self) -> Duration {
         self.path.rtt.pto_base() + self.max_ack_delay()
     }
 
+    /// Factor by which the probe timeout is currently extended due to unanswered probes
+    fn pto_backoff(&self) -> u32 {
+        let exponent = self
+            .pto_count
+            .min(self.max_pto_backoff)
+            .min(MAX_BACKOFF_EXPONENT);
+        2u32.pow(exponent)
+    }
+
     fn on_packet_authenticated(
         &mut self,
         now: Instant,
@@ -1423,6 +2201,7 @@ where
         }
         if packet >= space.rx_packet {
             space.rx_packet = packet;
+            space.rx_packet_time = now;
             // Update outgoing spin bit, inverting iff we're the client
             self.spin = self.side.is_client() ^ spin;
         }
@@ -1439,16 +2218,73 @@ where
         }
         let dt = cmp::max(timeout, 3 * self.pto());
         self.timers.set(Timer::Idle, now + dt);
+        match self.config.idle_timeout_warning {
+            Some(warning) if warning < dt && self.state.is_established() => {
+                self.timers.set(Timer::IdleWarning, now + (dt - warning));
+            }
+            _ => self.timers.stop(Timer::IdleWarning),
+        }
     }
 
     fn reset_keep_alive(&mut self, now: Instant) {
-        let interval = match self.config.keep_alive_interval {
+        let mut interval = match self.config.keep_alive_interval {
             Some(x) if self.state.is_established() => x,
             _ => return,
         };
+        if self.power_mode == PowerMode::PowerSaving {
+            let longer = interval * POWER_SAVING_KEEP_ALIVE_FACTOR;
+            interval = match self.idle_timeout {
+                // Keep-alives must still arrive well within the idle timeout to serve their purpose
+                Some(idle) => cmp::max(interval, cmp::min(longer, idle / 2)),
+                None => longer,
+            };
+        }
         self.timers.set(Timer::KeepAlive, now + interval);
     }
 
+    /// How long ACK-only packets may be held back, if at all
+    ///
+    /// The peer allows for no more than the `max_ack_delay` we advertised in its loss detection,
+    /// and in power-saving mode timeouts may fire up to the timer slack late.
+    fn ack_deferral(&self) -> Option<Duration> {
+        if self.low_latency {
+            return None;
+        }
+        let slack = match self.power_mode {
+            PowerMode::Performance => Duration::from_millis(0),
+            PowerMode::PowerSaving => POWER_SAVING_TIMER_SLACK,
+        };
+        let advertised = Duration::from_millis(self.config.max_ack_delay.as_millis() as u64);
+        advertised
+            .checked_sub(slack)
+            .filter(|&x| x > Duration::from_millis(0))
+    }
+
+    /// Hold back ACK-only packets for up to `deferral`, so that acknowledgements are batched
+    ///
+    /// Acknowledgements are still carried by any other packets we send meanwhile.
+    fn defer_acks(&mut self, now: Instant, deferral: Duration) {
+        self.deferred_acks = self.deferred_acks.saturating_add(1);
+        if self.deferred_acks >= ACK_BATCH {
+            return;
+        }
+        self.spaces[SpaceId::Data].permit_ack_only = false;
+        if self.timers.get(Timer::AckDelay).is_none() {
+            self.timers.set(Timer::AckDelay, now + deferral);
+        }
+    }
+
+    /// Allow ACK-only packets held back by `defer_acks` to be sent
+    fn release_acks(&mut self) {
+        self.timers.stop(Timer::AckDelay);
+        if self.deferred_acks == 0 {
+            return;
+        }
+        // Don't hold back further acks until these are sent
+        self.deferred_acks = ACK_BATCH;
+        self.spaces[SpaceId::Data].permit_ack_only = true;
+    }
+
     fn reset_cid_retirement(&mut self) {
         if let Some(t) = self.local_cid_state.next_timeout() {
             self.timers.set(Timer::PushNewCid, t);
@@ -1462,7 +2298,7 @@ where
     pub(crate) fn handle_first_packet(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         ecn: Option<EcnCodepoint>,
         packet_number: u64,
         packet: Packet,
@@ -1489,6 +2325,47 @@ where
         Ok(())
     }
 
+    /// Hold back the first packet of an incoming connection until the connection is next driven
+    pub(crate) fn defer_first_packet(
+        &mut self,
+        now: Instant,
+        remote: A,
+        ecn: Option<EcnCodepoint>,
+        packet_number: u64,
+        packet: Packet,
+        remaining: Option<BytesMut>,
+    ) {
+        self.deferred_packet = Some(DeferredPacket {
+            now,
+            remote,
+            ecn,
+            packet_number,
+            packet,
+            remaining,
+        });
+    }
+
+    fn process_deferred_packet(&mut self) {
+        let DeferredPacket {
+            now,
+            remote,
+            ecn,
+            packet_number,
+            packet,
+            remaining,
+        } = match self.deferred_packet.take() {
+            Some(x) => x,
+            None => return,
+        };
+        let was_closed = self.state.is_closed();
+        let was_drained = self.state.is_drained();
+        let result = self.handle_first_packet(now, remote, ecn, packet_number, packet, remaining);
+        if let Err(ref e) = result {
+            debug!("handshake failed: {}", e);
+        }
+        self.on_packet_processed(now, remote, was_closed, was_drained, result);
+    }
+
     fn init_0rtt(&mut self) {
         let (header, packet) = match self.crypto.early_crypto() {
             Some(x) => x,
@@ -1567,6 +2444,9 @@ where
             trace!("consumed {} CRYPTO bytes", chunk.bytes.len());
             if self.crypto.read_handshake(&chunk.bytes)? {
                 self.events.push_back(Event::HandshakeDataReady);
+                if self.crypto.ticket_mismatch() {
+                    debug!("ignored session ticket issued for another server name or protocol");
+                }
             }
         }
 
@@ -1655,7 +2535,7 @@ where
     fn handle_coalesced(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         ecn: Option<EcnCodepoint>,
         data: BytesMut,
     ) {
@@ -1678,7 +2558,7 @@ where
     fn handle_decode(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         ecn: Option<EcnCodepoint>,
         partial_decode: PartialDecode,
     ) {
@@ -1713,10 +2593,18 @@ where
         }
     }
 
+    fn on_stateless_reset(&mut self, now: Instant, remote: A) {
+        self.stateless_reset = Some(StatelessReset {
+            received: now,
+            remote,
+            local_ip: self.local_ip,
+        });
+    }
+
     fn handle_packet(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         ecn: Option<EcnCodepoint>,
         mut packet: Packet,
     ) {
@@ -1751,6 +2639,7 @@ where
             Err(None) => {
                 if stateless_reset {
                     debug!("got stateless reset");
+                    self.on_stateless_reset(now, remote);
                     Err(ConnectionError::Reset)
                 } else {
                     debug!("failed to authenticate packet");
@@ -1779,6 +2668,7 @@ where
                 let is_duplicate = |n| self.spaces[packet.header.space()].dedup.insert(n);
                 if number.map_or(false, is_duplicate) {
                     if stateless_reset {
+                        self.on_stateless_reset(now, remote);
                         Err(ConnectionError::Reset)
                     } else {
                         warn!("discarding possible duplicate packet");
@@ -1808,6 +2698,17 @@ where
             }
         };
 
+        self.on_packet_processed(now, remote, was_closed, was_drained, result);
+    }
+
+    fn on_packet_processed(
+        &mut self,
+        now: Instant,
+        remote: A,
+        was_closed: bool,
+        was_drained: bool,
+        result: Result<(), ConnectionError>,
+    ) {
         // State transitions for error cases
         if let Err(conn_err) = result {
             self.error = Some(conn_err.clone());
@@ -1819,7 +2720,9 @@ where
                     code: TransportErrorCode::AEAD_LIMIT_REACHED,
                     ..
                 }) => State::Drained,
-                ConnectionError::TimedOut => {
+                ConnectionError::TimedOut
+                | ConnectionError::HandshakeTimedOut(_)
+                | ConnectionError::UdpBlocked => {
                     unreachable!("timeouts aren't generated by packet processing");
                 }
                 ConnectionError::TransportError(err) => {
@@ -1830,6 +2733,9 @@ where
                 ConnectionError::LocallyClosed => {
                     unreachable!("LocallyClosed isn't generated by packet processing")
                 }
+                ConnectionError::QuotaExceeded => {
+                    unreachable!("quota violations aren't reported as errors by packet processing")
+                }
             };
         }
 
@@ -1855,7 +2761,7 @@ where
     fn process_decrypted_packet(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         number: Option<u64>,
         packet: Packet,
     ) -> Result<(), ConnectionError> {
@@ -2030,10 +2936,17 @@ where
                     // Server-only
                     self.spaces[SpaceId::Data].pending.handshake_done = true;
                     self.discard_space(now, SpaceId::Handshake);
+                    // The handshake is confirmed, so any 0-RTT data can no longer be a replay
+                    self.streams.defer_remote = false;
                 }
 
                 self.events.push_back(Event::Connected);
+                self.handshake_reported = true;
+                self.endpoint_events
+                    .push_back(EndpointEventInner::Connected);
                 self.state = State::Established;
+                self.timers.stop(Timer::HandshakeDeadline);
+                self.search_mtu(now);
                 trace!("established");
                 Ok(())
             }
@@ -2166,7 +3079,7 @@ where
     fn process_payload(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         number: u64,
         payload: Bytes,
     ) -> Result<(), TransportError> {
@@ -2237,7 +3150,12 @@ where
                     self.read_crypto(SpaceId::Data, &frame, payload_len)?;
                 }
                 Frame::Stream(frame) => {
-                    if self.streams.received(frame, payload_len)?.should_transmit() {
+                    self.check_stream_initiator(frame.id)?;
+                    if self
+                        .streams
+                        .received(frame, payload_len, now)?
+                        .should_transmit()
+                    {
                         self.spaces[SpaceId::Data].pending.max_data = true;
                     }
                 }
@@ -2288,12 +3206,14 @@ where
                     self.streams.received_max_data(bytes);
                 }
                 Frame::MaxStreamData { id, offset } => {
+                    self.check_stream_initiator(id)?;
                     self.streams.received_max_stream_data(id, offset)?;
                 }
                 Frame::MaxStreams { dir, count } => {
                     self.streams.received_max_streams(dir, count)?;
                 }
                 Frame::ResetStream(frame) => {
+                    self.check_stream_initiator(frame.id)?;
                     if self.streams.received_reset(frame)?.should_transmit() {
                         self.spaces[SpaceId::Data].pending.max_data = true;
                     }
@@ -2302,6 +3222,7 @@ where
                     debug!(offset, "peer claims to be blocked at connection level");
                 }
                 Frame::StreamDataBlocked { id, offset } => {
+                    self.check_stream_initiator(id)?;
                     if id.initiator() == self.side && id.dir() == Dir::Uni {
                         debug!("got STREAM_DATA_BLOCKED on send-only {}", id);
                         return Err(TransportError::STREAM_STATE_ERROR(
@@ -2325,6 +3246,7 @@ where
                     );
                 }
                 Frame::StopSending(frame::StopSending { id, error_code }) => {
+                    self.check_stream_initiator(id)?;
                     if id.initiator() != self.side {
                         if id.dir() == Dir::Uni {
                             debug!("got STOP_SENDING on recv-only {}", id);
@@ -2438,11 +3360,17 @@ where
             }
         }
 
+        if self.spaces[SpaceId::Data].permit_ack_only {
+            if let Some(deferral) = self.ack_deferral() {
+                self.defer_acks(now, deferral);
+            }
+        }
+
         // Issue stream ID credit due to ACKs of outgoing finish/resets and incoming finish/resets
         // on stopped streams
         let pending = &mut self.spaces[SpaceId::Data].pending;
         for dir in Dir::iter() {
-            if self.streams.take_max_streams_dirty(dir) {
+            if self.streams.max_streams_due(dir) {
                 match dir {
                     Dir::Uni => pending.max_uni_stream_id = true,
                     Dir::Bi => pending.max_bi_stream_id = true,
@@ -2472,15 +3400,64 @@ where
             let _ = self.update_rem_cid();
         }
 
+        self.check_quota(now);
         Ok(())
     }
 
-    fn migrate(&mut self, now: Instant, remote: SocketAddr) {
-        trace!(%remote, "migration initiated");
+    /// Close the connection if the peer exceeded the configured quota
+    fn check_quota(&mut self, now: Instant) {
+        let quota = match self.config.quota {
+            Some(x) => x,
+            None => return,
+        };
+        if self.state.is_closed() {
+            return;
+        }
+        let received = self
+            .streams
+            .data_recvd()
+            .saturating_add(self.datagrams.recvd_bytes);
+        let exceeded = quota.max_received_bytes.map_or(false, |max| received > max)
+            || quota
+                .max_streams
+                .map_or(false, |max| self.streams.remote_opened() > max);
+        if !exceeded {
+            return;
+        }
+        debug!("peer exceeded quota");
+        self.close_inner(
+            now,
+            Close::Application(frame::ApplicationClose {
+                error_code: quota.error_code,
+                reason: Bytes::new(),
+            }),
+        );
+        self.error = Some(ConnectionError::QuotaExceeded);
+    }
+
+    /// Check the peer's use of `id` against the configured `stream_initiator` restriction
+    fn check_stream_initiator(&self, id: StreamId) -> Result<(), TransportError> {
+        match self.config.stream_initiator {
+            Some(side) if id.initiator() != self.side && id.initiator() != side => {
+                debug!(stream = %id, "peer initiated a stream in a forbidden direction");
+                Err(TransportError::PROTOCOL_VIOLATION(
+                    "stream initiated by forbidden side",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn migrate(&mut self, now: Instant, remote: A) {
+        trace!(?remote, "migration initiated");
         // Reset rtt/congestion state for new path unless it looks like a NAT rebinding.
         // Note that the congestion window will not grow until validation terminates. Helps mitigate
         // amplification attacks performed by spoofing source addresses.
-        let mut new_path = if remote.is_ipv4() && remote.ip() == self.path.remote.ip() {
+        let rebinding = match (remote.socket_addr(), self.path.remote.socket_addr()) {
+            (Some(new), Some(old)) => new.is_ipv4() && new.ip() == old.ip(),
+            _ => false,
+        };
+        let mut new_path = if rebinding {
             PathData::from_previous(remote, &self.path, now)
         } else {
             PathData::new(
@@ -2489,6 +3466,8 @@ where
                 self.config.congestion_controller_factory.build(now),
                 now,
                 false,
+                self.base_mtu(),
+                self.config.pacer,
             )
         };
         new_path.challenge = Some(self.rng.gen());
@@ -2507,6 +3486,12 @@ where
             Timer::PathValidation,
             now + 3 * cmp::max(self.pto(), prev_pto),
         );
+        if self.header_compression {
+            self.endpoint_events
+                .push_back(EndpointEventInner::HeaderCompression(remote));
+        }
+        // The new path's MTU is unknown
+        self.search_mtu(now);
     }
 
     /// Returns Err(()) if no CIDs were available
@@ -2543,6 +3528,7 @@ where
 
     fn populate_packet(
         &mut self,
+        now: Instant,
         space_id: SpaceId,
         buf: &mut Vec<u8>,
         max_size: usize,
@@ -2565,6 +3551,9 @@ where
             trace!("PING");
             buf.write(frame::Type::PING);
             self.stats.frame_tx.ping += 1;
+            if space_id == SpaceId::Data && !is_0rtt {
+                sent.requires_padding |= mem::replace(&mut self.path_probe_pending, false);
+            }
         }
 
         // ACK
@@ -2577,7 +3566,15 @@ where
             } else {
                 None
             };
-            frame::Ack::encode(0, &space.pending_acks, ecn, buf);
+            // The peer only accounts for delays in acknowledging 1-RTT packets
+            let delay =
+                if space_id == SpaceId::Data && space.pending_acks.max() == Some(space.rx_packet) {
+                    let delay = now.saturating_duration_since(space.rx_packet_time);
+                    delay.as_micros() as u64 >> self.config.ack_delay_exponent
+                } else {
+                    0
+                };
+            frame::Ack::encode(delay, &space.pending_acks, ecn, buf);
             sent.acks = space.pending_acks.clone();
             self.stats.frame_tx.acks += 1;
         }
@@ -2716,6 +3713,15 @@ where
         for &timer in &Timer::VALUES {
             self.timers.stop(timer);
         }
+        if let (Some(cache), Some(server_name), Some(rtt)) = (
+            &self.config.rtt_cache,
+            self.crypto.server_name(),
+            self.path.rtt.smoothed(),
+        ) {
+            if self.side.is_client() {
+                cache.insert(server_name, rtt);
+            }
+        }
     }
 
     fn set_close_timer(&mut self, now: Instant) {
@@ -2734,18 +3740,25 @@ where
             ));
         }
 
+        if self.config.header_compression && params.header_compression {
+            self.header_compression = true;
+            self.endpoint_events
+                .push_back(EndpointEventInner::HeaderCompression(self.path.remote));
+        }
         self.set_peer_params(params);
         Ok(())
     }
 
     fn set_peer_params(&mut self, params: TransportParameters) {
         self.streams.set_params(&params);
-        self.idle_timeout = match (self.config.max_idle_timeout, params.max_idle_timeout.0) {
-            (None, 0) => None,
-            (None, x) => Some(Duration::from_millis(x)),
-            (Some(x), 0) => Some(x),
-            (Some(x), y) => Some(cmp::min(x, Duration::from_millis(y))),
-        };
+        self.negotiated_idle_timeout =
+            match (self.config.max_idle_timeout, params.max_idle_timeout.0) {
+                (None, 0) => None,
+                (None, x) => Some(Duration::from_millis(x)),
+                (Some(x), 0) => Some(x),
+                (Some(x), y) => Some(cmp::min(x, Duration::from_millis(y))),
+            };
+        self.update_idle_timeout();
         if let Some(ref info) = params.preferred_address {
             self.rem_cids.insert(IssuedCid {
                 sequence: 1,
@@ -2754,6 +3767,28 @@ where
             }).expect("preferred address CID is the first received, and hence is guaranteed to be legal");
         }
         self.peer_params = params;
+        self.path.mtu = cmp::min(self.path.mtu, self.base_mtu());
+    }
+
+    /// Change the size of UDP payloads sent on the established path
+    fn set_mtu(&mut self, mtu: u16) {
+        if mtu == self.path.mtu {
+            return;
+        }
+        self.path.mtu = mtu;
+        // Queued datagrams which no longer fit in a packet would otherwise never be sent
+        if !self.datagrams.outgoing.is_empty() {
+            if let Some(max) = self.datagrams().max_size() {
+                self.datagrams.drop_oversized(max);
+            }
+        }
+        self.events.push_back(Event::PathMtuChanged { mtu });
+    }
+
+    /// Size of the UDP payloads to send on a path we know nothing about
+    fn base_mtu(&self) -> u16 {
+        let peer_max = self.peer_params.max_udp_payload_size.into_inner();
+        cmp::min(self.config.initial_mtu() as u64, peer_max) as u16
     }
 
     fn decrypt_packet(
@@ -2876,12 +3911,17 @@ where
             .saturating_sub(self.in_flight.bytes)
     }
 
-    /// Whether no timers but keepalive, idle and pushnewcid are running
+    /// Whether no timers but keepalive, idle, idle warning and pushnewcid are running
     #[cfg(test)]
     pub(crate) fn is_idle(&self) -> bool {
         Timer::VALUES
             .iter()
-            .filter(|&&t| t != Timer::KeepAlive && t != Timer::PushNewCid)
+            .filter(|&&t| {
+                t != Timer::KeepAlive
+                    && t != Timer::PushNewCid
+                    && t != Timer::IdleWarning
+                    && t != Timer::MtuDiscovery
+            })
             .filter_map(|&t| Some((t, self.timers.get(t)?)))
             .min_by_key(|&(_, time)| time)
             .map_or(true, |(timer, _)| timer == Timer::Idle)
@@ -2919,7 +3959,7 @@ where
         self.rem_cids.active_seq()
     }
 
-    fn max_ack_delay(&self) -> Duration {
+    pub(crate) fn max_ack_delay(&self) -> Duration {
         Duration::from_micros(self.peer_params.max_ack_delay.0 * 1000)
     }
 
@@ -2944,6 +3984,14 @@ where
         self.spaces[space].in_flight -= u64::from(packet.size);
     }
 
+    /// How far the handshake got, for reporting a failure to complete it
+    fn handshake_phase(&self) -> HandshakePhase {
+        match self.highest_space {
+            SpaceId::Initial => HandshakePhase::Initial,
+            _ => HandshakePhase::Handshake,
+        }
+    }
+
     /// Terminate the connection instantly, without sending a close packet
     fn kill(&mut self, reason: ConnectionError) {
         self.close_common();
@@ -2953,9 +4001,10 @@ where
     }
 }
 
-impl<S> fmt::Debug for Connection<S>
+impl<S, A> fmt::Debug for Connection<S, A>
 where
     S: crypto::Session,
+    A: Address,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Connection")
@@ -2980,6 +4029,9 @@ pub enum ConnectionError {
     #[error("closed by peer: {0}")]
     ApplicationClosed(frame::ApplicationClose),
     /// The peer is unable to continue processing this connection, usually due to having restarted
+    ///
+    /// Signalled by a stateless reset, details of which are available from
+    /// `Connection::stateless_reset`.
     #[error("reset by peer")]
     Reset,
     /// Communication with the peer has lapsed for longer than the negotiated idle timeout
@@ -2989,9 +4041,95 @@ pub enum ConnectionError {
     /// and [`TransportConfig::keep_alive_interval()`].
     #[error("timed out")]
     TimedOut,
+    /// The handshake didn't complete within the idle timeout
+    #[error("handshake timed out in {0} phase")]
+    HandshakeTimedOut(HandshakePhase),
     /// The local application closed the connection
     #[error("closed")]
     LocallyClosed,
+    /// The peer exceeded the connection's quota
+    ///
+    /// See [`TransportConfig::quota()`].
+    #[error("quota exceeded")]
+    QuotaExceeded,
+    /// Nothing was received from the server in response to repeated Initial packets
+    ///
+    /// Suggests that UDP traffic between the client and server is blocked, e.g. by a firewall, so
+    /// that falling back to another transport may succeed. See
+    /// [`TransportConfig::udp_blocked_probes()`].
+    #[error("no response from server, UDP may be blocked")]
+    UdpBlocked,
+}
+
+impl ConnectionError {
+    /// Classify errors that indicate the handshake failed
+    ///
+    /// Allows clients to decide between retrying, falling back to other transports, and reporting
+    /// the failure. Returns `None` for errors that aren't specific to the handshake.
+    pub fn handshake_failure(&self) -> Option<HandshakeFailure> {
+        let (code, sent_by_peer) = match *self {
+            ConnectionError::VersionMismatch => return Some(HandshakeFailure::VersionMismatch),
+            ConnectionError::HandshakeTimedOut(phase) => {
+                return Some(HandshakeFailure::TimedOut(phase))
+            }
+            ConnectionError::UdpBlocked => return Some(HandshakeFailure::UdpBlocked),
+            ConnectionError::TransportError(ref e) => (e.code, false),
+            ConnectionError::ConnectionClosed(ref close) => (close.error_code, true),
+            _ => return None,
+        };
+        if code == TransportErrorCode::INVALID_TOKEN {
+            return Some(HandshakeFailure::InvalidToken);
+        }
+        code.tls_alert().map(|alert| HandshakeFailure::TlsAlert {
+            alert,
+            sent_by_peer,
+        })
+    }
+}
+
+/// Structured reason for a failed handshake
+///
+/// See `ConnectionError::handshake_failure`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum HandshakeFailure {
+    /// The cryptographic handshake was aborted with a TLS alert
+    TlsAlert {
+        /// The TLS alert description, e.g. 42 for `bad_certificate`
+        alert: u8,
+        /// Whether the alert was raised by the peer rather than locally
+        sent_by_peer: bool,
+    },
+    /// The server rejected the client's address validation token
+    ///
+    /// Typically transient, e.g. because the server restarted since issuing the token.
+    InvalidToken,
+    /// The peer doesn't implement any supported version
+    VersionMismatch,
+    /// The handshake didn't complete within the idle timeout
+    TimedOut(HandshakePhase),
+    /// The server never responded, suggesting that UDP traffic is blocked
+    UdpBlocked,
+}
+
+/// How far a handshake progressed before it failed
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HandshakePhase {
+    /// Only Initial packets were exchanged
+    ///
+    /// For clients, this usually means the server is unreachable.
+    Initial,
+    /// Handshake keys were established, but the handshake wasn't completed
+    Handshake,
+}
+
+impl fmt::Display for HandshakePhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            HandshakePhase::Initial => "initial",
+            HandshakePhase::Handshake => "handshake",
+        })
+    }
 }
 
 impl From<Close> for ConnectionError {
@@ -3008,9 +4146,11 @@ impl From<ConnectionError> for io::Error {
     fn from(x: ConnectionError) -> io::Error {
         use self::ConnectionError::*;
         let kind = match x {
-            TimedOut => io::ErrorKind::TimedOut,
+            TimedOut | HandshakeTimedOut(_) | UdpBlocked => io::ErrorKind::TimedOut,
             Reset => io::ErrorKind::ConnectionReset,
-            ApplicationClosed(_) | ConnectionClosed(_) => io::ErrorKind::ConnectionAborted,
+            ApplicationClosed(_) | ConnectionClosed(_) | QuotaExceeded => {
+                io::ErrorKind::ConnectionAborted
+            }
             TransportError(_) | VersionMismatch | LocallyClosed => io::ErrorKind::Other,
         };
         io::Error::new(kind, x)
@@ -3125,6 +4265,72 @@ impl InFlight {
     }
 }
 
+/// A stateless reset received from the peer
+///
+/// See `Connection::stateless_reset`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct StatelessReset<A = SocketAddr> {
+    /// When the reset was received
+    pub received: Instant,
+    /// The address the reset was received from
+    pub remote: A,
+    /// The local IP address the reset was received on, if known
+    pub local_ip: Option<IpAddr>,
+}
+
+/// How a connection's cryptographic handshake was completed
+///
+/// See `Connection::handshake_summary`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct HandshakeSummary {
+    /// Whether the session was resumed from an earlier connection
+    ///
+    /// `None` if the crypto layer can't tell. With rustls, clients only know that a resumption
+    /// succeeded if their early data was accepted.
+    pub resumed: Option<bool>,
+    /// What became of early data
+    pub early_data: EarlyData,
+}
+
+/// How a connection balances responsiveness against power consumption
+///
+/// See `Connection::set_power_mode`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PowerMode {
+    /// Handle timers and acknowledge packets as promptly as possible
+    Performance,
+    /// Wake up less often, e.g. so a mobile device's radio can stay idle for longer
+    ///
+    /// Timers may fire somewhat late so that several can be handled at once, and keep-alives are
+    /// sent less frequently, though still well within the idle timeout. If a `max_ack_delay` longer
+    /// than the timer slack was advertised, ACK-only packets are held back so that acknowledgements
+    /// are batched, without exceeding it. Loss recovery and throughput may suffer a little.
+    PowerSaving,
+}
+
+impl Default for PowerMode {
+    fn default() -> Self {
+        Self::Performance
+    }
+}
+
+/// Outcome of 0-RTT on a connection
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum EarlyData {
+    /// 0-RTT wasn't possible, e.g. because the client had no session ticket for the server
+    NotAttempted,
+    /// 0-RTT data was accepted
+    ///
+    /// For servers, this means the client's offer of early data was accepted, though the client
+    /// may not have sent any.
+    Accepted,
+    /// The client sent 0-RTT data, but the server rejected it
+    ///
+    /// The rejected data is retransmitted as 1-RTT data. Only reported to clients.
+    Rejected,
+}
+
 /// Events of interest to the application
 #[derive(Debug)]
 pub enum Event {
@@ -3143,6 +4349,21 @@ pub enum Event {
     Stream(StreamEvent),
     /// One or more application datagrams have been received
     DatagramReceived,
+    /// The size of UDP payloads sent on the connection changed
+    ///
+    /// See `Connection::max_udp_payload_size`.
+    PathMtuChanged {
+        /// Largest UDP payload that will now be sent
+        mtu: u16,
+    },
+    /// Packets larger than the minimum MTU were found to be dropped by the path
+    ///
+    /// Followed by a `PathMtuChanged` event as the connection falls back to the minimum MTU.
+    BlackHoleDetected,
+    /// The connection will time out soon unless there's activity on it
+    ///
+    /// Emitted `TransportConfig::idle_timeout_warning` before the idle timeout expires.
+    IdleTimeoutWarning,
 }
 
 struct PathResponse {
@@ -3151,6 +4372,16 @@ struct PathResponse {
     token: u64,
 }
 
+/// An incoming connection's first packet, as passed to `Connection::handle_first_packet`
+struct DeferredPacket<A> {
+    now: Instant,
+    remote: A,
+    ecn: Option<EcnCodepoint>,
+    packet_number: u64,
+    packet: Packet,
+    remaining: Option<BytesMut>,
+}
+
 fn instant_saturating_sub(x: Instant, y: Instant) -> Duration {
     if x > y {
         x - y
@@ -3161,6 +4392,13 @@ fn instant_saturating_sub(x: Instant, y: Instant) -> Duration {
 
 // Prevents overflow and improves behavior in extreme circumstances
 const MAX_BACKOFF_EXPONENT: u32 = 16;
+/// How late timers may fire in power-saving mode
+const POWER_SAVING_TIMER_SLACK: Duration = Duration::from_millis(10);
+/// Number of ack-eliciting packets after which an ACK is sent without delay while acknowledgements
+/// are batched
+const ACK_BATCH: u32 = 10;
+/// How much longer keep-alive intervals are in power-saving mode
+const POWER_SAVING_KEEP_ALIVE_FACTOR: u32 = 4;
 // Minimal remaining size to allow packet coalescing
 const MIN_PACKET_SPACE: usize = 40;
 /// The maximum amount of datagrams that are sent in a single transmit
@@ -0,0 +1,89 @@
+//! Packetization layer path MTU discovery
+//!
+//! Larger UDP payloads are probed for with padded PING packets, which are tracked separately from
+//! other traffic so that their loss is taken to mean the path can't carry them, rather than as a
+//! sign of congestion.
+
+/// Probing state of a single path
+#[derive(Debug, Default)]
+pub struct MtuDiscovery {
+    /// Size of the probe to send next, if any
+    pub pending: Option<u16>,
+    /// Packet number and size of the probe awaiting acknowledgement, if any
+    in_flight: Option<(u64, u16)>,
+    /// Number of times the probe of the current size was lost
+    lost: u32,
+}
+
+impl MtuDiscovery {
+    /// Size of the probe to send now, if one is pending and none is in flight
+    pub fn next_probe(&self) -> Option<u16> {
+        match self.in_flight {
+            None => self.pending,
+            Some(_) => None,
+        }
+    }
+
+    /// Whether a probe is pending or in flight
+    pub fn is_probing(&self) -> bool {
+        self.pending.is_some() || self.in_flight.is_some()
+    }
+
+    /// Packet number of the probe awaiting acknowledgement, if any
+    pub fn in_flight_packet(&self) -> Option<u64> {
+        self.in_flight.map(|(packet, _)| packet)
+    }
+
+    /// Queue a probe for `size`, replacing any pending probe of a different size
+    pub fn queue(&mut self, size: u16) {
+        if self.pending != Some(size) {
+            self.lost = 0;
+        }
+        self.pending = Some(size);
+    }
+
+    /// Record that the pending probe was sent as `packet`
+    pub fn on_sent(&mut self, packet: u64) {
+        let size = self.pending.take().expect("no probe pending");
+        self.in_flight = Some((packet, size));
+    }
+
+    /// Record the acknowledgement of `packet`, returning the size it confirmed if it was a probe
+    pub fn on_acked(&mut self, packet: u64) -> Option<u16> {
+        let (probe, size) = self.in_flight?;
+        if probe != packet {
+            return None;
+        }
+        self.in_flight = None;
+        self.lost = 0;
+        Some(size)
+    }
+
+    /// Record the loss of `packet`, returning whether it was a probe
+    ///
+    /// Lost probes are retried a few times, as they may have been lost for unrelated reasons,
+    /// before the size is given up on.
+    pub fn on_lost(&mut self, packet: u64) -> bool {
+        let (probe, size) = match self.in_flight {
+            Some(x) => x,
+            None => return false,
+        };
+        if probe != packet {
+            return false;
+        }
+        self.in_flight = None;
+        self.lost += 1;
+        if self.lost < MAX_PROBES {
+            // Don't clobber a different size queued meanwhile
+            self.pending.get_or_insert(size);
+        } else {
+            self.lost = 0;
+        }
+        true
+    }
+}
+
+/// Number of times a probe is sent before the size is found not to be supported
+///
+/// Matches MAX_PROBES as suggested by RFC 8899.
+const MAX_PROBES: u32 = 3;
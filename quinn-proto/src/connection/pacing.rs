@@ -13,6 +13,7 @@ use tracing::warn;
 /// than one congestion window per RTT, as recommended in
 /// https://tools.ietf.org/html/draft-ietf-quic-recovery-34#section-7.7
 pub struct Pacer {
+    config: PacerConfig,
     capacity: u64,
     last_window: u64,
     tokens: u64,
@@ -21,9 +22,16 @@ pub struct Pacer {
 
 impl Pacer {
     /// Obtains a new [`Pacer`].
-    pub fn new(smoothed_rtt: Duration, window: u64, mtu: u16, now: Instant) -> Self {
-        let capacity = optimal_capacity(smoothed_rtt, window, mtu);
+    pub fn new(
+        config: PacerConfig,
+        smoothed_rtt: Duration,
+        window: u64,
+        mtu: u16,
+        now: Instant,
+    ) -> Self {
+        let capacity = config.optimal_capacity(smoothed_rtt, window, mtu);
         Self {
+            config,
             capacity,
             last_window: window,
             tokens: capacity,
@@ -31,6 +39,10 @@ impl Pacer {
         }
     }
 
+    pub fn config(&self) -> PacerConfig {
+        self.config
+    }
+
     /// Record that a packet has been transmitted.
     pub fn on_transmit(&mut self, packet_length: u16) {
         self.tokens = self.tokens.saturating_sub(packet_length.into())
@@ -57,7 +69,7 @@ impl Pacer {
         );
 
         if window != self.last_window {
-            self.capacity = optimal_capacity(smoothed_rtt, window, mtu);
+            self.capacity = self.config.optimal_capacity(smoothed_rtt, window, mtu);
 
             // Clamp the tokens
             self.tokens = self.capacity.min(self.tokens);
@@ -110,29 +122,50 @@ impl Pacer {
     }
 }
 
-/// Calculates a pacer capacity for a certain window and RTT
-///
-/// The goal is to emit a burst (of size `capacity`) in timer intervals
-/// which compromise between
-/// - ideally distributing datagrams over time
-/// - constantly waking up the connection to produce additional datagrams
-///
-/// Too short burst intervals means we will never meet them since the timer
-/// accuracy in user-space is not high enough. If we miss the interval by more
-/// than 25%, we will lose that part of the congestion window since no additional
-/// tokens for the extra-elapsed time can be stored.
-///
-/// Too long burst intervals make pacing less effective.
-fn optimal_capacity(smoothed_rtt: Duration, window: u64, mtu: u16) -> u64 {
-    let rtt = smoothed_rtt.as_nanos().max(1);
+/// Parameters controlling the size and frequency of the pacer's bursts
+#[derive(Debug, Copy, Clone)]
+pub struct PacerConfig {
+    pub(crate) burst_interval: Duration,
+    /// Bounds on the size of a burst, in packets
+    pub(crate) min_burst_size: u64,
+    pub(crate) max_burst_size: u64,
+}
+
+impl PacerConfig {
+    /// Calculates a pacer capacity for a certain window and RTT
+    ///
+    /// The goal is to emit a burst (of size `capacity`) in timer intervals
+    /// which compromise between
+    /// - ideally distributing datagrams over time
+    /// - constantly waking up the connection to produce additional datagrams
+    ///
+    /// Too short burst intervals means we will never meet them since the timer
+    /// accuracy in user-space is not high enough. If we miss the interval by more
+    /// than 25%, we will lose that part of the congestion window since no additional
+    /// tokens for the extra-elapsed time can be stored.
+    ///
+    /// Too long burst intervals make pacing less effective.
+    fn optimal_capacity(&self, smoothed_rtt: Duration, window: u64, mtu: u16) -> u64 {
+        let rtt = smoothed_rtt.as_nanos().max(1);
 
-    let capacity = ((window as u128 * BURST_INTERVAL_NANOS) / rtt) as u64;
+        let capacity = ((window as u128 * self.burst_interval.as_nanos()) / rtt) as u64;
 
-    // Small bursts are less efficient (no GSO), could increase latency and don't effectively
-    // use the channel's buffer capacity. Large bursts might block the connection on sending.
-    capacity
-        .max(MIN_BURST_SIZE * mtu as u64)
-        .min(MAX_BURST_SIZE * mtu as u64)
+        // Small bursts are less efficient (no GSO), could increase latency and don't effectively
+        // use the channel's buffer capacity. Large bursts might block the connection on sending.
+        capacity
+            .max(self.min_burst_size * mtu as u64)
+            .min(self.max_burst_size * mtu as u64)
+    }
+}
+
+impl Default for PacerConfig {
+    fn default() -> Self {
+        Self {
+            burst_interval: Duration::from_nanos(BURST_INTERVAL_NANOS as u64),
+            min_burst_size: MIN_BURST_SIZE,
+            max_burst_size: MAX_BURST_SIZE,
+        }
+    }
 }
 
 /// The burst interval
@@ -159,15 +192,21 @@ mod tests {
         let new_instant = old_instant + Duration::from_micros(15);
         let rtt = Duration::from_micros(400);
 
-        assert!(Pacer::new(rtt, 30000, 1500, new_instant)
-            .delay(Duration::from_micros(0), 0, 1500, 1, old_instant)
-            .is_none());
-        assert!(Pacer::new(rtt, 30000, 1500, new_instant)
-            .delay(Duration::from_micros(0), 1600, 1500, 1, old_instant)
-            .is_none());
-        assert!(Pacer::new(rtt, 30000, 1500, new_instant)
-            .delay(Duration::from_micros(0), 1500, 1500, 3000, old_instant)
-            .is_none());
+        assert!(
+            Pacer::new(PacerConfig::default(), rtt, 30000, 1500, new_instant)
+                .delay(Duration::from_micros(0), 0, 1500, 1, old_instant)
+                .is_none()
+        );
+        assert!(
+            Pacer::new(PacerConfig::default(), rtt, 30000, 1500, new_instant)
+                .delay(Duration::from_micros(0), 1600, 1500, 1, old_instant)
+                .is_none()
+        );
+        assert!(
+            Pacer::new(PacerConfig::default(), rtt, 30000, 1500, new_instant)
+                .delay(Duration::from_micros(0), 1500, 1500, 3000, old_instant)
+                .is_none()
+        );
     }
 
     #[test]
@@ -177,22 +216,53 @@ mod tests {
         let rtt = Duration::from_millis(50);
         let now = Instant::now();
 
-        let pacer = Pacer::new(rtt, window, mtu, now);
+        let pacer = Pacer::new(PacerConfig::default(), rtt, window, mtu, now);
         assert_eq!(
             pacer.capacity,
             (window as u128 * BURST_INTERVAL_NANOS / rtt.as_nanos()) as u64
         );
         assert_eq!(pacer.tokens, pacer.capacity);
 
-        let pacer = Pacer::new(Duration::from_millis(0), window, mtu, now);
+        let pacer = Pacer::new(
+            PacerConfig::default(),
+            Duration::from_millis(0),
+            window,
+            mtu,
+            now,
+        );
         assert_eq!(pacer.capacity, MAX_BURST_SIZE * mtu as u64);
         assert_eq!(pacer.tokens, pacer.capacity);
 
-        let pacer = Pacer::new(rtt, 1, mtu, now);
+        let pacer = Pacer::new(PacerConfig::default(), rtt, 1, mtu, now);
         assert_eq!(pacer.capacity, MIN_BURST_SIZE * mtu as u64);
         assert_eq!(pacer.tokens, pacer.capacity);
     }
 
+    #[test]
+    fn configures_capacity() {
+        let window = 1_000_000;
+        let mtu = 1500;
+        let rtt = Duration::from_millis(50);
+        let now = Instant::now();
+        let config = PacerConfig {
+            burst_interval: Duration::from_millis(5),
+            min_burst_size: 2,
+            max_burst_size: 100,
+        };
+
+        let pacer = Pacer::new(config, rtt, window, mtu, now);
+        assert_eq!(
+            pacer.capacity,
+            (window as u128 * 5_000_000 / rtt.as_nanos()) as u64
+        );
+
+        let pacer = Pacer::new(config, Duration::from_millis(0), window, mtu, now);
+        assert_eq!(pacer.capacity, 100 * mtu as u64);
+
+        let pacer = Pacer::new(config, rtt, 1, mtu, now);
+        assert_eq!(pacer.capacity, 2 * mtu as u64);
+    }
+
     #[test]
     fn adjusts_capacity() {
         let window = 2_000_000;
@@ -200,7 +270,7 @@ mod tests {
         let rtt = Duration::from_millis(50);
         let now = Instant::now();
 
-        let mut pacer = Pacer::new(rtt, window, mtu, now);
+        let mut pacer = Pacer::new(PacerConfig::default(), rtt, window, mtu, now);
         assert_eq!(
             pacer.capacity,
             (window as u128 * BURST_INTERVAL_NANOS / rtt.as_nanos()) as u64
@@ -230,7 +300,7 @@ mod tests {
         let rtt = Duration::from_millis(50);
         let old_instant = Instant::now();
 
-        let mut pacer = Pacer::new(rtt, window, mtu, old_instant);
+        let mut pacer = Pacer::new(PacerConfig::default(), rtt, window, mtu, old_instant);
         let packet_capacity = pacer.capacity / mtu as u64;
 
         for _ in 0..packet_capacity {
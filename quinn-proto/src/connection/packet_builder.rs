@@ -9,11 +9,13 @@ use crate::{
     crypto::{HeaderKey, PacketKey, Session},
     frame::{self, Close},
     packet::{Header, LongType, PacketNumber, PartialEncode, SpaceId},
-    TransportError, TransportErrorCode,
+    Address, TransportError, TransportErrorCode,
 };
 
 pub(super) struct PacketBuilder {
     pub datagram_start: usize,
+    /// End of the space the datagram may occupy in the buffer
+    pub datagram_end: usize,
     pub space: SpaceId,
     pub partial_encode: PartialEncode,
     pub ack_eliciting: bool,
@@ -30,14 +32,14 @@ impl PacketBuilder {
     ///
     /// Marks the connection drained and returns `None` if the confidentiality limit would be
     /// violated.
-    pub fn new<S: Session>(
+    pub fn new<S: Session, A: Address>(
         now: Instant,
         space_id: SpaceId,
         buffer: &mut Vec<u8>,
         buffer_capacity: usize,
         datagram_start: usize,
         ack_eliciting: bool,
-        conn: &mut Connection<S>,
+        conn: &mut Connection<S, A>,
         version: u32,
     ) -> Option<PacketBuilder> {
         // Initiate key update if we're approaching the confidentiality limit
@@ -139,6 +141,7 @@ impl PacketBuilder {
 
         Some(PacketBuilder {
             datagram_start,
+            datagram_end: buffer_capacity,
             space: space_id,
             partial_encode,
             exact_number,
@@ -157,10 +160,10 @@ impl PacketBuilder {
         debug_assert!(self.min_size >= prev, "padding must not shrink datagram");
     }
 
-    pub fn finish_and_track<S: Session>(
+    pub fn finish_and_track<S: Session, A: Address>(
         self,
         now: Instant,
-        conn: &mut Connection<S>,
+        conn: &mut Connection<S, A>,
         sent: Option<SentFrames>,
         buffer: &mut Vec<u8>,
     ) {
@@ -204,9 +207,9 @@ impl PacketBuilder {
     }
 
     /// Encrypt packet, returning the length of the packet and whether padding was added
-    pub fn finish<S: Session>(
+    pub fn finish<S: Session, A: Address>(
         self: PacketBuilder,
-        conn: &mut Connection<S>,
+        conn: &mut Connection<S, A>,
         buffer: &mut Vec<u8>,
     ) -> (usize, bool) {
         let pad = buffer.len() < self.min_size;
@@ -232,7 +235,7 @@ impl PacketBuilder {
         );
 
         buffer.resize(buffer.len() + packet_crypto.tag_len(), 0);
-        debug_assert!(buffer.len() <= self.datagram_start + conn.path.mtu as usize);
+        debug_assert!(buffer.len() <= self.datagram_end);
         let encode_start = self.partial_encode.start;
         let packet_buf = &mut buffer[encode_start..];
         self.partial_encode.finish(
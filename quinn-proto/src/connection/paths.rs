@@ -1,11 +1,14 @@
-use std::{cmp, net::SocketAddr, time::Duration, time::Instant};
+use std::{cmp, time::Duration, time::Instant};
 
-use super::pacing::Pacer;
-use crate::{congestion, MIN_MTU, TIMER_GRANULARITY};
+use super::{
+    mtud::MtuDiscovery,
+    pacing::{Pacer, PacerConfig},
+};
+use crate::{congestion, MIN_INITIAL_SIZE, TIMER_GRANULARITY};
 
 /// Description of a particular network path
-pub struct PathData {
-    pub remote: SocketAddr,
+pub struct PathData<A> {
+    pub remote: A,
     pub rtt: RttEstimator,
     /// Whether we're enabling ECN on outgoing packets
     pub sending_ecn: bool,
@@ -25,38 +28,50 @@ pub struct PathData {
     /// Total size of all UDP datagrams received on this path
     pub total_recvd: u64,
     pub mtu: u16,
+    pub black_hole: BlackHoleDetector,
+    pub mtud: MtuDiscovery,
 }
 
-impl PathData {
+impl<A> PathData<A> {
     pub fn new(
-        remote: SocketAddr,
+        remote: A,
         initial_rtt: Duration,
         congestion: Box<dyn congestion::Controller>,
         now: Instant,
         validated: bool,
+        mtu: u16,
+        pacer: PacerConfig,
     ) -> Self {
         PathData {
             remote,
             rtt: RttEstimator::new(initial_rtt),
             sending_ecn: true,
-            pacing: Pacer::new(initial_rtt, congestion.initial_window(), MIN_MTU, now),
+            pacing: Pacer::new(pacer, initial_rtt, congestion.initial_window(), mtu, now),
             congestion,
             challenge: None,
             challenge_pending: false,
             validated,
             total_sent: 0,
             total_recvd: 0,
-            mtu: MIN_MTU,
+            mtu,
+            black_hole: BlackHoleDetector::default(),
+            mtud: MtuDiscovery::default(),
         }
     }
 
-    pub fn from_previous(remote: SocketAddr, prev: &PathData, now: Instant) -> Self {
+    pub fn from_previous(remote: A, prev: &PathData<A>, now: Instant) -> Self {
         let congestion = prev.congestion.clone_box();
         let smoothed_rtt = prev.rtt.get();
         PathData {
             remote,
             rtt: prev.rtt,
-            pacing: Pacer::new(smoothed_rtt, congestion.window(), prev.mtu, now),
+            pacing: Pacer::new(
+                prev.pacing.config(),
+                smoothed_rtt,
+                congestion.window(),
+                prev.mtu,
+                now,
+            ),
             sending_ecn: true,
             congestion,
             challenge: None,
@@ -65,6 +80,8 @@ impl PathData {
             total_sent: 0,
             total_recvd: 0,
             mtu: prev.mtu,
+            black_hole: BlackHoleDetector::default(),
+            mtud: MtuDiscovery::default(),
         }
     }
 
@@ -103,7 +120,7 @@ impl RttEstimator {
         self.min = cmp::min(self.min, self.latest);
         // Based on RFC6298.
         if let Some(smoothed) = self.smoothed {
-            let adjusted_rtt = if self.min + ack_delay < self.latest {
+            let adjusted_rtt = if self.min + ack_delay <= self.latest {
                 self.latest - ack_delay
             } else {
                 self.latest
@@ -126,6 +143,11 @@ impl RttEstimator {
         self.smoothed.unwrap_or(self.latest)
     }
 
+    /// The smoothed RTT, once at least one sample has been taken
+    pub fn smoothed(&self) -> Option<Duration> {
+        self.smoothed
+    }
+
     /// Conservative estimate of RTT
     ///
     /// Takes the maximum of smoothed and latest RTT, as recommended
@@ -138,3 +160,79 @@ impl RttEstimator {
         self.get() + cmp::max(4 * self.var, TIMER_GRANULARITY)
     }
 }
+
+/// Detects paths which silently drop packets larger than the minimum size every QUIC path must
+/// support
+///
+/// A loss burst is suspicious if every packet lost in it was large, and no large packet sent after
+/// it has been acknowledged. Several suspicious bursts without any large packet getting through
+/// indicate a black hole.
+#[derive(Default)]
+pub struct BlackHoleDetector {
+    /// Number of suspicious loss bursts since a large packet was last acknowledged
+    suspicious_bursts: u32,
+    /// Packet number of the most recently sent large packet to be acknowledged
+    largest_acked_large: Option<u64>,
+}
+
+impl BlackHoleDetector {
+    /// Record the loss of a set of packets, given their numbers and in-flight sizes, returning
+    /// whether a black hole is detected
+    pub fn on_loss_burst(&mut self, lost: impl IntoIterator<Item = (u64, u16)>) -> bool {
+        let mut suspicious = false;
+        for (packet, size) in lost {
+            if size == 0 {
+                // Not counted towards congestion control, hence likely tiny
+                continue;
+            }
+            if size <= MIN_INITIAL_SIZE || self.largest_acked_large.map_or(false, |x| packet < x) {
+                return false;
+            }
+            suspicious = true;
+        }
+        if !suspicious {
+            return false;
+        }
+        self.suspicious_bursts += 1;
+        if self.suspicious_bursts < BLACK_HOLE_THRESHOLD {
+            return false;
+        }
+        self.suspicious_bursts = 0;
+        true
+    }
+
+    /// Record the acknowledgement of a packet
+    pub fn on_acked(&mut self, packet: u64, size: u16) {
+        if size > MIN_INITIAL_SIZE && self.largest_acked_large.map_or(true, |x| packet > x) {
+            self.largest_acked_large = Some(packet);
+            self.suspicious_bursts = 0;
+        }
+    }
+}
+
+/// Number of suspicious loss bursts after which a path is deemed to be a black hole
+const BLACK_HOLE_THRESHOLD: u32 = 3;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn black_hole_detection() {
+        let mut detector = BlackHoleDetector::default();
+        detector.on_acked(0, 1300);
+        // Losing small packets, or packets sent before a large one got through, is congestion
+        assert!(!detector.on_loss_burst(vec![(1, 1300), (2, 1000)]));
+        detector.on_acked(5, 1300);
+        assert!(!detector.on_loss_burst(vec![(3, 1300), (4, 1300)]));
+        for i in 0..BLACK_HOLE_THRESHOLD - 1 {
+            assert!(!detector.on_loss_burst(vec![(6 + u64::from(i), 1300), (100, 0)]));
+        }
+        // Any large packet getting through resets detection
+        detector.on_acked(10, 1300);
+        for i in 0..BLACK_HOLE_THRESHOLD - 1 {
+            assert!(!detector.on_loss_burst(vec![(11 + u64::from(i), 1300)]));
+        }
+        assert!(detector.on_loss_burst(vec![(20, 1300)]));
+    }
+}
@@ -31,6 +31,16 @@ impl SendBuffer {
         Self::default()
     }
 
+    /// Reset to the state of a new buffer, keeping allocated storage
+    pub fn clear(&mut self) {
+        self.unacked_segments.clear();
+        self.unacked_len = 0;
+        self.offset = 0;
+        self.unsent = 0;
+        self.acks = RangeSet::new();
+        self.retransmits = RangeSet::new();
+    }
+
     /// Append application data to the end of the stream
     pub fn write(&mut self, data: Bytes) {
         self.unacked_len += data.len();
@@ -41,13 +51,13 @@ impl SendBuffer {
     /// Discard a range of acknowledged stream data
     pub fn ack(&mut self, mut range: Range<u64>) {
         // Clamp the range to data which is still tracked
-        let base_offset = self.offset - self.unacked_len as u64;
+        let base_offset = self.unacked_offset();
         range.start = base_offset.max(range.start);
         range.end = base_offset.max(range.end);
 
         self.acks.insert(range);
 
-        while self.acks.min() == Some(self.offset - self.unacked_len as u64) {
+        while self.acks.min() == Some(self.unacked_offset()) {
             let prefix = self.acks.pop_min().unwrap();
             let mut to_advance = (prefix.end - prefix.start) as usize;
 
@@ -172,6 +182,11 @@ impl SendBuffer {
         self.offset
     }
 
+    /// First stream offset that hasn't been acknowledged
+    pub fn unacked_offset(&self) -> u64 {
+        self.offset - self.unacked_len as u64
+    }
+
     /// Whether all sent data has been acknowledged
     pub fn is_fully_acked(&self) -> bool {
         self.unacked_len == 0
@@ -184,6 +199,11 @@ impl SendBuffer {
         self.unsent != self.offset || !self.retransmits.is_empty()
     }
 
+    /// Amount of new data that hasn't been sent yet
+    pub fn unsent(&self) -> u64 {
+        self.offset - self.unsent
+    }
+
     /// Compute the amount of data that hasn't been acknowledged
     pub fn unacked(&self) -> u64 {
         self.unacked_len as u64 - self.acks.iter().map(|x| x.end - x.start).sum::<u64>()
@@ -22,6 +22,8 @@ where
     pub(crate) dedup: Dedup,
     /// Highest received packet number
     pub(crate) rx_packet: u64,
+    /// When the packet numbered `rx_packet` was received
+    pub(crate) rx_packet_time: Instant,
 
     /// Data to send
     pub(crate) pending: Retransmits,
@@ -77,6 +79,7 @@ where
             crypto: None,
             dedup: Dedup::new(),
             rx_packet: 0,
+            rx_packet_time: now,
 
             pending: Retransmits::default(),
             pending_acks: ArrayRangeSet::new(),
@@ -126,6 +126,123 @@ pub struct PathStats {
     pub cwnd: u64,
     /// Congestion events on the connection
     pub congestion_events: u64,
+    /// Copies of the client's first flight sent, see `TransportConfig::initial_duplicates`
+    pub initial_duplicates: u64,
+    /// Whether the server's first acknowledgement omitted the original first flight, so that only
+    /// the copies got the handshake going
+    pub initial_duplicate_needed: bool,
+}
+
+/// Distribution of latency samples
+///
+/// Samples are counted in buckets of exponentially increasing width: bucket 0 counts samples under
+/// a microsecond, and bucket `i` those of at least 2<sup>i-1</sup> but less than 2<sup>i</sup>
+/// microseconds. The last bucket also counts all larger samples.
+#[derive(Debug, Default, Copy, Clone)]
+#[non_exhaustive]
+pub struct LatencyHistogram {
+    /// Number of samples in each bucket
+    pub buckets: [u64; LATENCY_BUCKETS],
+    /// Total number of samples
+    pub count: u64,
+    /// Sum of all samples
+    pub total: Duration,
+    /// Largest sample
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    /// Exclusive upper bound of the samples counted in bucket `i`, or `None` for the last bucket
+    pub fn bucket_bound(i: usize) -> Option<Duration> {
+        match i {
+            _ if i + 1 >= LATENCY_BUCKETS => None,
+            _ => Some(Duration::from_micros(1 << i)),
+        }
+    }
+
+    /// Mean of all samples, if any
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count {
+            0 => None,
+            n => Some(Duration::from_nanos(
+                (self.total.as_nanos() / n as u128) as u64,
+            )),
+        }
+    }
+
+    /// Upper bound on the `q`-quantile of the samples, if any, e.g. `0.99` for the 99th percentile
+    ///
+    /// Accurate to within a factor of two, since only bucket boundaries and the largest sample
+    /// are known.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.max(0.0).min(1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(Self::bucket_bound(i).map_or(self.max, |x| x.min(self.max)));
+            }
+        }
+        Some(self.max)
+    }
+
+    pub(crate) fn record(&mut self, sample: Duration) {
+        let micros = sample.as_micros();
+        let i = (128 - micros.leading_zeros()) as usize;
+        self.buckets[i.min(LATENCY_BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += sample;
+        self.max = self.max.max(sample);
+    }
+}
+
+/// Number of buckets in a `LatencyHistogram`
+const LATENCY_BUCKETS: usize = 32;
+
+/// Latency of stream data between the application and the network
+#[derive(Debug, Default, Copy, Clone)]
+#[non_exhaustive]
+pub struct StreamLatencyStats {
+    /// Time from data being written to a stream until the peer acknowledged all of it
+    ///
+    /// Data is considered written when the connection is next polled for transmission.
+    pub write_to_ack: LatencyHistogram,
+    /// Time from stream data being received until the application read it
+    ///
+    /// Only reads that supply the current time, as quinn's do, are measured.
+    pub receipt_to_read: LatencyHistogram,
+}
+
+/// Statistics about a single outgoing stream
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct SendStreamStats {
+    /// Bytes of stream data transmitted, including retransmissions
+    pub bytes_sent: u64,
+    /// Bytes of stream data that were transmitted more than once
+    pub bytes_retransmitted: u64,
+    /// Time from data first being written until the peer acknowledged the whole stream
+    ///
+    /// `None` until the stream has been finished and fully acknowledged.
+    pub duration: Option<Duration>,
+}
+
+/// Statistics about a single incoming stream
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct RecvStreamStats {
+    /// Bytes of stream data received, including duplicates
+    pub bytes_received: u64,
+    /// Bytes of stream data read by the application
+    pub bytes_read: u64,
+    /// Time from data first being received until the application read the end of the stream
+    ///
+    /// `None` until the end of the stream has been read by a read that supplied the current
+    /// time, as quinn's do.
+    pub duration: Option<Duration>,
 }
 
 /// Connection statistics
@@ -142,4 +259,6 @@ pub struct ConnectionStats {
     pub frame_rx: FrameStats,
     /// Statistics related to the current transmission path
     pub path: PathStats,
+    /// Latency of stream data
+    pub stream_latency: StreamLatencyStats,
 }
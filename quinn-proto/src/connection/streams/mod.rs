@@ -1,6 +1,7 @@
 use std::{
     cell::RefCell,
     collections::{hash_map, BinaryHeap, VecDeque},
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
@@ -8,7 +9,8 @@ use thiserror::Error;
 use tracing::trace;
 
 use super::spaces::{Retransmits, ThinRetransmits};
-use crate::{frame, Dir, StreamId, VarInt};
+use super::stats::{RecvStreamStats, SendStreamStats};
+use crate::{frame, Dir, StreamId, TapDirection, VarInt};
 
 mod recv;
 use recv::Recv;
@@ -57,7 +59,9 @@ impl<'a> Streams<'a> {
     ///
     /// Returns `None` if there are no new incoming streams for this connection.
     pub fn accept(&mut self, dir: Dir) -> Option<StreamId> {
-        if self.state.next_remote[dir as usize] == self.state.next_reported_remote[dir as usize] {
+        if self.state.defer_remote
+            || self.state.next_remote[dir as usize] == self.state.next_reported_remote[dir as usize]
+        {
             return None;
         }
 
@@ -106,7 +110,29 @@ impl<'a> RecvStream<'a> {
     /// reads, but ordered reads on streams that have seen previous unordered reads will return
     /// `ReadError::IllegalOrderedRead`.
     pub fn read(&mut self, ordered: bool) -> Result<Chunks, ReadableError> {
-        Chunks::new(self.id, ordered, self.state, self.pending)
+        Chunks::new(self.id, ordered, self.state, self.pending, None)
+    }
+
+    /// Read from the given recv stream, measuring how long the data waited to be read
+    ///
+    /// Behaves like [`read()`](Self::read), additionally recording the time between the data
+    /// being received and `now` in [`StreamLatencyStats::receipt_to_read`].
+    ///
+    /// [`StreamLatencyStats::receipt_to_read`]: crate::StreamLatencyStats::receipt_to_read
+    pub fn read_at(&mut self, ordered: bool, now: Instant) -> Result<Chunks, ReadableError> {
+        Chunks::new(self.id, ordered, self.state, self.pending, Some(now))
+    }
+
+    /// Whether an ordered read would yield data, the end of the stream, or an error, rather than
+    /// being blocked
+    ///
+    /// Allows waiting for data without consuming it: while this is `false`, a
+    /// [`StreamEvent::Readable`] is emitted once more data arrives.
+    pub fn is_readable(&self) -> Result<bool, UnknownStream> {
+        match self.state.recv.get(&self.id) {
+            Some(s) if !s.stopped => Ok(s.is_readable()),
+            _ => Err(UnknownStream { _private: () }),
+        }
     }
 
     /// Stop accepting data on the given receive stream
@@ -132,7 +158,8 @@ impl<'a> RecvStream<'a> {
         // connection-level flow control to account for discarded data. Otherwise, we can discard
         // state immediately.
         if !stream.receiving_unknown_size() {
-            entry.remove();
+            let stream = entry.remove();
+            self.state.recycle_recv(stream);
             self.state.stream_freed(self.id, StreamHalf::Recv);
         }
 
@@ -142,6 +169,35 @@ impl<'a> RecvStream<'a> {
 
         Ok(())
     }
+
+    /// Statistics about the stream so far
+    ///
+    /// The stream's final statistics, once its end has been read, are available from
+    /// [`Chunks::stats()`].
+    pub fn stats(&self) -> Result<RecvStreamStats, UnknownStream> {
+        match self.state.recv.get(&self.id) {
+            Some(s) => Ok(s.stats),
+            None => Err(UnknownStream { _private: () }),
+        }
+    }
+
+    /// Allow the peer to send `bytes` more data on the given receive stream
+    ///
+    /// Only has an effect on connections using `CreditPolicy::Manual`, where this is the only way
+    /// for stream-level flow control credit to be returned to the peer.
+    pub fn credit(&mut self, bytes: u64) -> Result<(), UnknownStream> {
+        let window = self.state.stream_window(self.id.dir());
+        let policy = self.state.credit_policy;
+        let stream = match self.state.recv.get_mut(&self.id) {
+            Some(s) => s,
+            None => return Err(UnknownStream { _private: () }),
+        };
+        stream.credit(bytes)?;
+        if stream.max_stream_data(window, policy).1.should_transmit() {
+            self.pending.max_stream_data.insert(self.id);
+        }
+        Ok(())
+    }
 }
 
 /// Access to streams
@@ -210,16 +266,64 @@ impl<'a> SendStream<'a> {
         }
 
         let was_pending = stream.is_pending();
+        let start = stream.pending.offset();
+        let was_stamped = stream.stamped_offset() == start;
         let written = stream.write(source, limit)?;
+        if let Some(ref tap) = self.state.tap {
+            let end = start + written.bytes as u64;
+            let mut offset = start;
+            while offset < end {
+                let data = stream.pending.get(offset..end);
+                tap.stream_data(self.id, TapDirection::Sent, offset, data);
+                offset += data.len() as u64;
+            }
+        }
         self.state.data_sent += written.bytes as u64;
         self.state.unacked_data += written.bytes as u64;
         trace!(stream = %self.id, "wrote {} bytes", written.bytes);
+        if was_stamped && written.bytes > 0 {
+            self.state.unstamped.push(self.id);
+        }
         if !was_pending {
-            push_pending(&mut self.state.pending, self.id, stream.priority);
+            if stream.should_hold() {
+                self.state.held.push((self.id, None));
+            } else {
+                push_pending(&mut self.state.pending, self.id, stream.priority);
+            }
         }
         Ok(written)
     }
 
+    /// Whether a write would accept data, or fail outright, rather than being blocked
+    ///
+    /// If not, a [`StreamEvent::Writable`] is emitted once flow control permits writing again.
+    pub fn is_writable(&mut self) -> Result<bool, UnknownStream> {
+        if self.conn_state.is_closed() {
+            return Ok(false);
+        }
+        let limit = self.state.write_limit();
+        let stream = self
+            .state
+            .send
+            .get_mut(&self.id)
+            .ok_or(UnknownStream { _private: () })?;
+        if !stream.is_writable() || stream.stop_reason.is_some() {
+            return Ok(true);
+        }
+        if stream.max_data == stream.offset() {
+            // Unblocked by MAX_STREAM_DATA
+            return Ok(false);
+        }
+        if limit == 0 {
+            if !stream.connection_blocked {
+                stream.connection_blocked = true;
+                self.state.connection_blocked.push(self.id);
+            }
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
     /// Check if this stream was stopped, get the reason if it was
     pub fn stopped(&mut self) -> Result<Option<VarInt>, UnknownStream> {
         match self.state.send.get(&self.id) {
@@ -289,6 +393,42 @@ impl<'a> SendStream<'a> {
         Ok(())
     }
 
+    /// Set how long data may go unacknowledged before the stream is abandoned
+    ///
+    /// See [`Reliability`] for details. Applies to data that hasn't yet been considered for
+    /// transmission.
+    ///
+    /// # Panics
+    /// - when applied to a receive stream
+    pub fn set_reliability(&mut self, reliability: Reliability) -> Result<(), UnknownStream> {
+        let stream = match self.state.send.get_mut(&self.id) {
+            Some(ss) => ss,
+            None => return Err(UnknownStream { _private: () }),
+        };
+
+        if stream.reliability == Reliability::Reliable && reliability != Reliability::Reliable {
+            self.state.deadline_streams.push(self.id);
+        }
+        stream.reliability = reliability;
+        Ok(())
+    }
+
+    /// Set whether small writes may be held back so they can share packets
+    ///
+    /// See [`Coalescing`] for details. Disabled by default.
+    ///
+    /// # Panics
+    /// - when applied to a receive stream
+    pub fn set_coalescing(&mut self, coalescing: Option<Coalescing>) -> Result<(), UnknownStream> {
+        let stream = match self.state.send.get_mut(&self.id) {
+            Some(ss) => ss,
+            None => return Err(UnknownStream { _private: () }),
+        };
+
+        stream.coalescing = coalescing;
+        Ok(())
+    }
+
     /// Get the priority of a stream
     ///
     /// # Panics
@@ -301,6 +441,21 @@ impl<'a> SendStream<'a> {
 
         Ok(stream.priority)
     }
+
+    /// Statistics about the stream so far
+    ///
+    /// Once the stream is finished and fully acknowledged, its final statistics remain available
+    /// from when the [`StreamEvent::Finished`] event is yielded until the next call to
+    /// [`Connection::poll()`](crate::Connection::poll).
+    pub fn stats(&self) -> Result<SendStreamStats, UnknownStream> {
+        if let Some(stream) = self.state.send.get(&self.id) {
+            return Ok(stream.stats);
+        }
+        match self.state.last_finished {
+            Some((id, stats)) if id == self.id => Ok(stats),
+            _ => Err(UnknownStream { _private: () }),
+        }
+    }
 }
 
 fn push_pending(pending: &mut BinaryHeap<PendingLevel>, id: StreamId, priority: i32) {
@@ -365,6 +520,9 @@ pub enum StreamEvent {
         id: StreamId,
     },
     /// A finished stream has been fully acknowledged or stopped
+    ///
+    /// The stream's final statistics can be obtained from [`SendStream::stats()`] until the next
+    /// call to [`Connection::poll()`](crate::Connection::poll).
     Finished {
         /// Which stream has been finished
         id: StreamId,
@@ -381,6 +539,70 @@ pub enum StreamEvent {
         /// Directionality for which streams are newly available
         dir: Dir,
     },
+    /// Data on a partially reliable stream outlived its deadline, so the stream was reset
+    ///
+    /// See [`Reliability::Deadline`].
+    Expired {
+        /// Which stream has been reset
+        id: StreamId,
+    },
+}
+
+/// Whether data written to a stream is delivered reliably
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Reliability {
+    /// Retransmit data until it's acknowledged, the default
+    Reliable,
+    /// Abandon the stream once any data written to it goes unacknowledged for this long
+    ///
+    /// When the deadline passes, the stream is reset with error code 0 and
+    /// [`StreamEvent::Expired`] is raised. This provides partially reliable delivery for data
+    /// that's worthless once stale, such as media frames, each sent on a stream of its own.
+    Deadline(Duration),
+}
+
+impl Default for Reliability {
+    fn default() -> Self {
+        Self::Reliable
+    }
+}
+
+/// When small writes to a stream are held back so that more data can share their packets
+///
+/// Trades a little latency for far fewer packets when an application makes many small writes in
+/// quick succession, as chatty RPC protocols tend to. Data is held back until at least
+/// `min_bytes` of it is waiting to be sent, the stream is finished, or it has waited for `delay`,
+/// whichever comes first.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Coalescing {
+    /// Longest time written data is held back for
+    pub delay: Duration,
+    /// Amount of unsent data that is sent without delay
+    pub min_bytes: u64,
+}
+
+/// A stream holding data that would be lost if the connection closed
+///
+/// See [`Connection::drain_streams()`](crate::Connection::drain_streams).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct DrainedStream {
+    /// The stream's identity
+    pub id: StreamId,
+    /// Bytes written by the application that the peer hasn't acknowledged
+    pub unacked: u64,
+    /// Bytes received from the peer that the application hasn't read
+    pub unread: u64,
+}
+
+impl DrainedStream {
+    fn new(id: StreamId) -> Self {
+        Self {
+            id,
+            unacked: 0,
+            unread: 0,
+        }
+    }
 }
 
 /// Indicates whether a frame needs to be transmitted
@@ -1,20 +1,29 @@
-use std::collections::hash_map::Entry;
+use std::collections::{hash_map::Entry, VecDeque};
 use std::mem;
+use std::time::Instant;
 
 use thiserror::Error;
 use tracing::debug;
 
 use super::{Retransmits, ShouldTransmit, StreamHalf, StreamId, StreamsState, UnknownStream};
 use crate::connection::assembler::{Assembler, Chunk, IllegalOrderedRead};
-use crate::{frame, Dir, TransportError, VarInt};
+use crate::connection::stats::{LatencyHistogram, RecvStreamStats};
+use crate::{frame, CreditPolicy, Dir, TapDirection, TransportError, VarInt};
 
 #[derive(Debug, Default)]
 pub(super) struct Recv {
     state: RecvState,
     pub(super) assembler: Assembler,
     sent_max_stream_data: u64,
+    /// Limit on incoming data granted by the application under `CreditPolicy::Manual`
+    granted: u64,
     pub(super) end: u64,
     pub(super) stopped: bool,
+    /// End offsets of received data, and when it arrived
+    arrivals: VecDeque<(u64, Instant)>,
+    /// When data was first received
+    first_arrival: Option<Instant>,
+    pub(super) stats: RecvStreamStats,
 }
 
 impl Recv {
@@ -23,11 +32,28 @@ impl Recv {
             state: RecvState::default(),
             assembler: Assembler::new(),
             sent_max_stream_data: initial_max_data,
+            granted: initial_max_data,
             end: 0,
             stopped: false,
+            arrivals: VecDeque::new(),
+            first_arrival: None,
+            stats: RecvStreamStats::default(),
         }
     }
 
+    /// Reset to the state of a new stream, keeping allocated storage
+    pub(super) fn reuse(&mut self, initial_max_data: u64) {
+        let mut assembler = mem::take(&mut self.assembler);
+        assembler.reuse();
+        let mut arrivals = mem::take(&mut self.arrivals);
+        arrivals.clear();
+        *self = Self {
+            assembler,
+            arrivals,
+            ..Self::new(initial_max_data)
+        };
+    }
+
     /// Process a STREAM frame
     ///
     /// Return value is `(number_of_new_bytes_ingested, stream_is_closed)`
@@ -37,6 +63,7 @@ impl Recv {
         payload_len: usize,
         received: u64,
         max_data: u64,
+        now: Instant,
     ) -> Result<(u64, bool), TransportError> {
         let end = frame.offset + frame.data.len() as u64;
         if end >= 2u64.pow(62) {
@@ -53,6 +80,8 @@ impl Recv {
         }
 
         let new_bytes = self.credit_consumed_by(end, received, max_data)?;
+        self.stats.bytes_received += frame.data.len() as u64;
+        self.first_arrival.get_or_insert(now);
 
         // Stopped streams don't need to wait for the actual data, they just need to know
         // how much there was.
@@ -62,6 +91,9 @@ impl Recv {
             }
         }
 
+        if end > self.end && !self.stopped {
+            self.record_arrival(end, now);
+        }
         self.end = self.end.max(end);
         if !self.stopped {
             self.assembler.insert(frame.offset, frame.data, payload_len);
@@ -72,6 +104,30 @@ impl Recv {
         Ok((new_bytes, frame.fin && self.stopped))
     }
 
+    /// Note that data up to `end` arrived at `now`
+    ///
+    /// Data arriving together with the previous data, or once `MAX_ARRIVALS` entries are waiting to
+    /// be read, is merged into the latest entry, so that a peer sending many small frames can't
+    /// grow the record without bound. Merged data counts as having arrived with the entry.
+    fn record_arrival(&mut self, end: u64, now: Instant) {
+        let full = self.arrivals.len() >= MAX_ARRIVALS;
+        match self.arrivals.back_mut() {
+            Some(last) if full || last.1 == now => last.0 = end,
+            _ => self.arrivals.push_back((end, now)),
+        }
+    }
+
+    /// Whether an ordered read would yield anything rather than being blocked
+    pub(super) fn is_readable(&self) -> bool {
+        match self.state {
+            RecvState::ResetRecvd { .. } => true,
+            RecvState::Recv { size } => {
+                size == Some(self.end) && self.assembler.bytes_read() == self.end
+                    || self.assembler.is_readable()
+            }
+        }
+    }
+
     pub(super) fn stop(&mut self) -> Result<(u64, ShouldTransmit), UnknownStream> {
         if self.stopped {
             return Err(UnknownStream { _private: () });
@@ -79,6 +135,7 @@ impl Recv {
 
         self.stopped = true;
         self.assembler.clear();
+        self.arrivals.clear();
         // Issue flow control credit for unread data
         let read_credits = self.end - self.assembler.bytes_read();
         // This may send a spurious STOP_SENDING if we've already received all data, but it's a bit
@@ -88,6 +145,22 @@ impl Recv {
         Ok((read_credits, ShouldTransmit(self.is_receiving())))
     }
 
+    /// Record how long data that has now been read spent waiting to be read
+    ///
+    /// Data is only forgotten, not recorded, if the time of the read isn't known.
+    fn record_read(&mut self, now: Option<Instant>, latency: &mut LatencyHistogram) {
+        let read = self.assembler.bytes_read();
+        while let Some(&(end, received)) = self.arrivals.front() {
+            if end > read {
+                break;
+            }
+            if let Some(now) = now {
+                latency.record(now.saturating_duration_since(received));
+            }
+            self.arrivals.pop_front();
+        }
+    }
+
     /// Returns the window that should be advertised in a `MAX_STREAM_DATA` frame
     ///
     /// The method returns a tuple which consists of the window that should be
@@ -95,8 +168,15 @@ impl Recv {
     /// transmission of the value is recommended. If the boolean value is
     /// `false` the new window should only be transmitted if a previous transmission
     /// had failed.
-    pub(super) fn max_stream_data(&mut self, stream_receive_window: u64) -> (u64, ShouldTransmit) {
-        let max_stream_data = self.assembler.bytes_read() + stream_receive_window;
+    pub(super) fn max_stream_data(
+        &mut self,
+        stream_receive_window: u64,
+        policy: CreditPolicy,
+    ) -> (u64, ShouldTransmit) {
+        let max_stream_data = match policy {
+            CreditPolicy::Manual => self.granted,
+            _ => self.assembler.bytes_read() + stream_receive_window,
+        };
 
         // Only announce a window update if it's significant enough
         // to make it worthwhile sending a MAX_STREAM_DATA frame.
@@ -106,10 +186,23 @@ impl Recv {
         // smaller than `stream_receive_window` in order to make sure the stream
         // does not get stuck.
         let diff = max_stream_data - self.sent_max_stream_data;
-        let transmit = self.receiving_unknown_size() && diff >= (stream_receive_window / 8);
+        let transmit =
+            self.receiving_unknown_size() && diff >= policy.threshold(stream_receive_window);
         (max_stream_data, ShouldTransmit(transmit))
     }
 
+    /// Allow the peer to send `bytes` more data under `CreditPolicy::Manual`
+    pub(super) fn credit(&mut self, bytes: u64) -> Result<(), UnknownStream> {
+        if self.stopped {
+            return Err(UnknownStream { _private: () });
+        }
+        self.granted = self
+            .granted
+            .saturating_add(bytes)
+            .min(VarInt::MAX.into_inner());
+        Ok(())
+    }
+
     /// Records that a `MAX_STREAM_DATA` announcing a certain window was sent
     ///
     /// This will suppress enqueuing further `MAX_STREAM_DATA` frames unless
@@ -198,6 +291,9 @@ impl Recv {
     }
 }
 
+/// Maximum number of distinct arrivals tracked per stream for the receipt-to-read latency
+const MAX_ARRIVALS: usize = 64;
+
 /// Chunks
 pub struct Chunks<'a> {
     id: StreamId,
@@ -206,6 +302,8 @@ pub struct Chunks<'a> {
     pending: &'a mut Retransmits,
     state: ChunksState,
     read: u64,
+    /// When the data is being read, if known
+    now: Option<Instant>,
 }
 
 impl<'a> Chunks<'a> {
@@ -214,6 +312,7 @@ impl<'a> Chunks<'a> {
         ordered: bool,
         streams: &'a mut StreamsState,
         pending: &'a mut Retransmits,
+        now: Option<Instant>,
     ) -> Result<Self, ReadableError> {
         let entry = match streams.recv.entry(id) {
             Entry::Occupied(entry) => entry,
@@ -233,6 +332,7 @@ impl<'a> Chunks<'a> {
             pending,
             state: ChunksState::Readable(recv),
             read: 0,
+            now,
         })
     }
 
@@ -245,7 +345,7 @@ impl<'a> Chunks<'a> {
             ChunksState::Reset(error_code) => {
                 return Err(ReadError::Reset(error_code));
             }
-            ChunksState::Finished => {
+            ChunksState::Finished(_) => {
                 return Ok(None);
             }
             ChunksState::Finalized => panic!("must not call next() after finalize()"),
@@ -253,20 +353,35 @@ impl<'a> Chunks<'a> {
 
         if let Some(chunk) = rs.assembler.read(max_length, self.ordered) {
             self.read += chunk.bytes.len() as u64;
+            rs.stats.bytes_read += chunk.bytes.len() as u64;
+            rs.record_read(self.now, &mut self.streams.latency.receipt_to_read);
+            if let Some(ref tap) = self.streams.tap {
+                tap.stream_data(self.id, TapDirection::Received, chunk.offset, &chunk.bytes);
+            }
             return Ok(Some(chunk));
         }
 
         match rs.state {
             RecvState::ResetRecvd { error_code, .. } => {
                 debug_assert_eq!(self.read, 0, "reset streams have empty buffers");
+                let state = mem::replace(&mut self.state, ChunksState::Reset(error_code));
+                if let ChunksState::Readable(rs) = state {
+                    self.streams.recycle_recv(rs);
+                }
                 self.streams.stream_freed(self.id, StreamHalf::Recv);
-                self.state = ChunksState::Reset(error_code);
                 Err(ReadError::Reset(error_code))
             }
             RecvState::Recv { size } => {
                 if size == Some(rs.end) && rs.assembler.bytes_read() == rs.end {
+                    if let (Some(now), Some(first)) = (self.now, rs.first_arrival) {
+                        rs.stats.duration = Some(now.saturating_duration_since(first));
+                    }
+                    let stats = rs.stats;
+                    let state = mem::replace(&mut self.state, ChunksState::Finished(stats));
+                    if let ChunksState::Readable(rs) = state {
+                        self.streams.recycle_recv(rs);
+                    }
                     self.streams.stream_freed(self.id, StreamHalf::Recv);
-                    self.state = ChunksState::Finished;
                     Ok(None)
                 } else {
                     // We don't need a distinct `ChunksState` variant for a blocked stream because
@@ -279,6 +394,19 @@ impl<'a> Chunks<'a> {
         }
     }
 
+    /// Statistics about the stream being read
+    ///
+    /// Unlike [`RecvStream::stats()`](crate::RecvStream::stats), this remains available after the
+    /// end of the stream has been read, yielding the stream's final statistics. Returns `None` if
+    /// the stream was reset.
+    pub fn stats(&self) -> Option<RecvStreamStats> {
+        match self.state {
+            ChunksState::Readable(ref rs) => Some(rs.stats),
+            ChunksState::Finished(stats) => Some(stats),
+            ChunksState::Reset(_) | ChunksState::Finalized => None,
+        }
+    }
+
     /// Finalize
     pub fn finalize(mut self) -> ShouldTransmit {
         self.finalize_inner(false)
@@ -296,9 +424,11 @@ impl<'a> Chunks<'a> {
         }
 
         let mut should_transmit = false;
-        // We issue additional stream ID credit iff a remotely-initiated stream stream is finished or reset
-        if matches!(state, ChunksState::Finished | ChunksState::Reset(_))
+        // We issue additional stream ID credit iff a remotely-initiated stream stream is finished or
+        // reset, and enough has accumulated
+        if matches!(state, ChunksState::Finished(_) | ChunksState::Reset(_))
             && self.streams.side != self.id.initiator()
+            && self.streams.max_streams_due(self.id.dir())
         {
             match self.id.dir() {
                 Dir::Uni => self.pending.max_uni_stream_id = true,
@@ -309,7 +439,10 @@ impl<'a> Chunks<'a> {
 
         // If the stream hasn't finished, we may need to issue stream-level flow control credit
         if let ChunksState::Readable(mut rs) = state {
-            let (_, max_stream_data) = rs.max_stream_data(self.streams.stream_receive_window);
+            let (_, max_stream_data) = rs.max_stream_data(
+                self.streams.stream_window(self.id.dir()),
+                self.streams.credit_policy,
+            );
             should_transmit |= max_stream_data.0;
             if max_stream_data.0 {
                 self.pending.max_stream_data.insert(self.id);
@@ -335,7 +468,8 @@ impl<'a> Drop for Chunks<'a> {
 enum ChunksState {
     Readable(Recv),
     Reset(VarInt),
-    Finished,
+    /// All data was read, with the stream's final statistics
+    Finished(RecvStreamStats),
     Finalized,
 }
 
@@ -386,3 +520,35 @@ impl Default for RecvState {
         RecvState::Recv { size: None }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::Side;
+
+    #[test]
+    fn arrivals_bounded() {
+        let mut recv = Recv::new(1024 * 1024);
+        let start = Instant::now();
+        // Two 1-byte frames at a time
+        for i in 0..2 * MAX_ARRIVALS as u64 {
+            for offset in 2 * i..2 * i + 2 {
+                let frame = frame::Stream {
+                    id: StreamId::new(Side::Client, Dir::Uni, 0),
+                    offset,
+                    fin: false,
+                    data: Bytes::from_static(&[0]),
+                };
+                let now = start + Duration::from_millis(i);
+                recv.ingest(frame, 1, offset, 1024 * 1024, now).unwrap();
+            }
+        }
+        assert_eq!(recv.arrivals.len(), MAX_ARRIVALS);
+        assert_eq!(recv.arrivals[1], (4, start + Duration::from_millis(1)));
+        assert_eq!(recv.arrivals.back().unwrap().0, 4 * MAX_ARRIVALS as u64);
+    }
+}
@@ -1,7 +1,16 @@
+use std::{collections::VecDeque, mem, time::Instant};
+
 use bytes::Bytes;
 use thiserror::Error;
 
-use crate::{connection::send_buffer::SendBuffer, frame, VarInt};
+use super::{Coalescing, Reliability};
+use crate::{
+    connection::{
+        send_buffer::SendBuffer,
+        stats::{LatencyHistogram, SendStreamStats},
+    },
+    frame, VarInt,
+};
 
 #[derive(Debug)]
 pub(super) struct Send {
@@ -15,6 +24,15 @@ pub(super) struct Send {
     pub(super) connection_blocked: bool,
     /// The reason the peer wants us to stop, if `STOP_SENDING` was received
     pub(super) stop_reason: Option<VarInt>,
+    pub(super) reliability: Reliability,
+    /// End offsets of written data, and when the stream expires if they aren't acknowledged
+    pub(super) deadlines: VecDeque<(u64, Instant)>,
+    pub(super) coalescing: Option<Coalescing>,
+    /// End offsets of written data, and when it was written
+    pub(super) write_times: VecDeque<(u64, Instant)>,
+    /// When data was first written, as stamped by `stamp_writes`
+    first_write: Option<Instant>,
+    pub(super) stats: SendStreamStats,
 }
 
 impl Send {
@@ -27,9 +45,31 @@ impl Send {
             fin_pending: false,
             connection_blocked: false,
             stop_reason: None,
+            reliability: Reliability::Reliable,
+            deadlines: VecDeque::new(),
+            coalescing: None,
+            write_times: VecDeque::new(),
+            first_write: None,
+            stats: SendStreamStats::default(),
         }
     }
 
+    /// Reset to the state of a new stream, keeping allocated storage
+    pub(super) fn reuse(&mut self, max_data: VarInt) {
+        let mut pending = mem::take(&mut self.pending);
+        pending.clear();
+        let mut deadlines = mem::take(&mut self.deadlines);
+        deadlines.clear();
+        let mut write_times = mem::take(&mut self.write_times);
+        write_times.clear();
+        *self = Self {
+            pending,
+            deadlines,
+            write_times,
+            ..Self::new(max_data)
+        };
+    }
+
     /// Whether the stream has been reset
     pub(super) fn is_reset(&self) -> bool {
         matches!(self.state, SendState::ResetSent { .. })
@@ -83,8 +123,71 @@ impl Send {
         Ok(result)
     }
 
+    /// Track the age of unacknowledged data according to the stream's `Reliability`
+    ///
+    /// Data written since the last call is considered written at `now`. Returns the earliest time
+    /// at which outstanding data expires, if any.
+    pub(super) fn update_deadlines(&mut self, now: Instant) -> Option<Instant> {
+        let lifetime = match self.reliability {
+            Reliability::Reliable => {
+                self.deadlines.clear();
+                return None;
+            }
+            Reliability::Deadline(x) => x,
+        };
+        let acked = self.pending.unacked_offset();
+        while self
+            .deadlines
+            .front()
+            .map_or(false, |&(end, _)| end <= acked)
+        {
+            self.deadlines.pop_front();
+        }
+        let stamped = self.deadlines.back().map_or(acked, |&(end, _)| end);
+        if self.pending.offset() > stamped {
+            self.deadlines
+                .push_back((self.pending.offset(), now + lifetime));
+        }
+        self.deadlines.front().map(|&(_, deadline)| deadline)
+    }
+
+    /// Offset up to which written data has been stamped by `stamp_writes`
+    pub(super) fn stamped_offset(&self) -> u64 {
+        self.write_times
+            .back()
+            .map_or(self.pending.unacked_offset(), |&(end, _)| end)
+    }
+
+    /// Consider data written since the last call to have been written at `now`
+    pub(super) fn stamp_writes(&mut self, now: Instant) {
+        if self.pending.offset() > self.stamped_offset() {
+            self.write_times.push_back((self.pending.offset(), now));
+            self.first_write.get_or_insert(now);
+        }
+    }
+
+    /// Record how long newly acknowledged writes took to be acknowledged
+    pub(super) fn record_acked(&mut self, now: Instant, latency: &mut LatencyHistogram) {
+        let acked = self.pending.unacked_offset();
+        while let Some(&(end, written)) = self.write_times.front() {
+            if end > acked {
+                break;
+            }
+            latency.record(now.saturating_duration_since(written));
+            self.write_times.pop_front();
+        }
+    }
+
+    /// Record that the stream was fully acknowledged at `now`
+    pub(super) fn record_finished(&mut self, now: Instant) {
+        self.stats.duration = self
+            .first_write
+            .map(|written| now.saturating_duration_since(written));
+    }
+
     /// Update stream state due to a reset sent by the local application
     pub(super) fn reset(&mut self) {
+        self.write_times.clear();
         use SendState::*;
         if let DataSent { .. } | Ready = self.state {
             self.state = ResetSent;
@@ -130,6 +233,13 @@ impl Send {
         self.pending.has_unsent_data() || self.fin_pending
     }
 
+    /// Whether newly written data should be held back in the hope of more following
+    pub(super) fn should_hold(&self) -> bool {
+        self.coalescing.map_or(false, |c| {
+            !self.fin_pending && !self.is_reset() && self.pending.unsent() < c.min_bytes
+        })
+    }
+
     pub(super) fn is_writable(&self) -> bool {
         matches!(self.state, SendState::Ready)
     }
@@ -1,7 +1,9 @@
 use std::{
-    collections::{binary_heap::PeekMut, hash_map, BinaryHeap, VecDeque},
+    collections::{binary_heap::PeekMut, hash_map, BTreeMap, BinaryHeap, VecDeque},
     convert::TryFrom,
     mem,
+    sync::Arc,
+    time::Instant,
 };
 
 use bytes::BufMut;
@@ -9,15 +11,15 @@ use fxhash::FxHashMap;
 use tracing::{debug, trace};
 
 use super::{
-    push_pending, PendingLevel, Recv, Retransmits, Send, SendState, ShouldTransmit, StreamEvent,
-    StreamHalf, ThinRetransmits,
+    push_pending, DrainedStream, PendingLevel, Recv, Reliability, Retransmits, Send, SendState,
+    ShouldTransmit, StreamEvent, StreamHalf, ThinRetransmits,
 };
 use crate::{
     coding::BufMutExt,
-    connection::stats::FrameStats,
+    connection::stats::{FrameStats, SendStreamStats, StreamLatencyStats},
     frame::{self, FrameStruct, StreamMetaVec},
     transport_parameters::TransportParameters,
-    Dir, Side, StreamId, TransportError, VarInt, MAX_STREAM_COUNT,
+    CreditPolicy, Dir, Side, StreamId, Tap, TransportError, VarInt, MAX_STREAM_COUNT,
 };
 
 pub struct StreamsState {
@@ -46,10 +48,21 @@ pub struct StreamsState {
     pub(super) pending: BinaryHeap<PendingLevel>,
 
     events: VecDeque<StreamEvent>,
+    /// Final statistics of finished streams whose `Finished` event hasn't been yielded yet
+    finished: FxHashMap<StreamId, SendStreamStats>,
+    /// Final statistics of the stream whose `Finished` event was yielded by the last `poll`
+    pub(super) last_finished: Option<(StreamId, SendStreamStats)>,
     /// Streams blocked on connection-level flow control or stream window space
     ///
     /// Streams are only added to this list when a write fails.
     pub(super) connection_blocked: Vec<StreamId>,
+    /// Streams which may have been made partially reliable
+    pub(super) deadline_streams: Vec<StreamId>,
+    /// Streams with small writes held back from `pending`, and since when, once known
+    pub(super) held: Vec<(StreamId, Option<Instant>)>,
+    /// Streams with data written since the last call to `stamp_writes`
+    pub(super) unstamped: Vec<StreamId>,
+    pub(crate) latency: StreamLatencyStats,
     /// Connection-level flow control budget dictated by the peer
     pub(super) max_data: u64,
     /// The initial receive window
@@ -69,8 +82,25 @@ pub struct StreamsState {
     pub(super) send_window: u64,
     /// Configured upper bound for how much unacked data the peer can send us per stream
     pub(super) stream_receive_window: u64,
-    /// Whether the corresponding `max_remote` has increased
-    max_streams_dirty: [bool; 2],
+    /// Configured upper bound for how much unacked data the peer can send us per unidirectional
+    /// stream
+    uni_stream_receive_window: u64,
+    /// When to return stream-level flow control credit to the peer
+    pub(super) credit_policy: CreditPolicy,
+    /// When to return connection-level flow control credit to the peer
+    connection_credit_policy: CreditPolicy,
+    /// Values of `max_remote` last announced to the peer
+    sent_max_remote: [u64; 2],
+    /// Number of streams whose credit is withheld from the peer to be announced in one frame
+    max_streams_batch: [u64; 2],
+    /// State of closed streams, kept to be reused by new streams
+    free_send: Vec<Send>,
+    free_recv: Vec<Recv>,
+    /// Receives copies of stream data written and read by the application
+    pub(crate) tap: Option<Arc<dyn Tap>>,
+    /// Whether streams opened by the peer are withheld from the application, as they may carry
+    /// replayed 0-RTT data
+    pub(crate) defer_remote: bool,
 
     // Pertinent state from the TransportParameters supplied by the peer
     initial_max_stream_data_uni: VarInt,
@@ -86,6 +116,9 @@ impl StreamsState {
         send_window: u64,
         receive_window: VarInt,
         stream_receive_window: VarInt,
+        uni_stream_receive_window: VarInt,
+        credit_policy: CreditPolicy,
+        connection_credit_policy: CreditPolicy,
     ) -> Self {
         let mut this = Self {
             side,
@@ -100,7 +133,13 @@ impl StreamsState {
             send_streams: 0,
             pending: BinaryHeap::new(),
             events: VecDeque::new(),
+            finished: FxHashMap::default(),
+            last_finished: None,
             connection_blocked: Vec::new(),
+            deadline_streams: Vec::new(),
+            held: Vec::new(),
+            unstamped: Vec::new(),
+            latency: StreamLatencyStats::default(),
             max_data: 0,
             receive_window: receive_window.into(),
             local_max_data: receive_window.into(),
@@ -110,7 +149,18 @@ impl StreamsState {
             unacked_data: 0,
             send_window,
             stream_receive_window: stream_receive_window.into(),
-            max_streams_dirty: [false, false],
+            uni_stream_receive_window: uni_stream_receive_window.into(),
+            credit_policy,
+            connection_credit_policy,
+            sent_max_remote: [max_remote_bi.into(), max_remote_uni.into()],
+            max_streams_batch: [
+                max_streams_batch(max_remote_bi),
+                max_streams_batch(max_remote_uni),
+            ],
+            free_send: Vec::new(),
+            free_recv: Vec::new(),
+            tap: None,
+            defer_remote: false,
             initial_max_stream_data_uni: 0u32.into(),
             initial_max_stream_data_bidi_local: 0u32.into(),
             initial_max_stream_data_bidi_remote: 0u32.into(),
@@ -143,7 +193,6 @@ impl StreamsState {
         self.max_remote[dir as usize] += 1;
         let id = StreamId::new(!self.side, dir, self.max_remote[dir as usize] - 1);
         self.insert(true, id);
-        self.max_streams_dirty[dir as usize] = true;
     }
 
     pub fn zero_rtt_rejected(&mut self) {
@@ -153,9 +202,11 @@ impl StreamsState {
                 // We don't bother calling `stream_freed` here because we explicitly reset affected
                 // counters below.
                 let id = StreamId::new(self.side, dir, i);
-                self.send.remove(&id).unwrap();
+                let stream = self.send.remove(&id).unwrap();
+                self.recycle_send(stream);
                 if let Dir::Bi = dir {
-                    self.recv.remove(&id).unwrap();
+                    let stream = self.recv.remove(&id).unwrap();
+                    self.recycle_recv(stream);
                 }
             }
             self.next[dir as usize] = 0;
@@ -173,6 +224,7 @@ impl StreamsState {
         &mut self,
         frame: frame::Stream,
         payload_len: usize,
+        now: Instant,
     ) -> Result<ShouldTransmit, TransportError> {
         let stream = frame.id;
         self.validate_receive_id(stream).map_err(|e| {
@@ -193,8 +245,13 @@ impl StreamsState {
             return Ok(ShouldTransmit(false));
         }
 
-        let (new_bytes, closed) =
-            rs.ingest(frame, payload_len, self.data_recvd, self.local_max_data)?;
+        let (new_bytes, closed) = rs.ingest(
+            frame,
+            payload_len,
+            self.data_recvd,
+            self.local_max_data,
+            now,
+        )?;
         self.data_recvd = self.data_recvd.saturating_add(new_bytes);
 
         if !rs.stopped {
@@ -204,7 +261,9 @@ impl StreamsState {
 
         // Stopped streams become closed instantly on FIN, so check whether we need to clean up
         if closed {
-            self.recv.remove(&stream);
+            if let Some(rs) = self.recv.remove(&stream) {
+                self.recycle_recv(rs);
+            }
             self.stream_freed(stream, StreamHalf::Recv);
         }
 
@@ -252,7 +311,9 @@ impl StreamsState {
         let end = rs.end;
         if stopped {
             // Stopped streams should be disposed immediately on reset
-            self.recv.remove(&id);
+            if let Some(rs) = self.recv.remove(&id) {
+                self.recycle_recv(rs);
+            }
         }
         self.on_stream_frame(!stopped, id);
 
@@ -268,6 +329,41 @@ impl StreamsState {
         })
     }
 
+    /// Sum of end offsets of all receive streams
+    pub fn data_recvd(&self) -> u64 {
+        self.data_recvd
+    }
+
+    /// Number of streams the peer has opened over the connection's lifetime
+    pub fn remote_opened(&self) -> u64 {
+        self.next_remote[0] + self.next_remote[1]
+    }
+
+    /// Streams with written data the peer hasn't acknowledged or received data that hasn't been
+    /// read, in order of ID
+    pub fn drained(&self) -> Vec<DrainedStream> {
+        let mut streams = BTreeMap::new();
+        for (&id, stream) in &self.send {
+            let unacked = stream.pending.unacked();
+            if unacked > 0 {
+                streams
+                    .entry(id)
+                    .or_insert_with(|| DrainedStream::new(id))
+                    .unacked = unacked;
+            }
+        }
+        for (&id, stream) in &self.recv {
+            let unread = stream.assembler.unread();
+            if unread > 0 {
+                streams
+                    .entry(id)
+                    .or_insert_with(|| DrainedStream::new(id))
+                    .unread = unread;
+            }
+        }
+        streams.values().copied().collect()
+    }
+
     /// Process incoming `STOP_SENDING` frame
     pub fn received_stop_sending(&mut self, id: StreamId, error_code: VarInt) {
         let stream = match self.send.get_mut(&id) {
@@ -285,7 +381,8 @@ impl StreamsState {
             hash_map::Entry::Vacant(_) => {}
             hash_map::Entry::Occupied(e) => {
                 if let SendState::ResetSent = e.get().state {
-                    e.remove_entry();
+                    let stream = e.remove();
+                    self.recycle_send(stream);
                     self.stream_freed(id, StreamHalf::Send);
                 }
             }
@@ -377,6 +474,7 @@ impl StreamsState {
                 None => break,
             };
             pending.max_stream_data.remove(&id);
+            let window = self.stream_window(id.dir());
             let rs = match self.recv.get_mut(&id) {
                 Some(x) => x,
                 None => continue,
@@ -386,7 +484,7 @@ impl StreamsState {
             }
             retransmits.get_or_create().max_stream_data.insert(id);
 
-            let (max, _) = rs.max_stream_data(self.stream_receive_window);
+            let (max, _) = rs.max_stream_data(window, self.credit_policy);
             rs.record_sent_max_stream_data(max);
 
             trace!(stream = %id, max = max, "MAX_STREAM_DATA");
@@ -406,6 +504,7 @@ impl StreamsState {
             );
             buf.write(frame::Type::MAX_STREAMS_UNI);
             buf.write_var(self.max_remote[Dir::Uni as usize]);
+            self.sent_max_remote[Dir::Uni as usize] = self.max_remote[Dir::Uni as usize];
             stats.max_streams_uni += 1;
         }
 
@@ -419,6 +518,7 @@ impl StreamsState {
             );
             buf.write(frame::Type::MAX_STREAMS_BIDI);
             buf.write_var(self.max_remote[Dir::Bi as usize]);
+            self.sent_max_remote[Dir::Bi as usize] = self.max_remote[Dir::Bi as usize];
             stats.max_streams_bidi += 1;
         }
     }
@@ -465,7 +565,14 @@ impl StreamsState {
             // Now that we know the `StreamId`, we can better account for how many bytes
             // are required to encode it.
             let max_buf_size = max_buf_size - buf.len() - 1 - VarInt::size(id.into());
+            // Anything below the offset of not-yet-sent data is being retransmitted
+            let sent = stream.pending.offset() - stream.pending.unsent();
             let (offsets, encode_length) = stream.pending.poll_transmit(max_buf_size);
+            let len = offsets.end - offsets.start;
+            stream.stats.bytes_sent += len;
+            if offsets.start < sent {
+                stream.stats.bytes_retransmitted += len;
+            }
             let fin = offsets.end == stream.pending.offset()
                 && matches!(stream.state, SendState::DataSent { .. });
             if fin {
@@ -517,7 +624,7 @@ impl StreamsState {
         }
     }
 
-    pub fn received_ack_of(&mut self, frame: frame::StreamMeta) {
+    pub fn received_ack_of(&mut self, frame: frame::StreamMeta, now: Instant) {
         let mut entry = match self.send.entry(frame.id) {
             hash_map::Entry::Vacant(_) => return,
             hash_map::Entry::Occupied(e) => e,
@@ -529,16 +636,100 @@ impl StreamsState {
         }
         let id = frame.id;
         self.unacked_data -= frame.offsets.end - frame.offsets.start;
-        if !stream.ack(frame) {
+        let finished = stream.ack(frame);
+        stream.record_acked(now, &mut self.latency.write_to_ack);
+        if !finished {
             // The stream is unfinished or may still need retransmits
             return;
         }
 
-        entry.remove_entry();
+        stream.record_finished(now);
+        let stats = stream.stats;
+        let stream = entry.remove();
+        self.recycle_send(stream);
         self.stream_freed(id, StreamHalf::Send);
+        self.finished.insert(id, stats);
         self.events.push_back(StreamEvent::Finished { id });
     }
 
+    /// Reset partially reliable streams with data that outlived its deadline
+    ///
+    /// Returns the earliest time at which data on a remaining stream expires, if any.
+    pub fn expire_data(&mut self, now: Instant, pending: &mut Retransmits) -> Option<Instant> {
+        let mut next = None;
+        let mut i = 0;
+        while i < self.deadline_streams.len() {
+            let id = self.deadline_streams[i];
+            let stream = match self.send.get_mut(&id) {
+                Some(x) if !x.is_reset() => x,
+                _ => {
+                    self.deadline_streams.swap_remove(i);
+                    continue;
+                }
+            };
+            match stream.update_deadlines(now) {
+                Some(deadline) if deadline <= now => {
+                    debug!(stream = %id, "abandoning expired data");
+                    self.unacked_data -= stream.pending.unacked();
+                    stream.reset();
+                    pending.reset_stream.push((id, VarInt(0)));
+                    self.events.push_back(StreamEvent::Expired { id });
+                    self.deadline_streams.swap_remove(i);
+                }
+                Some(deadline) => {
+                    next = Some(next.map_or(deadline, |x: Instant| x.min(deadline)));
+                    i += 1;
+                }
+                None if stream.reliability == Reliability::Reliable => {
+                    self.deadline_streams.swap_remove(i);
+                }
+                None => i += 1,
+            }
+        }
+        next
+    }
+
+    /// Consider data written since the last call to have been written at `now`
+    pub fn stamp_writes(&mut self, now: Instant) {
+        for id in self.unstamped.drain(..) {
+            if let Some(stream) = self.send.get_mut(&id) {
+                stream.stamp_writes(now);
+            }
+        }
+    }
+
+    /// Queue held back streams for transmission once they've gathered or waited for enough data
+    ///
+    /// Data held back since the last call is considered written at `now`. Returns the earliest time
+    /// at which a remaining stream must be released, if any.
+    pub fn release_held(&mut self, now: Instant) -> Option<Instant> {
+        let mut next = None;
+        let mut i = 0;
+        while i < self.held.len() {
+            let (id, ref mut since) = self.held[i];
+            let since = *since.get_or_insert(now);
+            let stream = match self.send.get(&id) {
+                Some(x) => x,
+                None => {
+                    self.held.swap_remove(i);
+                    continue;
+                }
+            };
+            let release = match stream.coalescing {
+                Some(c) if stream.should_hold() => since + c.delay,
+                _ => now,
+            };
+            if release <= now {
+                push_pending(&mut self.pending, id, stream.priority);
+                self.held.swap_remove(i);
+            } else {
+                next = Some(next.map_or(release, |x: Instant| x.min(release)));
+                i += 1;
+            }
+        }
+        next
+    }
+
     pub fn retransmit(&mut self, frame: frame::StreamMeta) {
         let stream = match self.send.get_mut(&frame.id) {
             // Loss of data on a closed stream is a noop
@@ -634,9 +825,13 @@ impl StreamsState {
 
     /// Yield stream events
     pub fn poll(&mut self) -> Option<StreamEvent> {
-        if let Some(dir) = Dir::iter().find(|&i| mem::replace(&mut self.opened[i as usize], false))
-        {
-            return Some(StreamEvent::Opened { dir });
+        self.last_finished = None;
+        if !self.defer_remote {
+            if let Some(dir) =
+                Dir::iter().find(|&i| mem::replace(&mut self.opened[i as usize], false))
+            {
+                return Some(StreamEvent::Opened { dir });
+            }
         }
 
         if self.write_limit() > 0 {
@@ -657,11 +852,23 @@ impl StreamsState {
             }
         }
 
-        self.events.pop_front()
+        let event = self.events.pop_front();
+        if let Some(StreamEvent::Finished { id }) = event {
+            self.last_finished = self.finished.remove(&id).map(|stats| (id, stats));
+        }
+        event
     }
 
-    pub fn take_max_streams_dirty(&mut self, dir: Dir) -> bool {
-        mem::replace(&mut self.max_streams_dirty[dir as usize], false)
+    /// Whether credit for streams of directionality `dir` should be announced to the peer
+    ///
+    /// Credit for closed streams is withheld until enough has accumulated to be worth a
+    /// `MAX_STREAMS` frame, unless the peer is about to run out.
+    pub fn max_streams_due(&self, dir: Dir) -> bool {
+        let dir = dir as usize;
+        let withheld = self.max_remote[dir] - self.sent_max_remote[dir];
+        let available = self.sent_max_remote[dir].saturating_sub(self.next_remote[dir]);
+        let batch = self.max_streams_batch[dir];
+        withheld > 0 && (withheld >= batch || available < batch)
     }
 
     /// Check for errors entailed by the peer's use of `id` as a send stream
@@ -704,14 +911,47 @@ impl StreamsState {
                 Dir::Bi if remote => self.initial_max_stream_data_bidi_local,
                 Dir::Bi => self.initial_max_stream_data_bidi_remote,
             };
-            let stream = Send::new(max_data);
+            let stream = match self.free_send.pop() {
+                Some(mut stream) => {
+                    stream.reuse(max_data);
+                    stream
+                }
+                None => Send::new(max_data),
+            };
             assert!(self.send.insert(id, stream).is_none());
         }
         if bi || remote {
-            assert!(self
-                .recv
-                .insert(id, Recv::new(self.stream_receive_window))
-                .is_none());
+            let window = self.stream_window(id.dir());
+            let stream = match self.free_recv.pop() {
+                Some(mut stream) => {
+                    stream.reuse(window);
+                    stream
+                }
+                None => Recv::new(window),
+            };
+            assert!(self.recv.insert(id, stream).is_none());
+        }
+    }
+
+    /// Keep the state of a closed send stream for reuse
+    fn recycle_send(&mut self, stream: Send) {
+        if self.free_send.len() < MAX_FREE_STREAMS {
+            self.free_send.push(stream);
+        }
+    }
+
+    /// Keep the state of a closed receive stream for reuse
+    pub(super) fn recycle_recv(&mut self, stream: Recv) {
+        if self.free_recv.len() < MAX_FREE_STREAMS {
+            self.free_recv.push(stream);
+        }
+    }
+
+    /// Per-stream receive window for streams of directionality `dir`
+    pub(super) fn stream_window(&self, dir: Dir) -> u64 {
+        match dir {
+            Dir::Bi => self.stream_receive_window,
+            Dir::Uni => self.uni_stream_receive_window,
         }
     }
 
@@ -724,6 +964,21 @@ impl StreamsState {
     /// suppress sending further updates until the window increases significantly
     /// again.
     pub(super) fn add_read_credits(&mut self, credits: u64) -> ShouldTransmit {
+        if self.connection_credit_policy == CreditPolicy::Manual {
+            return ShouldTransmit(false);
+        }
+        self.add_credits(credits)
+    }
+
+    /// Adds credits granted by the application under `CreditPolicy::Manual`
+    pub(crate) fn grant_credits(&mut self, credits: u64) -> ShouldTransmit {
+        if self.connection_credit_policy != CreditPolicy::Manual {
+            return ShouldTransmit(false);
+        }
+        self.add_credits(credits)
+    }
+
+    fn add_credits(&mut self, credits: u64) -> ShouldTransmit {
         self.local_max_data = self.local_max_data.saturating_add(credits);
 
         if self.local_max_data > VarInt::MAX.into_inner() {
@@ -736,7 +991,8 @@ impl StreamsState {
         // the decision, to accomodate for connection using bigger windows requring
         // less updates.
         let diff = self.local_max_data - self.sent_max_data.into_inner();
-        ShouldTransmit(diff >= (self.receive_window / 8))
+        let threshold = self.connection_credit_policy.threshold(self.receive_window);
+        ShouldTransmit(diff >= threshold)
     }
 
     /// Update counters for removal of a stream
@@ -757,14 +1013,25 @@ impl StreamsState {
     }
 }
 
+/// Number of streams credit is withheld for before sending `MAX_STREAMS`, given the concurrency
+/// limit
+fn max_streams_batch(max_concurrent: VarInt) -> u64 {
+    (max_concurrent.into_inner() / 8).max(1)
+}
+
+/// Upper bound on the number of closed streams whose state is kept for reuse, per half
+const MAX_FREE_STREAMS: usize = 64;
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
-        connection::State as ConnState, connection::Streams, ReadableError, RecvStream, SendStream,
-        TransportErrorCode, WriteError,
+        connection::State as ConnState, connection::Streams, Coalescing, ReadableError, RecvStream,
+        SendStream, TransportErrorCode, WriteError,
     };
+    use assert_matches::assert_matches;
     use bytes::Bytes;
+    use std::time::Duration;
 
     fn make(side: Side) -> StreamsState {
         StreamsState::new(
@@ -774,6 +1041,9 @@ mod tests {
             1024 * 1024,
             (1024 * 1024u32).into(),
             (1024 * 1024u32).into(),
+            (1024 * 1024u32).into(),
+            CreditPolicy::Threshold(8),
+            CreditPolicy::Threshold(8),
         )
     }
 
@@ -792,7 +1062,8 @@ mod tests {
                         fin: true,
                         data: Bytes::from_static(&[0; MESSAGE_SIZE]),
                     },
-                    2048
+                    2048,
+                    Instant::now()
                 )
                 .unwrap(),
             ShouldTransmit(false)
@@ -814,11 +1085,59 @@ mod tests {
         );
         assert!(chunks.next(0).unwrap().is_none());
         let should_transmit = chunks.finalize();
-        assert!(should_transmit.0);
-        assert!(pending.max_uni_stream_id);
+        // Neither the connection's nor the peer's stream limit has moved enough to be worth sending
+        assert!(!should_transmit.0);
+        assert!(!pending.max_uni_stream_id);
         assert_eq!(client.local_max_data - initial_max, MESSAGE_SIZE as u64);
     }
 
+    #[test]
+    fn max_streams_batched() {
+        let mut client = make(Side::Client);
+        let mut pending = Retransmits::default();
+        // The client permits 128 concurrent unidirectional streams, so credit goes out in batches
+        // of 16
+        for i in 0..16 {
+            let id = StreamId::new(Side::Server, Dir::Uni, i);
+            assert_eq!(
+                client
+                    .received(
+                        frame::Stream {
+                            id,
+                            offset: 0,
+                            fin: true,
+                            data: Bytes::from_static(&[0; 8]),
+                        },
+                        8,
+                        Instant::now(),
+                    )
+                    .unwrap(),
+                ShouldTransmit(false)
+            );
+            let mut recv = RecvStream {
+                id,
+                state: &mut client,
+                pending: &mut pending,
+            };
+            let mut chunks = recv.read(true).unwrap();
+            assert_eq!(chunks.next(8).unwrap().unwrap().bytes.len(), 8);
+            assert!(chunks.next(0).unwrap().is_none());
+            let should_transmit = chunks.finalize();
+            assert_eq!(should_transmit.0, i == 15);
+            assert_eq!(pending.max_uni_stream_id, i == 15);
+        }
+
+        let mut buf = Vec::new();
+        let mut retransmits = ThinRetransmits::default();
+        let mut stats = FrameStats::default();
+        client.write_control_frames(&mut buf, &mut pending, &mut retransmits, &mut stats, 1200);
+        assert_eq!(stats.max_streams_uni, 1);
+        assert!(!client.max_streams_due(Dir::Uni));
+
+        // Closed streams' state is reused by the streams the peer may now open
+        assert!(client.free_recv.is_empty());
+    }
+
     #[test]
     fn reset_flow_control() {
         let mut client = make(Side::Client);
@@ -833,7 +1152,8 @@ mod tests {
                         fin: false,
                         data: Bytes::from_static(&[0; 2048]),
                     },
-                    2048
+                    2048,
+                    Instant::now()
                 )
                 .unwrap(),
             ShouldTransmit(false)
@@ -896,7 +1216,8 @@ mod tests {
                         fin: false,
                         data: Bytes::from_static(&[0; 0]),
                     },
-                    0
+                    0,
+                    Instant::now()
                 )
                 .unwrap(),
             ShouldTransmit(false)
@@ -959,7 +1280,8 @@ mod tests {
                         fin: false,
                         data: Bytes::from_static(&[0; 32]),
                     },
-                    32
+                    32,
+                    Instant::now()
                 )
                 .unwrap(),
             ShouldTransmit(false)
@@ -991,7 +1313,8 @@ mod tests {
                         fin: true,
                         data: Bytes::from_static(&[0; 16]),
                     },
-                    16
+                    16,
+                    Instant::now()
                 )
                 .unwrap(),
             ShouldTransmit(false)
@@ -1014,7 +1337,8 @@ mod tests {
                         fin: false,
                         data: Bytes::from_static(&[0; 32])
                     },
-                    32
+                    32,
+                    Instant::now()
                 )
                 .unwrap(),
             ShouldTransmit(false)
@@ -1078,6 +1402,86 @@ mod tests {
         assert_eq!(stream.write(&[]), Err(WriteError::UnknownStream));
     }
 
+    #[test]
+    fn readiness() {
+        let mut server = make(Side::Server);
+        server.set_params(&TransportParameters {
+            initial_max_streams_bidi: 1u32.into(),
+            initial_max_data: 8u32.into(),
+            initial_max_stream_data_bidi_remote: 16u32.into(),
+            ..Default::default()
+        });
+
+        let (mut pending, state) = (Retransmits::default(), ConnState::Established);
+        let id = Streams {
+            state: &mut server,
+            conn_state: &state,
+        }
+        .open(Dir::Bi)
+        .unwrap();
+
+        // Blocked by connection-level flow control
+        let mut stream = SendStream {
+            id,
+            state: &mut server,
+            pending: &mut pending,
+            conn_state: &state,
+        };
+        assert_eq!(stream.is_writable(), Ok(true));
+        assert_eq!(stream.write(&[0; 16]), Ok(8));
+        assert_eq!(stream.is_writable(), Ok(false));
+        assert_eq!(stream.write(&[0; 16]), Err(WriteError::Blocked));
+        server.received_max_data(32u32.into());
+        assert_matches!(server.poll(), Some(StreamEvent::Writable { id: x }) if x == id);
+
+        // Blocked by stream-level flow control
+        let mut stream = SendStream {
+            id,
+            state: &mut server,
+            pending: &mut pending,
+            conn_state: &state,
+        };
+        assert_eq!(stream.write(&[0; 16]), Ok(8));
+        assert_eq!(stream.is_writable(), Ok(false));
+        server.received_max_stream_data(id, 24).unwrap();
+        assert_matches!(server.poll(), Some(StreamEvent::Writable { id: x }) if x == id);
+
+        // Readable only once the data at the read offset has arrived
+        let data = |offset, fin| frame::Stream {
+            id,
+            offset,
+            fin,
+            data: Bytes::from_static(&[0; 4]),
+        };
+        assert_eq!(
+            server.received(data(4, true), 4, Instant::now()).unwrap(),
+            ShouldTransmit(false)
+        );
+        let mut recv = RecvStream {
+            id,
+            state: &mut server,
+            pending: &mut pending,
+        };
+        assert_eq!(recv.is_readable(), Ok(false));
+        assert_eq!(
+            recv.state
+                .received(data(0, false), 4, Instant::now())
+                .unwrap(),
+            ShouldTransmit(false)
+        );
+        assert_eq!(recv.is_readable(), Ok(true));
+        let mut chunks = recv.read(true).unwrap();
+        assert_eq!(chunks.next(8).unwrap().unwrap().bytes.len(), 4);
+        assert_eq!(chunks.next(8).unwrap().unwrap().bytes.len(), 4);
+        let _ = chunks.finalize();
+        // The end of the stream is ready to be read, too
+        assert_eq!(recv.is_readable(), Ok(true));
+        let mut chunks = recv.read(true).unwrap();
+        assert!(chunks.next(8).unwrap().is_none());
+        let _ = chunks.finalize();
+        assert!(recv.is_readable().is_err());
+    }
+
     #[test]
     fn final_offset_flow_control() {
         let mut client = make(Side::Client);
@@ -1147,6 +1551,72 @@ mod tests {
         assert_eq!(meta[2].id, id_low);
     }
 
+    #[test]
+    fn coalescing() {
+        let mut server = make(Side::Server);
+        server.set_params(&TransportParameters {
+            initial_max_streams_bidi: 1u32.into(),
+            initial_max_data: 100u32.into(),
+            initial_max_stream_data_bidi_remote: 100u32.into(),
+            ..Default::default()
+        });
+
+        let (mut pending, state) = (Retransmits::default(), ConnState::Established);
+        let id = Streams {
+            state: &mut server,
+            conn_state: &state,
+        }
+        .open(Dir::Bi)
+        .unwrap();
+
+        let mut stream = SendStream {
+            id,
+            state: &mut server,
+            pending: &mut pending,
+            conn_state: &state,
+        };
+        let delay = Duration::from_millis(1);
+        stream
+            .set_coalescing(Some(Coalescing {
+                delay,
+                min_bytes: 8,
+            }))
+            .unwrap();
+        stream.write(b"abc").unwrap();
+
+        let now = Instant::now();
+        let mut buf = Vec::with_capacity(40);
+        assert_eq!(server.release_held(now), Some(now + delay));
+        assert!(server.write_stream_frames(&mut buf, 40).is_empty());
+
+        // Enough data to send immediately
+        SendStream {
+            id,
+            state: &mut server,
+            pending: &mut pending,
+            conn_state: &state,
+        }
+        .write(b"defgh")
+        .unwrap();
+        assert_eq!(server.release_held(now), None);
+        let meta = server.write_stream_frames(&mut buf, 40);
+        assert_eq!(meta[0].offsets, 0..8);
+
+        // Small write is released once the delay passes
+        SendStream {
+            id,
+            state: &mut server,
+            pending: &mut pending,
+            conn_state: &state,
+        }
+        .write(b"i")
+        .unwrap();
+        assert_eq!(server.release_held(now), Some(now + delay));
+        assert_eq!(server.release_held(now + delay), None);
+        let meta = server.write_stream_frames(&mut buf, 40);
+        assert_eq!(meta[0].offsets, 8..9);
+    }
+
     #[test]
     fn stop_finished() {
         let mut client = make(Side::Client);
@@ -1161,6 +1631,7 @@ mod tests {
                     data: Bytes::from_static(&[0; 32]),
                 },
                 32,
+                Instant::now(),
             )
             .unwrap();
         let mut pending = Retransmits::default();
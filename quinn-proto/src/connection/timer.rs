@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub(crate) enum Timer {
@@ -18,10 +18,24 @@ pub(crate) enum Timer {
     Pacing = 6,
     /// When to invalidate old CID and proactively push new one via NEW_CONNECTION_ID frame
     PushNewCid = 7,
+    /// When to stop holding back acknowledgements in power-saving mode
+    AckDelay = 8,
+    /// When to abandon a handshake that hasn't completed
+    HandshakeDeadline = 9,
+    /// When data on a partially reliable stream expires
+    StreamDeadline = 10,
+    /// When to warn the application that the idle timeout is about to expire
+    IdleWarning = 11,
+    /// When to search for a larger path MTU again
+    MtuDiscovery = 12,
+    /// When to stop holding back small writes to a stream
+    Coalescing = 13,
+    /// When to send another copy of the client's first flight
+    InitialDuplicate = 14,
 }
 
 impl Timer {
-    pub(crate) const VALUES: [Self; 8] = [
+    pub(crate) const VALUES: [Self; 15] = [
         Timer::LossDetection,
         Timer::Idle,
         Timer::Close,
@@ -30,13 +44,20 @@ impl Timer {
         Timer::KeepAlive,
         Timer::Pacing,
         Timer::PushNewCid,
+        Timer::AckDelay,
+        Timer::HandshakeDeadline,
+        Timer::StreamDeadline,
+        Timer::IdleWarning,
+        Timer::MtuDiscovery,
+        Timer::Coalescing,
+        Timer::InitialDuplicate,
     ];
 }
 
 /// A table of data associated with each distinct kind of `Timer`
 #[derive(Debug, Copy, Clone, Default)]
 pub(crate) struct TimerTable {
-    data: [Option<Instant>; 8],
+    data: [Option<Instant>; 15],
 }
 
 impl TimerTable {
@@ -56,7 +77,43 @@ impl TimerTable {
         self.data.iter().filter_map(|&x| x).min()
     }
 
+    /// Postpone by `delay` every running timer that hasn't been rearmed or stopped since `before`
+    pub fn delay_unchanged(&mut self, before: &TimerTable, delay: Duration) {
+        for (time, &old) in self.data.iter_mut().zip(before.data.iter()) {
+            if *time == old {
+                *time = time.map(|x| x + delay);
+            }
+        }
+    }
+
     pub fn is_expired(&self, timer: Timer, after: Instant) -> bool {
         self.data[timer as usize].map_or(false, |x| x <= after)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_unchanged() {
+        let now = Instant::now();
+        let mut timers = TimerTable::default();
+        timers.set(Timer::Idle, now);
+        timers.set(Timer::KeepAlive, now);
+        timers.set(Timer::Close, now);
+        let before = timers;
+
+        timers.set(Timer::KeepAlive, now + Duration::from_secs(1));
+        timers.stop(Timer::Close);
+        timers.set(Timer::PathValidation, now);
+        timers.delay_unchanged(&before, Duration::from_secs(10));
+        assert_eq!(timers.get(Timer::Idle), Some(now + Duration::from_secs(10)));
+        assert_eq!(
+            timers.get(Timer::KeepAlive),
+            Some(now + Duration::from_secs(1))
+        );
+        assert_eq!(timers.get(Timer::Close), None);
+        assert_eq!(timers.get(Timer::PathValidation), Some(now));
+    }
+}
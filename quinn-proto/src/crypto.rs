@@ -17,6 +17,9 @@ use crate::{
     ConnectError, Side, TransportError,
 };
 
+/// Insecure session without any cryptography, for benchmarking
+#[cfg(feature = "null-crypto")]
+pub mod null;
 /// Cryptography interface based on *ring*
 #[cfg(feature = "ring")]
 pub(crate) mod ring;
@@ -70,6 +73,30 @@ pub trait Session: Send + Sized {
     /// If the 0-RTT-encrypted data has been accepted by the peer
     fn early_data_accepted(&self) -> Option<bool>;
 
+    /// Whether the session was resumed from an earlier connection, if that can be determined
+    ///
+    /// Only meaningful once the handshake has completed. Defaults to `None`, for crypto protocols
+    /// that can't tell.
+    fn is_resumed(&self) -> Option<bool> {
+        None
+    }
+
+    /// Whether a session ticket offered by the client was ignored for having been issued for
+    /// another server name or application protocol
+    ///
+    /// Only reported by server sessions started with [`ServerConfig::start_pinned_session()`].
+    fn ticket_mismatch(&self) -> bool {
+        false
+    }
+
+    /// The server name this session is for
+    ///
+    /// For clients, the name that was dialed; for servers, the name indicated by the client, if
+    /// any has been received yet. Defaults to `None`, for crypto protocols without server names.
+    fn server_name(&self) -> Option<&str> {
+        None
+    }
+
     /// Returns `true` until the connection is fully established.
     fn is_handshaking(&self) -> bool;
 
@@ -165,6 +192,15 @@ where
 
     /// Start a server session with this configuration
     fn start_session(&self, params: &TransportParameters) -> S;
+
+    /// Start a server session that only resumes from tickets issued for the same server name and
+    /// application protocol
+    ///
+    /// Tickets issued for anything else are ignored in favor of a full handshake. Crypto protocols
+    /// that can't tell where a ticket was issued fall back to `start_session`.
+    fn start_pinned_session(&self, params: &TransportParameters) -> S {
+        self.start_session(params)
+    }
 }
 
 /// Keys used to protect packet payloads
@@ -218,6 +254,39 @@ pub trait HmacKey: Send + Sized + Sync {
 #[derive(Debug, PartialEq, Eq)]
 pub struct ExportKeyingMaterialError;
 
+/// A value identifying a connection's cryptographic session, for binding credentials to it
+///
+/// Derived from the session's exported keying material like the `tls-exporter` channel binding
+/// of [RFC 9266](https://www.rfc-editor.org/rfc/rfc9266), so both peers of a connection obtain
+/// the same value, and no other connection has it. Applications authenticating requests with
+/// tokens can have clients sign or MAC the binding along with the token, and servers check it
+/// against their own, so that a token captured from one connection can't be replayed on another.
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelBinding([u8; 32]);
+
+impl ChannelBinding {
+    /// Derive the binding of `session`
+    ///
+    /// Fails if the session's keys aren't available yet, i.e. before the handshake completes.
+    pub fn new<S: Session>(session: &S) -> Result<Self, ExportKeyingMaterialError> {
+        let mut value = [0; 32];
+        session.export_keying_material(&mut value, b"EXPORTER-Channel-Binding", &[])?;
+        Ok(Self(value))
+    }
+
+    /// The binding's value, to be included in what the peer authenticates
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Whether `presented`, as obtained from the peer, is this binding
+    ///
+    /// Compares in constant time.
+    pub fn verify(&self, presented: &[u8]) -> bool {
+        crate::constant_time::eq(&self.0, presented)
+    }
+}
+
 /// A pseudo random key for HKDF
 pub trait HandshakeTokenKey: Send + Sized + Sync {
     /// AEAD key type
@@ -0,0 +1,383 @@
+//! A cryptographic session which provides no security whatsoever
+//!
+//! Exchanges transport parameters in the clear and leaves packets unprotected, so that benchmarks
+//! can measure the cost of the transport protocol in isolation. Anyone on the path can read,
+//! modify and inject packets of connections using it, and any server accepts any client. Never use
+//! this outside of benchmarks and tests.
+
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+use ring::{hkdf, hmac};
+
+use crate::{
+    crypto::{self, CryptoError, ExportKeyingMaterialError, KeyPair, Keys},
+    transport_parameters::TransportParameters,
+    ConnectError, ConnectionId, Side, TransportError,
+};
+
+/// A session exchanging transport parameters without any cryptographic protection
+#[derive(Debug)]
+pub struct NullSession {
+    side: Side,
+    state: State,
+    /// Encoded local transport parameters, sent with our hello
+    params: Vec<u8>,
+    peer_params: Option<TransportParameters>,
+    server_name: Option<String>,
+    /// Handshake data received but not yet completely read
+    incoming: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    /// Client about to send its hello
+    Start,
+    /// Waiting for the peer's next handshake message
+    Waiting,
+    /// Received a message requiring a response, or new keys
+    Respond,
+    /// Server sent its hello, but not yet its finished message
+    ServerHelloSent,
+    /// Server waiting for the client's finished message
+    AwaitClientFinished,
+    Done,
+}
+
+impl NullSession {
+    fn new(side: Side, params: &TransportParameters, server_name: Option<String>) -> Self {
+        let mut encoded = Vec::new();
+        params.write(&mut encoded);
+        Self {
+            side,
+            state: match side {
+                Side::Client => State::Start,
+                Side::Server => State::Waiting,
+            },
+            params: encoded,
+            peer_params: None,
+            server_name,
+            incoming: Vec::new(),
+        }
+    }
+
+    /// Parse a hello carrying the peer's transport parameters, and for clients, a server name
+    fn read_hello(&mut self, mut body: &[u8]) -> Result<(), TransportError> {
+        if self.side.is_server() {
+            let len = take_u16(&mut body)? as usize;
+            if body.len() < len {
+                return Err(malformed());
+            }
+            let (name, rest) = body.split_at(len);
+            self.server_name = Some(String::from_utf8(name.to_vec()).map_err(|_| malformed())?);
+            body = rest;
+        }
+        let params = TransportParameters::read(self.side, &mut io::Cursor::new(body))?;
+        self.peer_params = Some(params);
+        Ok(())
+    }
+}
+
+impl crypto::Session for NullSession {
+    type HandshakeData = HandshakeData;
+    type Identity = ();
+    type ClientConfig = NullClientConfig;
+    type HmacKey = hmac::Key;
+    type HandshakeTokenKey = hkdf::Prk;
+    type HeaderKey = NullKey;
+    type PacketKey = NullKey;
+    type ServerConfig = NullServerConfig;
+
+    fn initial_keys(_: &ConnectionId, _: Side) -> Keys<Self> {
+        keys()
+    }
+
+    fn handshake_data(&self) -> Option<HandshakeData> {
+        self.peer_params.as_ref()?;
+        Some(HandshakeData {
+            server_name: match self.side {
+                Side::Client => None,
+                Side::Server => self.server_name.clone(),
+            },
+            auth_token: match self.side {
+                Side::Client => None,
+                Side::Server => self
+                    .peer_params
+                    .as_ref()?
+                    .auth_token
+                    .map(|x| Bytes::copy_from_slice(&x)),
+            },
+        })
+    }
+
+    fn peer_identity(&self) -> Option<()> {
+        None
+    }
+
+    fn early_crypto(&self) -> Option<(NullKey, NullKey)> {
+        None
+    }
+
+    fn early_data_accepted(&self) -> Option<bool> {
+        None
+    }
+
+    fn is_resumed(&self) -> Option<bool> {
+        Some(false)
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    fn is_handshaking(&self) -> bool {
+        self.state != State::Done
+    }
+
+    fn read_handshake(&mut self, buf: &[u8]) -> Result<bool, TransportError> {
+        self.incoming.extend_from_slice(buf);
+        let mut got_handshake_data = false;
+        loop {
+            let mut buf = &self.incoming[..];
+            let ty = match buf.first() {
+                Some(&x) => x,
+                None => break,
+            };
+            buf = &buf[1..];
+            let len = match take_u16(&mut buf) {
+                Ok(x) => x as usize,
+                Err(_) => break,
+            };
+            if buf.len() < len {
+                break;
+            }
+            let body = buf[..len].to_vec();
+            self.incoming.drain(..3 + len);
+
+            match (self.side, self.state, ty) {
+                (Side::Server, State::Waiting, CLIENT_HELLO) => {
+                    self.read_hello(&body)?;
+                    got_handshake_data = true;
+                    self.state = State::Respond;
+                }
+                (Side::Client, State::Waiting, SERVER_HELLO) if self.peer_params.is_none() => {
+                    // Proceed to the handshake space
+                    self.state = State::Respond;
+                }
+                (Side::Client, State::Waiting, SERVER_FINISHED) => {
+                    self.read_hello(&body)?;
+                    got_handshake_data = true;
+                    self.state = State::Respond;
+                }
+                (Side::Server, State::AwaitClientFinished, CLIENT_FINISHED) => {
+                    self.state = State::Done;
+                }
+                _ => {
+                    return Err(TransportError::PROTOCOL_VIOLATION(
+                        "unexpected handshake message",
+                    ))
+                }
+            }
+        }
+        Ok(got_handshake_data)
+    }
+
+    fn transport_parameters(&self) -> Result<Option<TransportParameters>, TransportError> {
+        Ok(self.peer_params)
+    }
+
+    fn write_handshake(&mut self, buf: &mut Vec<u8>) -> Option<Keys<Self>> {
+        match (self.side, self.state) {
+            (Side::Client, State::Start) => {
+                let name = self.server_name.clone().unwrap_or_default();
+                let mut body = Vec::with_capacity(2 + name.len() + self.params.len());
+                body.extend_from_slice(&(name.len() as u16).to_be_bytes());
+                body.extend_from_slice(name.as_bytes());
+                body.extend_from_slice(&self.params);
+                write_message(buf, CLIENT_HELLO, &body);
+                self.state = State::Waiting;
+                None
+            }
+            // Received the server's hello; move on to the handshake space
+            (Side::Client, State::Respond) if self.peer_params.is_none() => {
+                self.state = State::Waiting;
+                Some(keys())
+            }
+            // Received the server's finished message; send our own and move on to 1-RTT
+            (Side::Client, State::Respond) => {
+                write_message(buf, CLIENT_FINISHED, &[]);
+                self.state = State::Done;
+                Some(keys())
+            }
+            (Side::Server, State::Respond) => {
+                write_message(buf, SERVER_HELLO, &[]);
+                self.state = State::ServerHelloSent;
+                Some(keys())
+            }
+            (Side::Server, State::ServerHelloSent) => {
+                let params = self.params.clone();
+                write_message(buf, SERVER_FINISHED, &params);
+                self.state = State::AwaitClientFinished;
+                Some(keys())
+            }
+            _ => None,
+        }
+    }
+
+    fn next_1rtt_keys(&mut self) -> Option<KeyPair<NullKey>> {
+        Some(KeyPair {
+            local: NullKey,
+            remote: NullKey,
+        })
+    }
+
+    fn retry_tag(_: &ConnectionId, _: &[u8]) -> [u8; 16] {
+        [0; 16]
+    }
+
+    fn is_valid_retry(_: &ConnectionId, _: &[u8], payload: &[u8]) -> bool {
+        payload.len() >= 16
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        _: &[u8],
+        _: &[u8],
+    ) -> Result<(), ExportKeyingMaterialError> {
+        for x in output {
+            *x = 0;
+        }
+        Ok(())
+    }
+}
+
+/// Parameters determined during a [`NullSession`] handshake
+#[derive(Debug)]
+pub struct HandshakeData {
+    /// The server name specified by the client, if any
+    ///
+    /// Always `None` for outgoing connections
+    pub server_name: Option<String>,
+    /// The token the client authenticated itself with, if any
+    ///
+    /// Always `None` for outgoing connections
+    pub auth_token: Option<Bytes>,
+}
+
+/// Client configuration for [`NullSession`], which has nothing to configure
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NullClientConfig;
+
+impl crypto::ClientConfig<NullSession> for NullClientConfig {
+    fn new() -> Self {
+        Self
+    }
+
+    fn start_session(
+        &self,
+        server_name: &str,
+        params: &TransportParameters,
+    ) -> Result<NullSession, ConnectError> {
+        Ok(NullSession::new(
+            Side::Client,
+            params,
+            Some(server_name.into()),
+        ))
+    }
+}
+
+/// Server configuration for [`NullSession`], which has nothing to configure
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NullServerConfig;
+
+impl crypto::ServerConfig<NullSession> for NullServerConfig {
+    fn new() -> Self {
+        Self
+    }
+
+    fn start_session(&self, params: &TransportParameters) -> NullSession {
+        NullSession::new(Side::Server, params, None)
+    }
+}
+
+/// Packet and header "protection" leaving packets as they are
+///
+/// Packets still carry a tag's worth of padding, so that they're laid out exactly like packets
+/// protected by real keys.
+#[derive(Debug, Copy, Clone)]
+pub struct NullKey;
+
+impl crypto::PacketKey for NullKey {
+    fn encrypt(&self, _: u64, _: &mut [u8], _: usize) {}
+
+    fn decrypt(&self, _: u64, _: &[u8], payload: &mut BytesMut) -> Result<(), CryptoError> {
+        if payload.len() < TAG_LEN {
+            return Err(CryptoError);
+        }
+        payload.truncate(payload.len() - TAG_LEN);
+        Ok(())
+    }
+
+    fn tag_len(&self) -> usize {
+        TAG_LEN
+    }
+
+    fn confidentiality_limit(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn integrity_limit(&self) -> u64 {
+        u64::MAX
+    }
+}
+
+impl crypto::HeaderKey for NullKey {
+    fn decrypt(&self, _: usize, _: &mut [u8]) {}
+
+    fn encrypt(&self, _: usize, _: &mut [u8]) {}
+
+    fn sample_size(&self) -> usize {
+        TAG_LEN
+    }
+}
+
+fn keys() -> Keys<NullSession> {
+    Keys {
+        header: KeyPair {
+            local: NullKey,
+            remote: NullKey,
+        },
+        packet: KeyPair {
+            local: NullKey,
+            remote: NullKey,
+        },
+    }
+}
+
+fn write_message(buf: &mut Vec<u8>, ty: u8, body: &[u8]) {
+    buf.push(ty);
+    buf.extend_from_slice(&(body.len() as u16).to_be_bytes());
+    buf.extend_from_slice(body);
+}
+
+fn take_u16(buf: &mut &[u8]) -> Result<u16, TransportError> {
+    if buf.len() < 2 {
+        return Err(malformed());
+    }
+    let x = u16::from_be_bytes([buf[0], buf[1]]);
+    *buf = &buf[2..];
+    Ok(x)
+}
+
+fn malformed() -> TransportError {
+    TransportError::PROTOCOL_VIOLATION("malformed handshake message")
+}
+
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const SERVER_FINISHED: u8 = 3;
+const CLIENT_FINISHED: u8 = 4;
+
+/// Length of the space reserved for authentication tags, matching AES-GCM
+const TAG_LEN: usize = 16;
@@ -1,15 +1,16 @@
 use std::{
-    io,
+    fmt, io,
     ops::{Deref, DerefMut},
     str,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use ring::{aead, aead::quic::HeaderProtectionKey, hkdf, hmac};
 pub use rustls::TLSError;
 use rustls::{
     self,
+    internal::msgs::enums::AlertDescription,
     quic::{ClientQuicExt, PacketKey, ServerQuicExt},
     Session,
 };
@@ -18,7 +19,8 @@ use webpki::DNSNameRef;
 use crate::{
     crypto::{self, CryptoError, ExportKeyingMaterialError, KeyPair, Keys},
     transport_parameters::TransportParameters,
-    CertificateChain, ConnectError, ConnectionId, Side, TransportError, TransportErrorCode,
+    CertificateChain, ConnectError, ConnectionId, PrivateKey, Side, TransportError,
+    TransportErrorCode,
 };
 
 /// A rustls TLS session
@@ -26,7 +28,12 @@ use crate::{
 pub struct TlsSession {
     using_alpn: bool,
     got_handshake_data: bool,
+    /// Name the client dialed; servers get it from the session instead
+    server_name: Option<String>,
     inner: SessionKind,
+    /// Set on pinned server sessions until the ClientHello has been processed
+    pinning: Option<Box<TicketPinning>>,
+    ticket_mismatch: bool,
 }
 
 #[derive(Debug)]
@@ -42,6 +49,133 @@ impl TlsSession {
             SessionKind::Server(_) => Side::Server,
         }
     }
+
+    fn tls_error(&self, e: TLSError) -> TransportError {
+        if self.side().is_server()
+            && matches!(e, TLSError::General(_))
+            && self.get_alert() == Some(AlertDescription::AccessDenied)
+            && self.get_negotiated_ciphersuite().is_none()
+        {
+            // The certificate resolver declining the ClientHello is the only access_denied failure
+            // before a cipher suite is chosen. rustls reports it as such, but that's almost always
+            // down to an unknown server name.
+            TransportError {
+                code: TransportErrorCode::crypto(AlertDescription::UnrecognisedName.get_u8()),
+                frame: None,
+                reason: "unrecognized server name".into(),
+            }
+        } else if let Some(alert) = self.get_alert() {
+            TransportError {
+                code: TransportErrorCode::crypto(alert.get_u8()),
+                frame: None,
+                reason: e.to_string(),
+            }
+        } else {
+            TransportError::PROTOCOL_VIOLATION(format!("TLS error: {}", e))
+        }
+    }
+
+    /// Start over without resumption if the ClientHello resumed from a ticket issued for another
+    /// server name or protocol, and bind tickets issued from now on to the current ones
+    fn check_ticket(&mut self) -> Result<(), TransportError> {
+        let pinning = self.pinning.take().unwrap();
+        let session = match self.inner {
+            SessionKind::Server(ref session) => session,
+            SessionKind::Client(_) => unreachable!("clients don't pin tickets"),
+        };
+        let binding = ticket_binding(session.get_sni_hostname(), session.get_alpn_protocol());
+        if session
+            .received_resumption_data()
+            .map_or(false, |x| x != &binding[..])
+        {
+            let mut config = (*pinning.config).clone();
+            config.ticketer = Arc::new(IssueOnly(config.ticketer.clone()));
+            config.session_storage = Arc::new(StoreOnly(config.session_storage.clone()));
+            self.inner = SessionKind::Server(rustls::ServerSession::new_quic(
+                &Arc::new(config),
+                pinning.params,
+            ));
+            if let Err(e) = self.read_hs(&pinning.hello) {
+                return Err(self.tls_error(e));
+            }
+            self.ticket_mismatch = true;
+        }
+        if let SessionKind::Server(ref mut session) = self.inner {
+            session.set_resumption_data(&binding);
+        }
+        Ok(())
+    }
+}
+
+/// What a pinned server session needs to start over without resumption
+struct TicketPinning {
+    config: Arc<rustls::ServerConfig>,
+    params: Vec<u8>,
+    /// Handshake data received so far
+    hello: Vec<u8>,
+}
+
+impl fmt::Debug for TicketPinning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TicketPinning")
+            .field("hello_len", &self.hello.len())
+            .finish()
+    }
+}
+
+/// Encode what a ticket is pinned to, for storage as the ticket's resumption data
+fn ticket_binding(server_name: Option<&str>, protocol: Option<&[u8]>) -> Vec<u8> {
+    let mut binding = Vec::new();
+    for field in [server_name.map(str::as_bytes), protocol].iter() {
+        match *field {
+            // Server names and ALPN protocols are both at most 255 bytes long
+            Some(x) => {
+                binding.push(1);
+                binding.push(x.len() as u8);
+                binding.extend_from_slice(x);
+            }
+            None => binding.push(0),
+        }
+    }
+    binding
+}
+
+/// Ticket encrypter that issues tickets but never accepts them
+struct IssueOnly(Arc<dyn rustls::ProducesTickets>);
+
+impl rustls::ProducesTickets for IssueOnly {
+    fn enabled(&self) -> bool {
+        self.0.enabled()
+    }
+
+    fn get_lifetime(&self) -> u32 {
+        self.0.get_lifetime()
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.0.encrypt(plain)
+    }
+
+    fn decrypt(&self, _: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// Session store that records sessions but never resumes them
+struct StoreOnly(Arc<dyn rustls::StoresServerSessions + Send + Sync>);
+
+impl rustls::StoresServerSessions for StoreOnly {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.0.put(key, value)
+    }
+
+    fn get(&self, _: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn take(&self, _: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 impl crypto::Session for TlsSession {
@@ -84,6 +218,15 @@ impl crypto::Session for TlsSession {
                 SessionKind::Client(_) => None,
                 SessionKind::Server(ref session) => session.get_sni_hostname().map(|x| x.into()),
             },
+            auth_token: match self.inner {
+                SessionKind::Client(_) => None,
+                SessionKind::Server(_) => self
+                    .transport_parameters()
+                    .ok()
+                    .flatten()
+                    .and_then(|params| params.auth_token)
+                    .map(|x| Bytes::copy_from_slice(&x)),
+            },
         })
     }
 
@@ -103,6 +246,23 @@ impl crypto::Session for TlsSession {
         }
     }
 
+    fn is_resumed(&self) -> Option<bool> {
+        match self.inner {
+            // rustls doesn't report whether a client's resumption attempt succeeded, but accepted
+            // early data implies that it did
+            SessionKind::Client(ref session) if session.is_early_data_accepted() => Some(true),
+            SessionKind::Client(_) => None,
+            SessionKind::Server(ref session) => Some(session.received_resumption_data().is_some()),
+        }
+    }
+
+    fn server_name(&self) -> Option<&str> {
+        match self.inner {
+            SessionKind::Client(_) => self.server_name.as_deref(),
+            SessionKind::Server(ref session) => session.get_sni_hostname(),
+        }
+    }
+
     fn is_handshaking(&self) -> bool {
         match self.inner {
             SessionKind::Client(ref session) => session.is_handshaking(),
@@ -110,18 +270,21 @@ impl crypto::Session for TlsSession {
         }
     }
 
+    fn ticket_mismatch(&self) -> bool {
+        self.ticket_mismatch
+    }
+
     fn read_handshake(&mut self, buf: &[u8]) -> Result<bool, TransportError> {
-        self.read_hs(buf).map_err(|e| {
-            if let Some(alert) = self.get_alert() {
-                TransportError {
-                    code: TransportErrorCode::crypto(alert.get_u8()),
-                    frame: None,
-                    reason: e.to_string(),
-                }
-            } else {
-                TransportError::PROTOCOL_VIOLATION(format!("TLS error: {}", e))
-            }
-        })?;
+        if let Some(ref mut pinning) = self.pinning {
+            pinning.hello.extend_from_slice(buf);
+        }
+        if let Err(e) = self.read_hs(buf) {
+            return Err(self.tls_error(e));
+        }
+        // Transport parameters appear once the ClientHello has been processed
+        if self.pinning.is_some() && self.get_quic_transport_parameters().is_some() {
+            self.check_ticket()?;
+        }
         if !self.got_handshake_data {
             // Hack around the lack of an explicit signal from rustls to reflect ClientHello being
             // ready on incoming connections, or ALPN negotiation completing on outgoing
@@ -272,6 +435,10 @@ pub struct HandshakeData {
     ///
     /// Always `None` for outgoing connections
     pub server_name: Option<String>,
+    /// The token the client authenticated itself with, if any
+    ///
+    /// See `TransportConfig::auth_token`. Always `None` for outgoing connections.
+    pub auth_token: Option<Bytes>,
 }
 
 impl crypto::ClientConfig<TlsSession> for Arc<rustls::ClientConfig> {
@@ -309,15 +476,97 @@ impl crypto::ClientConfig<TlsSession> for Arc<rustls::ClientConfig> {
         Ok(TlsSession {
             using_alpn: !self.alpn_protocols.is_empty(),
             got_handshake_data: false,
+            server_name: Some(server_name.into()),
             inner: SessionKind::Client(rustls::ClientSession::new_quic(
                 self,
                 pki_server_name,
                 to_vec(params),
             )),
+            pinning: None,
+            ticket_mismatch: false,
         })
     }
 }
 
+/// Certificate verifier accepting any certificate the server presents
+///
+/// Leaves connections using it open to interception by anyone on the path.
+#[cfg(feature = "dangerous_configuration")]
+pub(crate) struct SkipServerVerification;
+
+#[cfg(feature = "dangerous_configuration")]
+impl rustls::ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        _presented_certs: &[rustls::Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, TLSError> {
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// Certificate verifier requiring servers to staple an OCSP response to their certificate
+///
+/// Only the presence of the response is checked; its contents are left to the application.
+#[cfg(feature = "ocsp")]
+pub(crate) struct RequireAnyOcspStaple(pub(crate) rustls::WebPKIVerifier);
+
+#[cfg(feature = "ocsp")]
+impl rustls::ServerCertVerifier for RequireAnyOcspStaple {
+    fn verify_server_cert(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        dns_name: DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, TLSError> {
+        if ocsp_response.is_empty() {
+            return Err(TLSError::General("no stapled OCSP response".into()));
+        }
+        self.0
+            .verify_server_cert(roots, presented_certs, dns_name, ocsp_response)
+    }
+}
+
+/// A certificate chain served along with an OCSP response that can be replaced while in use
+///
+/// Install with `ServerConfig::cert_resolver`, keeping a reference to refresh the response with
+/// [`set_ocsp()`](Self::set_ocsp) before it expires. Handshakes already underway keep the
+/// response they started with.
+pub struct StapledCertificate {
+    key: Mutex<rustls::sign::CertifiedKey>,
+}
+
+impl StapledCertificate {
+    /// Serve `cert_chain`, proving possession of `key`, with the DER-encoded OCSP response `ocsp`
+    pub fn new(
+        cert_chain: CertificateChain,
+        key: PrivateKey,
+        ocsp: Vec<u8>,
+    ) -> Result<Arc<Self>, TLSError> {
+        let signing_key = rustls::sign::any_supported_type(&key.inner)
+            .map_err(|()| TLSError::General("invalid private key".into()))?;
+        let mut key = rustls::sign::CertifiedKey::new(cert_chain.certs, Arc::new(signing_key));
+        key.ocsp = Some(ocsp);
+        Ok(Arc::new(Self {
+            key: Mutex::new(key),
+        }))
+    }
+
+    /// Replace the OCSP response stapled to the certificate in future handshakes
+    pub fn set_ocsp(&self, ocsp: Vec<u8>) {
+        self.key.lock().unwrap().ocsp = Some(ocsp);
+    }
+}
+
+impl rustls::ResolvesServerCert for StapledCertificate {
+    fn resolve(&self, _: rustls::ClientHello) -> Option<rustls::sign::CertifiedKey> {
+        Some(self.key.lock().unwrap().clone())
+    }
+}
+
 impl crypto::ServerConfig<TlsSession> for Arc<rustls::ServerConfig> {
     fn new() -> Self {
         let mut cfg = rustls::ServerConfig::with_ciphersuites(
@@ -333,9 +582,52 @@ impl crypto::ServerConfig<TlsSession> for Arc<rustls::ServerConfig> {
         TlsSession {
             using_alpn: !self.alpn_protocols.is_empty(),
             got_handshake_data: false,
+            server_name: None,
             inner: SessionKind::Server(rustls::ServerSession::new_quic(self, to_vec(params))),
+            pinning: None,
+            ticket_mismatch: false,
         }
     }
+
+    fn start_pinned_session(&self, params: &TransportParameters) -> TlsSession {
+        let params = to_vec(params);
+        TlsSession {
+            using_alpn: !self.alpn_protocols.is_empty(),
+            got_handshake_data: false,
+            server_name: None,
+            inner: SessionKind::Server(rustls::ServerSession::new_quic(self, params.clone())),
+            pinning: Some(Box::new(TicketPinning {
+                config: self.clone(),
+                params,
+                hello: Vec::new(),
+            })),
+            ticket_mismatch: false,
+        }
+    }
+}
+
+/// Restrict an application-supplied client configuration to what QUIC supports
+pub(crate) fn quic_client_config(cfg: &mut rustls::ClientConfig) {
+    cfg.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+    retain_quic_suites(&mut cfg.ciphersuites);
+}
+
+/// Restrict an application-supplied server configuration to what QUIC supports
+pub(crate) fn quic_server_config(cfg: &mut rustls::ServerConfig) {
+    cfg.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+    retain_quic_suites(&mut cfg.ciphersuites);
+    // QUIC requires servers accepting early data at all to accept any amount of it
+    if cfg.max_early_data_size != 0 {
+        cfg.max_early_data_size = u32::MAX;
+    }
+}
+
+/// Drop cipher suites that can't be used with TLS 1.3, falling back to the defaults if none remain
+fn retain_quic_suites(suites: &mut Vec<&'static rustls::SupportedCipherSuite>) {
+    suites.retain(|x| x.usable_for_version(rustls::ProtocolVersion::TLSv1_3));
+    if suites.is_empty() {
+        suites.extend_from_slice(&QUIC_CIPHER_SUITES);
+    }
 }
 
 fn to_vec(params: &TransportParameters) -> Vec<u8> {
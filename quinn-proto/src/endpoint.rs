@@ -16,23 +16,27 @@ use thiserror::Error;
 use tracing::{debug, trace, warn};
 
 use crate::{
+    admission::{Admission, EndpointLoad, HandshakeRate},
     cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator},
-    coding::BufMutExt,
-    config::{ClientConfig, ConfigError, EndpointConfig, ServerConfig},
-    connection::{Connection, ConnectionError},
+    coding::{BufExt, BufMutExt},
+    config::{ClientConfig, ConfigError, EndpointConfig, ServerConfig, TransportConfig},
+    connection::{Connection, ConnectionError, HandshakeFailure},
     crypto::{
         self, ClientConfig as ClientCryptoConfig, Keys, PacketKey,
         ServerConfig as ServerCryptoConfig,
     },
     frame,
-    packet::{Header, Packet, PacketDecodeError, PacketNumber, PartialDecode},
+    listener::{ClientHello, ListenerFilter, ListenerId},
+    packet::{
+        Header, Packet, PacketDecodeError, PacketNumber, PartialDecode, FIXED_BIT, LONG_HEADER_FORM,
+    },
     shared::{
         ConnectionEvent, ConnectionEventInner, ConnectionId, EcnCodepoint, EndpointEvent,
         EndpointEventInner, IssuedCid,
     },
     transport_parameters::TransportParameters,
-    ResetToken, RetryToken, Side, Transmit, TransportError, MAX_CID_SIZE, MIN_INITIAL_SIZE,
-    MIN_MTU, RESET_TOKEN_SIZE,
+    Address, ResetToken, RetryToken, Side, Transmit, TransportError, MAX_CID_SIZE,
+    MIN_INITIAL_SIZE, MIN_MTU, RESET_TOKEN_SIZE,
 };
 
 /// The main entry point to the library
@@ -40,12 +44,13 @@ use crate::{
 /// This object performs no I/O whatsoever. Instead, it generates a stream of packets to send via
 /// `poll_transmit`, and consumes incoming packets and connection-generated events via `handle` and
 /// `handle_event`.
-pub struct Endpoint<S>
+pub struct Endpoint<S, A = SocketAddr>
 where
     S: crypto::Session,
+    A: Address,
 {
     rng: StdRng,
-    transmits: VecDeque<Transmit>,
+    transmits: VecDeque<Transmit<A>>,
     /// Identifies connections based on the initial DCID the peer utilized
     ///
     /// Uses a standard `HashMap` to protect against hash collision attacks.
@@ -57,25 +62,36 @@ where
     /// Identifies connections with zero-length CIDs
     ///
     /// Uses a standard `HashMap` to protect against hash collision attacks.
-    connection_remotes: HashMap<SocketAddr, ConnectionHandle>,
+    connection_remotes: HashMap<A, ConnectionHandle>,
+    /// Identifies connections whose peers may compress packet headers by their remote address
+    compressed_remotes: HashMap<A, ConnectionHandle>,
     /// Reset tokens provided by the peer for the CID each connection is currently sending to
     ///
     /// Incoming stateless resets do not have correct CIDs, so we need this to identify the correct
     /// recipient, if any.
-    connection_reset_tokens: ResetTokenTable,
-    connections: Slab<ConnectionMeta>,
+    connection_reset_tokens: ResetTokenTable<A>,
+    connections: Slab<ConnectionMeta<A>>,
     local_cid_generator: Box<dyn ConnectionIdGenerator>,
     config: Arc<EndpointConfig<S>>,
     server_config: Option<Arc<ServerConfig<S>>>,
+    /// Server configurations selected by the ClientHello, in order of precedence
+    listeners: Vec<Listener<S>>,
+    next_listener: u64,
     /// Whether incoming connections should be unconditionally rejected by a server
     ///
     /// Equivalent to a `ServerConfig.accept_buffer` of `0`, but can be changed after the endpoint is constructed.
     reject_new_connections: bool,
+    /// Recent rate of incoming connection attempts, reported to the admission controller
+    handshake_rate: HandshakeRate,
+    /// Memory usage last reported by the application, reported to the admission controller
+    memory_usage: Option<u64>,
+    stats: EndpointStats,
 }
 
-impl<S> Endpoint<S>
+impl<S, A> Endpoint<S, A>
 where
     S: crypto::Session,
+    A: Address,
 {
     /// Create a new endpoint
     ///
@@ -84,28 +100,68 @@ where
         config: Arc<EndpointConfig<S>>,
         server_config: Option<Arc<ServerConfig<S>>>,
     ) -> Self {
+        let mut rng = config
+            .rng_seed
+            .map_or_else(StdRng::from_entropy, StdRng::from_seed);
+        let mut local_cid_generator = if config.single_connection {
+            Box::new(RandomConnectionIdGenerator::new(0))
+        } else {
+            (config.connection_id_generator_factory.as_ref())()
+        };
+        if config.rng_seed.is_some() {
+            local_cid_generator.set_seed(rng.gen());
+        }
         Self {
-            rng: StdRng::from_entropy(),
+            rng,
             transmits: VecDeque::new(),
             connection_ids_initial: HashMap::default(),
             connection_ids: FxHashMap::default(),
             connection_remotes: HashMap::default(),
+            compressed_remotes: HashMap::default(),
             connection_reset_tokens: ResetTokenTable::default(),
             connections: Slab::new(),
-            local_cid_generator: (config.connection_id_generator_factory.as_ref())(),
+            local_cid_generator,
+            listeners: Vec::new(),
+            next_listener: 0,
             reject_new_connections: false,
+            handshake_rate: HandshakeRate::default(),
+            memory_usage: None,
+            stats: EndpointStats::default(),
             config,
             server_config,
         }
     }
 
     fn is_server(&self) -> bool {
-        self.server_config.is_some()
+        self.server_config.is_some() || !self.listeners.is_empty()
+    }
+
+    /// Accept incoming connections matching `filter` using `config`
+    ///
+    /// Takes precedence over the default server configuration, as well as over listeners added
+    /// later. Connections accepted this way report the returned ID from
+    /// [`Connection::listener()`].
+    pub fn add_listener(
+        &mut self,
+        filter: ListenerFilter,
+        config: Arc<ServerConfig<S>>,
+    ) -> ListenerId {
+        let id = ListenerId(self.next_listener);
+        self.next_listener += 1;
+        self.listeners.push(Listener { id, filter, config });
+        id
+    }
+
+    /// Stop accepting new connections through a listener
+    ///
+    /// Established connections are unaffected.
+    pub fn remove_listener(&mut self, id: ListenerId) {
+        self.listeners.retain(|x| x.id != id);
     }
 
     /// Get the next packet to transmit
     #[must_use]
-    pub fn poll_transmit(&mut self) -> Option<Transmit> {
+    pub fn poll_transmit(&mut self) -> Option<Transmit<A>> {
         self.transmits.pop_front()
     }
 
@@ -115,8 +171,8 @@ where
     pub fn handle_event(
         &mut self,
         ch: ConnectionHandle,
-        event: EndpointEvent,
-    ) -> Option<ConnectionEvent> {
+        event: EndpointEvent<A>,
+    ) -> Option<ConnectionEvent<A>> {
         use EndpointEventInner::*;
         match event.0 {
             NeedIdentifiers(now, n) => {
@@ -139,6 +195,24 @@ where
                     }
                 }
             }
+            HeaderCompression(remote) => {
+                if matches!(self.compressed_remotes.get(&remote), Some(&x) if x != ch) {
+                    // The peer moved to the address of another connection compressing headers, so
+                    // its compressed packets can't be told apart from that connection's
+                    debug!(?remote, "header compression address already in use");
+                    return None;
+                }
+                if let Some(old) = self.connections[ch].compressed_remote.replace(remote) {
+                    self.compressed_remotes.remove(&old);
+                }
+                self.compressed_remotes.insert(remote, ch);
+            }
+            Connected => {
+                self.stats.handshakes_completed += 1;
+            }
+            HandshakeFailed(failure) => {
+                self.stats.handshake_failures.record(failure);
+            }
             Drained => {
                 let conn = self.connections.remove(ch.0);
                 if conn.init_cid.len() > 0 {
@@ -148,6 +222,11 @@ where
                     self.connection_ids.remove(&cid);
                 }
                 self.connection_remotes.remove(&conn.initial_remote);
+                if let Some(remote) = conn.compressed_remote {
+                    if self.compressed_remotes.get(&remote) == Some(&ch) {
+                        self.compressed_remotes.remove(&remote);
+                    }
+                }
                 if let Some((remote, token)) = conn.reset_token {
                     self.connection_reset_tokens.remove(remote, token);
                 }
@@ -160,11 +239,19 @@ where
     pub fn handle(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         local_ip: Option<IpAddr>,
         ecn: Option<EcnCodepoint>,
         data: BytesMut,
-    ) -> Option<(ConnectionHandle, DatagramEvent<S>)> {
+    ) -> Option<(ConnectionHandle, DatagramEvent<S, A>)> {
+        let data = match self.decompress_header(&remote, data) {
+            Some(x) => x,
+            None => {
+                trace!("dropping compressed packet with unknown connection ID");
+                self.stats.dropped_packets += 1;
+                return None;
+            }
+        };
         let datagram_len = data.len();
         let (first_decode, remaining) = match PartialDecode::new(
             data,
@@ -179,9 +266,11 @@ where
             }) => {
                 if !self.is_server() {
                     debug!("dropping packet with unsupported version");
+                    self.stats.dropped_packets += 1;
                     return None;
                 }
                 trace!("sending version negotiation");
+                self.stats.version_negotiations_sent += 1;
                 // Negotiate versions
                 let mut buf = Vec::<u8>::new();
                 Header::VersionNegotiate {
@@ -203,11 +292,14 @@ where
                     contents: buf,
                     segment_size: None,
                     src_ip: local_ip,
+                    dscp: None,
+                    flow_label: None,
                 });
                 return None;
             }
             Err(e) => {
                 trace!("malformed header: {}", e);
+                self.stats.dropped_packets += 1;
                 return None;
             }
         };
@@ -276,10 +368,12 @@ where
                     "ignoring non-initial packet for unknown connection {}",
                     dst_cid
                 );
+                self.stats.dropped_packets += 1;
                 return None;
             }
             if datagram_len < MIN_INITIAL_SIZE as usize {
                 debug!("ignoring short initial for connection {}", dst_cid);
+                self.stats.dropped_packets += 1;
                 return None;
             }
 
@@ -290,6 +384,7 @@ where
                     .map(|(ch, conn)| (ch, DatagramEvent::NewConnection(conn))),
                 Err(e) => {
                     trace!("unable to decode initial packet: {}", e);
+                    self.stats.dropped_packets += 1;
                     None
                 }
             };
@@ -304,6 +399,7 @@ where
             self.stateless_reset(datagram_len, remote, local_ip, &dst_cid);
         } else {
             trace!("dropping unrecognized short packet without ID");
+            self.stats.dropped_packets += 1;
         }
         None
     }
@@ -311,7 +407,7 @@ where
     fn stateless_reset(
         &mut self,
         inciting_dgram_len: usize,
-        remote: SocketAddr,
+        remote: A,
         local_ip: Option<IpAddr>,
         dst_cid: &ConnectionId,
     ) {
@@ -324,11 +420,13 @@ where
             Some(headroom) if headroom > MIN_PADDING_LEN => headroom - 1,
             _ => {
                 debug!("ignoring unexpected {} byte packet: not larger than minimum stateless reset size", inciting_dgram_len);
+                self.stats.dropped_packets += 1;
                 return;
             }
         };
 
         debug!("sending stateless reset for {} to {}", dst_cid, remote);
+        self.stats.stateless_resets_sent += 1;
         let mut buf = Vec::<u8>::new();
         // Resets with at least this much padding can't possibly be distinguished from real packets
         const IDEAL_MIN_PADDING_LEN: usize = MIN_PADDING_LEN + MAX_CID_SIZE;
@@ -351,23 +449,72 @@ where
             contents: buf,
             segment_size: None,
             src_ip: local_ip,
+            dscp: None,
+            flow_label: None,
         });
     }
 
+    /// `config`, with header compression disabled if another connection with `remote` may use it
+    ///
+    /// Compressed packets are attributed to connections by their source address, so at most one
+    /// connection per remote address may compress headers.
+    fn transport_config_for(
+        &self,
+        config: &Arc<TransportConfig>,
+        remote: &A,
+    ) -> Arc<TransportConfig> {
+        if !config.header_compression || !self.compressed_remotes.contains_key(remote) {
+            return config.clone();
+        }
+        debug!(?remote, "header compression address already in use");
+        Arc::new(TransportConfig {
+            header_compression: false,
+            ..(**config).clone()
+        })
+    }
+
+    /// Restore the destination CID of a packet whose header was compressed by the peer
+    ///
+    /// Returns `None` if the packet was compressed but doesn't identify a known CID. See
+    /// `TransportConfig::header_compression`.
+    fn decompress_header(&self, remote: &A, data: BytesMut) -> Option<BytesMut> {
+        if data.is_empty() || data[0] & (LONG_HEADER_FORM | FIXED_BIT) != 0 {
+            return Some(data);
+        }
+        let ch = match self.compressed_remotes.get(remote) {
+            Some(&x) => x,
+            // Not a compressed packet, and will be dropped as malformed
+            None => return Some(data),
+        };
+        let mut rest = &data[1..];
+        let seq = rest.get_var().ok()?;
+        let cid = self.connections[ch].loc_cids.get(&seq)?;
+        let mut decompressed = BytesMut::with_capacity(1 + cid.len() + rest.len());
+        decompressed.put_u8(data[0] | FIXED_BIT);
+        decompressed.put_slice(cid);
+        decompressed.put_slice(rest);
+        Some(decompressed)
+    }
+
     /// Initiate a connection
     pub fn connect(
         &mut self,
+        now: Instant,
         config: ClientConfig<S>,
-        remote: SocketAddr,
+        remote: A,
         server_name: &str,
-    ) -> Result<(ConnectionHandle, Connection<S>), ConnectError> {
+    ) -> Result<(ConnectionHandle, Connection<S, A>), ConnectError<A>> {
         if self.is_full() {
             return Err(ConnectError::TooManyConnections);
         }
-        if remote.port() == 0 {
+        if remote.socket_addr().map_or(false, |x| x.port() == 0) {
             return Err(ConnectError::InvalidRemoteAddress(remote));
         }
-        let remote_id = RandomConnectionIdGenerator::new(MAX_CID_SIZE).generate_cid();
+        if self.local_cid_generator.cid_len() == 0 && self.connection_remotes.contains_key(&remote)
+        {
+            return Err(ConnectError::RemoteInUse(remote));
+        }
+        let remote_id = ConnectionId::new(&self.rng.gen::<[u8; MAX_CID_SIZE]>());
         trace!(initial_dcid = %remote_id);
         let (ch, conn) = self.add_connection(
             remote_id,
@@ -378,7 +525,7 @@ where
                 config,
                 server_name: server_name.into(),
             },
-            Instant::now(),
+            now,
         )?;
         Ok((ch, conn))
     }
@@ -388,7 +535,7 @@ where
         now: Instant,
         ch: ConnectionHandle,
         num: u64,
-    ) -> ConnectionEvent {
+    ) -> ConnectionEvent<A> {
         let mut ids = vec![];
         for _ in 0..num {
             let id = self.new_cid();
@@ -420,19 +567,20 @@ where
         &mut self,
         init_cid: ConnectionId,
         rem_cid: ConnectionId,
-        remote: SocketAddr,
+        remote: A,
         local_ip: Option<IpAddr>,
         opts: ConnectionOpts<S>,
         now: Instant,
-    ) -> Result<(ConnectionHandle, Connection<S>), ConnectError> {
+    ) -> Result<(ConnectionHandle, Connection<S, A>), ConnectError<A>> {
         let loc_cid = self.new_cid();
         let (server_config, tls, transport_config) = match opts {
             ConnectionOpts::Client {
                 config,
                 server_name,
             } => {
+                let transport = self.transport_config_for(&config.transport, &remote);
                 let params = TransportParameters::new::<S>(
-                    &config.transport,
+                    &transport,
                     &self.config,
                     self.local_cid_generator.as_ref(),
                     loc_cid,
@@ -440,21 +588,25 @@ where
                 );
                 (
                     None,
-                    config.crypto.start_session(&server_name, &params)?,
-                    config.transport,
+                    config
+                        .crypto
+                        .start_session(&server_name, &params)
+                        .map_err(ConnectError::with_address)?,
+                    transport,
                 )
             }
             ConnectionOpts::Server {
+                config,
                 orig_dst_cid,
                 retry_src_cid,
             } => {
-                let config = self.server_config.as_ref().unwrap();
+                let transport = self.transport_config_for(&config.transport, &remote);
                 let params = TransportParameters::new(
-                    &config.transport,
+                    &transport,
                     &self.config,
                     self.local_cid_generator.as_ref(),
                     loc_cid,
-                    Some(config),
+                    Some(&config),
                 );
                 let server_params = TransportParameters {
                     stateless_reset_token: Some(ResetToken::new(&*self.config.reset_key, &loc_cid)),
@@ -462,14 +614,16 @@ where
                     retry_src_cid,
                     ..params
                 };
-                (
-                    Some(config.clone()),
-                    config.crypto.start_session(&server_params),
-                    config.transport.clone(),
-                )
+                let tls = if config.pin_session_tickets {
+                    config.crypto.start_pinned_session(&server_params)
+                } else {
+                    config.crypto.start_session(&server_params)
+                };
+                (Some(config), tls, transport)
             }
         };
 
+        let header_compression = transport_config.header_compression;
         let conn = Connection::new(
             server_config,
             transport_config,
@@ -482,15 +636,22 @@ where
             self.local_cid_generator.as_ref(),
             now,
             self.config.initial_version,
+            StdRng::from_seed(self.rng.gen()),
         );
         let id = self.connections.insert(ConnectionMeta {
             init_cid,
             cids_issued: 0,
             loc_cids: iter::once((0, loc_cid)).collect(),
             initial_remote: remote,
+            compressed_remote: None,
             reset_token: None,
         });
         let ch = ConnectionHandle(id);
+        if header_compression {
+            // Claimed now, so that concurrent handshakes from the same address don't also compress
+            self.connections[ch].compressed_remote = Some(remote);
+            self.compressed_remotes.insert(remote, ch);
+        }
 
         if self.local_cid_generator.cid_len() > 0 {
             self.connection_ids.insert(loc_cid, ch);
@@ -503,13 +664,13 @@ where
     fn handle_first_packet(
         &mut self,
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         local_ip: Option<IpAddr>,
         ecn: Option<EcnCodepoint>,
         mut packet: Packet,
         rest: Option<BytesMut>,
         crypto: &Keys<S>,
-    ) -> Option<(ConnectionHandle, Connection<S>)> {
+    ) -> Option<(ConnectionHandle, Connection<S, A>)> {
         let (src_cid, dst_cid, token, packet_number) = match packet.header {
             Header::Initial {
                 src_cid,
@@ -533,23 +694,48 @@ where
             .is_err()
         {
             debug!(packet_number, "failed to authenticate initial packet");
+            self.stats.dropped_packets += 1;
             return None;
         };
 
         if !packet.reserved_bits_valid() {
             debug!("dropping connection attempt with invalid reserved bits");
+            self.stats.dropped_packets += 1;
             return None;
         }
+        let handshake_rate = self.handshake_rate.record(now);
 
         // Local CID used for stateless packets
         let temp_loc_cid = self.new_cid();
-        let server_config = self.server_config.as_ref().unwrap();
+        let listener = if self.listeners.is_empty() {
+            None
+        } else {
+            ClientHello::from_initial(Bytes::copy_from_slice(&packet.payload))
+                .and_then(|hello| self.listeners.iter().find(|x| x.filter.matches(&hello)))
+                .map(|x| (x.id, x.config.clone()))
+        };
+        let (listener, server_config) = match (listener, &self.server_config) {
+            (Some((id, config)), _) => (Some(id), config),
+            (None, Some(config)) if !self.reject_new_connections => (None, config.clone()),
+            (None, _) => {
+                debug!("refusing connection matching no listener");
+                self.stats.connections_refused += 1;
+                self.initial_close(
+                    remote,
+                    local_ip,
+                    crypto,
+                    &src_cid,
+                    &temp_loc_cid,
+                    TransportError::CONNECTION_REFUSED(""),
+                );
+                return None;
+            }
+        };
 
-        if self.connections.len() >= server_config.concurrent_connections as usize
-            || self.reject_new_connections
-            || self.is_full()
+        if self.connections.len() >= server_config.concurrent_connections as usize || self.is_full()
         {
             debug!("refusing connection");
+            self.stats.connections_refused += 1;
             self.initial_close(
                 remote,
                 local_ip,
@@ -561,14 +747,18 @@ where
             return None;
         }
 
+        // Tokens are only issued through Retry packets, which may also be sent at the request of an
+        // admission controller
+        let retry_enabled =
+            server_config.use_stateless_retry || server_config.admission_controller.is_some();
         if dst_cid.len() < 8
-            && (!server_config.use_stateless_retry
-                || dst_cid.len() != self.local_cid_generator.cid_len())
+            && (!retry_enabled || dst_cid.len() != self.local_cid_generator.cid_len())
         {
             debug!(
                 "rejecting connection due to invalid DCID length {}",
                 dst_cid.len()
             );
+            self.stats.handshake_failures.record(None);
             self.initial_close(
                 remote,
                 local_ip,
@@ -580,53 +770,19 @@ where
             return None;
         }
 
-        let (retry_src_cid, orig_dst_cid) = if server_config.use_stateless_retry {
-            if token.is_empty() {
-                // First Initial
-                let mut random_bytes = vec![0u8; RetryToken::RANDOM_BYTES_LEN];
-                self.rng.fill_bytes(&mut random_bytes);
-
-                let token = RetryToken {
-                    orig_dst_cid: dst_cid,
-                    issued: SystemTime::now(),
-                    random_bytes: &random_bytes,
-                }
-                .encode(&*server_config.token_key, &remote, &temp_loc_cid);
-
-                let header = Header::Retry {
-                    src_cid: temp_loc_cid,
-                    dst_cid: src_cid,
-                    version: self.config.initial_version,
-                };
-
-                let mut buf = Vec::new();
-                let encode = header.encode(&mut buf);
-                buf.put_slice(&token);
-                buf.extend_from_slice(&S::retry_tag(&dst_cid, &buf));
-                encode.finish::<S::PacketKey, S::HeaderKey>(&mut buf, &crypto.header.local, None);
-
-                self.transmits.push_back(Transmit {
-                    destination: remote,
-                    ecn: None,
-                    contents: buf,
-                    segment_size: None,
-                    src_ip: local_ip,
-                });
-                return None;
-            }
-
+        let (retry_src_cid, orig_dst_cid) = if retry_enabled && !token.is_empty() {
             match RetryToken::from_bytes(&*server_config.token_key, &remote, &dst_cid, &token) {
                 Ok(token)
-                    if token.issued
-                        + Duration::from_micros(
-                            self.server_config.as_ref().unwrap().retry_token_lifetime,
-                        )
+                    if token.issued + Duration::from_micros(server_config.retry_token_lifetime)
                         > SystemTime::now() =>
                 {
                     (Some(dst_cid), token.orig_dst_cid)
                 }
                 _ => {
                     debug!("rejecting invalid stateless retry token");
+                    self.stats
+                        .handshake_failures
+                        .record(Some(HandshakeFailure::InvalidToken));
                     self.initial_close(
                         remote,
                         local_ip,
@@ -641,7 +797,68 @@ where
         } else {
             (None, dst_cid)
         };
+        let validated = retry_src_cid.is_some();
+
+        let admission = match server_config.admission_controller {
+            Some(ref controller) => controller.admit(&EndpointLoad {
+                remote: remote.socket_addr(),
+                validated,
+                connections: self.connections.len(),
+                handshake_rate,
+                memory_usage: self.memory_usage,
+            }),
+            None => Admission::Accept,
+        };
+        if admission == Admission::Reject {
+            debug!("connection refused by admission controller");
+            self.stats.connections_refused += 1;
+            self.initial_close(
+                remote,
+                local_ip,
+                crypto,
+                &src_cid,
+                &temp_loc_cid,
+                TransportError::CONNECTION_REFUSED(""),
+            );
+            return None;
+        }
 
+        if !validated && (server_config.use_stateless_retry || admission == Admission::Retry) {
+            let mut random_bytes = vec![0u8; RetryToken::RANDOM_BYTES_LEN];
+            self.rng.fill_bytes(&mut random_bytes);
+
+            let token = RetryToken {
+                orig_dst_cid: dst_cid,
+                issued: SystemTime::now(),
+                random_bytes: &random_bytes,
+            }
+            .encode(&*server_config.token_key, &remote, &temp_loc_cid);
+
+            let header = Header::Retry {
+                src_cid: temp_loc_cid,
+                dst_cid: src_cid,
+                version: self.config.initial_version,
+            };
+
+            let mut buf = Vec::new();
+            let encode = header.encode(&mut buf);
+            buf.put_slice(&token);
+            buf.extend_from_slice(&S::retry_tag(&dst_cid, &buf));
+            encode.finish::<S::PacketKey, S::HeaderKey>(&mut buf, &crypto.header.local, None);
+
+            self.transmits.push_back(Transmit {
+                destination: remote,
+                ecn: None,
+                contents: buf,
+                segment_size: None,
+                src_ip: local_ip,
+                dscp: None,
+                flow_label: None,
+            });
+            return None;
+        }
+
+        let defer_handshake = server_config.defer_handshake;
         let (ch, mut conn) = self
             .add_connection(
                 dst_cid,
@@ -649,15 +866,22 @@ where
                 remote,
                 local_ip,
                 ConnectionOpts::Server {
+                    config: server_config,
                     retry_src_cid,
                     orig_dst_cid,
                 },
                 now,
             )
             .unwrap();
+        conn.listener = listener;
         if dst_cid.len() != 0 {
             self.connection_ids_initial.insert(dst_cid, ch);
         }
+        if defer_handshake {
+            trace!(id = ch.0, icid = %dst_cid, "connection incoming; handshake deferred");
+            conn.defer_first_packet(now, remote, ecn, packet_number, packet, rest);
+            return Some((ch, conn));
+        }
         match conn.handle_first_packet(now, remote, ecn, packet_number as u64, packet, rest) {
             Ok(()) => {
                 trace!(id = ch.0, icid = %dst_cid, "connection incoming");
@@ -665,6 +889,7 @@ where
             }
             Err(e) => {
                 debug!("handshake failed: {}", e);
+                self.stats.handshake_failures.record(e.handshake_failure());
                 self.handle_event(ch, EndpointEvent(EndpointEventInner::Drained));
                 if let ConnectionError::TransportError(e) = e {
                     self.initial_close(remote, local_ip, crypto, &src_cid, &temp_loc_cid, e);
@@ -676,7 +901,7 @@ where
 
     fn initial_close(
         &mut self,
-        destination: SocketAddr,
+        destination: A,
         local_ip: Option<IpAddr>,
         crypto: &Keys<S>,
         remote_id: &ConnectionId,
@@ -708,19 +933,39 @@ where
             contents: buf,
             segment_size: None,
             src_ip: local_ip,
+            dscp: None,
+            flow_label: None,
         })
     }
 
-    /// Unconditionally reject future incoming connections
+    /// Reject future incoming connections not matching any listener
     pub fn reject_new_connections(&mut self) {
         self.reject_new_connections = true;
     }
 
+    /// Report the application's current memory usage in bytes
+    ///
+    /// The most recently reported value is passed on to the `AdmissionController` configured in the
+    /// `ServerConfig`, if any. The endpoint itself doesn't otherwise interpret it.
+    pub fn set_memory_usage(&mut self, bytes: Option<u64>) {
+        self.memory_usage = bytes;
+    }
+
     /// Access the configuration used by this endpoint
     pub fn config(&self) -> &EndpointConfig<S> {
         &self.config
     }
 
+    /// Statistics aggregated across all connections, past and present
+    pub fn stats(&self) -> EndpointStats {
+        self.stats
+    }
+
+    /// Number of connections which haven't yet been drained, including those still handshaking
+    pub fn open_connections(&self) -> usize {
+        self.connections.len()
+    }
+
     #[cfg(test)]
     pub(crate) fn known_connections(&self) -> usize {
         let x = self.connections.len();
@@ -741,7 +986,11 @@ where
     ///
     /// We leave some space unused so that `new_cid` can be relied upon to finish quickly. We don't
     /// bother to check when CID longer than 4 bytes are used because 2^40 connections is a lot.
+    /// Single-connection endpoints are full as soon as they have a connection.
     fn is_full(&self) -> bool {
+        if self.config.single_connection {
+            return !self.connections.is_empty();
+        }
         self.local_cid_generator.cid_len() <= 4
             && self.local_cid_generator.cid_len() != 0
             && (2usize.pow(self.local_cid_generator.cid_len() as u32 * 8)
@@ -750,9 +999,10 @@ where
     }
 }
 
-impl<S> fmt::Debug for Endpoint<S>
+impl<S, A> fmt::Debug for Endpoint<S, A>
 where
     S: crypto::Session,
+    A: Address,
 {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("Endpoint<T>")
@@ -761,17 +1011,26 @@ where
             .field("connection_ids_initial", &self.connection_ids_initial)
             .field("connection_ids", &self.connection_ids)
             .field("connection_remotes", &self.connection_remotes)
+            .field("compressed_remotes", &self.compressed_remotes)
             .field("connection_reset_tokens", &self.connection_reset_tokens)
             .field("connections", &self.connections)
             .field("config", &self.config)
             .field("server_config", &self.server_config)
+            .field(
+                "listeners",
+                &self
+                    .listeners
+                    .iter()
+                    .map(|x| (x.id, &x.filter))
+                    .collect::<Vec<_>>(),
+            )
             .field("reject_new_connections", &self.reject_new_connections)
             .finish()
     }
 }
 
 #[derive(Debug)]
-pub(crate) struct ConnectionMeta {
+pub(crate) struct ConnectionMeta<A> {
     init_cid: ConnectionId,
     /// Number of local connection IDs that have been issued in NEW_CONNECTION_ID frames.
     cids_issued: u64,
@@ -780,10 +1039,67 @@ pub(crate) struct ConnectionMeta {
     ///
     /// Only needed to support connections with zero-length CIDs, which cannot migrate, so we don't
     /// bother keeping it up to date.
-    initial_remote: SocketAddr,
+    initial_remote: A,
+    /// Remote address from which packets with compressed headers are accepted, if any
+    compressed_remote: Option<A>,
     /// Reset token provided by the peer for the CID we're currently sending to, and the address
     /// being sent to
-    reset_token: Option<(SocketAddr, ResetToken)>,
+    reset_token: Option<(A, ResetToken)>,
+}
+
+/// Endpoint statistics, aggregated across all connections
+#[derive(Debug, Default, Copy, Clone)]
+#[non_exhaustive]
+pub struct EndpointStats {
+    /// Number of handshakes that completed successfully
+    pub handshakes_completed: u64,
+    /// Number of handshakes that failed, by cause
+    pub handshake_failures: HandshakeFailureStats,
+    /// Number of incoming connection attempts refused, e.g. by an admission controller or because
+    /// too many connections are open
+    pub connections_refused: u64,
+    /// Number of version negotiation packets sent in response to unsupported versions
+    pub version_negotiations_sent: u64,
+    /// Number of stateless resets sent in response to packets for unknown connections
+    pub stateless_resets_sent: u64,
+    /// Number of packets dropped without being associated with a connection, e.g. because they
+    /// were malformed or failed to authenticate
+    pub dropped_packets: u64,
+}
+
+/// Number of failed handshakes, by cause
+///
+/// See [`HandshakeFailure`] for the causes.
+#[derive(Debug, Default, Copy, Clone)]
+#[non_exhaustive]
+pub struct HandshakeFailureStats {
+    /// Handshakes aborted with a TLS alert, by either side
+    pub tls_alert: u64,
+    /// Handshakes failed due to an invalid address validation token
+    pub invalid_token: u64,
+    /// Handshakes failed for lack of a common version
+    pub version_mismatch: u64,
+    /// Handshakes that didn't complete in time
+    pub timed_out: u64,
+    /// Handshakes abandoned because the server never responded
+    pub udp_blocked: u64,
+    /// Handshakes that failed for any other reason, e.g. because either application closed the
+    /// connection
+    pub other: u64,
+}
+
+impl HandshakeFailureStats {
+    pub(crate) fn record(&mut self, failure: Option<HandshakeFailure>) {
+        let counter = match failure {
+            Some(HandshakeFailure::TlsAlert { .. }) => &mut self.tls_alert,
+            Some(HandshakeFailure::InvalidToken) => &mut self.invalid_token,
+            Some(HandshakeFailure::VersionMismatch) => &mut self.version_mismatch,
+            Some(HandshakeFailure::TimedOut(_)) => &mut self.timed_out,
+            Some(HandshakeFailure::UdpBlocked) => &mut self.udp_blocked,
+            None => &mut self.other,
+        };
+        *counter += 1;
+    }
 }
 
 /// Internal identifier for a `Connection` currently associated with an endpoint
@@ -796,28 +1112,29 @@ impl From<ConnectionHandle> for usize {
     }
 }
 
-impl Index<ConnectionHandle> for Slab<ConnectionMeta> {
-    type Output = ConnectionMeta;
-    fn index(&self, ch: ConnectionHandle) -> &ConnectionMeta {
+impl<A> Index<ConnectionHandle> for Slab<ConnectionMeta<A>> {
+    type Output = ConnectionMeta<A>;
+    fn index(&self, ch: ConnectionHandle) -> &ConnectionMeta<A> {
         &self[ch.0]
     }
 }
 
-impl IndexMut<ConnectionHandle> for Slab<ConnectionMeta> {
-    fn index_mut(&mut self, ch: ConnectionHandle) -> &mut ConnectionMeta {
+impl<A> IndexMut<ConnectionHandle> for Slab<ConnectionMeta<A>> {
+    fn index_mut(&mut self, ch: ConnectionHandle) -> &mut ConnectionMeta<A> {
         &mut self[ch.0]
     }
 }
 
 /// Event resulting from processing a single datagram
-pub enum DatagramEvent<S>
+pub enum DatagramEvent<S, A = SocketAddr>
 where
     S: crypto::Session,
+    A: Address,
 {
     /// The datagram is redirected to its `Connection`
-    ConnectionEvent(ConnectionEvent),
+    ConnectionEvent(ConnectionEvent<A>),
     /// The datagram has resulted in starting a new `Connection`
-    NewConnection(Connection<S>),
+    NewConnection(Connection<S, A>),
 }
 
 enum ConnectionOpts<S: crypto::Session> {
@@ -826,16 +1143,24 @@ enum ConnectionOpts<S: crypto::Session> {
         server_name: String,
     },
     Server {
+        config: Arc<ServerConfig<S>>,
         retry_src_cid: Option<ConnectionId>,
         orig_dst_cid: ConnectionId,
     },
 }
 
+/// A server configuration used for incoming connections matching a filter
+struct Listener<S: crypto::Session> {
+    id: ListenerId,
+    filter: ListenerFilter,
+    config: Arc<ServerConfig<S>>,
+}
+
 /// Errors in the parameters being used to create a new connection
 ///
 /// These arise before any I/O has been performed.
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
-pub enum ConnectError {
+pub enum ConnectError<A = SocketAddr> {
     /// The endpoint can no longer create new connections
     ///
     /// Indicates that a necessary component of the endpoint has been dropped or otherwise disabled.
@@ -843,7 +1168,8 @@ pub enum ConnectError {
     EndpointStopping,
     /// The number of active connections on the local endpoint is at the limit
     ///
-    /// Try using longer connection IDs.
+    /// Try using longer connection IDs, or wait for the existing connection to be closed if the
+    /// endpoint is configured with `EndpointConfig::single_connection`.
     #[error("too many connections")]
     TooManyConnections,
     /// The domain name supplied was malformed
@@ -852,22 +1178,43 @@ pub enum ConnectError {
     /// The transport configuration was invalid
     #[error("transport configuration error: {0}")]
     Config(#[source] ConfigError),
-    /// The remote [`SocketAddr`] supplied was malformed
+    /// The remote address supplied was malformed
     ///
     /// Examples include attempting to connect to port 0, or using an inappropriate address family.
     #[error("invalid remote address: {0}")]
-    InvalidRemoteAddress(SocketAddr),
+    InvalidRemoteAddress(A),
+    /// A connection to the remote address supplied already exists
+    ///
+    /// Only reported when the local endpoint uses zero-length connection IDs, as incoming packets
+    /// could then only be told apart by their source address. Use nonzero-length connection IDs to
+    /// open multiple connections to the same remote.
+    #[error("remote address already in use: {0}")]
+    RemoteInUse(A),
+}
+
+impl ConnectError {
+    /// Convert an error which can't concern a remote address, e.g. from a cryptographic session
+    fn with_address<A>(self) -> ConnectError<A> {
+        use ConnectError::*;
+        match self {
+            EndpointStopping => EndpointStopping,
+            TooManyConnections => TooManyConnections,
+            InvalidDnsName(x) => InvalidDnsName(x),
+            Config(x) => Config(x),
+            InvalidRemoteAddress(_) | RemoteInUse(_) => unreachable!("error concerns an address"),
+        }
+    }
 }
 
 /// Reset Tokens which are associated with peer socket addresses
 ///
-/// The standard `HashMap` is used since both `SocketAddr` and `ResetToken` are
+/// The standard `HashMap` is used since both addresses and `ResetToken`s are
 /// peer generated and might be usable for hash collision attacks.
-#[derive(Default, Debug)]
-struct ResetTokenTable(HashMap<SocketAddr, HashMap<ResetToken, ConnectionHandle>>);
+#[derive(Debug)]
+struct ResetTokenTable<A>(HashMap<A, HashMap<ResetToken, ConnectionHandle>>);
 
-impl ResetTokenTable {
-    fn insert(&mut self, remote: SocketAddr, token: ResetToken, ch: ConnectionHandle) -> bool {
+impl<A: Address> ResetTokenTable<A> {
+    fn insert(&mut self, remote: A, token: ResetToken, ch: ConnectionHandle) -> bool {
         self.0
             .entry(remote)
             .or_default()
@@ -875,7 +1222,7 @@ impl ResetTokenTable {
             .is_some()
     }
 
-    fn remove(&mut self, remote: SocketAddr, token: ResetToken) {
+    fn remove(&mut self, remote: A, token: ResetToken) {
         use std::collections::hash_map::Entry;
         match self.0.entry(remote) {
             Entry::Vacant(_) => {}
@@ -888,8 +1235,14 @@ impl ResetTokenTable {
         }
     }
 
-    fn get(&self, remote: SocketAddr, token: &[u8]) -> Option<&ConnectionHandle> {
+    fn get(&self, remote: A, token: &[u8]) -> Option<&ConnectionHandle> {
         let token = ResetToken::from(<[u8; RESET_TOKEN_SIZE]>::try_from(token).ok()?);
         self.0.get(&remote)?.get(&token)
     }
 }
+
+impl<A> Default for ResetTokenTable<A> {
+    fn default() -> Self {
+        Self(HashMap::default())
+    }
+}
@@ -0,0 +1,81 @@
+use thiserror::Error;
+
+use crate::VarInt;
+
+/// Define an enum of application error codes, convertible to and from `VarInt`
+///
+/// Application protocols close connections, reset streams and stop streams with error codes of
+/// their own choosing. This generates an enum naming them, which converts into the `VarInt` taken
+/// by `close()`, `reset()` and `stop()`, and back with `TryFrom` from the `VarInt` found in
+/// `ApplicationClose`, `ReadError::Reset` and `WriteError::Stopped`, failing for codes the enum
+/// doesn't define. Codes are `u32`s, so they always fit in a `VarInt`.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use quinn_proto::{error_codes, VarInt};
+///
+/// error_codes! {
+///     /// Reasons for abandoning a request
+///     pub enum RequestError {
+///         /// The request was malformed
+///         Malformed = 0x10,
+///         /// The client lost interest in the response
+///         Cancelled = 0x11,
+///     }
+/// }
+///
+/// let code = VarInt::from(RequestError::Cancelled);
+/// assert_eq!(code, VarInt::from_u32(0x11));
+/// assert_eq!(RequestError::try_from(code), Ok(RequestError::Cancelled));
+/// assert!(RequestError::try_from(VarInt::from_u32(0x12)).is_err());
+/// ```
+#[macro_export]
+macro_rules! error_codes {
+    {
+        $(#[$attr:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$variant_attr:meta])* $variant:ident = $code:expr),* $(,)?
+        }
+    } => {
+        $(#[$attr])*
+        #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+        $vis enum $name {
+            $($(#[$variant_attr])* $variant,)*
+        }
+
+        impl $name {
+            /// The code identifying this error on the wire
+            pub const fn code(self) -> $crate::VarInt {
+                match self {
+                    $($name::$variant => $crate::VarInt::from_u32($code),)*
+                }
+            }
+        }
+
+        impl ::std::convert::From<$name> for $crate::VarInt {
+            fn from(x: $name) -> Self {
+                x.code()
+            }
+        }
+
+        impl ::std::convert::TryFrom<$crate::VarInt> for $name {
+            type Error = $crate::UnknownErrorCode;
+
+            fn try_from(code: $crate::VarInt) -> Result<Self, Self::Error> {
+                $(
+                    if code == $crate::VarInt::from_u32($code) {
+                        return Ok($name::$variant);
+                    }
+                )*
+                Err($crate::UnknownErrorCode(code))
+            }
+        }
+    };
+}
+
+/// An error code not defined by the enum it was converted to
+///
+/// See [`error_codes!`](crate::error_codes).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Error)]
+#[error("unknown error code {0}")]
+pub struct UnknownErrorCode(pub VarInt);
@@ -25,6 +25,12 @@ use std::{
     time::Duration,
 };
 
+mod address;
+pub use crate::address::Address;
+
+mod admission;
+pub use crate::admission::{Admission, AdmissionController, EndpointLoad};
+
 mod cid_queue;
 #[doc(hidden)]
 pub mod coding;
@@ -40,24 +46,40 @@ pub use varint::{VarInt, VarIntBoundsExceeded};
 
 mod connection;
 pub use crate::connection::{
-    BytesSource, Chunk, Chunks, ConnectionError, ConnectionStats, Event, FinishError, ReadError,
-    ReadableError, RecvStream, SendDatagramError, SendStream, StreamEvent, Streams, UnknownStream,
-    WriteError, Written,
+    BytesSource, Chunk, Chunks, Coalescing, ConnectionError, ConnectionStats, DatagramTransform,
+    DrainedStream, EarlyData, Event, FinishError, HandshakeFailure, HandshakePhase,
+    HandshakeSummary, LatencyHistogram, PowerMode, ReadError, ReadableError, RecvStream,
+    RecvStreamStats, Reliability, SendDatagramError, SendStream, SendStreamStats, StatelessReset,
+    StreamEvent, StreamLatencyStats, Streams, UnknownStream, WriteError, Written,
 };
 
 mod config;
-pub use config::{ConfigError, TransportConfig};
+pub use config::{
+    ConfigError, ConnectionQuota, CreditPolicy, MtuDiscoveryConfig, PaddingPolicy, TransportConfig,
+    TransportProfile,
+};
 
 pub mod crypto;
 #[cfg(feature = "rustls")]
 pub use crypto::types::*;
 
+mod error_code;
+pub use crate::error_code::UnknownErrorCode;
+
 mod frame;
 use crate::frame::Frame;
 pub use crate::frame::{ApplicationClose, ConnectionClose, Datagram};
 
 mod endpoint;
-pub use crate::endpoint::{ConnectError, ConnectionHandle, DatagramEvent};
+pub use crate::endpoint::{
+    ConnectError, ConnectionHandle, DatagramEvent, EndpointStats, HandshakeFailureStats,
+};
+
+mod listener;
+pub use crate::listener::{ListenerFilter, ListenerId};
+
+mod rtt_cache;
+pub use crate::rtt_cache::{MemoryRttCache, RttCache};
 
 mod shared;
 pub use crate::shared::{ConnectionEvent, ConnectionId, EcnCodepoint, EndpointEvent};
@@ -70,8 +92,11 @@ pub mod congestion;
 mod cid_generator;
 pub use crate::cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator};
 
+mod tap;
+pub use crate::tap::{Tap, TapDirection};
+
 mod token;
-use token::{ResetToken, RetryToken};
+use token::{AuthToken, ResetToken, RetryToken};
 
 /// Types that are generic over the crypto protocol implementation
 pub mod generic {
@@ -287,9 +312,9 @@ impl coding::Codec for StreamId {
 
 /// An outgoing packet
 #[derive(Debug)]
-pub struct Transmit {
+pub struct Transmit<A = SocketAddr> {
     /// The socket this datagram should be sent to
-    pub destination: SocketAddr,
+    pub destination: A,
     /// Explicit congestion notification bits to set on the packet
     pub ecn: Option<EcnCodepoint>,
     /// Contents of the datagram
@@ -299,6 +324,10 @@ pub struct Transmit {
     pub segment_size: Option<usize>,
     /// Optional source IP address for the datagram
     pub src_ip: Option<IpAddr>,
+    /// Differentiated services code point to mark the datagram with, if any
+    pub dscp: Option<u8>,
+    /// IPv6 flow label to mark the datagram with, if any
+    pub flow_label: Option<u32>,
 }
 
 //
@@ -314,3 +343,5 @@ const MIN_MTU: u16 = 1232;
 const TIMER_GRANULARITY: Duration = Duration::from_millis(1);
 /// Maximum number of streams that can be uniquely identified by a stream ID
 const MAX_STREAM_COUNT: u64 = 1 << 60;
+/// Largest client authentication token that fits comfortably in a ClientHello
+const MAX_AUTH_TOKEN_SIZE: usize = 1024;
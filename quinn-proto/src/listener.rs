@@ -0,0 +1,157 @@
+use bytes::Bytes;
+
+use crate::frame::{self, Frame};
+
+/// Identifies a listener registered through `Endpoint::add_listener`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ListenerId(pub(crate) u64);
+
+/// Selects the incoming connections a listener is responsible for
+///
+/// Connections are matched against the server name and application protocols offered in the
+/// client's TLS ClientHello, allowing a single endpoint to serve several applications with
+/// distinct server configurations. A filter without server names matches any server name, and a
+/// filter without protocols matches any protocols.
+#[derive(Debug, Clone, Default)]
+pub struct ListenerFilter {
+    server_names: Vec<String>,
+    protocols: Vec<Vec<u8>>,
+}
+
+impl ListenerFilter {
+    /// Match connections to `name`, in addition to any server names already added
+    pub fn server_name(&mut self, name: &str) -> &mut Self {
+        self.server_names.push(name.into());
+        self
+    }
+
+    /// Match connections offering `protocol`, in addition to any protocols already added
+    pub fn protocol(&mut self, protocol: &[u8]) -> &mut Self {
+        self.protocols.push(protocol.to_vec());
+        self
+    }
+
+    pub(crate) fn matches(&self, hello: &ClientHello) -> bool {
+        let name_matches = self.server_names.is_empty()
+            || hello.server_name.as_ref().map_or(false, |name| {
+                self.server_names
+                    .iter()
+                    .any(|x| x.eq_ignore_ascii_case(name))
+            });
+        let protocol_matches = self.protocols.is_empty()
+            || hello
+                .protocols
+                .iter()
+                .any(|offered| self.protocols.iter().any(|x| x == offered));
+        name_matches && protocol_matches
+    }
+}
+
+/// The parts of a TLS ClientHello relevant for selecting a listener
+#[derive(Debug, Default)]
+pub(crate) struct ClientHello {
+    server_name: Option<String>,
+    protocols: Vec<Vec<u8>>,
+}
+
+impl ClientHello {
+    /// Extract the ClientHello from the decrypted payload of a client's first Initial packet
+    ///
+    /// Returns `None` if the payload doesn't contain the whole ClientHello, e.g. if it spans
+    /// several packets, or if it's malformed.
+    pub(crate) fn from_initial(payload: Bytes) -> Option<Self> {
+        let mut crypto = frame::Iter::new(payload)
+            .filter_map(|frame| match frame {
+                Frame::Crypto(x) => Some(x),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        crypto.sort_unstable_by_key(|x| x.offset);
+        let mut data = Vec::new();
+        for frame in crypto {
+            let offset = frame.offset as usize;
+            if offset > data.len() {
+                break;
+            }
+            let end = offset + frame.data.len();
+            if end > data.len() {
+                data.extend_from_slice(&frame.data[data.len() - offset..]);
+            }
+        }
+        Self::decode(&data)
+    }
+
+    fn decode(mut buf: &[u8]) -> Option<Self> {
+        // Handshake message header
+        if take(&mut buf, 1)? != [CLIENT_HELLO] {
+            return None;
+        }
+        let len = take(&mut buf, 3)?;
+        let len = (len[0] as usize) << 16 | (len[1] as usize) << 8 | len[2] as usize;
+        let mut buf = take(&mut buf, len)?;
+        // Legacy version and random
+        take(&mut buf, 2 + 32)?;
+        // Legacy session ID, cipher suites and legacy compression methods
+        take_u8_prefixed(&mut buf)?;
+        take_u16_prefixed(&mut buf)?;
+        take_u8_prefixed(&mut buf)?;
+
+        let mut hello = ClientHello::default();
+        let mut extensions = take_u16_prefixed(&mut buf)?;
+        while !extensions.is_empty() {
+            let ty = take_u16(&mut extensions)?;
+            let mut data = take_u16_prefixed(&mut extensions)?;
+            match ty {
+                EXT_SERVER_NAME => {
+                    let mut names = take_u16_prefixed(&mut data)?;
+                    while !names.is_empty() {
+                        let name_type = take(&mut names, 1)?[0];
+                        let name = take_u16_prefixed(&mut names)?;
+                        if name_type == HOST_NAME {
+                            hello.server_name = Some(String::from_utf8(name.to_vec()).ok()?);
+                        }
+                    }
+                }
+                EXT_ALPN => {
+                    let mut protocols = take_u16_prefixed(&mut data)?;
+                    while !protocols.is_empty() {
+                        hello
+                            .protocols
+                            .push(take_u8_prefixed(&mut protocols)?.to_vec());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(hello)
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (x, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(x)
+}
+
+fn take_u16(buf: &mut &[u8]) -> Option<u16> {
+    let x = take(buf, 2)?;
+    Some(u16::from_be_bytes([x[0], x[1]]))
+}
+
+fn take_u8_prefixed<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = take(buf, 1)?[0] as usize;
+    take(buf, len)
+}
+
+fn take_u16_prefixed<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = take_u16(buf)? as usize;
+    take(buf, len)
+}
+
+const CLIENT_HELLO: u8 = 1;
+const EXT_SERVER_NAME: u16 = 0;
+const EXT_ALPN: u16 = 16;
+const HOST_NAME: u8 = 0;
@@ -0,0 +1,58 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+/// Remembers the round-trip time to servers between connections
+///
+/// Consulted by clients when connecting, to start loss recovery and pacing from the RTT observed
+/// on an earlier connection to the same server name rather than from
+/// [`TransportConfig::initial_rtt()`], and updated when a connection closes. Entries can also be
+/// inserted up front, to set the initial RTT for particular destinations, e.g. servers known to
+/// be reached over a satellite link.
+///
+/// See [`TransportConfig::rtt_cache()`].
+///
+/// [`TransportConfig::initial_rtt()`]: crate::TransportConfig::initial_rtt
+/// [`TransportConfig::rtt_cache()`]: crate::TransportConfig::rtt_cache
+pub trait RttCache: Send + Sync {
+    /// The RTT to assume for a new connection to `server_name`, if known
+    fn get(&self, server_name: &str) -> Option<Duration>;
+
+    /// Record the RTT of a connection to `server_name` as it closes
+    fn insert(&self, server_name: &str, rtt: Duration);
+}
+
+/// An [`RttCache`] held in memory, for the lifetime of the process
+#[derive(Debug)]
+pub struct MemoryRttCache {
+    capacity: usize,
+    entries: Mutex<HashMap<String, Duration>>,
+}
+
+impl MemoryRttCache {
+    /// Create a cache holding RTTs for up to `capacity` server names
+    ///
+    /// Once full, an arbitrary entry is evicted for every new server name.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl RttCache for MemoryRttCache {
+    fn get(&self, server_name: &str) -> Option<Duration> {
+        self.entries.lock().unwrap().get(server_name).copied()
+    }
+
+    fn insert(&self, server_name: &str, rtt: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(server_name) {
+            let evicted = entries.keys().next().cloned().unwrap();
+            entries.remove(&evicted);
+        }
+        entries.insert(server_name.into(), rtt);
+    }
+}
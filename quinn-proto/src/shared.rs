@@ -2,18 +2,18 @@ use std::{fmt, net::SocketAddr, time::Instant};
 
 use bytes::{Buf, BufMut, BytesMut};
 
-use crate::{coding::BufExt, packet::PartialDecode, ResetToken, MAX_CID_SIZE};
+use crate::{coding::BufExt, packet::PartialDecode, HandshakeFailure, ResetToken, MAX_CID_SIZE};
 
 /// Events sent from an Endpoint to a Connection
 #[derive(Debug)]
-pub struct ConnectionEvent(pub(crate) ConnectionEventInner);
+pub struct ConnectionEvent<A = SocketAddr>(pub(crate) ConnectionEventInner<A>);
 
 #[derive(Debug)]
-pub(crate) enum ConnectionEventInner {
+pub(crate) enum ConnectionEventInner<A> {
     /// A datagram has been received for the Connection
     Datagram {
         now: Instant,
-        remote: SocketAddr,
+        remote: A,
         ecn: Option<EcnCodepoint>,
         first_decode: PartialDecode,
         remaining: Option<BytesMut>,
@@ -24,9 +24,9 @@ pub(crate) enum ConnectionEventInner {
 
 /// Events sent from a Connection to an Endpoint
 #[derive(Debug)]
-pub struct EndpointEvent(pub(crate) EndpointEventInner);
+pub struct EndpointEvent<A = SocketAddr>(pub(crate) EndpointEventInner<A>);
 
-impl EndpointEvent {
+impl<A> EndpointEvent<A> {
     /// Construct an event that indicating that a `Connection` will no longer emit events
     ///
     /// Useful for notifying an `Endpoint` that a `Connection` has been destroyed outside of the
@@ -39,21 +39,27 @@ impl EndpointEvent {
     ///
     /// Useful for determining when connection-related event loop state can be freed.
     pub fn is_drained(&self) -> bool {
-        self.0 == EndpointEventInner::Drained
+        matches!(self.0, EndpointEventInner::Drained)
     }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) enum EndpointEventInner {
+pub(crate) enum EndpointEventInner<A> {
+    /// The handshake completed successfully
+    Connected,
+    /// The connection was lost before the handshake completed
+    HandshakeFailed(Option<HandshakeFailure>),
     /// The connection has been drained
     Drained,
     /// The reset token and/or address eligible for generating resets has been updated
-    ResetToken(SocketAddr, ResetToken),
+    ResetToken(A, ResetToken),
     /// The connection needs connection identifiers
     NeedIdentifiers(Instant, u64),
     /// Stop routing connection ID for this sequence number to the connection
     /// When `bool == true`, a new connection ID will be issued to peer
     RetireConnectionId(Instant, u64, bool),
+    /// Packets from this address, and no longer the previous one, may have compressed headers
+    HeaderCompression(A),
 }
 
 /// Protocol-level identifier for a connection.
@@ -0,0 +1,34 @@
+use crate::StreamId;
+
+/// Receives copies of the application data exchanged on a connection
+///
+/// Attached to a connection through `Connection::set_tap`. Intended for environments which must
+/// archive plaintext traffic. Data is reported as it crosses the boundary between the application
+/// and the transport: outgoing data when it's accepted by a write, incoming data when it's read by
+/// the application. Data which is never read, e.g. because the stream was stopped, is therefore
+/// not reported.
+///
+/// The tap is invoked synchronously while the connection's state is being accessed, so
+/// implementations should return quickly, e.g. by queuing the data for processing elsewhere.
+pub trait Tap: Send + Sync {
+    /// Called with a contiguous range of stream data starting at `offset`
+    ///
+    /// Data read using unordered reads may be reported out of order.
+    fn stream_data(&self, id: StreamId, direction: TapDirection, offset: u64, data: &[u8]) {
+        let _ = (id, direction, offset, data);
+    }
+
+    /// Called with the contents of an application datagram
+    fn datagram(&self, direction: TapDirection, data: &[u8]) {
+        let _ = (direction, data);
+    }
+}
+
+/// Whether data reported to a [`Tap`] is being sent or was received
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TapDirection {
+    /// Data written by the local application
+    Sent,
+    /// Data read by the local application
+    Received,
+}
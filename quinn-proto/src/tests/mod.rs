@@ -1,7 +1,7 @@
 use std::{
     convert::TryInto,
     net::{Ipv4Addr, Ipv6Addr, SocketAddr},
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
@@ -61,10 +61,10 @@ fn version_negotiate_client() {
         }),
         None,
     );
+    let now = Instant::now();
     let (_, mut client_ch) = client
-        .connect(client_config(), server_addr, "localhost")
+        .connect(now, client_config(), server_addr, "localhost")
         .unwrap();
-    let now = Instant::now();
     let opt_event = client.handle(
         now,
         server_addr,
@@ -129,6 +129,77 @@ fn stateless_retry() {
     pair.connect();
 }
 
+#[test]
+fn admission_retry() {
+    let _guard = subscribe();
+    struct RetryUnvalidated(Mutex<Vec<bool>>);
+    impl AdmissionController for RetryUnvalidated {
+        fn admit(&self, load: &EndpointLoad) -> Admission {
+            self.0.lock().unwrap().push(load.validated);
+            match load.validated {
+                true => Admission::Accept,
+                false => Admission::Retry,
+            }
+        }
+    }
+
+    let controller = Arc::new(RetryUnvalidated(Mutex::new(Vec::new())));
+    let mut server = server_config();
+    server.admission_controller = Some(controller.clone());
+    let mut pair = Pair::new(Default::default(), server);
+    pair.connect();
+    assert_eq!(*controller.0.lock().unwrap(), [false, true]);
+}
+
+#[test]
+fn admission_reject() {
+    let _guard = subscribe();
+    struct RejectAll;
+    impl AdmissionController for RejectAll {
+        fn admit(&self, _: &EndpointLoad) -> Admission {
+            Admission::Reject
+        }
+    }
+
+    let mut server = server_config();
+    server.admission_controller(RejectAll);
+    let mut pair = Pair::new(Default::default(), server);
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::CONNECTION_REFUSED,
+                ..
+            }),
+        })
+    );
+    assert_eq!(pair.server.connections.len(), 0);
+}
+
+#[test]
+fn deferred_handshake() {
+    let _guard = subscribe();
+    let mut server = server_config();
+    server.defer_handshake(true);
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
+    );
+    let _ = chunks.finalize();
+}
+
 #[test]
 fn server_stateless_reset() {
     let _guard = subscribe();
@@ -156,6 +227,9 @@ fn server_stateless_reset() {
             reason: ConnectionError::Reset
         })
     );
+    let reset = *pair.client_conn_mut(client_ch).stateless_reset().unwrap();
+    assert_eq!(reset.remote, pair.server.addr);
+    assert!(reset.received <= pair.time);
 }
 
 #[test]
@@ -213,6 +287,26 @@ fn export_keying_material() {
     assert_eq!(&client_buf[..], &server_buf[..]);
 }
 
+#[test]
+fn channel_binding() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let client = pair.client_conn_mut(client_ch).channel_binding().unwrap();
+    let server = pair.server_conn_mut(server_ch).channel_binding().unwrap();
+    assert!(server.verify(client.as_bytes()));
+
+    // Another connection is bound differently
+    let mut other_pair = Pair::default();
+    let (client_ch, _) = other_pair.connect();
+    let other = other_pair
+        .client_conn_mut(client_ch)
+        .channel_binding()
+        .unwrap();
+    assert!(!server.verify(other.as_bytes()));
+    assert!(!server.verify(&client.as_bytes()[..16]));
+}
+
 #[test]
 fn finish_stream_simple() {
     let _guard = subscribe();
@@ -229,7 +323,7 @@ fn finish_stream_simple() {
 
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
     );
     assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
     assert_eq!(pair.client_streams(client_ch).send_streams(), 0);
@@ -253,6 +347,119 @@ fn finish_stream_simple() {
     let _ = chunks.finalize();
 }
 
+#[test]
+fn drain_streams() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    let drained = pair.client_conn_mut(client_ch).drain_streams();
+    assert_eq!(drained.len(), 1);
+    assert_eq!(
+        (drained[0].id, drained[0].unacked, drained[0].unread),
+        (s, 5, 0)
+    );
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).drain_streams().is_empty());
+
+    // Unread data is reported whether or not the stream has been accepted
+    let drained = pair.server_conn_mut(server_ch).drain_streams();
+    assert_eq!(drained.len(), 1);
+    assert_eq!(
+        (drained[0].id, drained[0].unacked, drained[0].unread),
+        (s, 0, 5)
+    );
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    assert_matches!(chunks.next(2), Ok(Some(chunk)) if chunk.bytes == MSG[..2]);
+    let _ = chunks.finalize();
+    let drained = pair.server_conn_mut(server_ch).drain_streams();
+    assert_eq!(drained[0].unread, 3);
+}
+
+#[test]
+fn header_compression() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.header_compression(true);
+    let transport = Arc::new(transport);
+    let server = ServerConfig {
+        transport: transport.clone(),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport,
+        ..client_config()
+    });
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive_client();
+    let datagram = &pair.server.inbound.back().unwrap().2;
+    assert_eq!(
+        datagram[0] & (packet::LONG_HEADER_FORM | packet::FIXED_BIT),
+        0
+    );
+    // The 8-byte CID is replaced by a 1-byte sequence number
+    assert_eq!(datagram[1], 0);
+    pair.drive();
+
+    // The server's acknowledgements were decompressed too
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
+    );
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
+    );
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn poll_transmit_into() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    let now = pair.time;
+    let conn = pair.client_conn_mut(client_ch);
+
+    while conn.poll_transmit(now, 10).is_some() {}
+
+    let mut buf = Vec::with_capacity(64 * 1024);
+    buf.extend_from_slice(b"stale");
+    let ptr = buf.as_ptr();
+    assert_matches!(conn.poll_transmit_into(now, 10, &mut buf), None);
+    // Left in place, cleared, when there's nothing to send
+    assert!(buf.is_empty());
+    assert_eq!(buf.as_ptr(), ptr);
+
+    conn.ping();
+    let transmit = conn.poll_transmit_into(now, 10, &mut buf).unwrap();
+    assert_eq!(transmit.contents.as_ptr(), ptr);
+    assert_eq!(buf.capacity(), 0);
+}
+
 #[test]
 fn reset_stream() {
     let _guard = subscribe();
@@ -282,6 +489,119 @@ fn reset_stream() {
     assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
 }
 
+#[test]
+fn stream_deadline() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    const DEADLINE: Duration = Duration::from_millis(10);
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .set_reliability(Reliability::Deadline(DEADLINE))
+        .unwrap();
+
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive_client();
+    pair.server.inbound.clear(); // Lose it
+    pair.time += DEADLINE;
+    pair.drive();
+
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Expired { id })) if id == s
+    );
+    assert_matches!(
+        pair.client_send(client_ch, s).write(MSG),
+        Err(WriteError::UnknownStream)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Err(ReadError::Reset(VarInt(0))));
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn stream_latency() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    let write_to_ack = pair
+        .client_conn_mut(client_ch)
+        .stats()
+        .stream_latency
+        .write_to_ack;
+    assert_eq!(write_to_ack.count, 1);
+    assert_eq!(write_to_ack.quantile(0.5), Some(write_to_ack.max));
+
+    const WAIT: Duration = Duration::from_millis(5);
+    pair.time += WAIT;
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let now = pair.time;
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read_at(false, now).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Ok(Some(chunk)) if chunk.bytes == MSG);
+    let _ = chunks.finalize();
+    let receipt_to_read = pair
+        .server_conn_mut(server_ch)
+        .stats()
+        .stream_latency
+        .receipt_to_read;
+    assert_eq!(receipt_to_read.count, 1);
+    assert!(receipt_to_read.max >= WAIT);
+    assert_eq!(receipt_to_read.buckets.iter().sum::<u64>(), 1);
+}
+
+#[test]
+fn stream_stats() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"hello";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+    );
+    let stats = pair.client_send(client_ch, s).stats().unwrap();
+    assert_eq!(stats.bytes_sent, MSG.len() as u64);
+    assert_eq!(stats.bytes_retransmitted, 0);
+    assert!(stats.duration.is_some());
+    // Final statistics are only kept until the next poll
+    pair.client_conn_mut(client_ch).poll();
+    assert!(pair.client_send(client_ch, s).stats().is_err());
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let stats = pair.server_recv(server_ch, s).stats().unwrap();
+    assert_eq!(stats.bytes_received, MSG.len() as u64);
+    assert_eq!(stats.bytes_read, 0);
+    let now = pair.time;
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read_at(false, now).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Ok(Some(chunk)) if chunk.bytes == MSG);
+    assert_matches!(chunks.next(usize::MAX), Ok(None));
+    let stats = chunks.stats().unwrap();
+    let _ = chunks.finalize();
+    assert_eq!(stats.bytes_read, MSG.len() as u64);
+    assert!(stats.duration.is_some());
+    assert!(pair.server_recv(server_ch, s).stats().is_err());
+}
+
 #[test]
 fn stop_stream() {
     let _guard = subscribe();
@@ -327,96 +647,494 @@ fn reject_self_signed_server_cert() {
 }
 
 #[test]
-fn reject_missing_client_cert() {
+fn endpoint_stats() {
     let _guard = subscribe();
-    let mut server_config = server_config();
-    Arc::make_mut(&mut server_config.crypto).set_client_certificate_verifier(
-        rustls::AllowAnyAuthenticatedClient::new(rustls::RootCertStore::empty()),
-    );
-    let mut pair = Pair::new(Default::default(), server_config);
-    info!("connecting");
-    let client_ch = pair.begin_connect(client_config());
-    pair.drive();
+    let mut pair = Pair::default();
+    pair.connect();
+    assert_eq!(pair.client.endpoint.stats().handshakes_completed, 1);
+    assert_eq!(pair.server.endpoint.stats().handshakes_completed, 1);
 
-    // The client completes the connection, but finds it immediately closed
-    assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::HandshakeDataReady)
-    );
+    let mut pair = Pair::default();
+    let client_ch = pair.begin_connect(ClientConfig::default());
+    pair.drive();
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Connected)
-    );
-    assert_matches!(pair.client_conn_mut(client_ch).poll(),
-                    Some(Event::ConnectionLost { reason: ConnectionError::ConnectionClosed(ref close)})
-                    if close.error_code == TransportErrorCode::crypto(AlertDescription::CertificateRequired.get_u8()));
-
-    // The server never completes the connection
-    let server_ch = pair.server.assert_accept();
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::HandshakeDataReady)
+        Some(Event::ConnectionLost { .. })
     );
-    assert_matches!(pair.server_conn_mut(server_ch).poll(),
-                    Some(Event::ConnectionLost { reason: ConnectionError::TransportError(ref error)})
-                    if error.code == TransportErrorCode::crypto(AlertDescription::CertificateRequired.get_u8()));
+    pair.drive();
+    let stats = pair.client.endpoint.stats();
+    assert_eq!(stats.handshakes_completed, 0);
+    assert_eq!(stats.handshake_failures.tls_alert, 1);
 }
 
 #[test]
-fn congestion() {
+fn handshake_failure() {
     let _guard = subscribe();
     let mut pair = Pair::default();
-    let (client_ch, _) = pair.connect();
-
-    const TARGET: u64 = 2048;
-    assert!(pair.client_conn_mut(client_ch).congestion_state() > TARGET);
-    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
-    // Send data without receiving ACKs until the congestion state falls below target
-    while pair.client_conn_mut(client_ch).congestion_state() > TARGET {
-        let n = pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
-        assert_eq!(n, 1024);
-        pair.drive_client();
-    }
-    // Ensure that the congestion state recovers after receiving the ACKs
+    let client_ch = pair.begin_connect(ClientConfig::default());
     pair.drive();
-    assert!(pair.client_conn_mut(client_ch).congestion_state() >= TARGET);
-    pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost { reason }) if reason.handshake_failure() == Some(HandshakeFailure::TlsAlert {
+            alert: AlertDescription::BadCertificate.get_u8(),
+            sent_by_peer: false,
+        })
+    );
+
+    let close = frame::ConnectionClose {
+        error_code: TransportErrorCode::INVALID_TOKEN,
+        frame_type: None,
+        reason: Bytes::new(),
+    };
+    assert_eq!(
+        ConnectionError::ConnectionClosed(close).handshake_failure(),
+        Some(HandshakeFailure::InvalidToken)
+    );
+    assert_eq!(ConnectionError::TimedOut.handshake_failure(), None);
 }
 
 #[allow(clippy::field_reassign_with_default)] // https://github.com/rust-lang/rust-clippy/issues/6527
 #[test]
-fn high_latency_handshake() {
+fn handshake_timeout() {
     let _guard = subscribe();
     let mut pair = Pair::default();
-    pair.latency = Duration::from_micros(200 * 1000);
-    let (client_ch, server_ch) = pair.connect();
-    assert_eq!(pair.client_conn_mut(client_ch).bytes_in_flight(), 0);
+    pair.black_hole = Some(0); // Simulate an unreachable server
+    let client_ch = pair.begin_connect(client_config());
+    while !pair.client_conn_mut(client_ch).is_closed() {
+        if !pair.step() {
+            if let Some(t) = pair.client.next_wakeup() {
+                pair.time = t;
+            }
+        }
+    }
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::HandshakeTimedOut(HandshakePhase::Initial),
+        })
+    );
+}
+
+#[test]
+fn udp_blocked() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.black_hole = Some(0); // Simulate blocked UDP
+    let mut transport = TransportConfig::default();
+    transport.udp_blocked_probes(Some(2)).unwrap();
+    let client_config = ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    };
+    let start = pair.time;
+    let client_ch = pair.begin_connect(client_config);
+    while !pair.client_conn_mut(client_ch).is_closed() {
+        if !pair.step() {
+            if let Some(t) = pair.client.next_wakeup() {
+                pair.time = t;
+            }
+        }
+    }
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::UdpBlocked,
+        })
+    );
+    assert!(pair.time - start < Duration::from_secs(10));
+    pair.drive_client();
+    assert_eq!(
+        pair.client.endpoint.stats().handshake_failures.udp_blocked,
+        1
+    );
+}
+
+#[test]
+fn stapled_certificate() {
+    let _guard = subscribe();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let der = cert.serialize_der().unwrap();
+    let key = PrivateKey::from_der(&cert.serialize_private_key_der()).unwrap();
+    let chain = CertificateChain::from_certs(Certificate::from_der(&der));
+    let stapled = crypto::rustls::StapledCertificate::new(chain, key, vec![1, 2, 3]).unwrap();
+    stapled.set_ocsp(vec![4, 5, 6]);
+    let mut server_config = server_config();
+    server_config.cert_resolver(stapled);
+    let mut client_config = client_config();
+    client_config
+        .add_certificate_authority(Certificate::from_der(&der).unwrap())
+        .unwrap();
+    #[cfg(feature = "ocsp")]
+    client_config.require_any_ocsp_staple();
+
+    let mut pair = Pair::new(Default::default(), server_config);
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[cfg(feature = "ocsp")]
+#[test]
+fn require_any_ocsp_staple() {
+    let _guard = subscribe();
+    let mut client_config = client_config();
+    client_config.require_any_ocsp_staple();
+    let mut pair = Pair::default();
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TransportError(_)
+        })
+    );
+}
+
+#[test]
+fn server_name() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(
+        pair.client_conn_mut(client_ch).server_name(),
+        Some("localhost")
+    );
+    assert_eq!(
+        pair.server_conn_mut(server_ch).server_name(),
+        Some("localhost")
+    );
+}
+
+#[test]
+fn reject_unknown_server_name() {
+    let _guard = subscribe();
+    let cert = rcgen::generate_simple_self_signed(vec!["example.com".into()]).unwrap();
+    let key = rustls::PrivateKey(cert.serialize_private_key_der());
+    let key = rustls::sign::any_supported_type(&key).unwrap();
+    let cert = rustls::Certificate(cert.serialize_der().unwrap());
+    let mut resolver = rustls::ResolvesServerCertUsingSNI::new();
+    resolver
+        .add(
+            "example.com",
+            rustls::sign::CertifiedKey::new(vec![cert], Arc::new(key)),
+        )
+        .unwrap();
+    let mut server_config = server_config();
+    server_config.cert_resolver(Arc::new(resolver));
+    let mut pair = Pair::new(Default::default(), server_config);
+    info!("connecting to localhost");
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(pair.client_conn_mut(client_ch).poll(),
+                    Some(Event::ConnectionLost { reason: ConnectionError::ConnectionClosed(ref close)})
+                    if close.error_code == TransportErrorCode::crypto(AlertDescription::UnrecognisedName.get_u8()));
+}
+
+#[cfg(feature = "dangerous_configuration")]
+#[test]
+fn accept_invalid_server_cert() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let mut config = ClientConfig::default();
+    config.danger_accept_invalid_certs();
+    info!("connecting");
+    let client_ch = pair.begin_connect(config);
+    pair.drive();
+    pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+}
+
+#[test]
+fn reject_missing_client_cert() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    Arc::make_mut(&mut server_config.crypto).set_client_certificate_verifier(
+        rustls::AllowAnyAuthenticatedClient::new(rustls::RootCertStore::empty()),
+    );
+    let mut pair = Pair::new(Default::default(), server_config);
+    info!("connecting");
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+
+    // The client completes the connection, but finds it immediately closed
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_matches!(pair.client_conn_mut(client_ch).poll(),
+                    Some(Event::ConnectionLost { reason: ConnectionError::ConnectionClosed(ref close)})
+                    if close.error_code == TransportErrorCode::crypto(AlertDescription::CertificateRequired.get_u8()));
+
+    // The server never completes the connection
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(),
+                    Some(Event::ConnectionLost { reason: ConnectionError::TransportError(ref error)})
+                    if error.code == TransportErrorCode::crypto(AlertDescription::CertificateRequired.get_u8()));
+}
+
+#[test]
+fn congestion() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+
+    const TARGET: u64 = 2048;
+    assert!(pair.client_conn_mut(client_ch).congestion_state() > TARGET);
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    // Send data without receiving ACKs until the congestion state falls below target
+    while pair.client_conn_mut(client_ch).congestion_state() > TARGET {
+        let n = pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
+        assert_eq!(n, 1024);
+        pair.drive_client();
+    }
+    // Ensure that the congestion state recovers after receiving the ACKs
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).congestion_state() >= TARGET);
+    pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
+}
+
+#[allow(clippy::field_reassign_with_default)] // https://github.com/rust-lang/rust-clippy/issues/6527
+#[test]
+fn high_latency_handshake() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_micros(200 * 1000);
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(pair.client_conn_mut(client_ch).bytes_in_flight(), 0);
     assert_eq!(pair.server_conn_mut(server_ch).bytes_in_flight(), 0);
     assert!(pair.client_conn_mut(client_ch).using_ecn());
     assert!(pair.server_conn_mut(server_ch).using_ecn());
 }
 
+#[allow(clippy::field_reassign_with_default)] // https://github.com/rust-lang/rust-clippy/issues/6527
+#[test]
+fn rtt_cache() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(10);
+    let cache = Arc::new(MemoryRttCache::new(16));
+    cache.insert("localhost", Duration::from_secs(1));
+    let mut transport = TransportConfig::default();
+    transport.rtt_cache(Some(cache.clone()));
+    let config = ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    };
+
+    let client_ch = pair.begin_connect(config);
+    assert_eq!(
+        pair.client_conn_mut(client_ch).rtt(),
+        Duration::from_secs(1)
+    );
+    pair.drive();
+    pair.server.assert_accept();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    let rtt = cache.get("localhost").unwrap();
+    assert!(rtt >= Duration::from_millis(20) && rtt < Duration::from_millis(100));
+}
+
+#[test]
+fn zero_rtt_happypath() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            use_stateless_retry: true,
+            ..server_config()
+        },
+    );
+    let config = client_config();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
+    );
+    let _ = chunks.finalize();
+    assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
+}
+
+#[test]
+fn zero_rtt_deferred() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    server_config.defer_0rtt_data(true);
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+
+    // The 0-RTT data has arrived, but the handshake isn't complete
+    pair.drive_client();
+    pair.drive_server();
+    let server_ch = pair.server.assert_accept();
+    assert!(pair.server_conn_mut(server_ch).is_handshaking());
+    while let Some(event) = pair.server_conn_mut(server_ch).poll() {
+        assert!(!matches!(event, Event::Stream(StreamEvent::Opened { .. })));
+    }
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s));
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
+    );
+    let _ = chunks.finalize();
+}
+
+#[test]
+fn zero_rtt_release() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    server_config.defer_0rtt_data(true);
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(config.clone());
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert_eq!(pair.server_conn_mut(server_ch).zero_rtt_id(), None);
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s)
+        .write(b"Hello, 0-RTT!")
+        .unwrap();
+    pair.drive_client();
+    pair.drive_server();
+    let server_ch = pair.server.assert_accept();
+    assert_eq!(pair.client_conn_mut(client_ch).zero_rtt_id(), None);
+    assert!(pair.server_conn_mut(server_ch).zero_rtt_id().is_some());
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+
+    // Released streams are available before the handshake completes
+    pair.server_conn_mut(server_ch).release_0rtt_data();
+    assert!(pair.server_conn_mut(server_ch).is_handshaking());
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), Some(s));
+}
+
 #[test]
-fn zero_rtt_happypath() {
+fn handshake_summary() {
     let _guard = subscribe();
-    let mut pair = Pair::new(
-        Default::default(),
-        ServerConfig {
-            use_stateless_retry: true,
-            ..server_config()
-        },
-    );
+    let mut pair = Pair::default();
     let config = client_config();
 
-    // Establish normal connection
     let client_ch = pair.begin_connect(config.clone());
+    assert_eq!(pair.client_conn_mut(client_ch).handshake_summary(), None);
     pair.drive();
-    pair.server.assert_accept();
-    pair.client
-        .connections
-        .get_mut(&client_ch)
-        .unwrap()
-        .close(pair.time, VarInt(0), [][..].into());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).handshake_summary(),
+        Some(HandshakeSummary {
+            resumed: None,
+            early_data: EarlyData::NotAttempted,
+            ..
+        })
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).handshake_summary(),
+        Some(HandshakeSummary {
+            resumed: Some(false),
+            early_data: EarlyData::NotAttempted,
+            ..
+        })
+    );
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
     pair.drive();
 
     pair.client.addr = SocketAddr::new(
@@ -425,22 +1143,23 @@ fn zero_rtt_happypath() {
     );
     info!("resuming session");
     let client_ch = pair.begin_connect(config);
-    assert!(pair.client_conn_mut(client_ch).has_0rtt());
     let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
-    const MSG: &[u8] = b"Hello, 0-RTT!";
-    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.client_send(client_ch, s).write(b"early").unwrap();
     pair.drive();
-    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
     let server_ch = pair.server.assert_accept();
-
-    let mut recv = pair.server_recv(server_ch, s);
-    let mut chunks = recv.read(false).unwrap();
-    assert_matches!(
-        chunks.next(usize::MAX),
-        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
-    );
-    let _ = chunks.finalize();
-    assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
+    for summary in &[
+        pair.client_conn_mut(client_ch).handshake_summary(),
+        pair.server_conn_mut(server_ch).handshake_summary(),
+    ] {
+        assert_matches!(
+            summary,
+            Some(HandshakeSummary {
+                resumed: Some(true),
+                early_data: EarlyData::Accepted,
+                ..
+            })
+        );
+    }
 }
 
 #[test]
@@ -495,6 +1214,13 @@ fn zero_rtt_rejection() {
     pair.client_send(client_ch, s).write(MSG).unwrap();
     pair.drive();
     assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    assert_matches!(
+        pair.client_conn_mut(client_ch).handshake_summary(),
+        Some(HandshakeSummary {
+            early_data: EarlyData::Rejected,
+            ..
+        })
+    );
     let server_ch = pair.server.assert_accept();
     assert_matches!(
         pair.server_conn_mut(server_ch).poll(),
@@ -515,6 +1241,86 @@ fn zero_rtt_rejection() {
     assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
 }
 
+#[test]
+fn pinned_session_tickets() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    Arc::get_mut(&mut server_config.crypto)
+        .unwrap()
+        .set_protocols(&["foo".into(), "bar".into()]);
+    server_config.pin_session_tickets(true);
+    let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+    let mut client_config = client_config();
+    Arc::get_mut(&mut client_config.crypto)
+        .unwrap()
+        .set_protocols(&["foo".into()]);
+
+    // Establish normal connection, then resume it with the same protocol
+    for resumed in &[false, true] {
+        let client_ch = pair.begin_connect(client_config.clone());
+        pair.drive();
+        let server_ch = pair.server.assert_accept();
+        assert_matches!(
+            pair.server_conn_mut(server_ch).poll(),
+            Some(Event::HandshakeDataReady)
+        );
+        assert_matches!(
+            pair.server_conn_mut(server_ch).poll(),
+            Some(Event::Connected)
+        );
+        assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+        assert_eq!(
+            pair.server_conn_mut(server_ch)
+                .handshake_summary()
+                .unwrap()
+                .resumed,
+            Some(*resumed)
+        );
+        assert!(!pair.server_conn_mut(server_ch).ticket_mismatch());
+        assert_eq!(pair.client_conn_mut(client_ch).accepted_0rtt(), *resumed);
+        pair.client.connections.get_mut(&client_ch).unwrap().close(
+            pair.time,
+            VarInt(0),
+            [][..].into(),
+        );
+        pair.drive();
+        pair.client.connections.clear();
+        pair.server.connections.clear();
+    }
+
+    // A ticket issued for one protocol doesn't resume a session for another
+    Arc::make_mut(&mut client_config.crypto).set_protocols(&["bar".into()]);
+    info!("resuming session");
+    let client_ch = pair.begin_connect(client_config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    pair.drive();
+    assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert!(pair.server_conn_mut(server_ch).ticket_mismatch());
+    assert_matches!(
+        pair.server_conn_mut(server_ch).handshake_summary(),
+        Some(HandshakeSummary {
+            resumed: Some(false),
+            early_data: EarlyData::NotAttempted,
+            ..
+        })
+    );
+    let hd = pair
+        .client_conn_mut(client_ch)
+        .crypto_session()
+        .handshake_data()
+        .unwrap();
+    assert_eq!(hd.protocol.unwrap(), &b"bar"[..]);
+}
+
 #[test]
 fn alpn_success() {
     let _guard = subscribe();
@@ -549,6 +1355,62 @@ fn alpn_success() {
     assert_eq!(hd.protocol.unwrap(), &b"bar"[..]);
 }
 
+#[test]
+fn rustls_config() {
+    let _guard = subscribe();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let key = cert.serialize_private_key_der();
+    let cert = cert.serialize_der().unwrap();
+
+    // Plain rustls configurations, which also offer TLS 1.2
+    let mut server_crypto = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    server_crypto
+        .set_single_cert(
+            vec![rustls::Certificate(cert.clone())],
+            rustls::PrivateKey(key),
+        )
+        .unwrap();
+    let server_config = ServerConfig::with_rustls_config(server_crypto);
+    assert_eq!(
+        server_config.crypto.versions,
+        [rustls::ProtocolVersion::TLSv1_3]
+    );
+    assert_eq!(server_config.crypto.max_early_data_size, 0);
+
+    let mut client_crypto = rustls::ClientConfig::new();
+    let anchor = webpki::trust_anchor_util::cert_der_as_trust_anchor(&cert).unwrap();
+    client_crypto
+        .root_store
+        .add_server_trust_anchors(&webpki::TLSServerTrustAnchors(&[anchor]));
+    let client_config = ClientConfig::with_rustls_config(client_crypto);
+    assert!(client_config
+        .crypto
+        .ciphersuites
+        .iter()
+        .all(|x| x.usable_for_version(rustls::ProtocolVersion::TLSv1_3)));
+
+    let mut pair = Pair::new(Default::default(), server_config);
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected)
+    );
+}
+
 #[test]
 fn server_alpn_unset() {
     let _guard = subscribe();
@@ -636,7 +1498,7 @@ fn stream_id_limit() {
     pair.drive();
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
     );
     assert_eq!(
         pair.client_streams(client_ch).open(Dir::Uni),
@@ -810,6 +1672,37 @@ fn initial_retransmit() {
     );
 }
 
+#[test]
+fn initial_duplicates() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let mut transport = TransportConfig::default();
+    transport.initial_duplicates(2, Duration::from_millis(10));
+    let client_config = ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    };
+    let start = pair.time;
+    let client_ch = pair.begin_connect(client_config);
+    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.outbound.clear(); // Drop initial
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+    let stats = pair.client_conn_mut(client_ch).stats();
+    // The first copy got through, so the second wasn't sent
+    assert_eq!(stats.path.initial_duplicates, 1);
+    assert!(stats.path.initial_duplicate_needed);
+    // Well before the first probe timeout
+    assert!(pair.time - start < Duration::from_millis(100));
+}
+
 #[test]
 fn instant_close_1() {
     let _guard = subscribe();
@@ -920,55 +1813,284 @@ fn concurrent_connections_full() {
     );
     let client_ch = pair.begin_connect(client_config());
     pair.drive();
-    assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::ConnectionLost {
-            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
-                error_code: TransportErrorCode::CONNECTION_REFUSED,
-                ..
-            }),
-        })
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::CONNECTION_REFUSED,
+                ..
+            }),
+        })
+    );
+    assert_eq!(pair.server.connections.len(), 0);
+    assert_eq!(pair.server.known_connections(), 0);
+    assert_eq!(pair.server.known_cids(), 0);
+}
+
+#[test]
+fn server_hs_retransmit() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let client_ch = pair.begin_connect(client_config());
+    pair.step();
+    assert!(!pair.client.inbound.is_empty()); // Initial + Handshakes
+    pair.client.inbound.clear();
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[test]
+fn migration() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    pair.client.addr = SocketAddr::new(
+        Ipv4Addr::new(127, 0, 0, 1).into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive();
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    assert_eq!(
+        pair.server_conn_mut(server_ch).remote_address(),
+        pair.client.addr
+    );
+}
+
+#[test]
+fn local_address_changed() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    pair.client.addr = SocketAddr::new(
+        Ipv4Addr::new(127, 0, 0, 1).into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    let sent = pair.client_conn_mut(client_ch).stats().udp_tx.bytes;
+    pair.client_conn_mut(client_ch).local_address_changed();
+    pair.drive_client();
+    // The probe is large enough for the server to validate the new path in response
+    assert!(pair.client_conn_mut(client_ch).stats().udp_tx.bytes - sent >= MIN_INITIAL_SIZE as u64);
+    pair.drive();
+    assert_eq!(
+        pair.server_conn_mut(server_ch).remote_address(),
+        pair.client.addr
+    );
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .stats()
+            .frame_rx
+            .path_response,
+        1
+    );
+}
+
+#[test]
+fn pause_resume() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch).pause(now);
+    pair.server_conn_mut(server_ch).pause(now);
+    assert_eq!(pair.client_conn_mut(client_ch).poll_timeout(), None);
+
+    // Sleep for much longer than the idle timeout
+    pair.time += Duration::from_secs(3600);
+    let now = pair.time;
+    pair.client_conn_mut(client_ch).handle_timeout(now);
+    pair.server_conn_mut(server_ch).handle_timeout(now);
+    pair.client_conn_mut(client_ch).resume(now);
+    pair.server_conn_mut(server_ch).resume(now);
+    pair.drive();
+
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+    assert!(!pair.server_conn_mut(server_ch).is_closed());
+    // Each side revalidated the path on resuming
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .frame_rx
+            .path_response,
+        1
+    );
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .stats()
+            .frame_rx
+            .path_response,
+        1
+    );
+}
+
+#[test]
+fn power_saving_batches_acks() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.max_ack_delay(Duration::from_millis(25)).unwrap();
+    let mut pair = Pair::default();
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .set_power_mode(now, PowerMode::PowerSaving);
+    let acks = pair.client_conn_mut(client_ch).stats().frame_tx.acks;
+
+    let s = pair.server_streams(server_ch).open(Dir::Uni).unwrap();
+    for _ in 0..3 {
+        pair.server_send(server_ch, s).write(b"hello").unwrap();
+        pair.drive_server();
+        pair.drive_client();
+    }
+    // Nothing was acknowledged immediately
+    assert_eq!(pair.client_conn_mut(client_ch).stats().frame_tx.acks, acks);
+
+    pair.drive();
+    // All three packets were acknowledged at once
+    assert_eq!(
+        pair.client_conn_mut(client_ch).stats().frame_tx.acks,
+        acks + 1
+    );
+}
+
+#[test]
+fn power_saving_ack_deferral_bounded() {
+    let _guard = subscribe();
+    for &delay in &[0, 5, 25] {
+        let mut transport = TransportConfig::default();
+        transport
+            .max_ack_delay(Duration::from_millis(delay))
+            .unwrap();
+        let mut pair = Pair::default();
+        let client_ch = pair.begin_connect(ClientConfig {
+            transport: Arc::new(transport),
+            ..client_config()
+        });
+        pair.drive();
+        let server_ch = pair.server.assert_accept();
+        let now = pair.time;
+        pair.client_conn_mut(client_ch)
+            .set_power_mode(now, PowerMode::PowerSaving);
+        let acks = pair.client_conn_mut(client_ch).stats().frame_tx.acks;
+
+        let s = pair.server_streams(server_ch).open(Dir::Uni).unwrap();
+        pair.server_send(server_ch, s).write(b"hello").unwrap();
+        pair.drive_server();
+        pair.drive_client();
+        // Only deferred if the advertised delay leaves room beyond the timer slack
+        let deferred = pair.client_conn_mut(client_ch).stats().frame_tx.acks == acks;
+        assert_eq!(deferred, delay > 10);
+        if deferred {
+            // The ack is due within the delay the server allows for
+            let due = pair.client_conn_mut(client_ch).poll_timeout().unwrap();
+            let allowed = pair.server_conn_mut(server_ch).max_ack_delay();
+            assert!(due - pair.time <= allowed);
+        }
+    }
+}
+
+#[test]
+fn max_ack_delay_batches_acks() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    assert!(transport.max_ack_delay(Duration::from_secs(20)).is_err());
+    assert!(transport.ack_delay_exponent(21).is_err());
+    transport.profile(TransportProfile::AsymmetricUplink);
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(10);
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    let acks = pair.client_conn_mut(client_ch).stats().frame_tx.acks;
+
+    let s = pair.server_streams(server_ch).open(Dir::Uni).unwrap();
+    for _ in 0..3 {
+        pair.server_send(server_ch, s).write(b"hello").unwrap();
+        pair.drive_server();
+        pair.drive_client();
+    }
+    // Nothing was acknowledged immediately
+    assert_eq!(pair.client_conn_mut(client_ch).stats().frame_tx.acks, acks);
+
+    pair.drive();
+    // All three packets were acknowledged at once
+    assert_eq!(
+        pair.client_conn_mut(client_ch).stats().frame_tx.acks,
+        acks + 1
     );
-    assert_eq!(pair.server.connections.len(), 0);
-    assert_eq!(pair.server.known_connections(), 0);
-    assert_eq!(pair.server.known_cids(), 0);
+    // The server discounted the delay from its RTT estimate
+    assert!(pair.server_conn_mut(server_ch).rtt() < Duration::from_millis(21));
 }
 
 #[test]
-fn server_hs_retransmit() {
+fn full_padding() {
     let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.padding(PaddingPolicy::Full);
     let mut pair = Pair::default();
-    let client_ch = pair.begin_connect(client_config());
-    pair.step();
-    assert!(!pair.client.inbound.is_empty()); // Initial + Handshakes
-    pair.client.inbound.clear();
+    let client_ch = pair.begin_connect(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
     pair.drive();
-    assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::HandshakeDataReady)
-    );
-    assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Connected { .. })
-    );
+    let server_ch = pair.server.assert_accept();
+    let now = pair.time;
+
+    pair.client_conn_mut(client_ch).ping();
+    let transmit = pair
+        .client_conn_mut(client_ch)
+        .poll_transmit(now, 1)
+        .unwrap();
+    assert_eq!(transmit.contents.len(), usize::from(MIN_MTU));
+
+    // Only the client pads
+    pair.server_conn_mut(server_ch).ping();
+    let transmit = pair
+        .server_conn_mut(server_ch)
+        .poll_transmit(now, 1)
+        .unwrap();
+    assert!(transmit.contents.len() < usize::from(MIN_MTU));
 }
 
 #[test]
-fn migration() {
+fn low_latency_skips_ack_batching() {
     let _guard = subscribe();
     let mut pair = Pair::default();
     let (client_ch, server_ch) = pair.connect();
-    pair.client.addr = SocketAddr::new(
-        Ipv4Addr::new(127, 0, 0, 1).into(),
-        CLIENT_PORTS.lock().unwrap().next().unwrap(),
-    );
-    pair.client_conn_mut(client_ch).ping();
     pair.drive();
-    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
-    assert_eq!(
-        pair.server_conn_mut(server_ch).remote_address(),
-        pair.client.addr
-    );
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .set_power_mode(now, PowerMode::PowerSaving);
+    pair.client_conn_mut(client_ch).set_low_latency(true);
+    let acks = pair.client_conn_mut(client_ch).stats().frame_tx.acks;
+
+    let s = pair.server_streams(server_ch).open(Dir::Uni).unwrap();
+    for i in 1..=3 {
+        pair.server_send(server_ch, s).write(b"hello").unwrap();
+        pair.drive_server();
+        pair.drive_client();
+        // Each packet was acknowledged immediately
+        assert_eq!(
+            pair.client_conn_mut(client_ch).stats().frame_tx.acks,
+            acks + i
+        );
+    }
 }
 
 fn test_flow_control(config: TransportConfig, window_size: usize) {
@@ -1046,35 +2168,352 @@ fn test_flow_control(config: TransportConfig, window_size: usize) {
     );
 
     pair.drive();
-    let mut cursor = 0;
-    let mut recv = pair.server_recv(server_ch, s);
-    let mut chunks = recv.read(true).unwrap();
-    loop {
-        match chunks.next(usize::MAX) {
-            Ok(Some(chunk)) => {
-                cursor += chunk.bytes.len();
-            }
-            Ok(None) => {
-                panic!("end of stream");
-            }
-            Err(ReadError::Blocked) => {
-                break;
-            }
-            Err(e) => {
-                panic!("{}", e);
+    let mut cursor = 0;
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    loop {
+        match chunks.next(usize::MAX) {
+            Ok(Some(chunk)) => {
+                cursor += chunk.bytes.len();
+            }
+            Ok(None) => {
+                panic!("end of stream");
+            }
+            Err(ReadError::Blocked) => {
+                break;
+            }
+            Err(e) => {
+                panic!("{}", e);
+            }
+        }
+    }
+    assert_eq!(cursor, window_size);
+    let _ = chunks.finalize();
+    info!("finished reading");
+}
+
+#[test]
+fn stream_flow_control() {
+    test_flow_control(
+        TransportConfig {
+            stream_receive_window: 2000u32.into(),
+            ..TransportConfig::default()
+        },
+        2000,
+    );
+}
+
+#[test]
+fn uni_stream_flow_control() {
+    test_flow_control(
+        TransportConfig {
+            uni_stream_receive_window: Some(2000u32.into()),
+            ..TransportConfig::default()
+        },
+        2000,
+    );
+}
+
+#[test]
+fn manual_credit() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    assert!(transport.credit_policy(CreditPolicy::Threshold(0)).is_err());
+    transport
+        .stream_receive_window(2000)
+        .unwrap()
+        .credit_policy(CreditPolicy::Manual)
+        .unwrap();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: Arc::new(transport),
+            ..server_config()
+        },
+    );
+    let (client_ch, server_ch) = pair.connect();
+    let msg = vec![0xAB; 3000];
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write(&msg), Ok(2000));
+    pair.drive();
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    let mut cursor = 0;
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        cursor += chunk.bytes.len();
+    }
+    assert_eq!(cursor, 2000);
+    let _ = chunks.finalize();
+    pair.drive();
+
+    // Reading alone doesn't return any credit
+    assert_eq!(
+        pair.client_send(client_ch, s).write(&msg[2000..]),
+        Err(WriteError::Blocked)
+    );
+
+    pair.server_recv(server_ch, s).credit(500).unwrap();
+    pair.drive();
+    assert_eq!(pair.client_send(client_ch, s).write(&msg[2000..]), Ok(500));
+}
+
+#[test]
+fn max_udp_payload_size() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    assert!(transport.max_egress_udp_payload_size(Some(1000)).is_err());
+    transport.max_egress_udp_payload_size(Some(1220)).unwrap();
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1232);
+    assert_eq!(pair.server_conn_mut(server_ch).max_udp_payload_size(), 1220);
+
+    // Limits advertised by the peer are honored
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config.max_udp_payload_size(1210).unwrap();
+    let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1210);
+    assert_eq!(pair.server_conn_mut(server_ch).max_udp_payload_size(), 1210);
+}
+
+#[test]
+fn packet_marking() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    pair.client_conn_mut(client_ch).set_dscp(Some(46));
+    pair.client_conn_mut(client_ch)
+        .set_flow_label(Some(0x12345));
+    pair.client_conn_mut(client_ch).ping();
+    let (time, server_addr) = (pair.time, pair.server.addr);
+    pair.client.drive(time, server_addr);
+    assert!(!pair.client.outbound.is_empty());
+    for transmit in &pair.client.outbound {
+        assert_eq!(transmit.dscp, Some(46));
+        assert_eq!(transmit.flow_label, Some(0x12345));
+    }
+    pair.drive();
+}
+
+#[test]
+fn packet_too_big() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    let server_addr = pair.server.addr;
+
+    // Reports for other paths are ignored
+    let other = SocketAddr::new(server_addr.ip(), server_addr.port() + 1);
+    pair.client_conn_mut(client_ch)
+        .handle_packet_too_big(other, 1210);
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1232);
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+
+    // A datagram which no longer fits after the reduction is discarded
+    const DATA: &[u8] = b"whee";
+    let max = pair.client_datagrams(client_ch).max_size().unwrap();
+    pair.client_datagrams(client_ch)
+        .send(vec![0; max].into())
+        .unwrap();
+    pair.client_datagrams(client_ch).send(DATA.into()).unwrap();
+    pair.client_conn_mut(client_ch)
+        .handle_packet_too_big(server_addr, 1210);
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1210);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::PathMtuChanged { mtu: 1210 })
+    );
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::DatagramReceived)
+    );
+    assert_eq!(pair.server_datagrams(server_ch).recv().unwrap(), DATA);
+    assert_matches!(pair.server_datagrams(server_ch).recv(), None);
+
+    // The minimum MTU is never undercut
+    pair.client_conn_mut(client_ch)
+        .handle_packet_too_big(server_addr, 500);
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1200);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::PathMtuChanged { mtu: 1200 })
+    );
+    pair.client_conn_mut(client_ch)
+        .handle_packet_too_big(server_addr, 1300);
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1200);
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+}
+
+#[test]
+fn black_hole() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.initial_max_udp_payload_size(1400).unwrap();
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(pair.server_conn_mut(server_ch).max_udp_payload_size(), 1400);
+
+    pair.black_hole = Some(1300);
+    const MSG: &[u8] = &[0xAB; 16 * 1024];
+    let s = pair.server_streams(server_ch).open(Dir::Uni).unwrap();
+    pair.server_send(server_ch, s).write(MSG).unwrap();
+    pair.server_send(server_ch, s).finish().unwrap();
+    pair.drive();
+
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::BlackHoleDetected)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::PathMtuChanged { mtu: 1200 })
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
+    );
+    assert_eq!(pair.server_conn_mut(server_ch).max_udp_payload_size(), 1200);
+
+    assert_matches!(pair.client_streams(client_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.client_recv(client_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    let mut received = 0;
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        received += chunk.bytes.len();
+    }
+    let _ = chunks.finalize();
+    assert_eq!(received, MSG.len());
+}
+
+#[test]
+fn mtu_discovery() {
+    let _guard = subscribe();
+    let mut mtud = MtuDiscoveryConfig::default();
+    assert!(mtud.step(0).is_err());
+    mtud.upper_bound(1400).unwrap().step(50).unwrap();
+    let mut transport = TransportConfig::default();
+    transport.mtu_discovery_config(Some(mtud));
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    pair.black_hole = Some(1350);
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+
+    // Probes for 1382 bytes are lost, ending the search at the last size that got through
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::PathMtuChanged { mtu: 1282 })
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::PathMtuChanged { mtu: 1332 })
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    assert_eq!(pair.server_conn_mut(server_ch).max_udp_payload_size(), 1332);
+    assert_eq!(pair.server_conn_mut(server_ch).lost_packets(), 0);
+
+    // Discovery is disabled by default, but manual probes still work
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1232);
+    pair.client_conn_mut(client_ch).probe_mtu(1350);
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::PathMtuChanged { mtu: 1350 })
+    );
+    pair.client_conn_mut(client_ch).probe_mtu(1351);
+    pair.drive();
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    assert_eq!(pair.client_conn_mut(client_ch).max_udp_payload_size(), 1350);
+}
+
+#[test]
+fn quota() {
+    let _guard = subscribe();
+    let mut quota = ConnectionQuota::new(VarInt(7));
+    quota.max_streams(Some(2)).max_received_bytes(Some(1000));
+    let mut transport = TransportConfig::default();
+    transport.quota(Some(quota));
+    let server = ServerConfig {
+        transport: Arc::new(transport),
+        ..server_config()
+    };
+
+    // Too many streams
+    let mut pair = Pair::new(Default::default(), server.clone());
+    let (client_ch, server_ch) = pair.connect();
+    for _ in 0..2 {
+        let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+        pair.client_send(client_ch, s).write(b"hi").unwrap();
+    }
+    pair.drive();
+    while let Some(event) = pair.server_conn_mut(server_ch).poll() {
+        assert!(!matches!(event, Event::ConnectionLost { .. }));
+    }
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hi").unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Stream(StreamEvent::Opened { dir: Dir::Uni }))
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::QuotaExceeded
+        })
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ApplicationClosed(ApplicationClose {
+                error_code: VarInt(7),
+                ..
+            })
+        })
+    );
+
+    // Too much data
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(&[0; 600]).unwrap();
+    pair.drive();
+    pair.client_datagrams(client_ch)
+        .send(vec![0; 600].into())
+        .unwrap();
+    pair.drive();
+    let mut lost = false;
+    while let Some(event) = pair.server_conn_mut(server_ch).poll() {
+        lost |= matches!(
+            event,
+            Event::ConnectionLost {
+                reason: ConnectionError::QuotaExceeded
             }
-        }
+        );
     }
-    assert_eq!(cursor, window_size);
-    let _ = chunks.finalize();
-    info!("finished reading");
+    assert!(lost);
 }
 
 #[test]
-fn stream_flow_control() {
+fn conn_flow_control() {
     test_flow_control(
         TransportConfig {
-            stream_receive_window: 2000u32.into(),
+            receive_window: 2000u32.into(),
             ..TransportConfig::default()
         },
         2000,
@@ -1082,14 +2521,46 @@ fn stream_flow_control() {
 }
 
 #[test]
-fn conn_flow_control() {
-    test_flow_control(
-        TransportConfig {
-            receive_window: 2000u32.into(),
-            ..TransportConfig::default()
+fn manual_conn_credit() {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport
+        .receive_window(2000)
+        .unwrap()
+        .connection_credit_policy(CreditPolicy::Manual)
+        .unwrap();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: Arc::new(transport),
+            ..server_config()
         },
-        2000,
     );
+    let (client_ch, server_ch) = pair.connect();
+    let msg = vec![0xAB; 3000];
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(pair.client_send(client_ch, s).write(&msg), Ok(2000));
+    pair.drive();
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    let mut cursor = 0;
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        cursor += chunk.bytes.len();
+    }
+    assert_eq!(cursor, 2000);
+    let _ = chunks.finalize();
+    pair.drive();
+
+    // Reading alone doesn't return any credit
+    assert_eq!(
+        pair.client_send(client_ch, s).write(&msg[2000..]),
+        Err(WriteError::Blocked)
+    );
+
+    pair.server_conn_mut(server_ch).credit(500);
+    pair.drive();
+    assert_eq!(pair.client_send(client_ch, s).write(&msg[2000..]), Ok(500));
 }
 
 #[test]
@@ -1155,6 +2626,41 @@ fn implicit_open() {
     assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), None);
 }
 
+#[test]
+fn forbidden_stream_initiator() {
+    let _guard = subscribe();
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            stream_initiator: Some(Side::Server),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TransportError(TransportError {
+                code: TransportErrorCode::PROTOCOL_VIOLATION,
+                ..
+            }),
+        })
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::PROTOCOL_VIOLATION,
+                ..
+            }),
+        })
+    );
+}
+
 #[test]
 fn zero_length_cid() {
     let _guard = subscribe();
@@ -1184,6 +2690,144 @@ fn zero_length_cid() {
     pair.connect();
 }
 
+#[test]
+fn zero_length_cid_same_remote() {
+    let _guard = subscribe();
+    let cid_generator_factory: fn() -> Box<dyn ConnectionIdGenerator> =
+        || Box::new(RandomConnectionIdGenerator::new(0));
+    let mut pair = Pair::new(
+        Arc::new(EndpointConfig {
+            connection_id_generator_factory: Arc::new(cid_generator_factory),
+            ..EndpointConfig::default()
+        }),
+        server_config(),
+    );
+    pair.connect();
+    let server_addr = pair.server.addr;
+    // Packets from the server couldn't be attributed to either connection
+    assert_matches!(
+        pair.client.connect(pair.time, client_config(), server_addr, "localhost"),
+        Err(ConnectError::RemoteInUse(addr)) if addr == server_addr
+    );
+    assert_eq!(pair.client.open_connections(), 1);
+}
+
+#[test]
+fn single_connection() {
+    let _guard = subscribe();
+    let mut config = EndpointConfig::default();
+    config.single_connection(true);
+    let mut pair = Pair::new(Arc::new(config), server_config());
+    let (client_ch, _) = pair.connect();
+    assert_eq!(pair.client.known_cids(), 0);
+    let mut other = pair.server.addr;
+    other.set_port(other.port() + 1);
+    assert_matches!(
+        pair.client
+            .connect(pair.time, client_config(), other, "localhost"),
+        Err(ConnectError::TooManyConnections)
+    );
+    assert_eq!(pair.client.open_connections(), 1);
+
+    // Once the connection is gone, a new one may be established
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(42), Bytes::new());
+    pair.drive();
+    pair.time += Duration::from_secs(10);
+    pair.drive();
+    assert_eq!(pair.client.open_connections(), 0);
+    pair.connect();
+}
+
+#[test]
+fn idle_timeout_warning() {
+    let _guard = subscribe();
+    const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
+    const WARNING: Duration = Duration::from_millis(40);
+    let server = ServerConfig {
+        transport: Arc::new(TransportConfig {
+            max_idle_timeout: Some(IDLE_TIMEOUT),
+            idle_timeout_warning: Some(WARNING),
+            ..TransportConfig::default()
+        }),
+        ..server_config()
+    };
+    let mut pair = Pair::new(Default::default(), server);
+    let (client_ch, server_ch) = pair.connect();
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+
+    let mut warned_at = None;
+    let closed_at = loop {
+        // Timers due at `now` fire as the server is driven
+        let now = pair.time;
+        if !pair.step() {
+            if let Some(t) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                pair.time = t;
+            }
+        }
+        // Simulate total packet loss, so that neither side sees any activity
+        pair.client.inbound.clear();
+        pair.server.inbound.clear();
+        match pair.server_conn_mut(server_ch).poll() {
+            Some(Event::IdleTimeoutWarning) => {
+                assert!(warned_at.is_none(), "warned twice");
+                warned_at = Some(now);
+            }
+            Some(Event::ConnectionLost { reason }) => {
+                assert_matches!(reason, ConnectionError::TimedOut);
+                break now;
+            }
+            _ => {}
+        }
+    };
+
+    assert!(closed_at - warned_at.expect("no warning") >= WARNING);
+}
+
+#[test]
+fn set_max_idle_timeout() {
+    let _guard = subscribe();
+    const LIMIT: Duration = Duration::from_millis(50);
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let negotiated = pair.server_conn_mut(server_ch).idle_timeout();
+    assert_eq!(negotiated, TransportConfig::default().max_idle_timeout);
+
+    // Can't be extended beyond the negotiated timeout
+    let now = pair.time;
+    pair.server_conn_mut(server_ch)
+        .set_max_idle_timeout(now, Some(Duration::from_secs(3600)));
+    assert_eq!(pair.server_conn_mut(server_ch).idle_timeout(), negotiated);
+
+    pair.server_conn_mut(server_ch)
+        .set_max_idle_timeout(now, Some(LIMIT));
+    assert_eq!(pair.server_conn_mut(server_ch).idle_timeout(), Some(LIMIT));
+    let start = pair.time;
+    while !pair.server_conn_mut(server_ch).is_closed() {
+        if !pair.step() {
+            if let Some(t) = min_opt(pair.client.next_wakeup(), pair.server.next_wakeup()) {
+                pair.time = t;
+            }
+        }
+        // Simulate total packet loss
+        pair.client.inbound.clear();
+        pair.server.inbound.clear();
+    }
+
+    assert!(pair.time - start < negotiated.unwrap() / 2);
+    assert!(!pair.client_conn_mut(client_ch).is_closed());
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TimedOut,
+        })
+    );
+}
+
 #[test]
 fn keep_alive() {
     let _guard = subscribe();
@@ -1336,7 +2980,7 @@ fn finish_stream_flow_control_reordered() {
 
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
     );
     assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
     assert_matches!(
@@ -1476,6 +3120,123 @@ fn datagram_send_recv() {
     assert_matches!(pair.server_datagrams(server_ch).recv(), None);
 }
 
+#[test]
+fn tap() {
+    type Record = (Option<StreamId>, TapDirection, u64, Vec<u8>);
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Record>>);
+
+    impl Tap for Recorder {
+        fn stream_data(&self, id: StreamId, direction: TapDirection, offset: u64, data: &[u8]) {
+            let mut log = self.0.lock().unwrap();
+            log.push((Some(id), direction, offset, data.to_vec()));
+        }
+
+        fn datagram(&self, direction: TapDirection, data: &[u8]) {
+            let mut log = self.0.lock().unwrap();
+            log.push((None, direction, 0, data.to_vec()));
+        }
+    }
+
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let client_tap = Arc::new(Recorder::default());
+    let server_tap = Arc::new(Recorder::default());
+    pair.client_conn_mut(client_ch)
+        .set_tap(Some(client_tap.clone()));
+    pair.server_conn_mut(server_ch)
+        .set_tap(Some(server_tap.clone()));
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.client_send(client_ch, s).write(b" world").unwrap();
+    pair.client_datagrams(client_ch)
+        .send(b"whee"[..].into())
+        .unwrap();
+    pair.drive();
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(true).unwrap();
+    assert_matches!(chunks.next(usize::MAX), Ok(Some(_)));
+    let _ = chunks.finalize();
+    assert_eq!(
+        pair.server_datagrams(server_ch).recv().unwrap(),
+        &b"whee"[..]
+    );
+
+    assert_eq!(
+        *client_tap.0.lock().unwrap(),
+        [
+            (Some(s), TapDirection::Sent, 0, b"hello".to_vec()),
+            (Some(s), TapDirection::Sent, 5, b" world".to_vec()),
+            (None, TapDirection::Sent, 0, b"whee".to_vec()),
+        ]
+    );
+    assert_eq!(
+        *server_tap.0.lock().unwrap(),
+        [
+            (Some(s), TapDirection::Received, 0, b"hello world".to_vec()),
+            (None, TapDirection::Received, 0, b"whee".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn datagram_transform() {
+    /// Prefixes datagrams with a tag, discarding received datagrams lacking it
+    struct Tagger;
+
+    impl DatagramTransform for Tagger {
+        fn outgoing(&self, data: Bytes) -> Bytes {
+            let mut buf = Vec::with_capacity(data.len() + 1);
+            buf.push(0xAB);
+            buf.extend_from_slice(&data);
+            buf.into()
+        }
+
+        fn incoming(&self, mut data: Bytes) -> Option<Bytes> {
+            if data.first() != Some(&0xAB) {
+                return None;
+            }
+            Some(data.split_off(1))
+        }
+
+        fn overhead(&self) -> usize {
+            1
+        }
+    }
+
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    let max = pair.client_datagrams(client_ch).max_size().unwrap();
+    pair.client_datagrams(client_ch)
+        .set_transform(Some(Arc::new(Tagger)));
+    pair.server_datagrams(server_ch)
+        .set_transform(Some(Arc::new(Tagger)));
+    assert_eq!(pair.client_datagrams(client_ch).max_size(), Some(max - 1));
+
+    pair.client_datagrams(client_ch)
+        .send(b"whee"[..].into())
+        .unwrap();
+    pair.drive();
+    assert_eq!(
+        pair.server_datagrams(server_ch).recv().unwrap(),
+        &b"whee"[..]
+    );
+
+    // Datagrams not transformed by the sender are rejected
+    pair.client_datagrams(client_ch).set_transform(None);
+    pair.client_datagrams(client_ch)
+        .send(b"whee"[..].into())
+        .unwrap();
+    pair.drive();
+    assert_matches!(pair.server_datagrams(server_ch).recv(), None);
+}
+
 #[test]
 fn datagram_recv_buffer_overflow() {
     let _guard = subscribe();
@@ -1621,7 +3382,7 @@ fn finish_acked() {
     pair.drive();
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
     );
 
     let mut recv = pair.server_recv(server_ch, s);
@@ -1630,6 +3391,23 @@ fn finish_acked() {
     let _ = chunks.finalize();
 }
 
+#[test]
+fn max_pto_backoff() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    let base = pair.client_conn_mut(client_ch).current_pto();
+    pair.client_conn_mut(client_ch).set_max_pto_backoff(1);
+
+    pair.client_conn_mut(client_ch).ping();
+    for _ in 0..5 {
+        pair.drive_client();
+        pair.server.inbound.clear(); // Simulate total C->S packet loss
+        pair.time = pair.client.next_wakeup().unwrap();
+    }
+    assert_eq!(pair.client_conn_mut(client_ch).current_pto(), 2 * base);
+}
+
 #[test]
 /// Ensure that we don't yield a finish event while there's still unacknowledged data
 fn finish_retransmit() {
@@ -1657,7 +3435,7 @@ fn finish_retransmit() {
     pair.drive();
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Stream(StreamEvent::Finished { id })) if id == s
+        Some(Event::Stream(StreamEvent::Finished { id, .. })) if id == s
     );
 
     assert_matches!(
@@ -1771,3 +3549,47 @@ fn handshake_anti_deadlock_probe() {
         Some(Event::Connected { .. })
     );
 }
+
+#[test]
+fn listeners() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let mut filter = ListenerFilter::default();
+    filter.server_name("example.com");
+    pair.server.add_listener(filter, Arc::new(server_config()));
+    let mut filter = ListenerFilter::default();
+    filter.server_name("LOCALHOST");
+    let id = pair.server.add_listener(filter, Arc::new(server_config()));
+    // Server names are matched case-insensitively
+    let (_, server_ch) = pair.connect();
+    assert_eq!(pair.server_conn_mut(server_ch).listener(), Some(id));
+
+    // Falls back to the default server configuration once the listener is removed
+    let mut pair = Pair::default();
+    let mut filter = ListenerFilter::default();
+    filter.server_name("localhost");
+    let id = pair.server.add_listener(filter, Arc::new(server_config()));
+    pair.server.remove_listener(id);
+    let (_, server_ch) = pair.connect();
+    assert_eq!(pair.server_conn_mut(server_ch).listener(), None);
+}
+
+#[test]
+fn seeded_rng() {
+    let _guard = subscribe();
+    let first_cids = || {
+        let mut endpoint_config = EndpointConfig::default();
+        endpoint_config.rng_seed(Some([42; 32]));
+        let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+        pair.begin_connect(client_config());
+        pair.client.drive(pair.time, pair.server.addr);
+        // Long header: flags, version, then length-prefixed destination and source CIDs
+        let packet = &pair.client.outbound[0].contents;
+        let dst_len = packet[5] as usize;
+        let src_len = packet[6 + dst_len] as usize;
+        packet[5..7 + dst_len + src_len].to_vec()
+    };
+    let cids = first_cids();
+    assert_eq!(cids, first_cids());
+    assert_ne!(cids[1..9], [0; 8]);
+}
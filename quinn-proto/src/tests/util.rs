@@ -27,6 +27,8 @@ pub struct Pair {
     /// Number of spin bit flips
     pub spins: u64,
     last_spin: bool,
+    /// Size above which datagrams are silently dropped in either direction
+    pub black_hole: Option<usize>,
 }
 
 impl Pair {
@@ -53,6 +55,7 @@ impl Pair {
             latency: Duration::new(0, 0),
             spins: 0,
             last_spin: false,
+            black_hole: None,
         }
     }
 
@@ -104,6 +107,9 @@ impl Pair {
             if let Some(ref socket) = self.client.socket {
                 socket.send_to(&x.contents, x.destination).unwrap();
             }
+            if self.black_hole.map_or(false, |max| x.contents.len() > max) {
+                continue;
+            }
             if self.server.addr == x.destination {
                 self.server
                     .inbound
@@ -120,6 +126,9 @@ impl Pair {
             if let Some(ref socket) = self.server.socket {
                 socket.send_to(&x.contents, x.destination).unwrap();
             }
+            if self.black_hole.map_or(false, |max| x.contents.len() > max) {
+                continue;
+            }
             if self.client.addr == x.destination {
                 self.client
                     .inbound
@@ -158,7 +167,7 @@ impl Pair {
         let _guard = span.enter();
         let (client_ch, client_conn) = self
             .client
-            .connect(config, self.server.addr, "localhost")
+            .connect(self.time, config, self.server.addr, "localhost")
             .unwrap();
         self.client.connections.insert(client_ch, client_conn);
         client_ch
@@ -439,6 +448,8 @@ fn split_transmit(transmit: Transmit) -> Vec<Transmit> {
             contents,
             segment_size: None,
             src_ip: transmit.src_ip,
+            dscp: transmit.dscp,
+            flow_label: transmit.flow_label,
         });
 
         offset = end;
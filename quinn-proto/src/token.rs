@@ -1,6 +1,5 @@
 use std::{
     fmt, io,
-    net::{IpAddr, SocketAddr},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -10,7 +9,7 @@ use crate::{
     coding::{BufExt, BufMutExt},
     crypto::{AeadKey, CryptoError, HandshakeTokenKey, HmacKey},
     shared::ConnectionId,
-    RESET_TOKEN_SIZE,
+    Address, MAX_AUTH_TOKEN_SIZE, RESET_TOKEN_SIZE,
 };
 
 pub struct RetryToken<'a> {
@@ -26,7 +25,7 @@ impl<'a> RetryToken<'a> {
     pub fn encode(
         &self,
         key: &impl HandshakeTokenKey,
-        address: &SocketAddr,
+        address: &impl Address,
         retry_src_cid: &ConnectionId,
     ) -> Vec<u8> {
         let aead_key = key.aead_from_hkdf(self.random_bytes);
@@ -40,10 +39,8 @@ impl<'a> RetryToken<'a> {
                 .unwrap_or(0),
         );
 
-        let mut additional_data = [0u8; Self::MAX_ADDITIONAL_DATA_SIZE];
-        let additional_data =
-            Self::put_additional_data(address, retry_src_cid, &mut additional_data);
-        aead_key.seal(&mut buf, additional_data).unwrap();
+        let additional_data = Self::additional_data(address, retry_src_cid);
+        aead_key.seal(&mut buf, &additional_data).unwrap();
 
         let mut token = Vec::new();
         token.put_slice(self.random_bytes);
@@ -53,7 +50,7 @@ impl<'a> RetryToken<'a> {
 
     pub fn from_bytes(
         key: &impl HandshakeTokenKey,
-        address: &SocketAddr,
+        address: &impl Address,
         retry_src_cid: &ConnectionId,
         raw_token_bytes: &'a [u8],
     ) -> Result<Self, CryptoError> {
@@ -66,10 +63,8 @@ impl<'a> RetryToken<'a> {
         let aead_key = key.aead_from_hkdf(random_bytes);
         let mut sealed_token = raw_token_bytes[Self::RANDOM_BYTES_LEN..].to_vec();
 
-        let mut additional_data = [0u8; Self::MAX_ADDITIONAL_DATA_SIZE];
-        let additional_data =
-            Self::put_additional_data(address, retry_src_cid, &mut additional_data);
-        let data = aead_key.open(&mut sealed_token, additional_data)?;
+        let additional_data = Self::additional_data(address, retry_src_cid);
+        let data = aead_key.open(&mut sealed_token, &additional_data)?;
 
         let mut reader = io::Cursor::new(data);
         let orig_dst_cid = ConnectionId::decode_long(&mut reader).ok_or(CryptoError)?;
@@ -82,24 +77,13 @@ impl<'a> RetryToken<'a> {
         })
     }
 
-    fn put_additional_data<'b>(
-        address: &SocketAddr,
-        retry_src_cid: &ConnectionId,
-        additional_data: &'b mut [u8],
-    ) -> &'b [u8] {
-        let mut cursor = &mut *additional_data;
-        match address.ip() {
-            IpAddr::V4(x) => cursor.put_slice(&x.octets()),
-            IpAddr::V6(x) => cursor.put_slice(&x.octets()),
-        }
-        cursor.write(address.port());
-        retry_src_cid.encode_long(&mut cursor);
-
-        let size = Self::MAX_ADDITIONAL_DATA_SIZE - cursor.len();
-        &additional_data[..size]
+    fn additional_data(address: &impl Address, retry_src_cid: &ConnectionId) -> Vec<u8> {
+        let mut additional_data = Vec::new();
+        address.encode(&mut additional_data);
+        retry_src_cid.encode_long(&mut additional_data);
+        additional_data
     }
 
-    const MAX_ADDITIONAL_DATA_SIZE: usize = 39; // max(ipv4, ipv6) + port + retry_src_cid
     pub const RANDOM_BYTES_LEN: usize = 32;
 }
 
@@ -150,6 +134,51 @@ impl fmt::Display for ResetToken {
     }
 }
 
+/// Opaque token a client authenticates itself with, see `TransportConfig::auth_token`
+///
+/// Stored inline rather than on the heap to keep `TransportParameters` `Copy`.
+#[derive(Copy, Clone)]
+pub(crate) struct AuthToken {
+    len: u16,
+    bytes: [u8; MAX_AUTH_TOKEN_SIZE],
+}
+
+impl AuthToken {
+    /// `None` if `token` is longer than `MAX_AUTH_TOKEN_SIZE`
+    pub(crate) fn new(token: &[u8]) -> Option<Self> {
+        if token.len() > MAX_AUTH_TOKEN_SIZE {
+            return None;
+        }
+        let mut bytes = [0; MAX_AUTH_TOKEN_SIZE];
+        bytes[..token.len()].copy_from_slice(token);
+        Some(Self {
+            len: token.len() as u16,
+            bytes,
+        })
+    }
+}
+
+impl PartialEq for AuthToken {
+    fn eq(&self, other: &AuthToken) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for AuthToken {}
+
+impl std::ops::Deref for AuthToken {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[ redacted ]")
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(feature = "ring")]
@@ -161,7 +190,7 @@ mod test {
 
         use rand::RngCore;
         use std::{
-            net::Ipv6Addr,
+            net::{Ipv6Addr, SocketAddr},
             time::{Duration, UNIX_EPOCH},
         };
 
@@ -200,7 +229,7 @@ mod test {
         use crate::cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator};
         use crate::{crypto, MAX_CID_SIZE};
         use rand::RngCore;
-        use std::net::Ipv6Addr;
+        use std::net::{Ipv6Addr, SocketAddr};
 
         let rng = &mut rand::thread_rng();
 
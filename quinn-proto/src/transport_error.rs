@@ -52,6 +52,14 @@ impl Code {
     pub(crate) fn crypto(code: u8) -> Self {
         Code(0x100 | u64::from(code))
     }
+
+    /// The TLS alert this code was created from, if any
+    pub fn tls_alert(self) -> Option<u8> {
+        match self.0 {
+            0x100..=0x1ff => Some(self.0 as u8),
+            _ => None,
+        }
+    }
 }
 
 impl coding::Codec for Code {
@@ -21,8 +21,8 @@ use crate::{
     config::{EndpointConfig, ServerConfig, TransportConfig},
     crypto,
     shared::ConnectionId,
-    ResetToken, Side, TransportError, VarInt, LOC_CID_COUNT, MAX_CID_SIZE, MAX_STREAM_COUNT,
-    RESET_TOKEN_SIZE,
+    AuthToken, ResetToken, Side, TransportError, VarInt, LOC_CID_COUNT, MAX_AUTH_TOKEN_SIZE,
+    MAX_CID_SIZE, MAX_STREAM_COUNT, RESET_TOKEN_SIZE,
 };
 
 // Apply a given macro to a list of all the transport parameters having integer types, along with
@@ -79,6 +79,13 @@ macro_rules! make_struct {
             /// Initial packet it sends for the connection
             pub(crate) initial_src_cid: Option<ConnectionId>,
 
+            // Client-only
+            /// Opaque token the server may authenticate the client with
+            pub(crate) auth_token: Option<AuthToken>,
+
+            /// Whether the endpoint accepts packets with compressed headers
+            pub(crate) header_compression: bool,
+
             // Server-only
             /// The value of the Destination Connection ID field from the first Initial packet sent
             /// by the client
@@ -102,6 +109,10 @@ macro_rules! make_struct {
                     max_datagram_frame_size: None,
                     initial_src_cid: None,
 
+                    auth_token: None,
+
+                    header_compression: false,
+
                     original_dst_cid: None,
                     retry_src_cid: None,
                     stateless_reset_token: None,
@@ -132,14 +143,17 @@ impl TransportParameters {
             initial_max_data: config.receive_window,
             initial_max_stream_data_bidi_local: config.stream_receive_window,
             initial_max_stream_data_bidi_remote: config.stream_receive_window,
-            initial_max_stream_data_uni: config.stream_receive_window,
+            initial_max_stream_data_uni: config
+                .uni_stream_receive_window
+                .unwrap_or(config.stream_receive_window),
             max_udp_payload_size: endpoint_config.max_udp_payload_size,
             max_idle_timeout: config.max_idle_timeout.map_or(0u32.into(), |x| {
                 x.as_millis()
                     .try_into()
                     .expect("setter guarantees this is in-bounds")
             }),
-            max_ack_delay: 0u32.into(),
+            max_ack_delay: (config.max_ack_delay.as_millis() as u32).into(),
+            ack_delay_exponent: u32::from(config.ack_delay_exponent).into(),
             disable_active_migration: server_config.map_or(false, |c| !c.migration),
             active_connection_id_limit: if cid_gen.cid_len() == 0 {
                 2 // i.e. default, i.e. unsent
@@ -150,6 +164,13 @@ impl TransportParameters {
             max_datagram_frame_size: config
                 .datagram_receive_buffer_size
                 .map(|x| (x.min(u16::max_value().into()) as u16).into()),
+            auth_token: match server_config {
+                None => config.auth_token.as_ref().map(|x| {
+                    AuthToken::new(x).expect("length checked by TransportConfig::auth_token")
+                }),
+                Some(_) => None,
+            },
+            header_compression: config.header_compression,
             ..Self::default()
         }
     }
@@ -176,6 +197,13 @@ impl TransportParameters {
         Ok(())
     }
 
+    /// Opaque token sent by the client to authenticate itself, if any
+    ///
+    /// See `TransportConfig::auth_token`.
+    pub fn auth_token(&self) -> Option<&[u8]> {
+        self.auth_token.as_deref()
+    }
+
     /// Maximum number of CIDs to issue to this peer
     ///
     /// Consider both a) the active_connection_id_limit from the other end; and
@@ -313,6 +341,17 @@ impl TransportParameters {
             w.write(x);
         }
 
+        if let Some(ref x) = self.auth_token {
+            w.write_var(AUTH_TOKEN);
+            w.write_var(x.len() as u64);
+            w.put_slice(x);
+        }
+
+        if self.header_compression {
+            w.write_var(HEADER_COMPRESSION);
+            w.write_var(0);
+        }
+
         if let Some(ref x) = self.preferred_address {
             w.write_var(0x000d);
             w.write_var(x.wire_size() as u64);
@@ -390,6 +429,20 @@ impl TransportParameters {
                     }
                     params.max_datagram_frame_size = Some(r.get().unwrap());
                 }
+                AUTH_TOKEN => {
+                    if len > MAX_AUTH_TOKEN_SIZE || params.auth_token.is_some() {
+                        return Err(Error::Malformed);
+                    }
+                    let mut tok = [0; MAX_AUTH_TOKEN_SIZE];
+                    r.copy_to_slice(&mut tok[..len]);
+                    params.auth_token = AuthToken::new(&tok[..len]);
+                }
+                HEADER_COMPRESSION => {
+                    if len != 0 || params.header_compression {
+                        return Err(Error::Malformed);
+                    }
+                    params.header_compression = true;
+                }
                 _ => {
                     macro_rules! parse {
                         {$($(#[$doc:meta])* $name:ident ($code:expr) = $default:expr,)*} => {
@@ -418,6 +471,7 @@ impl TransportParameters {
             || params.initial_max_streams_uni.0 > MAX_STREAM_COUNT
             || (side.is_server()
                 && (params.stateless_reset_token.is_some() || params.preferred_address.is_some()))
+            || (side.is_client() && params.auth_token.is_some())
         {
             return Err(Error::IllegalValue);
         }
@@ -426,6 +480,12 @@ impl TransportParameters {
     }
 }
 
+/// Identifies the parameter carrying `auth_token`, which isn't registered with IANA
+const AUTH_TOKEN: u64 = 0x7175_696e;
+
+/// Identifies the parameter carrying `header_compression`, which isn't registered with IANA
+const HEADER_COMPRESSION: u64 = 0x7175_6863;
+
 fn decode_cid(len: usize, value: &mut Option<ConnectionId>, r: &mut impl Buf) -> Result<(), Error> {
     if len > MAX_CID_SIZE || value.is_some() || r.remaining() < len {
         return Err(Error::Malformed);
@@ -455,6 +515,7 @@ mod test {
                 connection_id: ConnectionId::new(&[]),
                 stateless_reset_token: [0xab; RESET_TOKEN_SIZE].into(),
             }),
+            header_compression: true,
             ..TransportParameters::default()
         };
         params.write(&mut buf);
@@ -464,6 +525,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn auth_token() {
+        let mut buf = Vec::new();
+        let params = TransportParameters {
+            auth_token: AuthToken::new(b"secret"),
+            ..TransportParameters::default()
+        };
+        params.write(&mut buf);
+        assert_eq!(
+            TransportParameters::read(Side::Server, &mut buf.as_slice()).unwrap(),
+            params
+        );
+        // Only clients may send tokens
+        assert_eq!(
+            TransportParameters::read(Side::Client, &mut buf.as_slice()),
+            Err(Error::IllegalValue)
+        );
+    }
+
     #[test]
     fn resumption_params_validation() {
         let high_limit = TransportParameters {
@@ -5,6 +5,7 @@ use std::{
 };
 
 use bencher::{benchmark_group, benchmark_main, Bencher};
+use bytes::Bytes;
 use futures::StreamExt;
 use tokio::runtime::{Builder, Runtime};
 use tracing::error_span;
@@ -17,7 +18,9 @@ benchmark_group!(
     large_data_1_stream,
     large_data_10_streams,
     small_data_1_stream,
-    small_data_100_streams
+    small_data_100_streams,
+    datagrams_1,
+    datagrams_100
 );
 benchmark_main!(benches);
 
@@ -37,6 +40,16 @@ fn small_data_100_streams(bench: &mut Bencher) {
     send_data(bench, SMALL_DATA, 100);
 }
 
+/// Exercises the receive, dispatch and acknowledgement path, one datagram at a time
+fn datagrams_1(bench: &mut Bencher) {
+    send_datagrams(bench, 1);
+}
+
+/// Exercises batching of outgoing datagrams by the endpoint driver
+fn datagrams_100(bench: &mut Bencher) {
+    send_datagrams(bench, 100);
+}
+
 fn send_data(bench: &mut Bencher, data: &'static [u8], concurrent_streams: usize) {
     let _ = tracing_subscriber::fmt::try_init();
 
@@ -66,9 +79,49 @@ fn send_data(bench: &mut Bencher, data: &'static [u8], concurrent_streams: usize
     });
     drop(client);
     runtime.block_on(endpoint.wait_idle());
+    report(&endpoint);
+    thread.join().unwrap()
+}
+
+fn send_datagrams(bench: &mut Bencher, count: usize) {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let ctx = Context::new();
+    let (addr, thread) = ctx.spawn_server();
+    let (endpoint, client, runtime) = ctx.make_client(addr);
+
+    bench.bytes = (SMALL_DATA.len() as u64) * (count as u64);
+    bench.iter(|| {
+        runtime.block_on(async {
+            for _ in 0..count {
+                client
+                    .send_datagram(Bytes::from_static(SMALL_DATA))
+                    .unwrap();
+            }
+            // Wait for the peer to acknowledge everything sent so far
+            let mut stream = client.open_uni().await.unwrap();
+            stream.finish().await.unwrap();
+        });
+    });
+    drop(client);
+    runtime.block_on(endpoint.wait_idle());
+    report(&endpoint);
     thread.join().unwrap()
 }
 
+#[cfg(feature = "driver_counters")]
+fn report(endpoint: &quinn::Endpoint) {
+    let counters = endpoint.driver_counters();
+    println!(
+        "client driver: {:?} per datagram received, {:?} per transmit sent",
+        counters.recv_time_per_datagram(),
+        counters.send_time_per_transmit()
+    );
+}
+
+#[cfg(not(feature = "driver_counters"))]
+fn report(_: &quinn::Endpoint) {}
+
 struct Context {
     server_config: quinn::ServerConfig,
     client_config: quinn::ClientConfig,
@@ -113,7 +166,9 @@ impl Context {
             let handle = runtime.spawn(
                 async move {
                     let quinn::NewConnection {
-                        mut uni_streams, ..
+                        mut uni_streams,
+                        mut datagrams,
+                        ..
                     } = incoming
                         .next()
                         .await
@@ -121,6 +176,8 @@ impl Context {
                         .await
                         .expect("connect");
 
+                    tokio::spawn(async move { while let Some(Ok(_)) = datagrams.next().await {} });
+
                     while let Some(Ok(mut stream)) = uni_streams.next().await {
                         tokio::spawn(async move {
                             while stream
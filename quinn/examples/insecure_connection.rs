@@ -3,7 +3,7 @@
 //! Checkout the `README.md` for guidance.
 
 use futures::StreamExt;
-use std::{error::Error, net::SocketAddr, sync::Arc};
+use std::{error::Error, net::SocketAddr};
 
 use quinn::{ClientConfig, ClientConfigBuilder, Endpoint};
 
@@ -53,34 +53,10 @@ async fn run_client(server_addr: SocketAddr) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-/// Dummy certificate verifier that treats any certificate as valid.
-/// NOTE, such verification is vulnerable to MITM attacks, but convenient for testing.
-struct SkipServerVerification;
-
-impl SkipServerVerification {
-    fn new() -> Arc<Self> {
-        Arc::new(Self)
-    }
-}
-
-impl rustls::ServerCertVerifier for SkipServerVerification {
-    fn verify_server_cert(
-        &self,
-        _roots: &rustls::RootCertStore,
-        _presented_certs: &[rustls::Certificate],
-        _dns_name: webpki::DNSNameRef,
-        _ocsp_response: &[u8],
-    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
-        Ok(rustls::ServerCertVerified::assertion())
-    }
-}
-
 fn configure_client() -> ClientConfig {
-    let mut cfg = ClientConfigBuilder::default().build();
-    let tls_cfg: &mut rustls::ClientConfig = Arc::get_mut(&mut cfg.crypto).unwrap();
-    // this is only available when compiled with "dangerous_configuration" feature
-    tls_cfg
-        .dangerous()
-        .set_certificate_verifier(SkipServerVerification::new());
-    cfg
+    let mut cfg = ClientConfigBuilder::default();
+    // this is only available when compiled with the "dangerous_configuration" feature
+    // NOTE, skipping verification is vulnerable to MITM attacks, but convenient for testing.
+    cfg.danger_accept_invalid_certs();
+    cfg.build()
 }
@@ -0,0 +1,110 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    sync::Mutex,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+
+/// A record of the 0-RTT attempts a server has seen, used to tell replays apart
+///
+/// 0-RTT data is sent before the handshake confirms that the client is live, so an attacker can
+/// replay it, e.g. to each instance of a server. Endpoints configured with
+/// [`EndpointBuilder::anti_replay()`] withhold streams opened in 0-RTT until the record reports the
+/// attempt as new, or until the handshake completes. Servers behind a load balancer should share
+/// the record between instances, as in the example below; a [`MemoryAntiReplay`] suffices for a
+/// single instance.
+///
+/// ```rust
+/// use std::{fmt, io, sync::Arc, time::Duration};
+///
+/// use futures::{future::BoxFuture, FutureExt};
+/// use quinn::AntiReplay;
+///
+/// /// Client for a key-value store shared between instances, e.g. Redis
+/// trait SharedStore: fmt::Debug + Send + Sync + 'static {
+///     /// Set `key` unless it's already set, e.g. `SET key 1 NX EX ttl`, returning whether it was
+///     fn set_if_absent(&self, key: Vec<u8>, ttl: Duration) -> BoxFuture<'static, io::Result<bool>>;
+/// }
+///
+/// #[derive(Debug)]
+/// struct SharedAntiReplay<T>(Arc<T>);
+///
+/// impl<T: SharedStore> AntiReplay for SharedAntiReplay<T> {
+///     fn check(&self, id: &[u8]) -> BoxFuture<'static, bool> {
+///         // Keep ids for as long as session tickets are valid
+///         let fresh = self.0.set_if_absent(id.to_vec(), Duration::from_secs(86400));
+///         // If the store is unavailable, fall back to waiting for the handshake
+///         fresh.map(|x| x.unwrap_or(false)).boxed()
+///     }
+/// }
+/// ```
+///
+/// [`EndpointBuilder::anti_replay()`]: crate::generic::EndpointBuilder::anti_replay
+pub trait AntiReplay: Send + Sync + fmt::Debug + 'static {
+    /// Record the 0-RTT attempt identified by `id`, resolving to whether it wasn't seen before
+    ///
+    /// Checking and recording must happen atomically, so that concurrent attempts with the same
+    /// `id` aren't all considered new. The future is always driven to completion. If the record
+    /// can't be consulted, it should resolve to `false`, which delays the connection's streams
+    /// rather than exposing them to replays.
+    fn check(&self, id: &[u8]) -> BoxFuture<'static, bool>;
+}
+
+/// An [`AntiReplay`] record held in memory, for servers running as a single instance
+///
+/// Remembers up to a fixed number of attempts, forgetting the oldest beyond that. Replays of
+/// forgotten attempts go undetected, so the capacity should cover the attempts expected over the
+/// lifetime of the server's session tickets.
+pub struct MemoryAntiReplay {
+    capacity: usize,
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryAntiReplay {
+    /// Create a record remembering up to `capacity` attempts
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be nonzero");
+        Self {
+            capacity,
+            state: Mutex::new(MemoryState {
+                seen: HashSet::new(),
+                order: VecDeque::new(),
+            }),
+        }
+    }
+}
+
+impl AntiReplay for MemoryAntiReplay {
+    fn check(&self, id: &[u8]) -> BoxFuture<'static, bool> {
+        let state = &mut *self.state.lock().unwrap();
+        let fresh = state.seen.insert(id.into());
+        if fresh {
+            state.order.push_back(id.into());
+            if state.order.len() > self.capacity {
+                let oldest = state.order.pop_front().unwrap();
+                state.seen.remove(&oldest);
+            }
+        }
+        futures::future::ready(fresh).boxed()
+    }
+}
+
+impl fmt::Debug for MemoryAntiReplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryAntiReplay")
+            .field("capacity", &self.capacity)
+            .field("len", &self.state.lock().unwrap().order.len())
+            .finish()
+    }
+}
+
+struct MemoryState {
+    seen: HashSet<Box<[u8]>>,
+    /// Ids in `seen`, oldest first
+    order: VecDeque<Box<[u8]>>,
+}
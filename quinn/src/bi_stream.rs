@@ -0,0 +1,119 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::io::{AsyncRead, AsyncWrite};
+use tokio::io::ReadBuf;
+
+use crate::{recv_stream::RecvStream, send_stream::SendStream, transport::Socket};
+
+/// The two halves of a bidirectional stream, joined into a single duplex object
+///
+/// Useful for APIs which expect a single type implementing both `AsyncRead` and `AsyncWrite`, such
+/// as `tokio_util::codec::Framed`. Reads are served by the receiving half and writes by the
+/// sending half; closing the `BiStream` finishes the sending half.
+#[derive(Debug)]
+pub struct BiStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    pub(crate) send: SendStream<S, T>,
+    pub(crate) recv: RecvStream<S, T>,
+}
+
+impl<S, T> BiStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Join the two halves of a bidirectional stream, e.g. as returned by `Connection::open_bi`
+    pub fn new(send: SendStream<S, T>, recv: RecvStream<S, T>) -> Self {
+        Self { send, recv }
+    }
+
+    /// Access the sending half of the stream
+    pub fn send(&mut self) -> &mut SendStream<S, T> {
+        &mut self.send
+    }
+
+    /// Access the receiving half of the stream
+    pub fn recv(&mut self) -> &mut RecvStream<S, T> {
+        &mut self.recv
+    }
+
+    /// Split the stream back into its two halves
+    pub fn into_inner(self) -> (SendStream<S, T>, RecvStream<S, T>) {
+        (self.send, self.recv)
+    }
+}
+
+impl<S, T> AsyncRead for BiStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncRead::poll_read(Pin::new(&mut self.get_mut().recv), cx, buf)
+    }
+}
+
+impl<S, T> tokio::io::AsyncRead for BiStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        tokio::io::AsyncRead::poll_read(Pin::new(&mut self.get_mut().recv), cx, buf)
+    }
+}
+
+impl<S, T> AsyncWrite for BiStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.get_mut().send), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().send), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(Pin::new(&mut self.get_mut().send), cx)
+    }
+}
+
+impl<S, T> tokio::io::AsyncWrite for BiStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        tokio::io::AsyncWrite::poll_write(Pin::new(&mut self.get_mut().send), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().send), cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_shutdown(Pin::new(&mut self.get_mut().send), cx)
+    }
+}
@@ -0,0 +1,45 @@
+use std::{mem, sync::Arc};
+
+use crate::mutex::Mutex;
+
+/// Buffers of transmits the endpoint driver has finished sending, for connections to reuse
+///
+/// Saves allocating a new buffer, sized for a full batch of datagrams, for every transmit, which
+/// otherwise dominates allocator traffic in workloads sending many small messages.
+///
+/// Only transmit buffers are pooled. Each connection's timer is already reset in place rather than
+/// reallocated, and the buffers of received datagrams can't be recycled, as stream data handed to
+/// the application may still refer to them.
+#[derive(Debug, Clone)]
+pub(crate) struct BufferPool(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl BufferPool {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(Mutex::new(Vec::new())))
+    }
+
+    /// Take a buffer from the pool, or an empty one if none are available
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.0.lock("buffer pool take").pop().unwrap_or_default()
+    }
+
+    /// Number of buffers in the pool
+    #[cfg(feature = "debug_counters")]
+    pub(crate) fn len(&self) -> usize {
+        self.0.lock("buffer pool len").len()
+    }
+
+    /// Return the buffers of `transmits` to the pool, as far as it has room
+    pub(crate) fn put_all<A>(&self, transmits: impl Iterator<Item = proto::Transmit<A>>) {
+        let mut buffers = self.0.lock("buffer pool put");
+        for mut transmit in transmits {
+            if buffers.len() < MAX_BUFFERS && transmit.contents.capacity() != 0 {
+                transmit.contents.clear();
+                buffers.push(mem::take(&mut transmit.contents));
+            }
+        }
+    }
+}
+
+/// Upper bound on the number of buffers kept, bounding the memory held by an idle endpoint
+const MAX_BUFFERS: usize = 64;
@@ -3,22 +3,36 @@ use std::{
     io,
     marker::PhantomData,
     net::SocketAddr,
+    num::NonZeroU64,
     sync::Arc,
 };
 
-use once_cell::sync::OnceCell;
+use bytes::Bytes;
 use proto::{
     generic::{ClientConfig, EndpointConfig, ServerConfig},
-    ConnectionIdGenerator,
+    AdmissionController, ConfigError, ConnectionIdGenerator,
 };
 use thiserror::Error;
+#[cfg(feature = "runtime-tokio")]
+use tokio::runtime::Handle;
+use tokio::sync::Semaphore;
 use tracing::error;
 
 use crate::{
-    endpoint::{Endpoint, EndpointDriver, EndpointRef, Incoming},
+    anti_replay::AntiReplay,
+    clock::Clock,
+    endpoint::{
+        Endpoint, EndpointDriver, EndpointRef, EraseReconnect, Incoming, ManualDrivers, Reconnect,
+        SocketErrorPolicy,
+    },
+    mutex::Mutex,
     platform::UdpSocket,
+    runtime::{default_runtime, Runtime},
     transport::Socket,
+    unwind::{DriverPanic, PanicHook},
 };
+#[cfg(feature = "runtime-tokio")]
+use crate::{connection::HandshakeRuntime, runtime::HandleRuntime};
 #[cfg(feature = "rustls")]
 use crate::{Certificate, CertificateChain, PrivateKey};
 
@@ -28,7 +42,7 @@ use crate::{Certificate, CertificateChain, PrivateKey};
 ///
 /// [`Endpoint`]: crate::generic::Endpoint
 /// [`ClientConfigBuilder`]: crate::generic::ClientConfigBuilder
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct EndpointBuilder<S, T>
 where
     S: proto::crypto::Session,
@@ -37,6 +51,20 @@ where
     server_config: Option<ServerConfig<S>>,
     config: EndpointConfig<S>,
     default_client_config: Option<ClientConfig<S>>,
+    #[cfg(feature = "runtime-tokio")]
+    handshake_runtime: Option<Handle>,
+    max_concurrent_handshakes: Option<usize>,
+    recreate_socket: bool,
+    fair_accept: bool,
+    clock: Option<Arc<dyn Clock>>,
+    runtime: Option<Arc<dyn Runtime>>,
+    health_check: Option<(Vec<u8>, ServerConfig<S>)>,
+    spawn_drivers: bool,
+    max_send_rate: Option<NonZeroU64>,
+    socket_error_policy: SocketErrorPolicy,
+    anti_replay: Option<Arc<dyn AntiReplay>>,
+    on_driver_panic: Option<PanicHook>,
+    reconnect: Option<EraseReconnect<S, T>>,
     socket_type: PhantomData<T>,
 }
 
@@ -60,6 +88,58 @@ where
         let socket = std::net::UdpSocket::bind(addr)?;
         self.with_socket(socket)
     }
+
+    /// Build an endpoint around a bound UDP socket inherited from another process
+    ///
+    /// Allows a server to be upgraded without giving up its port: the old process hands its socket
+    /// over with [`Endpoint::into_raw_fd()`], and the new one resumes serving on it with whatever
+    /// server configuration was passed to [`listen()`](Self::listen). Must be called from within
+    /// a tokio runtime context.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open UDP socket which isn't owned by anything else. The endpoint takes
+    /// ownership of it, closing it when the endpoint is dropped.
+    #[cfg(unix)]
+    pub unsafe fn from_raw_fd(
+        self,
+        fd: std::os::unix::io::RawFd,
+    ) -> Result<(Endpoint<S, UdpSocket>, Incoming<S, UdpSocket>), EndpointError> {
+        use std::os::unix::io::FromRawFd;
+        self.with_socket(std::net::UdpSocket::from_raw_fd(fd))
+    }
+
+    /// Build an endpoint around a UDP socket passed by systemd socket activation
+    ///
+    /// If `name` is given, the socket configured with a matching `FileDescriptorName=` in the
+    /// socket unit is used, allowing a daemon to be activated through several sockets. Otherwise,
+    /// the first datagram socket passed is used. Each socket can only be used by one endpoint.
+    /// Must be called from within a tokio runtime context.
+    #[cfg(all(unix, feature = "systemd"))]
+    pub fn from_systemd(
+        self,
+        name: Option<&str>,
+    ) -> Result<(Endpoint<S, UdpSocket>, Incoming<S, UdpSocket>), EndpointError> {
+        let socket = crate::systemd::take_socket(name)?.ok_or(EndpointError::NoActivatedSocket)?;
+        self.with_socket(socket)
+    }
+
+    /// Build an endpoint around a bound UDP socket inherited from another process
+    ///
+    /// See [`from_raw_fd()`](Self::from_raw_fd) for details.
+    ///
+    /// # Safety
+    ///
+    /// `socket` must be an open UDP socket which isn't owned by anything else. The endpoint takes
+    /// ownership of it, closing it when the endpoint is dropped.
+    #[cfg(windows)]
+    pub unsafe fn from_raw_socket(
+        self,
+        socket: std::os::windows::io::RawSocket,
+    ) -> Result<(Endpoint<S, UdpSocket>, Incoming<S, UdpSocket>), EndpointError> {
+        use std::os::windows::io::FromRawSocket;
+        self.with_socket(std::net::UdpSocket::from_raw_socket(socket))
+    }
 }
 
 #[allow(missing_docs)]
@@ -74,14 +154,34 @@ where
             server_config: None,
             config,
             default_client_config: Some(default_client_config),
+            #[cfg(feature = "runtime-tokio")]
+            handshake_runtime: None,
+            max_concurrent_handshakes: None,
+            recreate_socket: false,
+            fair_accept: false,
+            clock: None,
+            runtime: default_runtime(),
+            health_check: None,
+            spawn_drivers: true,
+            max_send_rate: None,
+            socket_error_policy: SocketErrorPolicy::Retry,
+            anti_replay: None,
+            on_driver_panic: None,
+            reconnect: None,
             socket_type: PhantomData,
         }
     }
 
     /// Build an endpoint around a pre-configured socket
     ///
-    /// Must be called from within a tokio runtime context. To avoid consuming the
+    /// Must be called from within a tokio runtime context, unless another
+    /// [`runtime()`](Self::runtime) is set and no `handshake_runtime()` is. To avoid consuming the
     /// `EndpointBuilder`, call `clone()` first.
+    ///
+    /// # Panics
+    ///
+    /// If no [`runtime()`](Self::runtime) is set and the `runtime-tokio` feature, which provides the
+    /// default, is disabled.
     pub fn with_socket<U>(
         self,
         socket: U,
@@ -92,26 +192,78 @@ where
     {
         let socket = socket.try_into()?;
         let addr = socket.local_addr().map_err(EndpointError::Socket)?;
+        let runtime = self
+            .runtime
+            .expect("no runtime set, see EndpointBuilder::runtime()");
+        let clock = self.clock.unwrap_or_else(|| runtime.clock());
+        let mut server_config = self.server_config;
+        #[cfg(feature = "runtime-tokio")]
+        let handshake_runtime = self.handshake_runtime.map(|handshake| HandshakeRuntime {
+            handshake: Arc::new(HandleRuntime(handshake)),
+            established: Arc::new(HandleRuntime(Handle::current())),
+        });
+        #[cfg(not(feature = "runtime-tokio"))]
+        let handshake_runtime = None;
+        let handshake_runtime_configured =
+            handshake_runtime.is_some() || self.max_concurrent_handshakes.is_some();
+        if handshake_runtime_configured {
+            if let Some(ref mut config) = server_config {
+                config.defer_handshake(true);
+            }
+        }
+        let anti_replay_configured = self.anti_replay.is_some();
+        if anti_replay_configured {
+            if let Some(ref mut config) = server_config {
+                config.defer_0rtt_data(true);
+            }
+        }
+        let handshake_permits = self
+            .max_concurrent_handshakes
+            .map(|n| Arc::new(Semaphore::new(n)));
         let rc = EndpointRef::new(
             socket,
-            proto::generic::Endpoint::new(Arc::new(self.config), self.server_config.map(Arc::new)),
-            addr.is_ipv6(),
+            proto::generic::Endpoint::new(Arc::new(self.config), server_config.map(Arc::new)),
+            addr,
+            handshake_runtime,
+            handshake_permits,
+            self.recreate_socket,
+            self.fair_accept,
+            clock,
+            self.max_send_rate,
+            self.socket_error_policy,
+            self.anti_replay,
+            self.on_driver_panic,
+            runtime.clone(),
         );
-        let driver = EndpointDriver(rc.clone());
-        tokio::spawn(async {
-            if let Err(e) = driver.await {
-                error!("I/O error: {}", e);
+        if let Some((protocol, mut config)) = self.health_check {
+            if handshake_runtime_configured {
+                config.defer_handshake(true);
             }
-        });
+            if anti_replay_configured {
+                config.defer_0rtt_data(true);
+            }
+            rc.lock("with_socket").set_health_check(&protocol, config);
+        }
+        let driver = EndpointDriver(rc.clone());
+        let drivers = if self.spawn_drivers {
+            runtime.spawn(Box::pin(async {
+                if let Err(e) = driver.await {
+                    error!("I/O error: {}", e);
+                }
+            }));
+            None
+        } else {
+            let new = rc.lock("with_socket").drive_manually();
+            Some(Arc::new(Mutex::new(ManualDrivers::new(driver, new))))
+        };
         Ok((
             Endpoint {
                 inner: rc.clone(),
                 // If a default client config hasn't been specified explicitly, leave the OnceCell
                 // empty so `Endpoint` can initialize it iff needed.
-                default_client_config: self
-                    .default_client_config
-                    .map(OnceCell::from)
-                    .unwrap_or_default(),
+                default_client_config: Arc::new(Mutex::new(self.default_client_config)),
+                drivers,
+                reconnect: self.reconnect,
             },
             Incoming::new(rc),
         ))
@@ -143,6 +295,231 @@ where
         self.config.cid_generator(factory);
         self
     }
+
+    /// Maximum UDP payload size accepted from peers
+    ///
+    /// See [`EndpointConfig::max_udp_payload_size()`].
+    ///
+    /// [`EndpointConfig::max_udp_payload_size()`]: proto::generic::EndpointConfig::max_udp_payload_size
+    pub fn max_udp_payload_size(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        self.config.max_udp_payload_size(value)?;
+        Ok(self)
+    }
+
+    /// Drive connections on a dedicated runtime until their handshake completes
+    ///
+    /// The cryptography involved in a handshake, such as signing and validating certificates, is
+    /// comparatively expensive. Performing it on a separate runtime, e.g. one with its own pool of
+    /// worker threads, prevents bursts of connection attempts from adding latency to established
+    /// connections. Once a connection is established, its driver moves back to the runtime the
+    /// endpoint was built on.
+    ///
+    /// Enables [`ServerConfig::defer_handshake`] on the server configuration, if any, so that the
+    /// first flight of incoming connections is also processed on `runtime`.
+    ///
+    /// [`ServerConfig::defer_handshake`]: proto::generic::ServerConfig::defer_handshake
+    #[cfg(feature = "runtime-tokio")]
+    pub fn handshake_runtime(&mut self, runtime: Handle) -> &mut Self {
+        self.handshake_runtime = Some(runtime);
+        self
+    }
+
+    /// Limit the number of incoming connections whose handshakes are processed at once
+    ///
+    /// Handshakes proceed concurrently on their connections' own tasks, but under a burst of
+    /// connection attempts, e.g. with expensive certificate chains, processing all of them at once
+    /// can starve established connections. Beyond this limit, connection attempts wait for an
+    /// earlier handshake to complete or fail before they're processed. Unlimited by default.
+    ///
+    /// Enables [`ServerConfig::defer_handshake`] on the server configuration, if any, so that the
+    /// first flight of incoming connections is also subject to the limit.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is zero.
+    ///
+    /// [`ServerConfig::defer_handshake`]: proto::generic::ServerConfig::defer_handshake
+    pub fn max_concurrent_handshakes(&mut self, limit: usize) -> &mut Self {
+        assert!(limit > 0, "at least one handshake must be allowed");
+        self.max_concurrent_handshakes = Some(limit);
+        self
+    }
+
+    /// Recreate the socket when sends keep failing due to a network change
+    ///
+    /// Errors such as `ENETUNREACH` arise when the network the socket was bound to goes away, e.g.
+    /// when a mobile device switches from Wi-Fi to cellular. Datagrams that can't be sent are
+    /// dropped, as with any transient socket error. When enabled, if the errors persist the
+    /// endpoint switches to a socket created by [`Socket::recreate()`], as with
+    /// [`Endpoint::rebind()`], and prompts its connections to migrate to it.
+    /// [`Endpoint::socket_recreated()`] resolves whenever this happens.
+    ///
+    /// Intended for clients, as the new socket is bound to an ephemeral port.
+    ///
+    /// [`Socket::recreate()`]: crate::transport::Socket::recreate
+    /// [`Endpoint::rebind()`]: crate::generic::Endpoint::rebind
+    /// [`Endpoint::socket_recreated()`]: crate::generic::Endpoint::socket_recreated
+    pub fn recreate_socket_on_network_error(&mut self, enabled: bool) -> &mut Self {
+        self.recreate_socket = enabled;
+        self
+    }
+
+    /// Yield incoming connections from different source IP addresses in turn
+    ///
+    /// By default, [`Incoming`] yields connections in the order they arrived, so a burst of
+    /// connection attempts from one client delays those from everyone else. When enabled, pending
+    /// connections are instead taken round-robin across the IP addresses they came from.
+    ///
+    /// [`Incoming`]: crate::generic::Incoming
+    pub fn fair_accept(&mut self, enabled: bool) -> &mut Self {
+        self.fair_accept = enabled;
+        self
+    }
+
+    /// Source of time for the endpoint and its connections
+    ///
+    /// Defaults to the clock of the [`runtime()`](Self::runtime). See [`Clock`] for details.
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Async runtime to spawn the tasks driving the endpoint and its connections on
+    ///
+    /// Defaults to `TokioRuntime` if the `runtime-tokio` feature is enabled, and must be set
+    /// otherwise. Also replaces the endpoint's clock with the runtime's, unless
+    /// [`clock()`](Self::clock) is called afterwards. See [`Runtime`] for details.
+    pub fn runtime(&mut self, runtime: Arc<dyn Runtime>) -> &mut Self {
+        self.clock = None;
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Answer health checks from clients requesting the application protocol `protocol`
+    ///
+    /// Such connections are accepted using `config`, which must offer `protocol`, and are handled
+    /// by the endpoint itself rather than being yielded by [`Incoming`]. Each bidirectional stream
+    /// opened on them is echoed back once finished, so that load balancers can check that the
+    /// server is up and measure its round trip time without involving the application. Echoes are
+    /// limited to 1KiB.
+    pub fn health_check(&mut self, protocol: &[u8], config: ServerConfig<S>) -> &mut Self {
+        self.health_check = Some((protocol.to_vec(), config));
+        self
+    }
+
+    /// Whether to spawn the tasks driving the endpoint and its connections on the runtime
+    ///
+    /// Enabled by default. When disabled, no tasks are spawned, and the application must instead
+    /// call [`Endpoint::poll_drivers()`] from its own event loop for any I/O to occur. Incompatible
+    /// with [`handshake_runtime()`], under which connection drivers are spawned regardless.
+    ///
+    /// [`Endpoint::poll_drivers()`]: crate::generic::Endpoint::poll_drivers
+    /// [`handshake_runtime()`]: Self::handshake_runtime
+    pub fn spawn_drivers(&mut self, enabled: bool) -> &mut Self {
+        self.spawn_drivers = enabled;
+        self
+    }
+
+    /// Limit the rate at which data is sent, in bytes per second, across all connections
+    ///
+    /// Suits background services which shouldn't saturate a shared link however many connections
+    /// they serve, e.g. `NonZeroU64::new(50_000_000 / 8)` for 50Mbps. Short bursts of up to 10ms worth of data
+    /// are sent at once; beyond that, datagrams queue in the endpoint until the rate permits, so
+    /// connections see the limit as increased latency. Unlimited by default. May be changed later
+    /// with [`Endpoint::set_max_send_rate()`].
+    ///
+    /// [`Endpoint::set_max_send_rate()`]: crate::generic::Endpoint::set_max_send_rate
+    pub fn max_send_rate(&mut self, rate: Option<NonZeroU64>) -> &mut Self {
+        self.max_send_rate = rate;
+        self
+    }
+
+    /// How to handle socket errors which don't prevent further I/O from succeeding
+    ///
+    /// Defaults to [`SocketErrorPolicy::Retry`].
+    ///
+    /// [`SocketErrorPolicy::Retry`]: crate::SocketErrorPolicy::Retry
+    pub fn socket_error_policy(&mut self, policy: SocketErrorPolicy) -> &mut Self {
+        self.socket_error_policy = policy;
+        self
+    }
+
+    /// Check incoming connections' 0-RTT attempts against `record` to detect replays
+    ///
+    /// Streams the client opens in 0-RTT are withheld until `record` reports the attempt as new,
+    /// rather than until the handshake completes, so that 0-RTT keeps its latency benefit without
+    /// letting replayed requests through. Streams of attempts seen before are still accepted once
+    /// the handshake completes, which a replay can't achieve.
+    ///
+    /// Enables [`ServerConfig::defer_0rtt_data`] on the server configuration, if any, and on those
+    /// of listeners created with [`Endpoint::listen()`].
+    ///
+    /// [`ServerConfig::defer_0rtt_data`]: proto::generic::ServerConfig::defer_0rtt_data
+    /// [`Endpoint::listen()`]: crate::generic::Endpoint::listen
+    pub fn anti_replay(&mut self, record: impl AntiReplay) -> &mut Self {
+        self.anti_replay = Some(Arc::new(record));
+        self
+    }
+
+    /// Call `hook` whenever the task driving one of the endpoint's connections panics
+    ///
+    /// Such panics are caught rather than left to take down the runtime or event loop driving other
+    /// connections. The connection is abandoned without notifying the peer, and fails with an
+    /// `INTERNAL_ERROR` [`TransportError`](crate::ConnectionError::TransportError) locally. `hook`
+    /// runs on the task that panicked, after the panic was logged at error level.
+    pub fn on_driver_panic<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&DriverPanic) + Send + Sync + 'static,
+    {
+        self.on_driver_panic = Some(PanicHook::new(hook));
+        self
+    }
+
+    /// Start a new connection to the same server if an outgoing connection is ended by a stateless
+    /// reset
+    ///
+    /// A stateless reset usually means that the server lost the connection's state, e.g. because
+    /// it restarted, while remaining reachable. The new connection is attempted once, with the
+    /// same configuration and label, and is obtained from
+    /// [`Connection::reconnected()`](crate::generic::Connection::reconnected).
+    pub fn reconnect_on_reset(&mut self) -> &mut Self
+    where
+        S: 'static,
+        S::ClientConfig: Send + Sync,
+    {
+        self.reconnect = Some(Reconnect::erase);
+        self
+    }
+}
+
+// Manual impl, as the socket type itself needn't be `Clone`
+impl<S, T> Clone for EndpointBuilder<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn clone(&self) -> Self {
+        Self {
+            server_config: self.server_config.clone(),
+            config: self.config.clone(),
+            default_client_config: self.default_client_config.clone(),
+            #[cfg(feature = "runtime-tokio")]
+            handshake_runtime: self.handshake_runtime.clone(),
+            max_concurrent_handshakes: self.max_concurrent_handshakes,
+            recreate_socket: self.recreate_socket,
+            fair_accept: self.fair_accept,
+            clock: self.clock.clone(),
+            runtime: self.runtime.clone(),
+            health_check: self.health_check.clone(),
+            spawn_drivers: self.spawn_drivers,
+            max_send_rate: self.max_send_rate,
+            socket_error_policy: self.socket_error_policy,
+            anti_replay: self.anti_replay.clone(),
+            on_driver_panic: self.on_driver_panic.clone(),
+            reconnect: self.reconnect,
+            socket_type: PhantomData,
+        }
+    }
 }
 
 impl<S, T> Default for EndpointBuilder<S, T>
@@ -155,6 +532,20 @@ where
             server_config: None,
             config: EndpointConfig::default(),
             default_client_config: None,
+            #[cfg(feature = "runtime-tokio")]
+            handshake_runtime: None,
+            max_concurrent_handshakes: None,
+            recreate_socket: false,
+            fair_accept: false,
+            clock: None,
+            runtime: default_runtime(),
+            health_check: None,
+            spawn_drivers: true,
+            max_send_rate: None,
+            socket_error_policy: SocketErrorPolicy::Retry,
+            anti_replay: None,
+            on_driver_panic: None,
+            reconnect: None,
             socket_type: PhantomData,
         }
     }
@@ -166,6 +557,12 @@ pub enum EndpointError {
     /// An error during setup of the underlying UDP socket.
     #[error("failed to set up UDP socket: {0}")]
     Socket(#[from] io::Error),
+    /// No matching datagram socket was passed by systemd socket activation
+    ///
+    /// Only returned by `EndpointBuilder::from_systemd()`, which requires the `systemd` feature
+    /// on Unix.
+    #[error("no matching socket was passed by systemd")]
+    NoActivatedSocket,
 }
 
 impl From<Infallible> for EndpointError {
@@ -205,10 +602,117 @@ where
         self.config.use_stateless_retry(enabled);
         self
     }
+
+    /// Whether to withhold streams opened by clients, and any 0-RTT data on them, until the
+    /// handshake is confirmed
+    ///
+    /// See [`ServerConfig::defer_0rtt_data()`].
+    ///
+    /// [`ServerConfig::defer_0rtt_data()`]: crate::generic::ServerConfig::defer_0rtt_data
+    pub fn defer_0rtt_data(&mut self, enabled: bool) -> &mut Self {
+        self.config.defer_0rtt_data(enabled);
+        self
+    }
+
+    /// Whether to resume sessions only from tickets issued for the same server name and
+    /// application protocol
+    ///
+    /// See [`ServerConfig::pin_session_tickets()`].
+    ///
+    /// [`ServerConfig::pin_session_tickets()`]: crate::generic::ServerConfig::pin_session_tickets
+    pub fn pin_session_tickets(&mut self, enabled: bool) -> &mut Self {
+        self.config.pin_session_tickets(enabled);
+        self
+    }
+
+    /// Consult `controller` on each connection attempt to decide whether to accept, reject, or
+    /// retry it
+    ///
+    /// Useful for integrating application-specific load shedding. See also
+    /// [`Endpoint::set_memory_usage()`].
+    ///
+    /// [`Endpoint::set_memory_usage()`]: crate::generic::Endpoint::set_memory_usage
+    pub fn admission_controller(
+        &mut self,
+        controller: impl AdmissionController + 'static,
+    ) -> &mut Self {
+        self.config.admission_controller(controller);
+        self
+    }
+
+    /// Maximum number of bidirectional streams clients may open concurrently on each incoming
+    /// connection
+    ///
+    /// See [`TransportConfig::max_concurrent_bidi_streams()`].
+    ///
+    /// [`TransportConfig::max_concurrent_bidi_streams()`]: crate::TransportConfig::max_concurrent_bidi_streams
+    pub fn max_concurrent_bidi_streams(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).max_concurrent_bidi_streams(value)?;
+        Ok(self)
+    }
+
+    /// Variant of `max_concurrent_bidi_streams` affecting unidirectional streams
+    pub fn max_concurrent_uni_streams(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).max_concurrent_uni_streams(value)?;
+        Ok(self)
+    }
+
+    /// Per-stream receive window for incoming connections
+    ///
+    /// See [`TransportConfig::stream_receive_window()`].
+    ///
+    /// [`TransportConfig::stream_receive_window()`]: crate::TransportConfig::stream_receive_window
+    pub fn stream_receive_window(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).stream_receive_window(value)?;
+        Ok(self)
+    }
+
+    /// Variant of `stream_receive_window` affecting unidirectional streams
+    pub fn uni_stream_receive_window(
+        &mut self,
+        value: Option<u64>,
+    ) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).uni_stream_receive_window(value)?;
+        Ok(self)
+    }
+
+    /// Size of the UDP payloads initially sent on incoming connections
+    ///
+    /// See [`TransportConfig::initial_max_udp_payload_size()`].
+    ///
+    /// [`TransportConfig::initial_max_udp_payload_size()`]: crate::TransportConfig::initial_max_udp_payload_size
+    pub fn initial_max_udp_payload_size(&mut self, value: u16) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).initial_max_udp_payload_size(value)?;
+        Ok(self)
+    }
+
+    /// Upper bound on the size of UDP payloads sent on incoming connections
+    ///
+    /// See [`TransportConfig::max_egress_udp_payload_size()`].
+    ///
+    /// [`TransportConfig::max_egress_udp_payload_size()`]: crate::TransportConfig::max_egress_udp_payload_size
+    pub fn max_egress_udp_payload_size(
+        &mut self,
+        value: Option<u16>,
+    ) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).max_egress_udp_payload_size(value)?;
+        Ok(self)
+    }
 }
 
 #[cfg(feature = "rustls")]
 impl ServerConfigBuilder<proto::crypto::rustls::TlsSession> {
+    /// Construct a builder using `crypto` for TLS, with the default transport configuration.
+    ///
+    /// An escape hatch for rustls features the builder doesn't expose, such as client
+    /// authentication, which keeps the rest of the builder available. Settings QUIC requires are
+    /// adjusted as described in [`ServerConfig::with_rustls_config()`].
+    ///
+    /// [`ServerConfig::with_rustls_config()`]: crate::generic::ServerConfig::with_rustls_config
+    pub fn with_rustls_config(crypto: rustls::ServerConfig) -> Self {
+        Self::new(ServerConfig::with_rustls_config(crypto))
+    }
+
     /// Enable NSS-compatible cryptographic key logging to the `SSLKEYLOGFILE` environment variable.
     ///
     /// Useful for debugging encrypted communications with protocol analyzers such as Wireshark.
@@ -227,6 +731,15 @@ impl ServerConfigBuilder<proto::crypto::rustls::TlsSession> {
         Ok(self)
     }
 
+    /// Select the certificate chain to present to each client from its ClientHello.
+    ///
+    /// Handshakes for which `resolver` returns `None` are aborted early with an
+    /// `unrecognized_name` alert. Replaces any chain set with `certificate`.
+    pub fn cert_resolver(&mut self, resolver: Arc<dyn rustls::ResolvesServerCert>) -> &mut Self {
+        self.config.cert_resolver(resolver);
+        self
+    }
+
     /// Set the application-layer protocols to accept, in order of descending preference.
     ///
     /// When set, clients which don't declare support for at least one of the supplied protocols will be rejected.
@@ -289,13 +802,11 @@ where
     ///
     /// The `ClientConfigBuilder` provides a number of shortcuts to customize the TLS client
     /// behavior. However, if you want to take full control over the client's behavior (such as
-    /// setting up TLS mutual authentication), you can use the associated [`new()`] function to
-    /// provide a [`ClientConfig`] with TLS configuration provided directly through its `crypto`
-    /// field).
+    /// setting up TLS mutual authentication), you can provide a complete rustls configuration
+    /// through [`with_rustls_config()`] instead.
     ///
     /// [`ClientConfigBuilder::default()`]: #method.default
-    /// [`new()`]: ClientConfigBuilder::new
-    /// [`ClientConfig`]: crate::generic::ClientConfig
+    /// [`with_rustls_config()`]: ClientConfigBuilder::with_rustls_config
     pub fn new(config: ClientConfig<S>) -> Self {
         Self { config }
     }
@@ -308,17 +819,97 @@ where
     pub fn build(self) -> ClientConfig<S> {
         self.config
     }
+
+    /// Maximum number of bidirectional streams the server may open concurrently on each outgoing
+    /// connection
+    ///
+    /// See [`TransportConfig::max_concurrent_bidi_streams()`].
+    ///
+    /// [`TransportConfig::max_concurrent_bidi_streams()`]: crate::TransportConfig::max_concurrent_bidi_streams
+    pub fn max_concurrent_bidi_streams(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).max_concurrent_bidi_streams(value)?;
+        Ok(self)
+    }
+
+    /// Variant of `max_concurrent_bidi_streams` affecting unidirectional streams
+    pub fn max_concurrent_uni_streams(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).max_concurrent_uni_streams(value)?;
+        Ok(self)
+    }
+
+    /// Per-stream receive window for outgoing connections
+    ///
+    /// See [`TransportConfig::stream_receive_window()`].
+    ///
+    /// [`TransportConfig::stream_receive_window()`]: crate::TransportConfig::stream_receive_window
+    pub fn stream_receive_window(&mut self, value: u64) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).stream_receive_window(value)?;
+        Ok(self)
+    }
+
+    /// Variant of `stream_receive_window` affecting unidirectional streams
+    pub fn uni_stream_receive_window(
+        &mut self,
+        value: Option<u64>,
+    ) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).uni_stream_receive_window(value)?;
+        Ok(self)
+    }
+
+    /// Size of the UDP payloads initially sent on outgoing connections
+    ///
+    /// See [`TransportConfig::initial_max_udp_payload_size()`].
+    ///
+    /// [`TransportConfig::initial_max_udp_payload_size()`]: crate::TransportConfig::initial_max_udp_payload_size
+    pub fn initial_max_udp_payload_size(&mut self, value: u16) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).initial_max_udp_payload_size(value)?;
+        Ok(self)
+    }
+
+    /// Upper bound on the size of UDP payloads sent on outgoing connections
+    ///
+    /// See [`TransportConfig::max_egress_udp_payload_size()`].
+    ///
+    /// [`TransportConfig::max_egress_udp_payload_size()`]: crate::TransportConfig::max_egress_udp_payload_size
+    pub fn max_egress_udp_payload_size(
+        &mut self,
+        value: Option<u16>,
+    ) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport).max_egress_udp_payload_size(value)?;
+        Ok(self)
+    }
+
+    /// Opaque token for servers to authenticate outgoing connections with
+    ///
+    /// See [`TransportConfig::auth_token()`].
+    ///
+    /// [`TransportConfig::auth_token()`]: crate::TransportConfig::auth_token
+    pub fn auth_token(&mut self, token: &[u8]) -> Result<&mut Self, ConfigError> {
+        Arc::make_mut(&mut self.config.transport)
+            .auth_token(Some(Bytes::copy_from_slice(token)))?;
+        Ok(self)
+    }
 }
 
 #[cfg(feature = "rustls")]
 impl ClientConfigBuilder<proto::crypto::rustls::TlsSession> {
+    /// Construct a builder using `crypto` for TLS, with the default transport configuration.
+    ///
+    /// An escape hatch for rustls features the builder doesn't expose, such as client
+    /// certificates, which keeps the rest of the builder available. Settings QUIC requires are
+    /// adjusted as described in [`ClientConfig::with_rustls_config()`].
+    ///
+    /// [`ClientConfig::with_rustls_config()`]: crate::generic::ClientConfig::with_rustls_config
+    pub fn with_rustls_config(crypto: rustls::ClientConfig) -> Self {
+        Self::new(ClientConfig::with_rustls_config(crypto))
+    }
+
     /// Add a trusted certificate authority.
     ///
-    /// For more advanced/less secure certificate verification, construct a [`ClientConfig`]
-    /// manually and use rustls's `dangerous_configuration` feature to override the certificate
-    /// verifier.
+    /// For more advanced/less secure certificate verification, start from a rustls configuration
+    /// with a custom certificate verifier using [`with_rustls_config()`].
     ///
-    /// [`ClientConfig`]: crate::generic::ClientConfig
+    /// [`with_rustls_config()`]: ClientConfigBuilder::with_rustls_config
     pub fn add_certificate_authority(
         &mut self,
         cert: Certificate,
@@ -327,6 +918,34 @@ impl ClientConfigBuilder<proto::crypto::rustls::TlsSession> {
         Ok(self)
     }
 
+    /// Accept any certificate the server presents, without verifying it at all.
+    ///
+    /// Convenient for development and for peers authenticated by other means, such as pinned keys
+    /// checked through [`Connection::peer_identity()`], but leaves connections open to
+    /// interception otherwise. To skip verification for a single connection only, call
+    /// [`ClientConfig::danger_accept_invalid_certs()`] on a copy of the usual configuration and
+    /// pass it to [`Endpoint::connect_with()`].
+    ///
+    /// [`Connection::peer_identity()`]: crate::generic::Connection::peer_identity
+    /// [`ClientConfig::danger_accept_invalid_certs()`]: crate::generic::ClientConfig::danger_accept_invalid_certs
+    /// [`Endpoint::connect_with()`]: crate::generic::Endpoint::connect_with
+    #[cfg(feature = "dangerous_configuration")]
+    pub fn danger_accept_invalid_certs(&mut self) -> &mut Self {
+        self.config.danger_accept_invalid_certs();
+        self
+    }
+
+    /// Reject servers that don't staple an OCSP response to their certificate, whatever it says.
+    ///
+    /// See [`ClientConfig::require_any_ocsp_staple()`].
+    ///
+    /// [`ClientConfig::require_any_ocsp_staple()`]: crate::generic::ClientConfig::require_any_ocsp_staple
+    #[cfg(feature = "ocsp")]
+    pub fn require_any_ocsp_staple(&mut self) -> &mut Self {
+        self.config.require_any_ocsp_staple();
+        self
+    }
+
     /// Enable NSS-compatible cryptographic key logging to the `SSLKEYLOGFILE` environment variable.
     ///
     /// Useful for debugging encrypted communications with protocol analyzers such as Wireshark.
@@ -0,0 +1,212 @@
+#[cfg(feature = "runtime-tokio")]
+use std::future::Future;
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fmt, io,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+    time::Instant,
+};
+
+use fxhash::FxHashMap;
+#[cfg(feature = "runtime-tokio")]
+use tokio::time::{sleep_until, Sleep};
+
+/// A source of time for an endpoint and its connections
+///
+/// All timestamps passed to the protocol state machines, and all timers driving them, come from
+/// the endpoint's clock. A custom clock can advance faster than real time to speed up simulations
+/// and tests, or read a cheaper high-resolution time source on hot paths.
+///
+/// See [`EndpointBuilder::clock()`](crate::generic::EndpointBuilder::clock).
+pub trait Clock: Send + Sync + fmt::Debug + 'static {
+    /// The current time
+    fn now(&self) -> Instant;
+
+    /// Construct a timer which completes once [`now()`](Clock::now) reaches `deadline`
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>>;
+}
+
+/// A timer constructed by a [`Clock`]
+pub trait AsyncTimer: Send + fmt::Debug {
+    /// Change the time at which the timer completes
+    fn reset(self: Pin<&mut Self>, deadline: Instant);
+
+    /// Check whether the deadline has passed, registering `cx` to be woken when it does if not
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()>;
+}
+
+/// The default [`Clock`], backed by tokio's time driver
+///
+/// Follows tokio's notion of time, so pausing and advancing time in tokio also applies to
+/// endpoints using this clock.
+#[cfg(feature = "runtime-tokio")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TokioClock;
+
+#[cfg(feature = "runtime-tokio")]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        Box::pin(sleep_until(deadline.into()))
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl AsyncTimer for Sleep {
+    fn reset(self: Pin<&mut Self>, deadline: Instant) {
+        Sleep::reset(self, deadline.into())
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Future::poll(self, cx)
+    }
+}
+
+/// A [`Clock`] whose timers are driven by a dedicated thread
+///
+/// For runtimes without a timer of their own, such as a plain thread pool behind
+/// [`ExecutorRuntime`](crate::ExecutorRuntime). Follows the system's monotonic clock. The thread
+/// exits once the clock is dropped, after which pending timers never complete.
+#[derive(Debug)]
+pub struct ThreadClock {
+    shared: Arc<TimerShared>,
+}
+
+impl ThreadClock {
+    /// Start the thread driving timers
+    pub fn new() -> io::Result<Self> {
+        let shared = Arc::new(TimerShared::default());
+        let driver = shared.clone();
+        thread::Builder::new()
+            .name("quinn-timer".into())
+            .spawn(move || driver.run())?;
+        Ok(Self { shared })
+    }
+}
+
+impl Clock for ThreadClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        Box::pin(ThreadTimer {
+            id,
+            deadline,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+impl Drop for ThreadClock {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.wake.notify_one();
+    }
+}
+
+#[derive(Debug, Default)]
+struct TimerShared {
+    state: Mutex<TimerState>,
+    /// Signalled when the earliest deadline moves forward, or on shutdown
+    wake: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    /// Deadlines of pending timers along with their IDs, including stale ones from earlier resets
+    queue: BinaryHeap<Reverse<(Instant, u64)>>,
+    /// The deadline and task of each pending timer
+    pending: FxHashMap<u64, (Instant, Waker)>,
+    next_id: u64,
+    shutdown: bool,
+}
+
+impl TimerShared {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        while !state.shutdown {
+            let now = Instant::now();
+            let mut expired = Vec::new();
+            while let Some(&Reverse((deadline, id))) = state.queue.peek() {
+                if deadline > now {
+                    break;
+                }
+                state.queue.pop();
+                if state
+                    .pending
+                    .get(&id)
+                    .map_or(false, |&(x, _)| x == deadline)
+                {
+                    expired.push(state.pending.remove(&id).unwrap().1);
+                }
+            }
+            if !expired.is_empty() {
+                drop(state);
+                for waker in expired {
+                    waker.wake();
+                }
+                state = self.state.lock().unwrap();
+                continue;
+            }
+            state = match state.queue.peek() {
+                Some(&Reverse((deadline, _))) => {
+                    self.wake.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.wake.wait(state).unwrap(),
+            };
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ThreadTimer {
+    id: u64,
+    deadline: Instant,
+    shared: Arc<TimerShared>,
+}
+
+impl AsyncTimer for ThreadTimer {
+    fn reset(self: Pin<&mut Self>, deadline: Instant) {
+        // Takes effect with the next poll
+        self.get_mut().deadline = deadline;
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if Instant::now() >= self.deadline {
+            state.pending.remove(&self.id);
+            return Poll::Ready(());
+        }
+        let previous = state
+            .pending
+            .insert(self.id, (self.deadline, cx.waker().clone()));
+        if previous.map_or(true, |(x, _)| x != self.deadline) {
+            let earliest = state
+                .queue
+                .peek()
+                .map_or(true, |&Reverse((x, _))| self.deadline < x);
+            state.queue.push(Reverse((self.deadline, self.id)));
+            if earliest {
+                self.shared.wake.notify_one();
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for ThreadTimer {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().pending.remove(&self.id);
+    }
+}
@@ -3,9 +3,13 @@ use std::{
     future::Future,
     marker::PhantomData,
     mem,
-    net::{IpAddr, SocketAddr},
+    net::IpAddr,
+    panic::{self, AssertUnwindSafe},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
     task::{Context, Poll, Waker},
     time::{Duration, Instant},
 };
@@ -13,20 +17,36 @@ use std::{
 use bytes::Bytes;
 use futures::{
     channel::{mpsc, oneshot},
+    task::AtomicWaker,
     FutureExt, StreamExt,
 };
 use fxhash::FxHashMap;
-use proto::{ConnectionError, ConnectionHandle, ConnectionStats, Dir, StreamEvent, StreamId};
+use proto::{
+    ConnectionError, ConnectionHandle, ConnectionStats, DatagramTransform, Dir, DrainedStream,
+    HandshakeSummary, PowerMode, SendStreamStats, StatelessReset, StreamEvent, StreamId, Tap,
+};
 use thiserror::Error;
-use tokio::time::{sleep_until, Instant as TokioInstant, Sleep};
-use tracing::info_span;
-
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+#[cfg(feature = "codec")]
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use tracing::{debug, error, info_span, warn};
+
+#[cfg(feature = "codec")]
+use crate::bi_stream::BiStream;
+#[cfg(feature = "debug_counters")]
+use crate::debug_counters::{Kind, LiveCounts, Tracked};
 use crate::{
+    anti_replay::AntiReplay,
     broadcast::{self, Broadcast},
+    buffer_pool::BufferPool,
+    clock::{AsyncTimer, Clock},
+    endpoint::{Restart, Spawner},
     mutex::Mutex,
     recv_stream::RecvStream,
+    runtime::Runtime,
     send_stream::{SendStream, WriteError},
     transport::Socket,
+    unwind::{DriverPanic, PanicHook},
     ConnectionEvent, EndpointEvent, VarInt,
 };
 
@@ -47,12 +67,27 @@ where
     S: proto::crypto::Session + 'static,
     T: Socket,
 {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         handle: ConnectionHandle,
-        conn: proto::generic::Connection<S>,
-        endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
-        conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
+        conn: proto::generic::Connection<S, T::Address>,
+        endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent<T::Address>)>,
+        conn_events: mpsc::UnboundedReceiver<ConnectionEvent<T::Address>>,
+        handshake_runtime: Option<&HandshakeRuntime>,
+        handshake_permits: Option<&Arc<Semaphore>>,
+        spawner: &Spawner,
+        clock: Arc<dyn Clock>,
+        buffers: BufferPool,
+        anti_replay: Option<&Arc<dyn AntiReplay>>,
+        on_panic: Option<&PanicHook>,
     ) -> Connecting<S, T> {
+        // Only incoming connections are limited, as the application controls outgoing ones
+        let handshake_permits = handshake_permits
+            .filter(|_| conn.side().is_server())
+            .cloned();
+        let anti_replay = anti_replay
+            .filter(|_| conn.side().is_server())
+            .map(|x| (x.clone(), spawner.clone()));
         let (on_handshake_data_send, on_handshake_data_recv) = oneshot::channel();
         let (on_connected_send, on_connected_recv) = oneshot::channel();
         let conn = ConnectionRef::new(
@@ -62,9 +97,30 @@ where
             conn_events,
             on_handshake_data_send,
             on_connected_send,
+            clock,
+            buffers,
+            spawner.clone(),
         );
 
-        tokio::spawn(ConnectionDriver(conn.clone()));
+        let mut driver = ConnectionDriver::new(
+            conn.clone(),
+            handshake_runtime.map(|x| x.established.clone()),
+        );
+        driver.anti_replay = anti_replay;
+        driver.on_panic = on_panic.cloned();
+        let driver = async move {
+            if let Some(permits) = handshake_permits {
+                // The semaphore is never closed
+                driver.handshake_permit = Some(permits.acquire_owned().await.unwrap());
+            }
+            driver.await
+        };
+        match handshake_runtime {
+            Some(runtime) => {
+                runtime.handshake.spawn(Box::pin(driver));
+            }
+            None => spawner.spawn(driver),
+        }
 
         Connecting {
             conn: Some(conn),
@@ -89,7 +145,8 @@ where
     ///
     /// On incoming connections, this enables transmission of 0.5-RTT data, which might be
     /// intercepted by a man-in-the-middle. If this occurs, the handshake will not complete
-    /// successfully.
+    /// successfully. Streams opened by the client may carry replayed 0-RTT data, unless
+    /// [`ServerConfig::defer_0rtt_data()`] is enabled.
     ///
     /// # Errors
     ///
@@ -98,6 +155,8 @@ where
     /// ticket is found, `self` is returned unmodified.
     ///
     /// For incoming connections, a 0.5-RTT connection will always be successfully constructed.
+    ///
+    /// [`ServerConfig::defer_0rtt_data()`]: proto::generic::ServerConfig::defer_0rtt_data
     pub fn into_0rtt(mut self) -> Result<(NewConnection<S, T>, ZeroRttAccepted), Self> {
         // This lock borrows `self` and would normally be dropped at the end of this scope, so we'll
         // have to release it explicitly before returning `self` by value.
@@ -112,6 +171,45 @@ where
         }
     }
 
+    /// Abandon the handshake if it hasn't completed within `timeout`
+    ///
+    /// Unlike wrapping `self` in a timeout future, this closes the connection when the time is up,
+    /// notifying the peer, and resolves to [`ConnectionError::HandshakeTimedOut`]. Has no effect
+    /// once the handshake has completed.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        let deadline = self.conn.as_ref().unwrap().lock("with_timeout").clock.now() + timeout;
+        self.with_deadline(deadline)
+    }
+
+    /// Tag the connection with `label`, e.g. the tenant it belongs to
+    ///
+    /// The label is recorded on the tracing span of the connection's driver, so that all
+    /// diagnostics emitted while driving the connection carry it, and is exposed through
+    /// [`Connection::label()`] for grouping statistics.
+    pub fn with_label(self, label: &str) -> Self {
+        self.conn.as_ref().unwrap().lock("with_label").label = Some(label.into());
+        self
+    }
+
+    /// Start `reconnect` if this connection is ended by a stateless reset
+    pub(crate) fn reconnect_on_reset(&self, reconnect: Arc<dyn Restart<S, T>>) {
+        self.conn
+            .as_ref()
+            .unwrap()
+            .lock("reconnect_on_reset")
+            .reconnect = Some(reconnect);
+    }
+
+    pub(crate) fn with_deadline(self, deadline: Instant) -> Self {
+        {
+            let conn = &mut *self.conn.as_ref().unwrap().lock("with_deadline");
+            conn.inner.set_handshake_deadline(deadline);
+            // The timer may need to be rescheduled
+            conn.wake();
+        }
+        self
+    }
+
     /// Parameters negotiated during the handshake
     pub async fn handshake_data(&mut self) -> Result<S::HandshakeData, ConnectionError> {
         // Taking &mut self allows us to use a single oneshot channel rather than dealing with
@@ -184,14 +282,29 @@ where
     S: proto::crypto::Session,
     T: Socket,
 {
-    /// The peer's UDP address.
+    /// The peer's address.
     ///
     /// Will panic if called after `poll` has returned `Ready`.
-    pub fn remote_address(&self) -> SocketAddr {
+    pub fn remote_address(&self) -> T::Address {
         let conn_ref: &ConnectionRef<S, T> =
             &self.conn.as_ref().expect("used after yielding Ready");
         conn_ref.lock("remote_address").inner.remote_address()
     }
+
+    pub(crate) fn downgrade(&self) -> WeakConnection<S, T> {
+        let conn = self.conn.as_ref().expect("used after yielding Ready");
+        WeakConnection {
+            conn: Arc::downgrade(&conn.0),
+            stable_id: conn.stable_id(),
+        }
+    }
+
+    /// Count the connection and its streams in `counts` for as long as they're alive
+    #[cfg(feature = "debug_counters")]
+    pub(crate) fn track(&self, counts: &Arc<LiveCounts>) {
+        let conn = self.conn.as_ref().expect("used after yielding Ready");
+        conn.lock("track").tracked = Some(Tracked::new(counts, Kind::Connection));
+    }
 }
 
 /// Future that completes when a connection is fully established
@@ -263,7 +376,10 @@ where
             connection: Connection(conn.clone()),
             uni_streams: IncomingUniStreams(conn.clone()),
             bi_streams: IncomingBiStreams(conn.clone()),
-            datagrams: Datagrams(conn),
+            datagrams: Datagrams {
+                conn,
+                state: broadcast::State::default(),
+            },
         }
     }
 }
@@ -280,20 +396,135 @@ where
 /// packets still in flight from the peer are handled gracefully.
 #[must_use = "connection drivers must be spawned for their connections to function"]
 #[derive(Debug)]
-struct ConnectionDriver<S: proto::crypto::Session, T: Socket>(ConnectionRef<S, T>);
+struct ConnectionDriver<S: proto::crypto::Session, T: Socket> {
+    conn: ConnectionRef<S, T>,
+    /// Runtime to move to once the handshake completes, if driven on a dedicated one until then
+    established: Option<Arc<dyn Runtime>>,
+    /// Whether the handshake has completed and the driver should move to `established`
+    moving: bool,
+    /// Held while handshaking if the number of concurrent handshakes is limited
+    handshake_permit: Option<OwnedSemaphorePermit>,
+    /// Checks the connection's 0-RTT attempt for replays, until it's known
+    anti_replay: Option<(Arc<dyn AntiReplay>, Spawner)>,
+    /// Told if the driver panics
+    on_panic: Option<PanicHook>,
+}
 
-impl<S, T> Future for ConnectionDriver<S, T>
+impl<S, T> ConnectionDriver<S, T>
 where
     S: proto::crypto::Session,
     T: Socket,
+{
+    fn new(conn: ConnectionRef<S, T>, established: Option<Arc<dyn Runtime>>) -> Self {
+        Self {
+            conn,
+            established,
+            moving: false,
+            handshake_permit: None,
+            anti_replay: None,
+            on_panic: None,
+        }
+    }
+}
+
+impl<S, T> Future for ConnectionDriver<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
 {
     type Output = ();
 
     #[allow(unused_mut)] // MSRV
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let conn = &mut *self.0.lock("poll");
+        let this = &mut *self;
+        // A panic must not take down the runtime or event loop driving other connections, so the
+        // connection is abandoned instead
+        match panic::catch_unwind(AssertUnwindSafe(|| this.drive(cx))) {
+            Ok(Poll::Ready(())) => {
+                this.reconnect();
+                Poll::Ready(())
+            }
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => {
+                let panic = {
+                    let conn = &mut *this.conn.lock("abandon");
+                    let panic = DriverPanic::new(&*payload, conn.label.clone());
+                    error!(
+                        id = conn.handle.0,
+                        label = conn.label.as_deref(),
+                        "connection driver panicked: {}",
+                        panic
+                    );
+                    // The connection's state can't be trusted anymore, so it's abandoned without
+                    // being closed, leaving the peer to time out
+                    conn.terminate(ConnectionError::TransportError(proto::TransportError {
+                        code: proto::TransportErrorCode::INTERNAL_ERROR,
+                        frame: None,
+                        reason: "connection driver panicked".into(),
+                    }));
+                    // If the endpoint driver is gone, noop.
+                    let _ = conn.endpoint_events.unbounded_send((
+                        conn.handle,
+                        EndpointEvent::Proto(proto::EndpointEvent::drained()),
+                    ));
+                    panic
+                };
+                if let Some(ref hook) = this.on_panic {
+                    hook.call(&panic);
+                }
+                Poll::Ready(())
+            }
+        }
+    }
+}
 
-        let span = info_span!("drive", id = conn.handle.0);
+impl<S, T> ConnectionDriver<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    /// Start a new connection if this one was reset and the application asked for that
+    fn reconnect(&self) {
+        let (reconnect, label) = {
+            let conn = self.conn.lock("reconnect");
+            match conn.error {
+                Some(ConnectionError::Reset) => (conn.reconnect.clone(), conn.label.clone()),
+                _ => return,
+            }
+        };
+        let reconnect = match reconnect {
+            Some(x) => x,
+            None => return,
+        };
+        // The endpoint must not be locked while holding a connection's lock
+        let connecting = match reconnect.start() {
+            Ok(x) => Some(match label {
+                Some(label) => x.with_label(&label),
+                None => x,
+            }),
+            Err(e) => {
+                debug!("failed to reconnect: {}", e);
+                None
+            }
+        };
+        let conn = &mut *self.conn.lock("reconnect");
+        conn.reconnect = None;
+        conn.reconnection = connecting;
+        conn.reconnect_waiters.wake();
+    }
+
+    fn drive(&mut self, cx: &mut Context) -> Poll<()> {
+        if self.moving {
+            let runtime = self.established.take().unwrap();
+            let mut driver = ConnectionDriver::new(self.conn.clone(), None);
+            driver.on_panic = self.on_panic.take();
+            runtime.spawn(Box::pin(driver));
+            return Poll::Ready(());
+        }
+
+        let conn = &mut *self.conn.lock("poll");
+
+        let span = info_span!("drive", id = conn.handle.0, label = conn.label.as_deref());
         let _guard = span.enter();
 
         loop {
@@ -313,7 +544,37 @@ where
             }
         }
 
+        if self.handshake_permit.is_some() && !conn.inner.is_handshaking() {
+            // Let the next connection attempt proceed with its handshake
+            self.handshake_permit = None;
+        }
+
+        if let Some(id) = conn.inner.zero_rtt_id() {
+            if let Some((anti_replay, spawner)) = self.anti_replay.take() {
+                // Run to completion even if the connection goes away, so the attempt is recorded
+                let check = anti_replay.check(&id);
+                let weak = Arc::downgrade(&self.conn.0);
+                spawner.spawn(async move {
+                    if !check.await {
+                        debug!("0-RTT attempt seen before, withholding streams until the handshake completes");
+                        return;
+                    }
+                    if let Some(conn) = weak.upgrade() {
+                        let conn = &mut *conn.lock("release_0rtt_data");
+                        conn.inner.release_0rtt_data();
+                        conn.wake();
+                    }
+                });
+            }
+        }
+
         if !conn.inner.is_drained() {
+            if self.established.is_some() && !conn.inner.is_handshaking() {
+                // The lock must be released before the connection can be handed off, so do so the
+                // next time we're polled.
+                self.moving = true;
+                cx.waker().wake_by_ref();
+            }
             conn.driver = Some(cx.waker().clone());
             return Poll::Pending;
         }
@@ -324,6 +585,15 @@ where
     }
 }
 
+/// Runtimes between which connection drivers move over the course of the handshake
+#[derive(Debug, Clone)]
+pub(crate) struct HandshakeRuntime {
+    /// Runtime on which drivers are spawned while their connection is handshaking
+    pub(crate) handshake: Arc<dyn Runtime>,
+    /// Runtime to which drivers move once their connection is established
+    pub(crate) established: Arc<dyn Runtime>,
+}
+
 /// A QUIC connection.
 ///
 /// If all references to a connection (including every clone of the `Connection` handle, streams of
@@ -366,6 +636,112 @@ where
         }
     }
 
+    /// Wait for the connection started after a stateless reset by endpoints built with
+    /// [`EndpointBuilder::reconnect_on_reset()`]
+    ///
+    /// [`EndpointBuilder::reconnect_on_reset()`]: crate::generic::EndpointBuilder::reconnect_on_reset
+    ///
+    /// Resolves once this connection is lost. Yields the new connection only if this one was ended
+    /// by a stateless reset and the new one could be started, and only to the first caller.
+    pub async fn reconnected(&self) -> Option<Connecting<S, T>> {
+        let mut state = broadcast::State::default();
+        futures::future::poll_fn(|cx| {
+            let conn = &mut *self.0.lock("reconnected");
+            if let Some(x) = conn.reconnection.take() {
+                return Poll::Ready(Some(x));
+            }
+            let pending =
+                matches!(conn.error, Some(ConnectionError::Reset)) && conn.reconnect.is_some();
+            if conn.error.is_some() && !pending {
+                return Poll::Ready(None);
+            }
+            conn.reconnect_waiters.register(cx, &mut state);
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Whether the connection has been closed or lost
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.lock("is_closed").error.is_some()
+    }
+
+    /// The stateless reset that ended the connection with [`ConnectionError::Reset`], if any
+    ///
+    /// Tells when the reset arrived and on which path, e.g. to tell a server restart apart from a
+    /// reset caused by a change of address.
+    pub fn stateless_reset(&self) -> Option<StatelessReset<T::Address>> {
+        self.0
+            .lock("stateless_reset")
+            .inner
+            .stateless_reset()
+            .cloned()
+    }
+
+    /// Wait for the next warning that the connection is about to time out
+    ///
+    /// Resolves once there's been no activity on the connection for all but
+    /// [`TransportConfig::idle_timeout_warning()`] of the idle timeout, leaving the application
+    /// time to open a stream, send a datagram, or close the connection gracefully. Only warnings
+    /// emitted after this is called are reported. Fails if the connection is lost first.
+    ///
+    /// [`TransportConfig::idle_timeout_warning()`]: crate::TransportConfig::idle_timeout_warning
+    pub fn idle_timeout_warning(&self) -> IdleTimeoutWarning<S, T> {
+        let seen = self.0.lock("idle_timeout_warning").idle_warnings;
+        IdleTimeoutWarning {
+            conn: self.0.clone(),
+            state: broadcast::State::default(),
+            seen,
+        }
+    }
+
+    /// Wait for the next change of the path MTU
+    ///
+    /// Resolves to the new [`max_udp_payload_size()`](Self::max_udp_payload_size) once probing
+    /// finds a larger MTU, or a Packet Too Big message or a black hole forces a smaller one. Only
+    /// changes after this is called are reported. Fails if the connection is lost first.
+    pub fn path_mtu_changed(&self) -> PathMtuChanged<S, T> {
+        let seen = self.0.lock("path_mtu_changed").path_mtu_changes;
+        PathMtuChanged {
+            conn: self.0.clone(),
+            state: broadcast::State::default(),
+            seen,
+        }
+    }
+
+    /// Wait for the next MTU black hole to be detected on the path
+    ///
+    /// Resolves once large packets keep getting lost while small ones get through, after which the
+    /// connection falls back to the minimum MTU and reports it through
+    /// [`path_mtu_changed()`](Self::path_mtu_changed). Only black holes detected after this is
+    /// called are reported. Fails if the connection is lost first.
+    pub fn black_hole_detected(&self) -> BlackHoleDetected<S, T> {
+        let seen = self.0.lock("black_hole_detected").black_holes;
+        BlackHoleDetected {
+            conn: self.0.clone(),
+            state: broadcast::State::default(),
+            seen,
+        }
+    }
+
+    /// Initiate a new outgoing bidirectional stream carrying length-delimited frames
+    ///
+    /// A shorthand for passing the halves returned by [`open_bi()`] to [`BiStream::new()`] and
+    /// framing the result with `LengthDelimitedCodec`. The peer can decode the frames by
+    /// treating the stream likewise.
+    ///
+    /// [`open_bi()`]: Connection::open_bi
+    #[cfg(feature = "codec")]
+    pub async fn open_bi_stream_pair(
+        &self,
+    ) -> Result<Framed<BiStream<S, T>, LengthDelimitedCodec>, ConnectionError> {
+        let (send, recv) = self.open_bi().await?;
+        Ok(Framed::new(
+            BiStream::new(send, recv),
+            LengthDelimitedCodec::new(),
+        ))
+    }
+
     /// Close the connection immediately.
     ///
     /// Pending operations will fail immediately with [`ConnectionError::LocallyClosed`]. Delivery
@@ -429,11 +805,11 @@ where
             .max_size()
     }
 
-    /// The peer's UDP address
+    /// The peer's address
     ///
     /// If `ServerConfig::migration` is `true`, clients may change addresses at will, e.g. when
     /// switching to a cellular internet connection.
-    pub fn remote_address(&self) -> SocketAddr {
+    pub fn remote_address(&self) -> T::Address {
         self.0.lock("remote_address").inner.remote_address()
     }
 
@@ -455,16 +831,53 @@ where
         self.0.lock("local_ip").inner.local_ip()
     }
 
+    /// The server name this connection is for
+    ///
+    /// For clients, the name passed to `connect`; for servers, the name the client indicated
+    /// via SNI, if any.
+    pub fn server_name(&self) -> Option<String> {
+        self.0
+            .lock("server_name")
+            .inner
+            .server_name()
+            .map(|x| x.into())
+    }
+
     /// Current best estimate of this connection's latency (round-trip-time)
     pub fn rtt(&self) -> Duration {
         self.0.lock("rtt").inner.rtt()
     }
 
+    /// Current probe timeout, including backoff due to unanswered probes
+    ///
+    /// Outstanding data that goes unacknowledged for this long is probed for, and retransmitted if
+    /// found lost.
+    pub fn current_pto(&self) -> Duration {
+        self.0.lock("current_pto").inner.current_pto()
+    }
+
     /// Returns connection statistics
     pub fn stats(&self) -> ConnectionStats {
         self.0.lock("stats").inner.stats()
     }
 
+    /// Streams whose data hasn't been fully delivered, with how much is outstanding
+    ///
+    /// Lists streams with written data the peer hasn't acknowledged and streams with received data
+    /// that hasn't been read, including incoming streams that were never accepted. Remains
+    /// available after [`close()`](Self::close), so applications can log what was lost or retry
+    /// it on a new connection.
+    pub fn drain_streams(&self) -> Vec<DrainedStream> {
+        self.0.lock("drain_streams").inner.drain_streams()
+    }
+
+    /// The label the connection was tagged with by [`Connecting::with_label()`], if any
+    ///
+    /// [`Connecting::with_label()`]: crate::generic::Connecting::with_label
+    pub fn label(&self) -> Option<Arc<str>> {
+        self.0.lock("label").label.clone()
+    }
+
     /// Parameters negotiated during the handshake
     ///
     /// Guaranteed to return `Some` on fully established connections or after
@@ -479,6 +892,24 @@ where
             .handshake_data()
     }
 
+    /// How the cryptographic handshake was completed, e.g. whether the session was resumed
+    ///
+    /// Returns `None` until the handshake has completed.
+    pub fn handshake_summary(&self) -> Option<HandshakeSummary> {
+        self.0.lock("handshake_summary").inner.handshake_summary()
+    }
+
+    /// Whether the client offered a session ticket issued for another server name or application
+    /// protocol
+    ///
+    /// Such tickets are ignored in favor of a full handshake by servers with
+    /// [`ServerConfig::pin_session_tickets`] enabled. Always `false` for clients.
+    ///
+    /// [`ServerConfig::pin_session_tickets`]: proto::generic::ServerConfig::pin_session_tickets
+    pub fn ticket_mismatch(&self) -> bool {
+        self.0.lock("ticket_mismatch").inner.ticket_mismatch()
+    }
+
     /// Cryptographic identity of the peer
     pub fn peer_identity(&self) -> Option<S::Identity> {
         self.0
@@ -491,11 +922,200 @@ where
     /// A stable identifier for this connection
     ///
     /// Peer addresses and connection IDs can change, but this value will remain
-    /// fixed for the lifetime of the connection.
+    /// fixed for the lifetime of the connection. Identifiers are never reused by later connections
+    /// within the process.
     pub fn stable_id(&self) -> usize {
         self.0.stable_id()
     }
 
+    /// Current upper bound on the size of UDP payloads sent on this connection
+    ///
+    /// Reflects both the local configuration and the limit advertised by the peer.
+    pub fn max_udp_payload_size(&self) -> u16 {
+        self.0
+            .lock("max_udp_payload_size")
+            .inner
+            .max_udp_payload_size()
+    }
+
+    /// Probe whether the path supports UDP payloads of `size` bytes
+    ///
+    /// If the probe is acknowledged, payloads of that size are sent from then on, and
+    /// [`max_udp_payload_size()`](Self::max_udp_payload_size) reflects the new size. Lost probes
+    /// are retried a few times. Has no effect if `size` doesn't exceed the current size; sizes
+    /// beyond the peer's limit are reduced accordingly.
+    pub fn probe_mtu(&self, size: u16) {
+        let conn = &mut *self.0.lock("probe_mtu");
+        conn.inner.probe_mtu(size);
+        conn.wake();
+    }
+
+    /// Ask the peer to acknowledge everything it has received so far without delay
+    ///
+    /// Sends an ACK-eliciting PING right away, which refreshes the RTT estimate and lets loss of
+    /// the tail of a transfer be detected sooner. Peers usually acknowledge it immediately, but
+    /// may take up to their `max_ack_delay` if they batch acknowledgements. The IMMEDIATE_ACK frame
+    /// of the ACK frequency extension is not supported.
+    pub fn request_immediate_ack(&self) {
+        let conn = &mut *self.0.lock("request_immediate_ack");
+        conn.inner.ping();
+        conn.wake();
+    }
+
+    /// Mark outgoing packets with a differentiated services code point, or `None` for the platform
+    /// default
+    ///
+    /// Lets network equipment classify the connection's traffic for quality of service purposes.
+    /// Only supported on Unix platforms.
+    ///
+    /// # Panics
+    ///
+    /// If `dscp` does not fit in 6 bits.
+    pub fn set_dscp(&self, dscp: Option<u8>) {
+        self.0.lock("set_dscp").inner.set_dscp(dscp)
+    }
+
+    /// Mark outgoing IPv6 packets with a flow label, or `None` for the platform default
+    ///
+    /// Only supported on Linux. Has no effect on IPv4 paths.
+    ///
+    /// # Panics
+    ///
+    /// If `label` does not fit in 20 bits.
+    pub fn set_flow_label(&self, label: Option<u32>) {
+        self.0.lock("set_flow_label").inner.set_flow_label(label)
+    }
+
+    /// Set the reordering in packet number space tolerated before a packet is deemed lost
+    ///
+    /// Overrides [`TransportConfig::packet_threshold()`] for this connection.
+    ///
+    /// [`TransportConfig::packet_threshold()`]: crate::TransportConfig::packet_threshold
+    pub fn set_packet_threshold(&self, value: u32) {
+        self.0
+            .lock("set_packet_threshold")
+            .inner
+            .set_packet_threshold(value)
+    }
+
+    /// Set the reordering in time tolerated before a packet is deemed lost, as a factor of RTT
+    ///
+    /// Overrides [`TransportConfig::time_threshold()`] for this connection.
+    ///
+    /// [`TransportConfig::time_threshold()`]: crate::TransportConfig::time_threshold
+    pub fn set_time_threshold(&self, value: f32) {
+        self.0
+            .lock("set_time_threshold")
+            .inner
+            .set_time_threshold(value)
+    }
+
+    /// Set the maximum number of times the probe timeout doubles while probes go unanswered
+    ///
+    /// Overrides [`TransportConfig::max_pto_backoff()`] for this connection.
+    ///
+    /// [`TransportConfig::max_pto_backoff()`]: crate::TransportConfig::max_pto_backoff
+    pub fn set_max_pto_backoff(&self, value: u32) {
+        self.0
+            .lock("set_max_pto_backoff")
+            .inner
+            .set_max_pto_backoff(value)
+    }
+
+    /// Limit the idle timeout in effect on this connection
+    ///
+    /// The idle timeout negotiated with the peer, which is used if `value` is `None`, can only be
+    /// shortened, e.g. to abandon peers quickly until they've authenticated and be more lenient
+    /// afterwards. Restarts the idle timer.
+    pub fn set_max_idle_timeout(&self, value: Option<Duration>) {
+        let conn = &mut *self.0.lock("set_max_idle_timeout");
+        let now = conn.clock.now();
+        conn.inner.set_max_idle_timeout(now, value);
+        conn.wake();
+    }
+
+    /// The idle timeout in effect, if any
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.0.lock("idle_timeout").inner.idle_timeout()
+    }
+
+    /// The power mode currently in effect
+    pub fn power_mode(&self) -> PowerMode {
+        self.0.lock("power_mode").inner.power_mode()
+    }
+
+    /// Choose how the endpoint orders this connection's datagrams relative to other connections'
+    ///
+    /// Connections start out as [`PriorityClass::Interactive`]. Takes effect for datagrams not yet
+    /// handed to the endpoint.
+    pub fn set_priority_class(&self, class: PriorityClass) {
+        self.0.lock("set_priority_class").priority_class = class;
+    }
+
+    /// How the endpoint orders this connection's datagrams relative to other connections'
+    pub fn priority_class(&self) -> PriorityClass {
+        self.0.lock("priority_class").priority_class
+    }
+
+    /// Allow the peer to send `bytes` more data across all streams
+    ///
+    /// Only has an effect on connections using [`CreditPolicy::Manual`] for connection-level flow
+    /// control, where this is the only way for connection-level credit to be returned to the peer.
+    /// See [`TransportConfig::connection_credit_policy()`].
+    ///
+    /// [`CreditPolicy::Manual`]: crate::CreditPolicy::Manual
+    /// [`TransportConfig::connection_credit_policy()`]: crate::TransportConfig::connection_credit_policy
+    pub fn credit(&self, bytes: u64) {
+        let conn = &mut *self.0.lock("credit");
+        conn.inner.credit(bytes);
+        conn.wake();
+    }
+
+    /// Trade latency for fewer wakeups, e.g. to save battery on mobile devices
+    ///
+    /// May be changed at any time. See [`PowerMode`] for details.
+    pub fn set_power_mode(&self, mode: PowerMode) {
+        let conn = &mut *self.0.lock("set_power_mode");
+        let now = conn.clock.now();
+        conn.inner.set_power_mode(now, mode);
+        // Timers and pending acks may have changed
+        conn.wake();
+    }
+
+    /// Disable or re-enable pacing and ACK batching
+    ///
+    /// Overrides [`TransportConfig::low_latency()`] for this connection.
+    ///
+    /// [`TransportConfig::low_latency()`]: crate::TransportConfig::low_latency
+    pub fn set_low_latency(&self, value: bool) {
+        let conn = &mut *self.0.lock("set_low_latency");
+        conn.inner.set_low_latency(value);
+        // Packets held back by the pacer and pending acks may now be sent
+        conn.wake();
+    }
+
+    /// Attach a [`Tap`] to receive copies of the application data exchanged on this connection
+    ///
+    /// Passing `None` detaches the current tap. Only data written or read after the tap is
+    /// attached is reported.
+    pub fn set_tap(&self, tap: Option<Arc<dyn Tap>>) {
+        self.0.lock("set_tap").inner.set_tap(tap)
+    }
+
+    /// Transform the payloads of datagrams subsequently sent and read, or stop doing so
+    ///
+    /// Passing `None` removes the current transform. Datagrams already passed to
+    /// [`send_datagram()`](Self::send_datagram) keep the transform in effect when they were
+    /// sent, while datagrams already received but not yet yielded by
+    /// [`NewConnection::datagrams`] go through the new one. See [`DatagramTransform`] for details.
+    pub fn set_datagram_transform(&self, transform: Option<Arc<dyn DatagramTransform>>) {
+        self.0
+            .lock("set_datagram_transform")
+            .inner
+            .datagrams()
+            .set_transform(transform)
+    }
+
     // Update traffic keys spontaneously for testing purposes.
     #[doc(hidden)]
     pub fn force_key_update(&self) {
@@ -522,6 +1142,20 @@ where
             .crypto_session()
             .export_keying_material(output, label, context)
     }
+
+    /// Value binding application-layer credentials, like request authentication tokens, to this
+    /// connection
+    ///
+    /// Peers obtain the same value, so a server can check that a token was issued for the
+    /// connection it's presented on. Fails before the handshake completes, e.g. on connections
+    /// obtained from [`Connecting::into_0rtt()`]. See [`ChannelBinding`] for details.
+    ///
+    /// [`ChannelBinding`]: proto::crypto::ChannelBinding
+    pub fn channel_binding(
+        &self,
+    ) -> Result<proto::crypto::ChannelBinding, proto::crypto::ExportKeyingMaterialError> {
+        self.0.lock("channel_binding").inner.channel_binding()
+    }
 }
 
 impl<S, T> Clone for Connection<S, T>
@@ -605,9 +1239,148 @@ where
     }
 }
 
+impl<S, T> IncomingBiStreams<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Spawn a task running `handler` for each stream opened by the peer
+    ///
+    /// Tasks are spawned like the connection's driver, on the endpoint's runtime or through
+    /// [`Endpoint::poll_drivers()`](crate::generic::Endpoint::poll_drivers). At most
+    /// `max_concurrent` handlers run at a time; further streams are left unaccepted, which holds
+    /// back the peer's stream credit, until one of them finishes. A handler that panics is logged
+    /// without affecting the others.
+    ///
+    /// Streams are accepted until the connection is lost or [`SpawnedHandlers::stop()`] is called.
+    /// The returned handle then waits for the remaining handlers to finish, and yields the reason
+    /// the connection was lost, or `Ok` if it was closed locally or stopped. Dropping the handle
+    /// leaves the handlers running.
+    ///
+    /// # Panics
+    ///
+    /// If `max_concurrent` is zero.
+    pub fn for_each_spawned<F, Fut>(
+        mut self,
+        max_concurrent: usize,
+        mut handler: F,
+    ) -> SpawnedHandlers
+    where
+        S: 'static,
+        F: FnMut(SendStream<S, T>, RecvStream<S, T>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        assert!(max_concurrent > 0, "max_concurrent must be nonzero");
+        let spawner = self.0.lock("for_each_spawned").spawner.clone();
+        let stop = Arc::new(StopSignal::default());
+        let (result_send, result_recv) = oneshot::channel();
+        let handle = SpawnedHandlers {
+            stop: stop.clone(),
+            result: result_recv,
+        };
+        let handler_spawner = spawner.clone();
+        spawner.spawn(async move {
+            let (done_send, mut done_recv) = mpsc::unbounded();
+            let mut running = 0usize;
+            let mut result = None;
+            futures::future::poll_fn(|cx| loop {
+                while let Poll::Ready(Some(())) = done_recv.poll_next_unpin(cx) {
+                    running -= 1;
+                }
+                if result.is_none() {
+                    stop.waker.register(cx.waker());
+                    if stop.stopped.load(Ordering::Acquire) {
+                        result = Some(Ok(()));
+                    }
+                }
+                if result.is_some() || running >= max_concurrent {
+                    return if running == 0 {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    };
+                }
+                match self.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok((send, recv)))) => {
+                        running += 1;
+                        let task = AssertUnwindSafe(handler(send, recv)).catch_unwind();
+                        let done = done_send.clone();
+                        handler_spawner.spawn(async move {
+                            if task.await.is_err() {
+                                warn!("stream handler panicked");
+                            }
+                            // The loop outlives its handlers, unless its runtime is shut down
+                            let _ = done.unbounded_send(());
+                        });
+                    }
+                    Poll::Ready(Some(Err(e))) => result = Some(Err(e)),
+                    Poll::Ready(None) => result = Some(Ok(())),
+                    Poll::Pending => return Poll::Pending,
+                }
+            })
+            .await;
+            // The handle may have been dropped
+            let _ = result_send.send(result.unwrap());
+        });
+        handle
+    }
+}
+
+/// Handle to the stream handlers spawned by [`IncomingBiStreams::for_each_spawned()`]
+///
+/// Resolves once streams are no longer accepted and every handler has finished.
+#[must_use = "dropping the handle leaves no way to stop accepting streams"]
+#[derive(Debug)]
+pub struct SpawnedHandlers {
+    stop: Arc<StopSignal>,
+    result: oneshot::Receiver<Result<(), ConnectionError>>,
+}
+
+impl SpawnedHandlers {
+    /// Stop accepting streams, leaving the handlers already running to finish
+    pub fn stop(&self) {
+        self.stop.stopped.store(true, Ordering::Release);
+        self.stop.waker.wake();
+    }
+}
+
+impl Future for SpawnedHandlers {
+    type Output = Result<(), ConnectionError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // The accept loop is only dropped unfinished if its runtime is shut down
+        self.result.poll_unpin(cx).map(|x| x.unwrap_or(Ok(())))
+    }
+}
+
+#[derive(Debug, Default)]
+struct StopSignal {
+    stopped: AtomicBool,
+    waker: AtomicWaker,
+}
+
 /// Stream of unordered, unreliable datagrams sent by the peer
+///
+/// May be cloned to receive datagrams from several tasks, e.g. one per worker thread. Each
+/// datagram is delivered to exactly one of the clones, whichever polls first.
 #[derive(Debug)]
-pub struct Datagrams<S: proto::crypto::Session, T: Socket>(ConnectionRef<S, T>);
+pub struct Datagrams<S: proto::crypto::Session, T: Socket> {
+    conn: ConnectionRef<S, T>,
+    state: broadcast::State,
+}
+
+impl<S, T> Clone for Datagrams<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn clone(&self) -> Self {
+        Self {
+            conn: self.conn.clone(),
+            state: broadcast::State::default(),
+        }
+    }
+}
 
 impl<S, T> futures::Stream for Datagrams<S, T>
 where
@@ -617,7 +1390,8 @@ where
     type Item = Result<Bytes, ConnectionError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        let mut conn = self.0.lock("Datagrams::poll_next");
+        let this = self.get_mut();
+        let mut conn = this.conn.lock("Datagrams::poll_next");
         if let Some(x) = conn.inner.datagrams().recv() {
             Poll::Ready(Some(Ok(x)))
         } else if let Some(ConnectionError::LocallyClosed) = conn.error {
@@ -625,7 +1399,7 @@ where
         } else if let Some(ref e) = conn.error {
             Poll::Ready(Some(Err(e.clone())))
         } else {
-            conn.datagram_reader = Some(cx.waker().clone());
+            conn.datagram_readers.register(cx, &mut this.state);
             Poll::Pending
         }
     }
@@ -664,6 +1438,111 @@ where
     }
 }
 
+/// A future that resolves when a connection is about to time out
+///
+/// See [`Connection::idle_timeout_warning()`].
+pub struct IdleTimeoutWarning<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    conn: ConnectionRef<S, T>,
+    state: broadcast::State,
+    /// Number of warnings emitted before this future was created
+    seen: u64,
+}
+
+impl<S, T> Future for IdleTimeoutWarning<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Output = Result<(), ConnectionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut conn = this.conn.lock("IdleTimeoutWarning::poll");
+        if conn.idle_warnings != this.seen {
+            return Poll::Ready(Ok(()));
+        }
+        if let Some(ref e) = conn.error {
+            return Poll::Ready(Err(e.clone()));
+        }
+        conn.idle_warning_waiters.register(cx, &mut this.state);
+        Poll::Pending
+    }
+}
+
+/// A future that resolves when the path MTU of a connection changes
+///
+/// See [`Connection::path_mtu_changed()`].
+pub struct PathMtuChanged<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    conn: ConnectionRef<S, T>,
+    state: broadcast::State,
+    /// Number of changes before this future was created
+    seen: u64,
+}
+
+impl<S, T> Future for PathMtuChanged<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Output = Result<u16, ConnectionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut conn = this.conn.lock("PathMtuChanged::poll");
+        if conn.path_mtu_changes != this.seen {
+            return Poll::Ready(Ok(conn.path_mtu));
+        }
+        if let Some(ref e) = conn.error {
+            return Poll::Ready(Err(e.clone()));
+        }
+        conn.path_waiters.register(cx, &mut this.state);
+        Poll::Pending
+    }
+}
+
+/// A future that resolves when an MTU black hole is detected on the path of a connection
+///
+/// See [`Connection::black_hole_detected()`].
+pub struct BlackHoleDetected<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    conn: ConnectionRef<S, T>,
+    state: broadcast::State,
+    /// Number of black holes detected before this future was created
+    seen: u64,
+}
+
+impl<S, T> Future for BlackHoleDetected<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Output = Result<(), ConnectionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut conn = this.conn.lock("BlackHoleDetected::poll");
+        if conn.black_holes != this.seen {
+            return Poll::Ready(Ok(()));
+        }
+        if let Some(ref e) = conn.error {
+            return Poll::Ready(Err(e.clone()));
+        }
+        conn.path_waiters.register(cx, &mut this.state);
+        Poll::Pending
+    }
+}
+
 /// A future that will resolve into an opened outgoing bidirectional stream
 pub struct OpenBi<S, T>
 where
@@ -708,21 +1587,31 @@ where
     S: proto::crypto::Session,
     T: Socket,
 {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         handle: ConnectionHandle,
-        conn: proto::generic::Connection<S>,
-        endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
-        conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
+        conn: proto::generic::Connection<S, T::Address>,
+        endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent<T::Address>)>,
+        conn_events: mpsc::UnboundedReceiver<ConnectionEvent<T::Address>>,
         on_handshake_data: oneshot::Sender<()>,
         on_connected: oneshot::Sender<bool>,
+        clock: Arc<dyn Clock>,
+        buffers: BufferPool,
+        spawner: Spawner,
     ) -> Self {
         Self(Arc::new(Mutex::new(ConnectionInner {
             inner: conn,
             driver: None,
             handle,
+            stable_id: NEXT_STABLE_ID.fetch_add(1, Ordering::Relaxed),
             on_handshake_data: Some(on_handshake_data),
             on_connected: Some(on_connected),
             connected: false,
+            clock,
+            buffers,
+            spawner,
+            transmit_buf: Vec::new(),
+            priority_class: PriorityClass::Interactive,
             timer: None,
             timer_deadline: None,
             conn_events,
@@ -733,17 +1622,29 @@ where
             bi_opening: Broadcast::new(),
             incoming_uni_streams_reader: None,
             incoming_bi_streams_reader: None,
-            datagram_reader: None,
+            datagram_readers: Broadcast::new(),
+            idle_warnings: 0,
+            idle_warning_waiters: Broadcast::new(),
+            path_mtu: 0,
+            path_mtu_changes: 0,
+            black_holes: 0,
+            path_waiters: Broadcast::new(),
             finishing: FxHashMap::default(),
             stopped: FxHashMap::default(),
             error: None,
             ref_count: 0,
+            label: None,
+            reconnect: None,
+            reconnection: None,
+            reconnect_waiters: Broadcast::new(),
+            #[cfg(feature = "debug_counters")]
+            tracked: None,
             socket_type: PhantomData,
         })))
     }
 
     fn stable_id(&self) -> usize {
-        &*self.0 as *const _ as usize
+        self.lock("stable_id").stable_id
     }
 }
 
@@ -778,6 +1679,80 @@ where
     }
 }
 
+/// A handle to a connection which doesn't keep it open
+///
+/// Obtained from [`Endpoint::connections()`](crate::generic::Endpoint::connections). Unlike a
+/// [`Connection`], doesn't prevent the connection from being implicitly closed once every
+/// `Connection` referring to it is dropped, so it can be held on to for bookkeeping.
+#[derive(Debug)]
+pub struct WeakConnection<S: proto::crypto::Session, T: Socket> {
+    conn: Weak<Mutex<ConnectionInner<S, T>>>,
+    stable_id: usize,
+}
+
+impl<S, T> WeakConnection<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Obtain a new handle to the connection, if it's established and hasn't been closed
+    pub fn upgrade(&self) -> Option<Connection<S, T>> {
+        let conn = self.conn.upgrade()?;
+        {
+            let inner = &mut *conn.lock("upgrade");
+            if !inner.is_open() {
+                return None;
+            }
+            inner.ref_count += 1;
+        }
+        Some(Connection(ConnectionRef(conn)))
+    }
+
+    /// The [`Connection::stable_id()`] of the connection referred to
+    pub fn stable_id(&self) -> usize {
+        self.stable_id
+    }
+
+    /// The peer's UDP address, if the connection hasn't been dropped
+    ///
+    /// See [`Connection::remote_address()`].
+    pub fn remote_address(&self) -> Option<T::Address> {
+        let conn = self.conn.upgrade()?;
+        let remote = conn.lock("remote_address").inner.remote_address();
+        Some(remote)
+    }
+
+    /// Connection statistics, if the connection hasn't been dropped
+    ///
+    /// See [`Connection::stats()`].
+    pub fn stats(&self) -> Option<ConnectionStats> {
+        let conn = self.conn.upgrade()?;
+        let stats = conn.lock("stats").inner.stats();
+        Some(stats)
+    }
+
+    /// Whether the connection is established and hasn't been closed
+    pub(crate) fn is_open(&self) -> bool {
+        match self.conn.upgrade() {
+            Some(conn) => conn.lock("is_open").is_open(),
+            None => false,
+        }
+    }
+}
+
+impl<S, T> Clone for WeakConnection<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn clone(&self) -> Self {
+        Self {
+            conn: self.conn.clone(),
+            stable_id: self.stable_id,
+        }
+    }
+}
+
 impl<S, T> std::ops::Deref for ConnectionRef<S, T>
 where
     S: proto::crypto::Session,
@@ -789,34 +1764,66 @@ where
     }
 }
 
+/// Source of [`Connection::stable_id()`]s
+static NEXT_STABLE_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub struct ConnectionInner<S, T>
 where
     S: proto::crypto::Session,
     T: Socket,
 {
-    pub(crate) inner: proto::generic::Connection<S>,
+    pub(crate) inner: proto::generic::Connection<S, T::Address>,
     driver: Option<Waker>,
     handle: ConnectionHandle,
+    stable_id: usize,
     on_handshake_data: Option<oneshot::Sender<()>>,
     on_connected: Option<oneshot::Sender<bool>>,
     connected: bool,
-    timer: Option<Pin<Box<Sleep>>>,
-    timer_deadline: Option<TokioInstant>,
-    conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
-    endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Source of buffers to assemble transmits in
+    buffers: BufferPool,
+    /// Spawns tasks on behalf of the connection, like its driver
+    spawner: Spawner,
+    /// Buffer the next transmit will be assembled in
+    transmit_buf: Vec<u8>,
+    priority_class: PriorityClass,
+    timer: Option<Pin<Box<dyn AsyncTimer>>>,
+    timer_deadline: Option<Instant>,
+    conn_events: mpsc::UnboundedReceiver<ConnectionEvent<T::Address>>,
+    endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent<T::Address>)>,
     pub(crate) blocked_writers: FxHashMap<StreamId, Waker>,
     pub(crate) blocked_readers: FxHashMap<StreamId, Waker>,
     uni_opening: Broadcast,
     bi_opening: Broadcast,
     incoming_uni_streams_reader: Option<Waker>,
     incoming_bi_streams_reader: Option<Waker>,
-    datagram_reader: Option<Waker>,
-    pub(crate) finishing: FxHashMap<StreamId, oneshot::Sender<Option<WriteError>>>,
+    datagram_readers: Broadcast,
+    /// Number of `IdleTimeoutWarning` events received
+    idle_warnings: u64,
+    idle_warning_waiters: Broadcast,
+    /// MTU reported by the latest `PathMtuChanged` event
+    path_mtu: u16,
+    /// Number of `PathMtuChanged` events received
+    path_mtu_changes: u64,
+    /// Number of `BlackHoleDetected` events received
+    black_holes: u64,
+    path_waiters: Broadcast,
+    pub(crate) finishing: FxHashMap<StreamId, oneshot::Sender<Result<SendStreamStats, WriteError>>>,
     pub(crate) stopped: FxHashMap<StreamId, Waker>,
     /// Always set to Some before the connection becomes drained
     pub(crate) error: Option<ConnectionError>,
     /// Number of live handles that can be used to initiate or handle I/O; excludes the driver
     ref_count: usize,
+    /// Set through `Connecting::with_label`
+    label: Option<Arc<str>>,
+    /// Set through `Connecting::reconnect_on_reset`, until the new connection has been started
+    reconnect: Option<Arc<dyn Restart<S, T>>>,
+    /// The connection started following a stateless reset, until taken by the application
+    reconnection: Option<Connecting<S, T>>,
+    reconnect_waiters: Broadcast,
+    /// Counts the connection as live, if its endpoint keeps count
+    #[cfg(feature = "debug_counters")]
+    pub(crate) tracked: Option<Tracked>,
     socket_type: PhantomData<T>,
 }
 
@@ -826,15 +1833,25 @@ where
     T: Socket,
 {
     fn drive_transmit(&mut self) {
-        let now = Instant::now();
+        let now = self.clock.now();
 
         let max_datagrams = T::caps().max_gso_segments;
 
-        while let Some(t) = self.inner.poll_transmit(now, max_datagrams) {
+        loop {
+            if self.transmit_buf.capacity() == 0 {
+                self.transmit_buf = self.buffers.take();
+            }
+            let t = match self
+                .inner
+                .poll_transmit_into(now, max_datagrams, &mut self.transmit_buf)
+            {
+                Some(x) => x,
+                None => break,
+            };
             // If the endpoint driver is gone, noop.
             let _ = self
                 .endpoint_events
-                .unbounded_send((self.handle, EndpointEvent::Transmit(t)));
+                .unbounded_send((self.handle, EndpointEvent::Transmit(t, self.priority_class)));
         }
     }
 
@@ -857,6 +1874,19 @@ where
                 Poll::Ready(Some(ConnectionEvent::Close { reason, error_code })) => {
                     self.close(error_code, reason);
                 }
+                Poll::Ready(Some(ConnectionEvent::PacketTooBig(report))) => {
+                    self.inner
+                        .handle_packet_too_big(report.destination, report.mtu);
+                }
+                Poll::Ready(Some(ConnectionEvent::SocketRecreated)) => {
+                    self.inner.local_address_changed();
+                }
+                Poll::Ready(Some(ConnectionEvent::Pause)) => {
+                    self.inner.pause(self.clock.now());
+                }
+                Poll::Ready(Some(ConnectionEvent::Resume)) => {
+                    self.inner.resume(self.clock.now());
+                }
                 Poll::Ready(None) => {
                     return Err(ConnectionError::TransportError(proto::TransportError {
                         code: proto::TransportErrorCode::INTERNAL_ERROR,
@@ -906,9 +1936,20 @@ where
                     }
                 }
                 DatagramReceived => {
-                    if let Some(x) = self.datagram_reader.take() {
-                        x.wake();
-                    }
+                    self.datagram_readers.wake();
+                }
+                IdleTimeoutWarning => {
+                    self.idle_warnings += 1;
+                    self.idle_warning_waiters.wake();
+                }
+                PathMtuChanged { mtu } => {
+                    self.path_mtu = mtu;
+                    self.path_mtu_changes += 1;
+                    self.path_waiters.wake();
+                }
+                BlackHoleDetected => {
+                    self.black_holes += 1;
+                    self.path_waiters.wake();
                 }
                 Stream(StreamEvent::Readable { id }) => {
                     if let Some(reader) = self.blocked_readers.remove(&id) {
@@ -924,8 +1965,10 @@ where
                 }
                 Stream(StreamEvent::Finished { id }) => {
                     if let Some(finishing) = self.finishing.remove(&id) {
+                        // Available until the next event is polled
+                        let stats = self.inner.send_stream(id).stats().unwrap_or_default();
                         // If the finishing stream was already dropped, there's nothing more to do.
-                        let _ = finishing.send(None);
+                        let _ = finishing.send(Ok(stats));
                     }
                 }
                 Stream(StreamEvent::Stopped { id, error_code }) => {
@@ -933,7 +1976,15 @@ where
                         stopped.wake();
                     }
                     if let Some(finishing) = self.finishing.remove(&id) {
-                        let _ = finishing.send(Some(WriteError::Stopped(error_code)));
+                        let _ = finishing.send(Err(WriteError::Stopped(error_code)));
+                    }
+                    if let Some(writer) = self.blocked_writers.remove(&id) {
+                        writer.wake();
+                    }
+                }
+                Stream(StreamEvent::Expired { id }) => {
+                    if let Some(finishing) = self.finishing.remove(&id) {
+                        let _ = finishing.send(Err(WriteError::UnknownStream));
                     }
                     if let Some(writer) = self.blocked_writers.remove(&id) {
                         writer.wake();
@@ -947,10 +1998,10 @@ where
         // Check whether we need to (re)set the timer. If so, we must poll again to ensure the
         // timer is registered with the runtime (and check whether it's already
         // expired).
-        match self.inner.poll_timeout().map(TokioInstant::from_std) {
+        match self.inner.poll_timeout() {
             Some(deadline) => {
                 if let Some(delay) = &mut self.timer {
-                    // There is no need to reset the tokio timer if the deadline
+                    // There is no need to reset the timer if the deadline
                     // did not change
                     if self
                         .timer_deadline
@@ -960,7 +2011,7 @@ where
                         delay.as_mut().reset(deadline);
                     }
                 } else {
-                    self.timer = Some(Box::pin(sleep_until(deadline)));
+                    self.timer = Some(self.clock.new_timer(deadline));
                 }
                 // Store the actual expiration time of the timer
                 self.timer_deadline = Some(deadline);
@@ -988,7 +2039,7 @@ where
 
         // A timer expired, so the caller needs to check for
         // new transmits, which might cause new timers to be set.
-        self.inner.handle_timeout(Instant::now());
+        self.inner.handle_timeout(self.clock.now());
         self.timer_deadline = None;
         true
     }
@@ -1017,11 +2068,11 @@ where
         if let Some(x) = self.incoming_bi_streams_reader.take() {
             x.wake();
         }
-        if let Some(x) = self.datagram_reader.take() {
-            x.wake();
-        }
+        self.datagram_readers.wake();
+        self.idle_warning_waiters.wake();
+        self.path_waiters.wake();
         for (_, x) in self.finishing.drain() {
-            let _ = x.send(Some(WriteError::ConnectionClosed(reason.clone())));
+            let _ = x.send(Err(WriteError::ConnectionClosed(reason.clone())));
         }
         if let Some(x) = self.on_connected.take() {
             let _ = x.send(false);
@@ -1032,11 +2083,16 @@ where
     }
 
     fn close(&mut self, error_code: VarInt, reason: Bytes) {
-        self.inner.close(Instant::now(), error_code, reason);
+        self.inner.close(self.clock.now(), error_code, reason);
         self.terminate(ConnectionError::LocallyClosed);
         self.wake();
     }
 
+    /// Whether the connection is established and hasn't been closed
+    fn is_open(&self) -> bool {
+        self.connected && !self.inner.is_closed()
+    }
+
     /// Close for a reason other than the application's explicit request
     pub fn implicit_close(&mut self) {
         self.close(0u32.into(), Bytes::new());
@@ -1082,6 +2138,21 @@ where
     }
 }
 
+/// How the endpoint orders a connection's datagrams relative to other connections'
+///
+/// Only matters while the endpoint produces datagrams faster than its socket can send them, e.g.
+/// under a `max_send_rate`. Set with [`Connection::set_priority_class()`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PriorityClass {
+    /// Datagrams are sent in the order they were produced
+    Interactive,
+    /// Datagrams are held back until no `Interactive` connection has any waiting
+    ///
+    /// Suits transfers that should yield to latency-sensitive traffic on the same endpoint. A
+    /// `Bulk` connection can be starved entirely while `Interactive` ones saturate the socket.
+    Bulk,
+}
+
 /// Errors that can arise when sending a datagram
 #[derive(Debug, Error, Clone, Eq, PartialEq)]
 pub enum SendDatagramError {
@@ -0,0 +1,51 @@
+use std::time::{Duration, Instant};
+
+/// Time an endpoint's driver spent on its hot paths, for catching performance regressions
+///
+/// Only collected with the `driver_counters` feature, as reading the time around every batch of
+/// I/O has a measurable cost of its own. The receive path covers reading datagrams from the
+/// socket and dispatching them to their connections; the send path covers collecting transmits
+/// from the protocol state machine and writing them to the socket.
+///
+/// See [`Endpoint::driver_counters()`](crate::generic::Endpoint::driver_counters).
+#[derive(Debug, Default, Copy, Clone)]
+#[non_exhaustive]
+pub struct DriverCounters {
+    /// Number of datagrams received
+    pub datagrams_received: u64,
+    /// Time spent receiving and dispatching datagrams
+    pub recv_time: Duration,
+    /// Number of transmits passed to the socket
+    pub transmits_sent: u64,
+    /// Time spent collecting and sending transmits
+    pub send_time: Duration,
+}
+
+impl DriverCounters {
+    /// Average time spent on the receive path per datagram received
+    pub fn recv_time_per_datagram(&self) -> Option<Duration> {
+        per(self.recv_time, self.datagrams_received)
+    }
+
+    /// Average time spent on the send path per transmit sent
+    pub fn send_time_per_transmit(&self) -> Option<Duration> {
+        per(self.send_time, self.transmits_sent)
+    }
+
+    pub(crate) fn record_recv(&mut self, start: Instant) {
+        self.recv_time += start.elapsed();
+    }
+
+    pub(crate) fn record_send(&mut self, start: Instant) {
+        self.send_time += start.elapsed();
+    }
+}
+
+fn per(time: Duration, count: u64) -> Option<Duration> {
+    if count == 0 {
+        return None;
+    }
+    Some(Duration::from_nanos(
+        (time.as_nanos() / u128::from(count)) as u64,
+    ))
+}
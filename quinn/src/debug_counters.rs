@@ -0,0 +1,137 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use crate::clock::{AsyncTimer, Clock};
+
+/// Objects an endpoint and its connections are holding on to, for detecting leaks
+///
+/// Only collected with the `debug_counters` feature, as keeping count costs a little on every
+/// connection, stream and timer. Once an endpoint's connections are gone and it has gone idle,
+/// every count but `buffers` should drop back to zero; steady growth under churn is a leak.
+///
+/// See [`Endpoint::debug_counters()`](crate::generic::Endpoint::debug_counters).
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct DebugCounters {
+    /// Connections whose state hasn't been freed, including closed ones still referenced
+    pub connections: usize,
+    /// Send and receive stream handles that haven't been dropped
+    pub streams: usize,
+    /// Transmit buffers held by the endpoint, queued for sending or kept for reuse
+    ///
+    /// Up to a small fixed number of buffers are kept for reuse even while idle.
+    pub buffers: usize,
+    /// Timers constructed from the endpoint's clock that haven't been dropped
+    pub timers: usize,
+}
+
+/// Live object counts shared between an endpoint and everything it creates
+#[derive(Debug, Default)]
+pub(crate) struct LiveCounts {
+    connections: AtomicUsize,
+    streams: AtomicUsize,
+    timers: AtomicUsize,
+}
+
+impl LiveCounts {
+    pub(crate) fn snapshot(&self, buffers: usize) -> DebugCounters {
+        DebugCounters {
+            connections: self.connections.load(Ordering::Relaxed),
+            streams: self.streams.load(Ordering::Relaxed),
+            buffers,
+            timers: self.timers.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get(&self, kind: Kind) -> &AtomicUsize {
+        match kind {
+            Kind::Connection => &self.connections,
+            Kind::Stream => &self.streams,
+            Kind::Timer => &self.timers,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Kind {
+    Connection,
+    Stream,
+    Timer,
+}
+
+/// Counts an object as live until dropped
+#[derive(Debug)]
+pub(crate) struct Tracked {
+    counts: Arc<LiveCounts>,
+    kind: Kind,
+}
+
+impl Tracked {
+    pub(crate) fn new(counts: &Arc<LiveCounts>, kind: Kind) -> Self {
+        counts.get(kind).fetch_add(1, Ordering::Relaxed);
+        Self {
+            counts: counts.clone(),
+            kind,
+        }
+    }
+
+    /// Count an object of `kind` belonging to the same endpoint
+    pub(crate) fn sibling(&self, kind: Kind) -> Self {
+        Self::new(&self.counts, kind)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.counts.get(self.kind).fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Wraps an endpoint's [`Clock`] to count the timers constructed from it
+#[derive(Debug)]
+pub(crate) struct CountingClock {
+    inner: Arc<dyn Clock>,
+    counts: Arc<LiveCounts>,
+}
+
+impl CountingClock {
+    pub(crate) fn new(inner: Arc<dyn Clock>, counts: Arc<LiveCounts>) -> Self {
+        Self { inner, counts }
+    }
+}
+
+impl Clock for CountingClock {
+    fn now(&self) -> Instant {
+        self.inner.now()
+    }
+
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        Box::pin(CountedTimer {
+            inner: self.inner.new_timer(deadline),
+            _tracked: Tracked::new(&self.counts, Kind::Timer),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct CountedTimer {
+    inner: Pin<Box<dyn AsyncTimer>>,
+    _tracked: Tracked,
+}
+
+impl AsyncTimer for CountedTimer {
+    fn reset(self: Pin<&mut Self>, deadline: Instant) {
+        self.get_mut().inner.as_mut().reset(deadline)
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.get_mut().inner.as_mut().poll(cx)
+    }
+}
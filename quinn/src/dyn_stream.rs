@@ -0,0 +1,400 @@
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{
+    future::{self, BoxFuture},
+    io::{AsyncRead, AsyncWrite},
+    ready, FutureExt,
+};
+use proto::{Chunk, RecvStreamStats, SendStreamStats, StreamId, Written};
+use tokio::io::ReadBuf;
+
+use crate::{
+    recv_stream::{ReadError, ReadExactError, ReadToEndError, RecvStream, UnknownStream},
+    send_stream::{SendStream, StoppedError, WriteError},
+    transport::Socket,
+    VarInt,
+};
+
+/// A [`SendStream`] whose crypto session and socket types have been erased
+///
+/// Lets protocol libraries accept streams from any endpoint without being generic over
+/// `proto::crypto::Session` and [`Socket`], at the cost of dynamic dispatch on every call. Obtained
+/// from a `SendStream` through `From`.
+///
+/// [`SendStream`]: crate::generic::SendStream
+/// [`Socket`]: crate::transport::Socket
+pub struct DynSendStream(Box<dyn ErasedSendStream>);
+
+impl DynSendStream {
+    /// Write bytes to the stream
+    ///
+    /// See [`SendStream::write()`](crate::generic::SendStream::write).
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, WriteError> {
+        future::poll_fn(|cx| self.0.poll_write(cx, buf)).await
+    }
+
+    /// Convenience method to write an entire buffer to the stream
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> Result<(), WriteError> {
+        while !buf.is_empty() {
+            let n = self.write(buf).await?;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    /// Write chunks to the stream
+    ///
+    /// See [`SendStream::write_chunks()`](crate::generic::SendStream::write_chunks).
+    pub async fn write_chunks(&mut self, bufs: &mut [Bytes]) -> Result<Written, WriteError> {
+        future::poll_fn(|cx| self.0.poll_write_chunks(cx, bufs)).await
+    }
+
+    /// Convenience method to write a single chunk in its entirety to the stream
+    pub async fn write_chunk(&mut self, buf: Bytes) -> Result<(), WriteError> {
+        let mut bufs = [buf];
+        while !bufs[0].is_empty() {
+            self.write_chunks(&mut bufs).await?;
+        }
+        Ok(())
+    }
+
+    /// Shut down the send stream gracefully
+    ///
+    /// See [`SendStream::finish()`](crate::generic::SendStream::finish).
+    pub async fn finish(&mut self) -> Result<(), WriteError> {
+        future::poll_fn(|cx| self.0.poll_finish(cx)).await
+    }
+
+    /// Close the send stream immediately
+    ///
+    /// See [`SendStream::reset()`](crate::generic::SendStream::reset).
+    pub fn reset(&mut self, error_code: VarInt) -> Result<(), UnknownStream> {
+        self.0.reset(error_code)
+    }
+
+    /// Set the priority of the send stream
+    ///
+    /// See [`SendStream::set_priority()`](crate::generic::SendStream::set_priority).
+    pub fn set_priority(&self, priority: i32) -> Result<(), UnknownStream> {
+        self.0.set_priority(priority)
+    }
+
+    /// Get the priority of the send stream
+    pub fn priority(&self) -> Result<i32, UnknownStream> {
+        self.0.priority()
+    }
+
+    /// Statistics about the stream
+    pub fn stats(&self) -> Result<SendStreamStats, UnknownStream> {
+        self.0.stats()
+    }
+
+    /// Completes if/when the peer stops the stream, yielding the error code
+    pub async fn stopped(&mut self) -> Result<VarInt, StoppedError> {
+        future::poll_fn(|cx| self.0.poll_stopped(cx)).await
+    }
+
+    /// Get the identity of this stream
+    pub fn id(&self) -> StreamId {
+        self.0.id()
+    }
+}
+
+impl<S, T> From<SendStream<S, T>> for DynSendStream
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    fn from(stream: SendStream<S, T>) -> Self {
+        Self(Box::new(stream))
+    }
+}
+
+impl fmt::Debug for DynSendStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynSendStream").field(&self.id()).finish()
+    }
+}
+
+impl AsyncWrite for DynSendStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().0.poll_write(cx, buf).map_err(Into::into)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.get_mut().0.poll_finish(cx).map_err(Into::into)
+    }
+}
+
+impl tokio::io::AsyncWrite for DynSendStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(self, cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(self, cx)
+    }
+}
+
+/// A [`RecvStream`] whose crypto session and socket types have been erased
+///
+/// Lets protocol libraries accept streams from any endpoint without being generic over
+/// `proto::crypto::Session` and [`Socket`], at the cost of dynamic dispatch on every call. Obtained
+/// from a `RecvStream` through `From`.
+///
+/// [`RecvStream`]: crate::generic::RecvStream
+/// [`Socket`]: crate::transport::Socket
+pub struct DynRecvStream(Box<dyn ErasedRecvStream>);
+
+impl DynRecvStream {
+    /// Read data contiguously from the stream
+    ///
+    /// Yields the number of bytes read into `buf` on success, or `None` if the stream was finished.
+    /// See [`RecvStream::read()`](crate::generic::RecvStream::read).
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, ReadError> {
+        let mut buf = ReadBuf::new(buf);
+        future::poll_fn(|cx| self.0.poll_read(cx, &mut buf)).await?;
+        match buf.filled().len() {
+            0 => Ok(None),
+            n => Ok(Some(n)),
+        }
+    }
+
+    /// Read an exact number of bytes contiguously from the stream
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError> {
+        let mut buf = ReadBuf::new(buf);
+        while buf.remaining() > 0 {
+            let remaining = buf.remaining();
+            future::poll_fn(|cx| self.0.poll_read(cx, &mut buf)).await?;
+            if buf.remaining() == remaining {
+                return Err(ReadExactError::FinishedEarly);
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the next segment of data
+    ///
+    /// See [`RecvStream::read_chunk()`](crate::generic::RecvStream::read_chunk).
+    pub async fn read_chunk(
+        &mut self,
+        max_length: usize,
+        ordered: bool,
+    ) -> Result<Option<Chunk>, ReadError> {
+        future::poll_fn(|cx| self.0.poll_read_chunk(cx, max_length, ordered)).await
+    }
+
+    /// Convenience method to read all remaining data into a buffer
+    ///
+    /// See [`RecvStream::read_to_end()`](crate::generic::RecvStream::read_to_end).
+    pub async fn read_to_end(self, size_limit: usize) -> Result<Vec<u8>, ReadToEndError> {
+        self.0.read_to_end(size_limit).await
+    }
+
+    /// Stop accepting data
+    ///
+    /// See [`RecvStream::stop()`](crate::generic::RecvStream::stop).
+    pub fn stop(&mut self, error_code: VarInt) -> Result<(), UnknownStream> {
+        self.0.stop(error_code)
+    }
+
+    /// Statistics about the stream
+    pub fn stats(&self) -> Result<RecvStreamStats, UnknownStream> {
+        self.0.stats()
+    }
+
+    /// Check if this stream has been opened during 0-RTT
+    pub fn is_0rtt(&self) -> bool {
+        self.0.is_0rtt()
+    }
+
+    /// Get the identity of this stream
+    pub fn id(&self) -> StreamId {
+        self.0.id()
+    }
+}
+
+impl<S, T> From<RecvStream<S, T>> for DynRecvStream
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    fn from(stream: RecvStream<S, T>) -> Self {
+        Self(Box::new(stream))
+    }
+}
+
+impl fmt::Debug for DynRecvStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynRecvStream").field(&self.id()).finish()
+    }
+}
+
+impl AsyncRead for DynRecvStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(self.get_mut().0.poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl tokio::io::AsyncRead for DynRecvStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.get_mut().0.poll_read(cx, buf))?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Object-safe subset of `SendStream`'s interface
+trait ErasedSendStream: Send {
+    fn poll_write(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize, WriteError>>;
+    fn poll_write_chunks(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [Bytes],
+    ) -> Poll<Result<Written, WriteError>>;
+    fn poll_finish(&mut self, cx: &mut Context) -> Poll<Result<(), WriteError>>;
+    fn poll_stopped(&mut self, cx: &mut Context) -> Poll<Result<VarInt, StoppedError>>;
+    fn reset(&mut self, error_code: VarInt) -> Result<(), UnknownStream>;
+    fn set_priority(&self, priority: i32) -> Result<(), UnknownStream>;
+    fn priority(&self) -> Result<i32, UnknownStream>;
+    fn stats(&self) -> Result<SendStreamStats, UnknownStream>;
+    fn id(&self) -> StreamId;
+}
+
+impl<S, T> ErasedSendStream for SendStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn poll_write(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<Result<usize, WriteError>> {
+        self.execute_poll(cx, |s| s.write(buf))
+    }
+
+    fn poll_write_chunks(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [Bytes],
+    ) -> Poll<Result<Written, WriteError>> {
+        self.execute_poll(cx, |s| s.write_chunks(bufs))
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context) -> Poll<Result<(), WriteError>> {
+        SendStream::poll_finish(self, cx)
+    }
+
+    fn poll_stopped(&mut self, cx: &mut Context) -> Poll<Result<VarInt, StoppedError>> {
+        SendStream::poll_stopped(self, cx)
+    }
+
+    fn reset(&mut self, error_code: VarInt) -> Result<(), UnknownStream> {
+        SendStream::reset(self, error_code)
+    }
+
+    fn set_priority(&self, priority: i32) -> Result<(), UnknownStream> {
+        SendStream::set_priority(self, priority)
+    }
+
+    fn priority(&self) -> Result<i32, UnknownStream> {
+        SendStream::priority(self)
+    }
+
+    fn stats(&self) -> Result<SendStreamStats, UnknownStream> {
+        SendStream::stats(self)
+    }
+
+    fn id(&self) -> StreamId {
+        SendStream::id(self)
+    }
+}
+
+/// Object-safe subset of `RecvStream`'s interface
+trait ErasedRecvStream: Send {
+    fn poll_read(&mut self, cx: &mut Context, buf: &mut ReadBuf<'_>)
+        -> Poll<Result<(), ReadError>>;
+    fn poll_read_chunk(
+        &mut self,
+        cx: &mut Context,
+        max_length: usize,
+        ordered: bool,
+    ) -> Poll<Result<Option<Chunk>, ReadError>>;
+    fn read_to_end(
+        self: Box<Self>,
+        size_limit: usize,
+    ) -> BoxFuture<'static, Result<Vec<u8>, ReadToEndError>>;
+    fn stop(&mut self, error_code: VarInt) -> Result<(), UnknownStream>;
+    fn stats(&self) -> Result<RecvStreamStats, UnknownStream>;
+    fn is_0rtt(&self) -> bool;
+    fn id(&self) -> StreamId;
+}
+
+impl<S, T> ErasedRecvStream for RecvStream<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    fn poll_read(
+        &mut self,
+        cx: &mut Context,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), ReadError>> {
+        RecvStream::poll_read(self, cx, buf)
+    }
+
+    fn poll_read_chunk(
+        &mut self,
+        cx: &mut Context,
+        max_length: usize,
+        ordered: bool,
+    ) -> Poll<Result<Option<Chunk>, ReadError>> {
+        RecvStream::poll_read_chunk(self, cx, max_length, ordered)
+    }
+
+    fn read_to_end(
+        self: Box<Self>,
+        size_limit: usize,
+    ) -> BoxFuture<'static, Result<Vec<u8>, ReadToEndError>> {
+        RecvStream::read_to_end(*self, size_limit).boxed()
+    }
+
+    fn stop(&mut self, error_code: VarInt) -> Result<(), UnknownStream> {
+        RecvStream::stop(self, error_code)
+    }
+
+    fn stats(&self) -> Result<RecvStreamStats, UnknownStream> {
+        RecvStream::stats(self)
+    }
+
+    fn is_0rtt(&self) -> bool {
+        RecvStream::is_0rtt(self)
+    }
+
+    fn id(&self) -> StreamId {
+        RecvStream::id(self)
+    }
+}
@@ -0,0 +1,484 @@
+//! Endpoint and connection types which aren't generic over the crypto session and socket
+//!
+//! The types in [`generic`] are parameterized by the crypto protocol and the
+//! socket, so code handling them must either pick both at compile time or be generic itself. The
+//! types here erase both behind trait objects, letting applications choose e.g. between rustls
+//! and another crypto protocol, or between UDP and another transport, at runtime, such as from a
+//! configuration file. Endpoints are built as usual and converted with `From`, after which
+//! everything they yield is type-erased as well. Sockets must address peers by `SocketAddr`.
+//!
+//! Settings which depend on the crypto protocol, such as client configurations, must be applied
+//! before conversion, e.g. with [`EndpointBuilder::default_client_config()`].
+//!
+//! [`EndpointBuilder::default_client_config()`]: crate::generic::EndpointBuilder::default_client_config
+
+use std::{
+    fmt,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, Stream},
+    FutureExt, StreamExt, TryFutureExt, TryStreamExt,
+};
+use proto::{
+    crypto::{ChannelBinding, ExportKeyingMaterialError},
+    ConnectError, ConnectionError, ConnectionStats, EndpointStats, HandshakeSummary,
+};
+
+use crate::{
+    connection::SendDatagramError,
+    dyn_stream::{DynRecvStream, DynSendStream},
+    generic,
+    transport::Socket,
+    VarInt,
+};
+
+/// A type-erased [`Endpoint`](crate::generic::Endpoint)
+///
+/// May be cloned to obtain another handle to the same endpoint.
+#[derive(Clone)]
+pub struct Endpoint(Arc<dyn ErasedEndpoint>);
+
+impl Endpoint {
+    /// Connect to a remote endpoint using the default client configuration
+    ///
+    /// See [`Endpoint::connect()`](crate::generic::Endpoint::connect).
+    pub fn connect(
+        &self,
+        addr: &SocketAddr,
+        server_name: &str,
+    ) -> Result<Connecting, ConnectError> {
+        self.0.connect(addr, server_name)
+    }
+
+    /// Get the local address the underlying socket is bound to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    /// Close all of this endpoint's connections immediately and cease accepting new connections
+    pub fn close(&self, error_code: VarInt, reason: &[u8]) {
+        self.0.close(error_code, reason)
+    }
+
+    /// Number of connections currently tracked by the endpoint
+    pub fn connection_count(&self) -> usize {
+        self.0.connection_count()
+    }
+
+    /// Statistics about the endpoint's traffic and load
+    pub fn stats(&self) -> EndpointStats {
+        self.0.stats()
+    }
+
+    /// Wait for all connections on the endpoint to be cleanly shut down
+    ///
+    /// See [`Endpoint::wait_idle()`](crate::generic::Endpoint::wait_idle).
+    pub async fn wait_idle(&self) {
+        self.0.wait_idle().await
+    }
+}
+
+impl<S, T> From<generic::Endpoint<S, T>> for Endpoint
+where
+    S: proto::crypto::Session + 'static,
+    S::ClientConfig: Send + Sync,
+    T: Socket<Address = SocketAddr>,
+{
+    fn from(endpoint: generic::Endpoint<S, T>) -> Self {
+        Self(Arc::new(endpoint))
+    }
+}
+
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("local_addr", &self.local_addr().ok())
+            .finish()
+    }
+}
+
+/// A type-erased [`Incoming`](crate::generic::Incoming)
+pub struct Incoming(BoxStream<'static, Connecting>);
+
+impl<S, T> From<generic::Incoming<S, T>> for Incoming
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket<Address = SocketAddr>,
+{
+    fn from(incoming: generic::Incoming<S, T>) -> Self {
+        Self(incoming.map(Connecting::from).boxed())
+    }
+}
+
+impl Stream for Incoming {
+    type Item = Connecting;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_next_unpin(cx)
+    }
+}
+
+impl fmt::Debug for Incoming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Incoming")
+    }
+}
+
+/// A type-erased [`Connecting`](crate::generic::Connecting)
+pub struct Connecting {
+    remote: SocketAddr,
+    future: BoxFuture<'static, Result<NewConnection, ConnectionError>>,
+}
+
+impl Connecting {
+    /// The peer's UDP address
+    pub fn remote_address(&self) -> SocketAddr {
+        self.remote
+    }
+}
+
+impl<S, T> From<generic::Connecting<S, T>> for Connecting
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket<Address = SocketAddr>,
+{
+    fn from(connecting: generic::Connecting<S, T>) -> Self {
+        Self {
+            remote: connecting.remote_address(),
+            future: connecting.map_ok(NewConnection::from).boxed(),
+        }
+    }
+}
+
+impl Future for Connecting {
+    type Output = Result<NewConnection, ConnectionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().future.poll_unpin(cx)
+    }
+}
+
+impl fmt::Debug for Connecting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connecting")
+            .field("remote", &self.remote)
+            .finish()
+    }
+}
+
+/// A type-erased [`NewConnection`](crate::generic::NewConnection)
+#[non_exhaustive]
+pub struct NewConnection {
+    /// Handle for interacting with the connection
+    pub connection: Connection,
+    /// Unidirectional streams initiated by the peer, in the order they were opened
+    pub uni_streams: BoxStream<'static, Result<DynRecvStream, ConnectionError>>,
+    /// Bidirectional streams initiated by the peer, in the order they were opened
+    pub bi_streams: BoxStream<'static, Result<(DynSendStream, DynRecvStream), ConnectionError>>,
+    /// Unordered, unreliable datagrams sent by the peer
+    pub datagrams: BoxStream<'static, Result<Bytes, ConnectionError>>,
+}
+
+impl<S, T> From<generic::NewConnection<S, T>> for NewConnection
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket<Address = SocketAddr>,
+{
+    fn from(conn: generic::NewConnection<S, T>) -> Self {
+        Self {
+            connection: conn.connection.into(),
+            uni_streams: conn.uni_streams.map_ok(DynRecvStream::from).boxed(),
+            bi_streams: conn
+                .bi_streams
+                .map_ok(|(send, recv)| (send.into(), recv.into()))
+                .boxed(),
+            datagrams: conn.datagrams.boxed(),
+        }
+    }
+}
+
+impl fmt::Debug for NewConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NewConnection")
+            .field("connection", &self.connection)
+            .finish()
+    }
+}
+
+/// A type-erased [`Connection`](crate::generic::Connection)
+///
+/// May be cloned to obtain another handle to the same connection.
+#[derive(Clone)]
+pub struct Connection(Arc<dyn ErasedConnection>);
+
+impl Connection {
+    /// Initiate a new outgoing unidirectional stream
+    ///
+    /// See [`Connection::open_uni()`](crate::generic::Connection::open_uni).
+    pub async fn open_uni(&self) -> Result<DynSendStream, ConnectionError> {
+        self.0.open_uni().await
+    }
+
+    /// Initiate a new outgoing bidirectional stream
+    ///
+    /// See [`Connection::open_bi()`](crate::generic::Connection::open_bi).
+    pub async fn open_bi(&self) -> Result<(DynSendStream, DynRecvStream), ConnectionError> {
+        self.0.open_bi().await
+    }
+
+    /// Close the connection immediately
+    ///
+    /// See [`Connection::close()`](crate::generic::Connection::close).
+    pub fn close(&self, error_code: VarInt, reason: &[u8]) {
+        self.0.close(error_code, reason)
+    }
+
+    /// Transmit `data` as an unreliable, unordered application datagram
+    ///
+    /// See [`Connection::send_datagram()`](crate::generic::Connection::send_datagram).
+    pub fn send_datagram(&self, data: Bytes) -> Result<(), SendDatagramError> {
+        self.0.send_datagram(data)
+    }
+
+    /// Compute the maximum size of datagrams that may be passed to `send_datagram`
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        self.0.max_datagram_size()
+    }
+
+    /// The peer's UDP address
+    pub fn remote_address(&self) -> SocketAddr {
+        self.0.remote_address()
+    }
+
+    /// The local IP address which was used when the peer established the connection
+    pub fn local_ip(&self) -> Option<IpAddr> {
+        self.0.local_ip()
+    }
+
+    /// The server name the connection was established with, as presented by the client
+    pub fn server_name(&self) -> Option<String> {
+        self.0.server_name()
+    }
+
+    /// Current best estimate of this connection's latency (round-trip-time)
+    pub fn rtt(&self) -> Duration {
+        self.0.rtt()
+    }
+
+    /// Returns connection statistics
+    pub fn stats(&self) -> ConnectionStats {
+        self.0.stats()
+    }
+
+    /// The label the connection was tagged with, if any
+    pub fn label(&self) -> Option<Arc<str>> {
+        self.0.label()
+    }
+
+    /// Summary of how the handshake was carried out, once it has completed
+    pub fn handshake_summary(&self) -> Option<HandshakeSummary> {
+        self.0.handshake_summary()
+    }
+
+    /// A stable identifier for this connection
+    ///
+    /// Peer addresses and connection IDs can change, but this value will remain
+    /// fixed for the lifetime of the connection.
+    pub fn stable_id(&self) -> usize {
+        self.0.stable_id()
+    }
+
+    /// Derive keying material from this connection's TLS session secrets
+    ///
+    /// See [`Connection::export_keying_material()`](crate::generic::Connection::export_keying_material).
+    pub fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), ExportKeyingMaterialError> {
+        self.0.export_keying_material(output, label, context)
+    }
+
+    /// Value binding application-layer credentials to this connection
+    ///
+    /// See [`Connection::channel_binding()`](crate::generic::Connection::channel_binding).
+    pub fn channel_binding(&self) -> Result<ChannelBinding, ExportKeyingMaterialError> {
+        self.0.channel_binding()
+    }
+}
+
+impl<S, T> From<generic::Connection<S, T>> for Connection
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket<Address = SocketAddr>,
+{
+    fn from(conn: generic::Connection<S, T>) -> Self {
+        Self(Arc::new(conn))
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("stable_id", &self.stable_id())
+            .field("remote_address", &self.remote_address())
+            .finish()
+    }
+}
+
+/// Object-safe subset of `generic::Endpoint`'s interface
+trait ErasedEndpoint: Send + Sync {
+    fn connect(&self, addr: &SocketAddr, server_name: &str) -> Result<Connecting, ConnectError>;
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    fn close(&self, error_code: VarInt, reason: &[u8]);
+    fn connection_count(&self) -> usize;
+    fn stats(&self) -> EndpointStats;
+    fn wait_idle(&self) -> BoxFuture<'static, ()>;
+}
+
+impl<S, T> ErasedEndpoint for generic::Endpoint<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    S::ClientConfig: Send + Sync,
+    T: Socket<Address = SocketAddr>,
+{
+    fn connect(&self, addr: &SocketAddr, server_name: &str) -> Result<Connecting, ConnectError> {
+        generic::Endpoint::connect(self, addr, server_name).map(Connecting::from)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        generic::Endpoint::local_addr(self)
+    }
+
+    fn close(&self, error_code: VarInt, reason: &[u8]) {
+        generic::Endpoint::close(self, error_code, reason)
+    }
+
+    fn connection_count(&self) -> usize {
+        generic::Endpoint::connection_count(self)
+    }
+
+    fn stats(&self) -> EndpointStats {
+        generic::Endpoint::stats(self)
+    }
+
+    fn wait_idle(&self) -> BoxFuture<'static, ()> {
+        let endpoint = self.clone();
+        async move { endpoint.wait_idle().await }.boxed()
+    }
+}
+
+/// Object-safe subset of `generic::Connection`'s interface
+trait ErasedConnection: Send + Sync {
+    fn open_uni(&self) -> BoxFuture<'static, Result<DynSendStream, ConnectionError>>;
+    #[allow(clippy::type_complexity)]
+    fn open_bi(
+        &self,
+    ) -> BoxFuture<'static, Result<(DynSendStream, DynRecvStream), ConnectionError>>;
+    fn close(&self, error_code: VarInt, reason: &[u8]);
+    fn send_datagram(&self, data: Bytes) -> Result<(), SendDatagramError>;
+    fn max_datagram_size(&self) -> Option<usize>;
+    fn remote_address(&self) -> SocketAddr;
+    fn local_ip(&self) -> Option<IpAddr>;
+    fn server_name(&self) -> Option<String>;
+    fn rtt(&self) -> Duration;
+    fn stats(&self) -> ConnectionStats;
+    fn label(&self) -> Option<Arc<str>>;
+    fn handshake_summary(&self) -> Option<HandshakeSummary>;
+    fn stable_id(&self) -> usize;
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), ExportKeyingMaterialError>;
+    fn channel_binding(&self) -> Result<ChannelBinding, ExportKeyingMaterialError>;
+}
+
+impl<S, T> ErasedConnection for generic::Connection<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket<Address = SocketAddr>,
+{
+    fn open_uni(&self) -> BoxFuture<'static, Result<DynSendStream, ConnectionError>> {
+        generic::Connection::open_uni(self)
+            .map_ok(DynSendStream::from)
+            .boxed()
+    }
+
+    fn open_bi(
+        &self,
+    ) -> BoxFuture<'static, Result<(DynSendStream, DynRecvStream), ConnectionError>> {
+        generic::Connection::open_bi(self)
+            .map_ok(|(send, recv)| (send.into(), recv.into()))
+            .boxed()
+    }
+
+    fn close(&self, error_code: VarInt, reason: &[u8]) {
+        generic::Connection::close(self, error_code, reason)
+    }
+
+    fn send_datagram(&self, data: Bytes) -> Result<(), SendDatagramError> {
+        generic::Connection::send_datagram(self, data)
+    }
+
+    fn max_datagram_size(&self) -> Option<usize> {
+        generic::Connection::max_datagram_size(self)
+    }
+
+    fn remote_address(&self) -> SocketAddr {
+        generic::Connection::remote_address(self)
+    }
+
+    fn local_ip(&self) -> Option<IpAddr> {
+        generic::Connection::local_ip(self)
+    }
+
+    fn server_name(&self) -> Option<String> {
+        generic::Connection::server_name(self)
+    }
+
+    fn rtt(&self) -> Duration {
+        generic::Connection::rtt(self)
+    }
+
+    fn stats(&self) -> ConnectionStats {
+        generic::Connection::stats(self)
+    }
+
+    fn label(&self) -> Option<Arc<str>> {
+        generic::Connection::label(self)
+    }
+
+    fn handshake_summary(&self) -> Option<HandshakeSummary> {
+        generic::Connection::handshake_summary(self)
+    }
+
+    fn stable_id(&self) -> usize {
+        generic::Connection::stable_id(self)
+    }
+
+    fn export_keying_material(
+        &self,
+        output: &mut [u8],
+        label: &[u8],
+        context: &[u8],
+    ) -> Result<(), ExportKeyingMaterialError> {
+        generic::Connection::export_keying_material(self, output, label, context)
+    }
+
+    fn channel_binding(&self) -> Result<ChannelBinding, ExportKeyingMaterialError> {
+        generic::Connection::channel_binding(self)
+    }
+}
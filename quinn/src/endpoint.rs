@@ -1,30 +1,51 @@
 use std::{
     collections::VecDeque,
     convert::TryInto,
+    fmt,
     future::Future,
     io,
     io::IoSliceMut,
     mem::MaybeUninit,
-    net::{SocketAddr, SocketAddrV6},
+    net::IpAddr,
+    num::NonZeroU64,
     pin::Pin,
     str,
-    sync::{Arc, Mutex},
+    sync::{Arc, Weak},
     task::{Context, Poll, Waker},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use bytes::Bytes;
-use futures::{channel::mpsc, StreamExt};
+use futures::{channel::mpsc, future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
 use fxhash::FxHashMap;
-use once_cell::sync::OnceCell;
-use proto::{self as proto, generic::ClientConfig, ConnectError, ConnectionHandle, DatagramEvent};
+use proto::{
+    self as proto,
+    generic::{ClientConfig, ServerConfig},
+    Address, ConnectError, ConnectionError, ConnectionHandle, DatagramEvent, EndpointStats,
+    ListenerFilter, ListenerId,
+};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
 
+#[cfg(feature = "debug_counters")]
+use crate::debug_counters::{CountingClock, LiveCounts};
 use crate::{
+    anti_replay::AntiReplay,
     broadcast::{self, Broadcast},
+    buffer_pool::BufferPool,
     builders::EndpointBuilder,
-    connection::Connecting,
-    platform::{RecvMeta, BATCH_SIZE},
+    clock::{AsyncTimer, Clock},
+    connection::{
+        Connecting, Connection, HandshakeRuntime, NewConnection, PriorityClass, WeakConnection,
+    },
+    health,
+    mutex::Mutex,
+    platform::{is_network_change, is_transient, RecvMeta, UdpSocket, BATCH_SIZE},
+    runtime::Runtime,
+    shaper::Shaper,
     transport::Socket,
+    unwind::PanicHook,
     ConnectionEvent, EndpointError, EndpointEvent, VarInt, IO_LOOP_BOUND,
 };
 
@@ -41,7 +62,12 @@ where
     T: Socket,
 {
     pub(crate) inner: EndpointRef<S, T>,
-    pub(crate) default_client_config: OnceCell<ClientConfig<S>>,
+    /// Shared by all handles, and initialized to the default when first needed if unset
+    pub(crate) default_client_config: Arc<Mutex<Option<ClientConfig<S>>>>,
+    /// Set if the endpoint was built not to spawn its drivers
+    pub(crate) drivers: Option<Arc<Mutex<ManualDrivers<S, T>>>>,
+    /// Set if outgoing connections should be repeated after a stateless reset
+    pub(crate) reconnect: Option<EraseReconnect<S, T>>,
 }
 
 impl<S, T> Endpoint<S, T>
@@ -64,16 +90,29 @@ where
     /// not be established.
     pub fn connect(
         &self,
-        addr: &SocketAddr,
+        addr: &T::Address,
         server_name: &str,
-    ) -> Result<Connecting<S, T>, ConnectError> {
-        self.connect_with(
-            self.default_client_config
-                .get_or_init(ClientConfig::default)
-                .clone(),
-            addr,
-            server_name,
-        )
+    ) -> Result<Connecting<S, T>, ConnectError<T::Address>> {
+        self.connect_with(self.default_client_config(), addr, server_name)
+    }
+
+    /// Set the client configuration used by [`connect()`] for future connections
+    ///
+    /// Allows changes such as new application protocols or certificate authorities to take effect
+    /// without rebuilding the endpoint. Connections already established or in progress are
+    /// unaffected. Applies to all handles to this endpoint.
+    ///
+    /// [`connect()`]: Endpoint::connect
+    pub fn set_default_client_config(&self, config: ClientConfig<S>) {
+        *self.default_client_config.lock("set_default_client_config") = Some(config);
+    }
+
+    /// The client configuration used by [`connect()`](Endpoint::connect)
+    pub(crate) fn default_client_config(&self) -> ClientConfig<S> {
+        self.default_client_config
+            .lock("default_client_config")
+            .get_or_insert_with(ClientConfig::default)
+            .clone()
     }
 
     /// Connect to a remote endpoint using a custom configuration.
@@ -84,23 +123,109 @@ where
     pub fn connect_with(
         &self,
         config: ClientConfig<S>,
-        addr: &SocketAddr,
+        addr: &T::Address,
         server_name: &str,
-    ) -> Result<Connecting<S, T>, ConnectError> {
-        let mut endpoint = self.inner.lock().unwrap();
-        if endpoint.driver_lost {
-            return Err(ConnectError::EndpointStopping);
+    ) -> Result<Connecting<S, T>, ConnectError<T::Address>> {
+        let reconnect = self
+            .reconnect
+            .map(|erase| erase(Reconnect::new(self, config.clone(), addr, server_name)));
+        let connecting = self
+            .inner
+            .lock("connect_with")
+            .connect(config, addr, server_name)?;
+        if let Some(reconnect) = reconnect {
+            connecting.reconnect_on_reset(reconnect);
+        }
+        Ok(connecting)
+    }
+
+    /// Connect to a remote endpoint using a custom configuration, giving up at `deadline`
+    ///
+    /// `deadline` is measured by the endpoint's [`Clock`]. See [`Connecting::with_timeout()`] for
+    /// details.
+    pub fn connect_with_deadline(
+        &self,
+        config: ClientConfig<S>,
+        addr: &T::Address,
+        server_name: &str,
+        deadline: Instant,
+    ) -> Result<Connecting<S, T>, ConnectError<T::Address>> {
+        Ok(self
+            .connect_with(config, addr, server_name)?
+            .with_deadline(deadline))
+    }
+
+    /// Connect to a remote endpoint with the first of several configurations that works
+    ///
+    /// Useful when servers differ in the versions or application protocols they support, e.g.
+    /// during a rollout. If `parallel` is set, all configurations are attempted at once and the
+    /// remaining attempts are abandoned as soon as one succeeds. Otherwise, each configuration is
+    /// attempted only after the previous one failed, in order.
+    ///
+    /// If every attempt fails, returns the error of the last one to do so.
+    ///
+    /// # Panics
+    ///
+    /// If `configs` is empty.
+    pub async fn connect_first<I>(
+        &self,
+        configs: I,
+        addr: &T::Address,
+        server_name: &str,
+        parallel: bool,
+    ) -> Result<NewConnection<S, T>, ConnectFirstError<T::Address>>
+    where
+        I: IntoIterator<Item = ClientConfig<S>>,
+    {
+        let addr = *addr;
+        let candidates = configs.into_iter().map(|config| (addr, config));
+        let (_, conn) = self.connect_any(candidates, server_name, parallel).await?;
+        Ok(conn)
+    }
+
+    /// Connect to the first of several candidate addresses that works, each with its own
+    /// configuration
+    ///
+    /// Useful when a remote is served by multiple sets of servers which differ in their
+    /// certificates or application protocols, e.g. IPv4 and IPv6 farms. Candidates are attempted
+    /// as by [`connect_first()`], and the index of the candidate whose connection succeeded is
+    /// returned along with it.
+    ///
+    /// If every attempt fails, returns the error of the last one to do so.
+    ///
+    /// # Panics
+    ///
+    /// If `candidates` is empty.
+    ///
+    /// [`connect_first()`]: Endpoint::connect_first
+    pub async fn connect_any<I>(
+        &self,
+        candidates: I,
+        server_name: &str,
+        parallel: bool,
+    ) -> Result<(usize, NewConnection<S, T>), ConnectFirstError<T::Address>>
+    where
+        I: IntoIterator<Item = (T::Address, ClientConfig<S>)>,
+    {
+        let mut last_error = None;
+        let mut pending = FuturesUnordered::new();
+        for (i, (addr, config)) in candidates.into_iter().enumerate() {
+            match self.connect_with(config, &addr, server_name) {
+                Ok(connecting) if parallel => pending.push(connecting.map(move |x| (i, x))),
+                Ok(connecting) => match connecting.await {
+                    Ok(conn) => return Ok((i, conn)),
+                    Err(e) => last_error = Some(e.into()),
+                },
+                Err(e) => last_error = Some(e.into()),
+            }
         }
-        if addr.is_ipv6() && !endpoint.ipv6 {
-            return Err(ConnectError::InvalidRemoteAddress(*addr));
+        while let Some((i, result)) = pending.next().await {
+            match result {
+                Ok(conn) => return Ok((i, conn)),
+                Err(e) => last_error = Some(e.into()),
+            }
         }
-        let addr = if endpoint.ipv6 {
-            SocketAddr::V6(ensure_ipv6(*addr))
-        } else {
-            *addr
-        };
-        let (ch, conn) = endpoint.inner.connect(config, addr, server_name)?;
-        Ok(endpoint.connections.insert(ch, conn))
+        Err(last_error.expect("no candidates to connect to"))
     }
 
     /// Switch to a new (UDP) socket
@@ -115,16 +240,171 @@ where
         EndpointError: From<<U as TryInto<T>>::Error>,
     {
         let socket = socket.try_into()?;
-        let addr = socket.local_addr()?;
-        let mut inner = self.inner.lock().unwrap();
-        inner.socket = socket;
-        inner.ipv6 = addr.is_ipv6();
+        self.inner.lock("rebind").socket = socket;
         Ok(())
     }
 
-    /// Get the local `SocketAddr` the underlying socket is bound to
-    pub fn local_addr(&self) -> io::Result<SocketAddr> {
-        self.inner.lock().unwrap().socket.local_addr()
+    /// Report the application's current memory usage in bytes to the admission controller
+    ///
+    /// Has no effect unless an [`AdmissionController`] was configured through
+    /// [`ServerConfigBuilder::admission_controller()`].
+    ///
+    /// [`AdmissionController`]: crate::AdmissionController
+    /// [`ServerConfigBuilder::admission_controller()`]: crate::generic::ServerConfigBuilder::admission_controller
+    pub fn set_memory_usage(&self, bytes: Option<u64>) {
+        self.inner
+            .lock("set_memory_usage")
+            .inner
+            .set_memory_usage(bytes);
+    }
+
+    /// Limit the rate at which data is sent, in bytes per second, across all connections
+    ///
+    /// Overrides [`EndpointBuilder::max_send_rate()`]. `None` lifts the limit.
+    ///
+    /// [`EndpointBuilder::max_send_rate()`]: crate::generic::EndpointBuilder::max_send_rate
+    pub fn set_max_send_rate(&self, rate: Option<NonZeroU64>) {
+        let endpoint = &mut *self.inner.lock("set_max_send_rate");
+        let now = endpoint.connections.clock.now();
+        match (rate, &mut endpoint.shaper) {
+            (Some(rate), Some(shaper)) => shaper.set_rate(rate, now),
+            (Some(rate), None) => endpoint.shaper = Some(Shaper::new(rate, now)),
+            (None, _) => endpoint.shaper = None,
+        }
+        if let Some(ref driver) = endpoint.driver {
+            driver.wake_by_ref();
+        }
+    }
+
+    /// The limit on the rate at which data is sent, in bytes per second, if any
+    pub fn max_send_rate(&self) -> Option<NonZeroU64> {
+        self.inner
+            .lock("max_send_rate")
+            .shaper
+            .as_ref()
+            .map(|x| x.rate())
+    }
+
+    /// Freeze the timers of all connections, e.g. before the device goes to sleep
+    ///
+    /// Mobile platforms may suspend the application for arbitrarily long periods, after which
+    /// expired idle and loss detection timers would otherwise fail connections that are still
+    /// healthy. Paused connections keep handling incoming packets, but take no action based on the
+    /// passage of time until [`resume()`] is called. Connections opened while paused aren't
+    /// affected.
+    ///
+    /// [`resume()`]: Endpoint::resume
+    pub fn pause(&self) {
+        self.broadcast(|| ConnectionEvent::Pause);
+    }
+
+    /// Unfreeze timers stopped by [`pause()`]
+    ///
+    /// Each connection immediately sends a PING and revalidates its path, so that a changed
+    /// network is noticed promptly and the peer learns that the connection is still alive.
+    ///
+    /// [`pause()`]: Endpoint::pause
+    pub fn resume(&self) {
+        self.broadcast(|| ConnectionEvent::Resume);
+    }
+
+    fn broadcast(&self, event: impl Fn() -> ConnectionEvent<T::Address>) {
+        let endpoint = self.inner.lock("broadcast");
+        for sender in endpoint.connections.senders.values() {
+            // Ignoring errors from dropped connections
+            let _ = sender.unbounded_send(event());
+        }
+    }
+
+    /// Number of connections on this endpoint, including those still handshaking or closing
+    pub fn connection_count(&self) -> usize {
+        self.inner.lock("connection_count").inner.open_connections()
+    }
+
+    /// Statistics aggregated across all of this endpoint's connections, past and present
+    pub fn stats(&self) -> EndpointStats {
+        self.inner.lock("stats").inner.stats()
+    }
+
+    /// Objects the endpoint and its connections are currently holding on to
+    ///
+    /// For checking that long-running applications don't leak connections, streams, buffers or
+    /// timers under churn.
+    #[cfg(feature = "debug_counters")]
+    pub fn debug_counters(&self) -> crate::DebugCounters {
+        let endpoint = self.inner.lock("debug_counters");
+        let buffers = endpoint.outgoing.len()
+            + endpoint.outgoing_bulk.len()
+            + endpoint.connections.buffers.len();
+        endpoint.connections.live.snapshot(buffers)
+    }
+
+    /// Time the endpoint's driver has spent on its receive and send paths so far
+    #[cfg(feature = "driver_counters")]
+    pub fn driver_counters(&self) -> crate::DriverCounters {
+        self.inner.lock("driver_counters").counters
+    }
+
+    /// Take a snapshot of [`stats()`] every `period`, e.g. for export to a monitoring system
+    ///
+    /// The first snapshot is taken one `period` from now, as measured by the endpoint's
+    /// [`Clock`]. Like any other handle, the stream keeps the endpoint running; it ends only if
+    /// the endpoint's driver stops.
+    ///
+    /// [`stats()`]: Endpoint::stats
+    pub fn stats_interval(&self, period: Duration) -> StatsInterval<S, T> {
+        let (next, timer) = {
+            let clock = &self.inner.lock("stats_interval").connections.clock;
+            let next = clock.now() + period;
+            (next, clock.new_timer(next))
+        };
+        StatsInterval {
+            endpoint: self.inner.clone(),
+            period,
+            next,
+            timer,
+        }
+    }
+
+    /// Handles to every established connection on this endpoint that hasn't been closed
+    ///
+    /// Useful for managing large numbers of connections, e.g. to close idle ones or collect
+    /// statistics. The handles don't keep their connections open; individual connections can be
+    /// found again later through their [`WeakConnection::stable_id()`] with [`find_connection()`].
+    ///
+    /// [`find_connection()`]: Endpoint::find_connection
+    pub fn connections(&self) -> Vec<WeakConnection<S, T>> {
+        let handles = self
+            .inner
+            .lock("connections")
+            .connections
+            .handles
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        // Connections are locked only after releasing the endpoint
+        handles
+            .into_iter()
+            .filter(WeakConnection::is_open)
+            .collect()
+    }
+
+    /// Look up an established connection by its [`Connection::stable_id()`]
+    ///
+    /// Returns `None` if no such connection exists on this endpoint, or if it has been closed.
+    pub fn find_connection(&self, stable_id: usize) -> Option<Connection<S, T>> {
+        let handle = self
+            .inner
+            .lock("find_connection")
+            .connections
+            .get(stable_id)?
+            .clone();
+        handle.upgrade()
+    }
+
+    /// Get the local address the underlying socket is bound to
+    pub fn local_addr(&self) -> io::Result<T::Address> {
+        self.inner.lock("local_addr").socket.local_addr()
     }
 
     /// Close all of this endpoint's connections immediately and cease accepting new connections.
@@ -134,7 +414,7 @@ where
     /// [`Connection::close()`]: crate::generic::Connection::close
     pub fn close(&self, error_code: VarInt, reason: &[u8]) {
         let reason = Bytes::copy_from_slice(reason);
-        let mut endpoint = self.inner.lock().unwrap();
+        let mut endpoint = self.inner.lock("close");
         endpoint.connections.close = Some((error_code, reason.clone()));
         for sender in endpoint.connections.senders.values() {
             // Ignoring errors from dropped connections
@@ -143,11 +423,66 @@ where
                 reason: reason.clone(),
             });
         }
-        if let Some(task) = endpoint.incoming_reader.take() {
-            task.wake();
+        endpoint.wake_readers();
+    }
+
+    /// Accept incoming connections matching `filter` using `config`, through a dedicated queue
+    ///
+    /// Allows one endpoint to host several applications, each with its own server configuration
+    /// and stream of incoming connections, selected by the server name and application protocols
+    /// requested by clients. Listeners take precedence over the server configuration the endpoint
+    /// was built with, whose connections are yielded by [`Incoming`], and over listeners created
+    /// later. Stops accepting connections once the returned [`Listener`] is dropped.
+    ///
+    /// [`Incoming`]: crate::generic::Incoming
+    pub fn listen(&self, filter: ListenerFilter, mut config: ServerConfig<S>) -> Listener<S, T> {
+        let id = {
+            let endpoint = &mut *self.inner.lock("listen");
+            if endpoint.connections.handshake_runtime.is_some()
+                || endpoint.connections.handshake_permits.is_some()
+            {
+                config.defer_handshake(true);
+            }
+            if endpoint.connections.anti_replay.is_some() {
+                config.defer_0rtt_data(true);
+            }
+            let id = endpoint.inner.add_listener(filter, Arc::new(config));
+            endpoint.listeners.insert(
+                id,
+                ListenerQueue {
+                    pending: VecDeque::new(),
+                    reader: None,
+                },
+            );
+            id
+        };
+        Listener {
+            endpoint: self.inner.clone(),
+            id,
         }
     }
 
+    /// Close the connection with the given [`Connection::stable_id()`] immediately
+    ///
+    /// Unlike [`find_connection()`], also applies to connections that are still handshaking. Has
+    /// the same effect as calling [`Connection::close()`] on a handle to the connection. Returns
+    /// whether such a connection was found.
+    ///
+    /// [`find_connection()`]: Endpoint::find_connection
+    pub fn close_connection(&self, stable_id: usize, error_code: VarInt, reason: &[u8]) -> bool {
+        let endpoint = self.inner.lock("close_connection");
+        let handle = match endpoint.connections.ids.get(&stable_id) {
+            Some(&handle) => handle,
+            None => return false,
+        };
+        endpoint.connections.senders[&handle]
+            .unbounded_send(ConnectionEvent::Close {
+                error_code,
+                reason: Bytes::copy_from_slice(reason),
+            })
+            .is_ok()
+    }
+
     /// Wait for all connections on the endpoint to be cleanly shut down
     ///
     /// Waiting for this condition before exiting ensures that a good-faith effort is made to notify
@@ -163,7 +498,7 @@ where
     pub async fn wait_idle(&self) {
         let mut state = broadcast::State::default();
         futures::future::poll_fn(|cx| {
-            let endpoint = &mut *self.inner.lock().unwrap();
+            let endpoint = &mut *self.inner.lock("wait_idle");
             if endpoint.connections.is_empty() {
                 return Poll::Ready(());
             }
@@ -172,6 +507,129 @@ where
         })
         .await;
     }
+
+    /// Drive I/O on the endpoint and its connections from a custom event loop
+    ///
+    /// Only needed for endpoints built with [`EndpointBuilder::spawn_drivers()`] disabled, which
+    /// spawn no tasks of their own. Does all the work that's currently possible, then returns
+    /// `Poll::Pending` and arranges for `cx` to be woken when there's more to do. Resolves with
+    /// an error if I/O on the socket fails, and with `Ok` once the endpoint has stopped, or
+    /// immediately if it drives itself.
+    ///
+    /// Timers are armed through the endpoint's [`Clock`], and I/O is performed through its
+    /// [`Socket`], so supplying custom implementations of both allows an application to drive
+    /// quinn entirely from its own event loop.
+    pub fn poll_drivers(&self, cx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let drivers = match self.drivers {
+            Some(ref x) => x,
+            None => return Poll::Ready(Ok(())),
+        };
+        let drivers = &mut *drivers.lock("poll_drivers");
+        let mut result = None;
+        if let Some(ref mut driver) = drivers.endpoint {
+            if let Poll::Ready(x) = Pin::new(driver).poll(cx) {
+                drivers.endpoint = None;
+                result = Some(x);
+            }
+        }
+        while let Poll::Ready(Some(driver)) = drivers.new.poll_next_unpin(cx) {
+            drivers.connections.push(driver);
+        }
+        while let Poll::Ready(Some(())) = drivers.connections.poll_next_unpin(cx) {}
+        match result {
+            Some(x) => Poll::Ready(x),
+            None if drivers.endpoint.is_none() && drivers.connections.is_empty() => {
+                Poll::Ready(Ok(()))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<S, T> Endpoint<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Wait for the endpoint to recreate its socket following a network change
+    ///
+    /// Resolves to the local address of the new socket the next time that happens after this is
+    /// called. Only happens if enabled through
+    /// [`EndpointBuilder::recreate_socket_on_network_error()`].
+    ///
+    /// [`EndpointBuilder::recreate_socket_on_network_error()`]: crate::generic::EndpointBuilder::recreate_socket_on_network_error
+    pub fn socket_recreated(&self) -> impl Future<Output = io::Result<T::Address>> + '_ {
+        let mut state = broadcast::State::default();
+        let generation = self.inner.lock("socket_recreated").socket_generation;
+        futures::future::poll_fn(move |cx| {
+            let endpoint = &mut *self.inner.lock("socket_recreated");
+            if endpoint.socket_generation != generation {
+                return Poll::Ready(endpoint.socket.local_addr());
+            }
+            endpoint.socket_recreated.register(cx, &mut state);
+            Poll::Pending
+        })
+    }
+
+    /// Wait for the endpoint's socket to fail
+    ///
+    /// Resolves to the next error reported after this is called, or immediately to the error that
+    /// stopped the endpoint if there was one. Errors which the endpoint recovers from are only
+    /// reported under [`SocketErrorPolicy::Surface`] and [`SocketErrorPolicy::Stop`].
+    pub fn socket_error(&self) -> impl Future<Output = SocketError> + '_ {
+        let mut state = broadcast::State::default();
+        let generation = self.inner.lock("socket_error").socket_errors.generation;
+        futures::future::poll_fn(move |cx| {
+            let errors = &mut self.inner.lock("socket_error").socket_errors;
+            if let Some(ref error) = errors.last {
+                if error.persistent || errors.generation != generation {
+                    return Poll::Ready(error.clone());
+                }
+            }
+            errors.reported.register(cx, &mut state);
+            Poll::Pending
+        })
+    }
+}
+
+impl<S> Endpoint<S, UdpSocket>
+where
+    S: proto::crypto::Session + 'static,
+{
+    /// Duplicate the endpoint's socket for handing over to another process
+    ///
+    /// The returned descriptor refers to the same bound socket and isn't closed on `exec`, so it
+    /// can be inherited by a child process or sent over a Unix domain socket. The receiving process
+    /// resumes serving with [`EndpointBuilder::from_raw_fd()`]. Both processes receive datagrams
+    /// from the socket until this endpoint is closed and dropped, so this should be done promptly
+    /// once the new process is ready; connections still open at that point are lost.
+    ///
+    /// The caller is responsible for closing the returned descriptor.
+    #[cfg(unix)]
+    pub fn into_raw_fd(self) -> io::Result<std::os::unix::io::RawFd> {
+        use std::os::unix::io::AsRawFd;
+        let fd = self.inner.lock("into_raw_fd").socket.as_raw_fd();
+        match unsafe { libc::dup(fd) } {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(fd),
+        }
+    }
+
+    /// Duplicate the endpoint's socket for handing over to another process
+    ///
+    /// The receiving process resumes serving with [`EndpointBuilder::from_raw_socket()`]. See
+    /// [`into_raw_fd()`](Self::into_raw_fd) for details.
+    #[cfg(windows)]
+    pub fn into_raw_socket(self) -> io::Result<std::os::windows::io::RawSocket> {
+        use std::{
+            mem::ManuallyDrop,
+            os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket},
+        };
+        let socket = self.inner.lock("into_raw_socket").socket.as_raw_socket();
+        // Borrow the socket without taking over ownership from the endpoint
+        let socket = ManuallyDrop::new(unsafe { std::net::UdpSocket::from_raw_socket(socket) });
+        Ok(socket.try_clone()?.into_raw_socket())
+    }
 }
 
 impl<S, T> Clone for Endpoint<S, T>
@@ -183,10 +641,85 @@ where
         Endpoint {
             inner: self.inner.clone(),
             default_client_config: self.default_client_config.clone(),
+            drivers: self.drivers.clone(),
+            reconnect: self.reconnect,
+        }
+    }
+}
+
+/// Drivers run by [`Endpoint::poll_drivers()`] instead of being spawned
+pub(crate) struct ManualDrivers<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Cleared once the endpoint driver has finished
+    endpoint: Option<EndpointDriver<S, T>>,
+    /// Drivers of new connections
+    new: mpsc::UnboundedReceiver<BoxFuture<'static, ()>>,
+    connections: FuturesUnordered<BoxFuture<'static, ()>>,
+}
+
+impl<S, T> ManualDrivers<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    pub(crate) fn new(
+        endpoint: EndpointDriver<S, T>,
+        new: mpsc::UnboundedReceiver<BoxFuture<'static, ()>>,
+    ) -> Self {
+        Self {
+            endpoint: Some(endpoint),
+            new,
+            connections: FuturesUnordered::new(),
+        }
+    }
+}
+
+impl<S, T> fmt::Debug for ManualDrivers<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManualDrivers")
+            .field("endpoint_running", &self.endpoint.is_some())
+            .field("connections", &self.connections.len())
+            .finish()
+    }
+}
+
+/// How an endpoint runs the tasks driving its connections
+#[derive(Clone)]
+pub(crate) enum Spawner {
+    /// Spawn them on the endpoint's runtime
+    Runtime(Arc<dyn Runtime>),
+    /// Hand them over to [`Endpoint::poll_drivers()`]
+    Manual(mpsc::UnboundedSender<BoxFuture<'static, ()>>),
+}
+
+impl Spawner {
+    pub(crate) fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+        match *self {
+            Spawner::Runtime(ref runtime) => runtime.spawn(Box::pin(future)),
+            Spawner::Manual(ref sender) => {
+                // Fails only if every `Endpoint` is gone, and with them any way to drive I/O
+                let _ = sender.unbounded_send(Box::pin(future));
+            }
         }
     }
 }
 
+impl fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            Spawner::Runtime(_) => "Runtime",
+            Spawner::Manual(_) => "Manual",
+        })
+    }
+}
+
 /// A future that drives IO on an endpoint
 ///
 /// This task functions as the switch point between the UDP socket object and the
@@ -212,16 +745,25 @@ where
 
     #[allow(unused_mut)] // MSRV
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
-        let endpoint = &mut *self.0.lock().unwrap();
+        let endpoint = &mut *self.0.lock("poll");
         if endpoint.driver.is_none() {
             endpoint.driver = Some(cx.waker().clone());
         }
         loop {
-            let now = Instant::now();
+            let now = endpoint.connections.clock.now();
             let mut keep_going = false;
+            #[cfg(feature = "driver_counters")]
+            let start = Instant::now();
             keep_going |= endpoint.drive_recv(cx, now)?;
+            #[cfg(feature = "driver_counters")]
+            endpoint.counters.record_recv(start);
             endpoint.handle_events(cx);
+            #[cfg(feature = "driver_counters")]
+            let start = Instant::now();
             keep_going |= endpoint.drive_send(cx)?;
+            #[cfg(feature = "driver_counters")]
+            endpoint.counters.record_send(start);
+            endpoint.handle_packet_too_big();
             if !keep_going {
                 break;
             }
@@ -245,14 +787,13 @@ where
     T: Socket,
 {
     fn drop(&mut self) {
-        let mut endpoint = self.0.lock().unwrap();
+        let mut endpoint = self.0.lock("drop");
         endpoint.driver_lost = true;
-        if let Some(task) = endpoint.incoming_reader.take() {
-            task.wake();
-        }
+        endpoint.wake_readers();
         // Drop all outgoing channels, signaling the termination of the endpoint to the associated
         // connections.
         endpoint.connections.senders.clear();
+        endpoint.connections.handles.clear();
     }
 }
 
@@ -263,19 +804,55 @@ where
     T: Socket,
 {
     socket: T,
-    inner: proto::generic::Endpoint<S>,
-    outgoing: VecDeque<proto::Transmit>,
-    incoming: VecDeque<Connecting<S, T>>,
+    inner: proto::generic::Endpoint<S, T::Address>,
+    /// Transmits of the endpoint itself and of `PriorityClass::Interactive` connections
+    outgoing: VecDeque<proto::Transmit<T::Address>>,
+    /// Transmits of `PriorityClass::Bulk` connections, only sent while `outgoing` is empty
+    outgoing_bulk: VecDeque<proto::Transmit<T::Address>>,
+    incoming: IncomingQueue<S, T>,
     incoming_reader: Option<Waker>,
+    listeners: FxHashMap<ListenerId, ListenerQueue<S, T>>,
+    /// Listener for connections answered by the built-in health check responder
+    health_check: Option<ListenerId>,
     driver: Option<Waker>,
-    ipv6: bool,
-    connections: ConnectionSet,
-    events: mpsc::UnboundedReceiver<(ConnectionHandle, EndpointEvent)>,
+    connections: ConnectionSet<S, T>,
+    events: mpsc::UnboundedReceiver<(ConnectionHandle, EndpointEvent<T::Address>)>,
     /// Number of live handles that can be used to initiate or handle I/O; excludes the driver
     ref_count: usize,
     driver_lost: bool,
     recv_buf: Box<[u8]>,
+    recv_meta: Box<[RecvMeta<T::Address>]>,
     idle: Broadcast,
+    /// Whether to recreate the socket when sends persistently fail due to network changes
+    recreate_socket: bool,
+    /// Number of consecutive sends that failed due to network changes
+    network_errors: u32,
+    /// Number of times the socket has been recreated
+    socket_generation: u64,
+    socket_recreated: Broadcast,
+    /// Limit on the rate at which data is sent, if any
+    shaper: Option<Shaper>,
+    socket_errors: SocketErrors,
+    #[cfg(feature = "driver_counters")]
+    counters: crate::DriverCounters,
+}
+
+impl<S, T> EndpointInner<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Wake up tasks waiting for incoming connections, e.g. to notice that there will be no more
+    fn wake_readers(&mut self) {
+        if let Some(task) = self.incoming_reader.take() {
+            task.wake();
+        }
+        for listener in self.listeners.values_mut() {
+            if let Some(task) = listener.reader.take() {
+                task.wake();
+            }
+        }
+    }
 }
 
 impl<S, T> EndpointInner<S, T>
@@ -285,7 +862,6 @@ where
 {
     fn drive_recv<'a>(&'a mut self, cx: &mut Context, now: Instant) -> Result<bool, io::Error> {
         let mut recvd = 0;
-        let mut metas = [RecvMeta::default(); BATCH_SIZE];
         let mut iovs = MaybeUninit::<[IoSliceMut<'a>; BATCH_SIZE]>::uninit();
         self.recv_buf
             .chunks_mut(self.recv_buf.len() / BATCH_SIZE)
@@ -298,18 +874,45 @@ where
             });
         let mut iovs = unsafe { iovs.assume_init() };
         loop {
-            match self.socket.poll_recv(cx, &mut iovs, &mut metas) {
+            match self.socket.poll_recv(cx, &mut iovs, &mut self.recv_meta) {
                 Poll::Ready(Ok(msgs)) => {
                     recvd += msgs;
-                    for (meta, buf) in metas.iter().zip(iovs.iter()).take(msgs) {
+                    #[cfg(feature = "driver_counters")]
+                    {
+                        self.counters.datagrams_received += msgs as u64;
+                    }
+                    for (meta, buf) in self.recv_meta.iter().zip(iovs.iter()).take(msgs) {
                         let data = buf[0..meta.len].into();
                         match self
                             .inner
                             .handle(now, meta.addr, meta.dst_ip, meta.ecn, data)
                         {
+                            Some((handle, DatagramEvent::NewConnection(conn)))
+                                if conn.listener().is_some()
+                                    && conn.listener() == self.health_check =>
+                            {
+                                let conn = self.connections.insert(handle, conn);
+                                let spawner = &self.connections.spawner;
+                                spawner.spawn(health::respond(conn, spawner.clone()));
+                            }
                             Some((handle, DatagramEvent::NewConnection(conn))) => {
+                                let listener = match conn.listener() {
+                                    Some(id) => self.listeners.get_mut(&id),
+                                    None => None,
+                                };
                                 let conn = self.connections.insert(handle, conn);
-                                self.incoming.push_back(conn);
+                                match listener {
+                                    Some(listener) => {
+                                        listener.pending.push_back(conn);
+                                        if let Some(task) = listener.reader.take() {
+                                            task.wake();
+                                        }
+                                    }
+                                    None => {
+                                        let source = meta.addr.socket_addr().map(|x| x.ip());
+                                        self.incoming.push(source, conn);
+                                    }
+                                }
                             }
                             Some((handle, DatagramEvent::ConnectionEvent(event))) => {
                                 // Ignoring errors from dropped connections that haven't yet been cleaned up
@@ -327,13 +930,19 @@ where
                 Poll::Pending => {
                     break;
                 }
-                // Ignore ECONNRESET as it's undefined in QUIC and may be injected by an
-                // attacker
-                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::ConnectionReset => {
-                    continue;
+                // Interrupted by a signal; try again
+                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {
+                    recvd += 1;
+                }
+                // Notably ECONNRESET, which is undefined in QUIC and may be injected by an attacker
+                Poll::Ready(Err(e)) if self.socket_errors.tolerates(&e) => {
+                    self.socket_errors.transient(SocketOperation::Recv, e);
+                    // Failures count towards the bound too, lest a lasting condition spin the
+                    // driver
+                    recvd += 1;
                 }
                 Poll::Ready(Err(e)) => {
-                    return Err(e);
+                    return Err(self.socket_errors.persistent(SocketOperation::Recv, e));
                 }
             }
             if recvd >= IO_LOOP_BOUND {
@@ -352,12 +961,34 @@ where
                     None => break,
                 }
             }
-            if self.outgoing.is_empty() {
+            if self.outgoing.is_empty() && self.outgoing_bulk.is_empty() {
                 return Ok(false);
             }
-            match self.socket.poll_send(cx, self.outgoing.as_mut_slices().0) {
+            // While the socket can't keep up, bulk transmits queue up behind interactive ones
+            let queue = match self.outgoing.is_empty() {
+                false => &mut self.outgoing,
+                true => &mut self.outgoing_bulk,
+            };
+            let mut batch = queue.as_mut_slices().0;
+            if let Some(ref mut shaper) = self.shaper {
+                let now = self.connections.clock.now();
+                let admitted = shaper.admit(now, batch.iter().map(|t| t.contents.len()));
+                if admitted == 0 {
+                    return Ok(shaper.poll_ready(cx, &*self.connections.clock));
+                }
+                batch = &mut batch[..admitted];
+            }
+            match self.socket.poll_send(cx, batch) {
                 Poll::Ready(Ok(n)) => {
-                    self.outgoing.drain(..n);
+                    if let Some(ref mut shaper) = self.shaper {
+                        shaper.consume(queue.iter().take(n).map(|t| t.contents.len()).sum());
+                    }
+                    self.connections.buffers.put_all(queue.drain(..n));
+                    #[cfg(feature = "driver_counters")]
+                    {
+                        self.counters.transmits_sent += n as u64;
+                    }
+                    self.network_errors = 0;
                     calls += 1;
                     if calls == IO_LOOP_BOUND {
                         return Ok(true);
@@ -366,16 +997,110 @@ where
                 Poll::Pending => {
                     return Ok(false);
                 }
-                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::PermissionDenied => {
-                    return Ok(false);
+                Poll::Ready(Err(ref e)) if self.recreate_socket && is_network_change(e) => {
+                    debug!("send failed: {}", e);
+                    // These datagrams can't be delivered from this socket; loss recovery will
+                    // retransmit as needed
+                    self.outgoing.clear();
+                    self.outgoing_bulk.clear();
+                    self.network_errors += 1;
+                    if self.network_errors < NETWORK_ERROR_THRESHOLD {
+                        return Ok(false);
+                    }
+                    self.network_errors = 0;
+                    // Keep driving so the new socket is polled for incoming datagrams
+                    return Ok(self.recreate_socket());
+                }
+                // Interrupted by a signal before anything was sent; try again
+                Poll::Ready(Err(ref e)) if e.kind() == io::ErrorKind::Interrupted => {
+                    calls += 1;
+                    if calls == IO_LOOP_BOUND {
+                        return Ok(true);
+                    }
+                }
+                Poll::Ready(Err(e)) if self.socket_errors.tolerates(&e) => {
+                    // Drop the datagram that failed and carry on with the rest; loss recovery will
+                    // retransmit as needed
+                    self.connections
+                        .buffers
+                        .put_all(queue.pop_front().into_iter());
+                    self.socket_errors.transient(SocketOperation::Send, e);
+                    calls += 1;
+                    if calls == IO_LOOP_BOUND {
+                        return Ok(true);
+                    }
                 }
                 Poll::Ready(Err(e)) => {
-                    return Err(e);
+                    return Err(self.socket_errors.persistent(SocketOperation::Send, e));
                 }
             }
         }
     }
 
+    fn connect(
+        &mut self,
+        config: ClientConfig<S>,
+        addr: &T::Address,
+        server_name: &str,
+    ) -> Result<Connecting<S, T>, ConnectError<T::Address>> {
+        if self.driver_lost {
+            return Err(ConnectError::EndpointStopping);
+        }
+        let addr = self
+            .socket
+            .map_remote(*addr)
+            .ok_or(ConnectError::InvalidRemoteAddress(*addr))?;
+        let now = self.connections.clock.now();
+        let (ch, conn) = self.inner.connect(now, config, addr, server_name)?;
+        Ok(self.connections.insert(ch, conn))
+    }
+
+    /// Answer connections requesting `protocol` with the built-in health check responder
+    pub(crate) fn set_health_check(&mut self, protocol: &[u8], config: ServerConfig<S>) {
+        let mut filter = ListenerFilter::default();
+        filter.protocol(protocol);
+        self.health_check = Some(self.inner.add_listener(filter, Arc::new(config)));
+    }
+
+    /// Hand the drivers of future connections over to the returned receiver instead of spawning
+    /// them
+    pub(crate) fn drive_manually(&mut self) -> mpsc::UnboundedReceiver<BoxFuture<'static, ()>> {
+        let (send, recv) = mpsc::unbounded();
+        self.connections.spawner = Spawner::Manual(send);
+        recv
+    }
+
+    /// Replace the socket following a network change, returning whether that succeeded
+    fn recreate_socket(&mut self) -> bool {
+        let socket = match self.socket.recreate() {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("failed to recreate socket: {}", e);
+                return false;
+            }
+        };
+        debug!(addr = ?socket.local_addr().ok(), "recreated socket");
+        self.socket = socket;
+        self.socket_generation += 1;
+        self.socket_recreated.wake();
+        for sender in self.connections.senders.values() {
+            // Ignoring errors from dropped connections that haven't yet been cleaned up
+            let _ = sender.unbounded_send(ConnectionEvent::SocketRecreated);
+        }
+        true
+    }
+
+    fn handle_packet_too_big(&mut self) {
+        while let Some(report) = self.socket.take_packet_too_big() {
+            // Connections may have migrated away from the addresses known to the endpoint, so let
+            // each one check whether the report concerns its current path. Such reports are rare.
+            for sender in self.connections.senders.values_mut() {
+                // Ignoring errors from dropped connections that haven't yet been cleaned up
+                let _ = sender.unbounded_send(ConnectionEvent::PacketTooBig(report));
+            }
+        }
+    }
+
     fn handle_events(&mut self, cx: &mut Context) {
         use EndpointEvent::*;
         loop {
@@ -383,7 +1108,7 @@ where
                 Poll::Ready(Some((ch, event))) => match event {
                     Proto(e) => {
                         if e.is_drained() {
-                            self.connections.senders.remove(&ch);
+                            self.connections.remove(ch);
                             if self.connections.is_empty() {
                                 self.idle.wake();
                             }
@@ -398,7 +1123,8 @@ where
                                 .unbounded_send(ConnectionEvent::Proto(event));
                         }
                     }
-                    Transmit(t) => self.outgoing.push_back(t),
+                    Transmit(t, PriorityClass::Interactive) => self.outgoing.push_back(t),
+                    Transmit(t, PriorityClass::Bulk) => self.outgoing_bulk.push_back(t),
                 },
                 Poll::Ready(None) => unreachable!("EndpointInner owns one sender"),
                 Poll::Pending => {
@@ -410,20 +1136,48 @@ where
 }
 
 #[derive(Debug)]
-struct ConnectionSet {
+struct ConnectionSet<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
     /// Senders for communicating with the endpoint's connections
-    senders: FxHashMap<ConnectionHandle, mpsc::UnboundedSender<ConnectionEvent>>,
+    senders: FxHashMap<ConnectionHandle, mpsc::UnboundedSender<ConnectionEvent<T::Address>>>,
+    /// Handles to the endpoint's connections which don't keep them alive
+    handles: FxHashMap<ConnectionHandle, WeakConnection<S, T>>,
+    /// Handles of the endpoint's connections by [`Connection::stable_id()`]
+    ids: FxHashMap<usize, ConnectionHandle>,
     /// Stored to give out clones to new ConnectionInners
-    sender: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
+    sender: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent<T::Address>)>,
     /// Set if the endpoint has been manually closed
     close: Option<(VarInt, Bytes)>,
+    /// Where to drive connections while they're handshaking, if not on the current runtime
+    handshake_runtime: Option<HandshakeRuntime>,
+    /// Limits the number of incoming connections handshaking at once, if configured
+    handshake_permits: Option<Arc<Semaphore>>,
+    /// Source of time for the endpoint and its connections
+    clock: Arc<dyn Clock>,
+    /// Buffers of sent transmits, for connections to reuse
+    buffers: BufferPool,
+    /// Runs connection drivers
+    spawner: Spawner,
+    /// Checked for replays of incoming connections' 0-RTT data, if configured
+    anti_replay: Option<Arc<dyn AntiReplay>>,
+    /// Told of panics in connection drivers, if configured
+    on_driver_panic: Option<PanicHook>,
+    #[cfg(feature = "debug_counters")]
+    live: Arc<LiveCounts>,
 }
 
-impl ConnectionSet {
-    fn insert<S: proto::crypto::Session + 'static, T: Socket>(
+impl<S, T> ConnectionSet<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    fn insert(
         &mut self,
         handle: ConnectionHandle,
-        conn: proto::generic::Connection<S>,
+        conn: proto::generic::Connection<S, T::Address>,
     ) -> Connecting<S, T> {
         let (send, recv) = mpsc::unbounded();
         if let Some((error_code, ref reason)) = self.close {
@@ -434,7 +1188,37 @@ impl ConnectionSet {
             .unwrap();
         }
         self.senders.insert(handle, send);
-        Connecting::new(handle, conn, self.sender.clone(), recv)
+        let conn = Connecting::new(
+            handle,
+            conn,
+            self.sender.clone(),
+            recv,
+            self.handshake_runtime.as_ref(),
+            self.handshake_permits.as_ref(),
+            &self.spawner,
+            self.clock.clone(),
+            self.buffers.clone(),
+            self.anti_replay.as_ref(),
+            self.on_driver_panic.as_ref(),
+        );
+        #[cfg(feature = "debug_counters")]
+        conn.track(&self.live);
+        let weak = conn.downgrade();
+        self.ids.insert(weak.stable_id(), handle);
+        self.handles.insert(handle, weak);
+        conn
+    }
+
+    fn remove(&mut self, handle: ConnectionHandle) {
+        self.senders.remove(&handle);
+        if let Some(weak) = self.handles.remove(&handle) {
+            self.ids.remove(&weak.stable_id());
+        }
+    }
+
+    /// The connection with the given [`Connection::stable_id()`], if it's on this endpoint
+    fn get(&self, stable_id: usize) -> Option<&WeakConnection<S, T>> {
+        self.handles.get(self.ids.get(&stable_id)?)
     }
 
     fn is_empty(&self) -> bool {
@@ -442,10 +1226,58 @@ impl ConnectionSet {
     }
 }
 
-fn ensure_ipv6(x: SocketAddr) -> SocketAddrV6 {
-    match x {
-        SocketAddr::V6(x) => x,
-        SocketAddr::V4(x) => SocketAddrV6::new(x.ip().to_ipv6_mapped(), x.port(), 0, 0),
+/// Connections awaiting acceptance by the application
+#[derive(Debug)]
+struct IncomingQueue<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Whether to take connections round-robin across source IPs, rather than in arrival order
+    ///
+    /// Connections over transports which don't use IP addresses are always taken in order.
+    fair: bool,
+    /// Sources with pending connections, in the order they'll be served; `None` when not `fair`
+    sources: VecDeque<Option<IpAddr>>,
+    pending: FxHashMap<Option<IpAddr>, VecDeque<Connecting<S, T>>>,
+}
+
+impl<S, T> IncomingQueue<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn new(fair: bool) -> Self {
+        Self {
+            fair,
+            sources: VecDeque::new(),
+            pending: FxHashMap::default(),
+        }
+    }
+
+    fn push(&mut self, source: Option<IpAddr>, conn: Connecting<S, T>) {
+        let source = if self.fair { source } else { None };
+        let queue = self.pending.entry(source).or_default();
+        if queue.is_empty() {
+            self.sources.push_back(source);
+        }
+        queue.push_back(conn);
+    }
+
+    fn pop(&mut self) -> Option<Connecting<S, T>> {
+        let source = self.sources.pop_front()?;
+        let queue = self.pending.get_mut(&source).unwrap();
+        let conn = queue.pop_front();
+        if queue.is_empty() {
+            self.pending.remove(&source);
+        } else {
+            self.sources.push_back(source);
+        }
+        conn
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sources.is_empty()
     }
 }
 
@@ -472,10 +1304,10 @@ where
 
     #[allow(unused_mut)] // MSRV
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        let endpoint = &mut *self.0.lock().unwrap();
+        let endpoint = &mut *self.0.lock("poll_next");
         if endpoint.driver_lost {
             Poll::Ready(None)
-        } else if let Some(conn) = endpoint.incoming.pop_front() {
+        } else if let Some(conn) = endpoint.incoming.pop() {
             Poll::Ready(Some(conn))
         } else if endpoint.connections.close.is_some() {
             Poll::Ready(None)
@@ -492,12 +1324,114 @@ where
     T: Socket,
 {
     fn drop(&mut self) {
-        let endpoint = &mut *self.0.lock().unwrap();
+        let endpoint = &mut *self.0.lock("drop");
         endpoint.inner.reject_new_connections();
         endpoint.incoming_reader = None;
     }
 }
 
+/// A stream of incoming connections accepted through a listener
+///
+/// Created by [`Endpoint::listen()`].
+#[derive(Debug)]
+pub struct Listener<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    endpoint: EndpointRef<S, T>,
+    id: ListenerId,
+}
+
+impl<S, T> futures::Stream for Listener<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Item = Connecting<S, T>;
+
+    #[allow(unused_mut)] // MSRV
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let endpoint = &mut *self.endpoint.lock("poll_next");
+        let listener = endpoint.listeners.get_mut(&self.id).unwrap();
+        if endpoint.driver_lost {
+            Poll::Ready(None)
+        } else if let Some(conn) = listener.pending.pop_front() {
+            Poll::Ready(Some(conn))
+        } else if endpoint.connections.close.is_some() {
+            Poll::Ready(None)
+        } else {
+            listener.reader = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<S, T> Drop for Listener<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn drop(&mut self) {
+        let listener = {
+            let endpoint = &mut *self.endpoint.lock("drop");
+            endpoint.inner.remove_listener(self.id);
+            endpoint.listeners.remove(&self.id)
+        };
+        // Dropping connections nobody will accept closes them, so do so without holding the lock
+        drop(listener);
+    }
+}
+
+/// A stream of periodic snapshots of an endpoint's statistics
+///
+/// Created by [`Endpoint::stats_interval()`].
+#[derive(Debug)]
+pub struct StatsInterval<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    endpoint: EndpointRef<S, T>,
+    period: Duration,
+    /// When the next snapshot is due
+    next: Instant,
+    timer: Pin<Box<dyn AsyncTimer>>,
+}
+
+impl<S, T> futures::Stream for StatsInterval<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Item = EndpointStats;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.timer.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        this.next += this.period;
+        this.timer.as_mut().reset(this.next);
+        let endpoint = this.endpoint.lock("poll_next");
+        if endpoint.driver_lost {
+            return Poll::Ready(None);
+        }
+        Poll::Ready(Some(endpoint.inner.stats()))
+    }
+}
+
+/// Connections awaiting acceptance through a [`Listener`]
+#[derive(Debug)]
+struct ListenerQueue<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    pending: VecDeque<Connecting<S, T>>,
+    reader: Option<Waker>,
+}
+
 #[derive(Debug)]
 pub(crate) struct EndpointRef<S: proto::crypto::Session, T: Socket>(
     Arc<Mutex<EndpointInner<S, T>>>,
@@ -508,28 +1442,80 @@ where
     S: proto::crypto::Session,
     T: Socket,
 {
-    pub(crate) fn new(socket: T, inner: proto::generic::Endpoint<S>, ipv6: bool) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        socket: T,
+        inner: proto::generic::Endpoint<S, T::Address>,
+        local_addr: T::Address,
+        handshake_runtime: Option<HandshakeRuntime>,
+        handshake_permits: Option<Arc<Semaphore>>,
+        recreate_socket: bool,
+        fair_accept: bool,
+        clock: Arc<dyn Clock>,
+        max_send_rate: Option<NonZeroU64>,
+        socket_error_policy: SocketErrorPolicy,
+        anti_replay: Option<Arc<dyn AntiReplay>>,
+        on_driver_panic: Option<PanicHook>,
+        runtime: Arc<dyn Runtime>,
+    ) -> Self {
         let recv_buf =
             vec![0; inner.config().get_max_udp_payload_size().min(64 * 1024) as usize * BATCH_SIZE];
         let (sender, events) = mpsc::unbounded();
+        let shaper = max_send_rate.map(|rate| Shaper::new(rate, clock.now()));
+        #[cfg(feature = "debug_counters")]
+        let live = Arc::new(LiveCounts::default());
+        #[cfg(feature = "debug_counters")]
+        let clock: Arc<dyn Clock> = Arc::new(CountingClock::new(clock, live.clone()));
         Self(Arc::new(Mutex::new(EndpointInner {
             socket,
             inner,
-            ipv6,
             events,
             outgoing: VecDeque::new(),
-            incoming: VecDeque::new(),
+            outgoing_bulk: VecDeque::new(),
+            incoming: IncomingQueue::new(fair_accept),
             incoming_reader: None,
+            listeners: FxHashMap::default(),
+            health_check: None,
             driver: None,
             connections: ConnectionSet {
                 senders: FxHashMap::default(),
+                handles: FxHashMap::default(),
+                ids: FxHashMap::default(),
                 sender,
                 close: None,
+                handshake_runtime,
+                handshake_permits,
+                clock,
+                buffers: BufferPool::new(),
+                spawner: Spawner::Runtime(runtime),
+                anti_replay,
+                on_driver_panic,
+                #[cfg(feature = "debug_counters")]
+                live,
             },
             ref_count: 0,
             driver_lost: false,
             recv_buf: recv_buf.into(),
+            // Placeholders, overwritten by the socket on receipt
+            recv_meta: vec![
+                RecvMeta {
+                    addr: local_addr,
+                    len: 0,
+                    ecn: None,
+                    dst_ip: None,
+                };
+                BATCH_SIZE
+            ]
+            .into(),
             idle: Broadcast::new(),
+            recreate_socket,
+            network_errors: 0,
+            socket_generation: 0,
+            socket_recreated: Broadcast::new(),
+            shaper,
+            socket_errors: SocketErrors::new(socket_error_policy),
+            #[cfg(feature = "driver_counters")]
+            counters: crate::DriverCounters::default(),
         })))
     }
 }
@@ -540,7 +1526,7 @@ where
     T: Socket,
 {
     fn clone(&self) -> Self {
-        self.0.lock().unwrap().ref_count += 1;
+        self.0.lock("clone").ref_count += 1;
         Self(self.0.clone())
     }
 }
@@ -551,7 +1537,7 @@ where
     T: Socket,
 {
     fn drop(&mut self) {
-        let endpoint = &mut *self.0.lock().unwrap();
+        let endpoint = &mut *self.0.lock("drop");
         if let Some(x) = endpoint.ref_count.checked_sub(1) {
             endpoint.ref_count = x;
             if x == 0 {
@@ -575,3 +1561,213 @@ where
         &self.0
     }
 }
+
+/// What's needed to repeat an outgoing connection attempt
+pub(crate) struct Reconnect<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    endpoint: Weak<Mutex<EndpointInner<S, T>>>,
+    config: ClientConfig<S>,
+    addr: T::Address,
+    server_name: String,
+}
+
+impl<S, T> Reconnect<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    pub(crate) fn new(
+        endpoint: &Endpoint<S, T>,
+        config: ClientConfig<S>,
+        addr: &T::Address,
+        server_name: &str,
+    ) -> Self {
+        Self {
+            endpoint: Arc::downgrade(&endpoint.inner.0),
+            config,
+            addr: *addr,
+            server_name: server_name.into(),
+        }
+    }
+
+    /// Start a new connection like the original, unless the endpoint is gone
+    pub(crate) fn start(&self) -> Result<Connecting<S, T>, ConnectError<T::Address>> {
+        let endpoint = self
+            .endpoint
+            .upgrade()
+            .ok_or(ConnectError::EndpointStopping)?;
+        let mut endpoint = endpoint.lock("start");
+        endpoint.connect(self.config.clone(), &self.addr, &self.server_name)
+    }
+
+    /// Convert into a form that connections can hold on to
+    pub(crate) fn erase(self) -> Arc<dyn Restart<S, T>>
+    where
+        S::ClientConfig: Send + Sync,
+    {
+        Arc::new(self)
+    }
+}
+
+/// A [`Reconnect`] whose client configuration is known to be `Send` and `Sync`
+///
+/// Connections must be `Send`, which the client configurations of arbitrary crypto protocols
+/// needn't be, so only the endpoints which reconnect require it, through [`EraseReconnect`].
+pub(crate) trait Restart<S, T>: Send + Sync
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn start(&self) -> Result<Connecting<S, T>, ConnectError<T::Address>>;
+}
+
+impl<S, T> Restart<S, T> for Reconnect<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    S::ClientConfig: Send + Sync,
+    T: Socket,
+{
+    fn start(&self) -> Result<Connecting<S, T>, ConnectError<T::Address>> {
+        Reconnect::start(self)
+    }
+}
+
+/// [`Reconnect::erase`] for a particular session and socket type
+pub(crate) type EraseReconnect<S, T> = fn(Reconnect<S, T>) -> Arc<dyn Restart<S, T>>;
+
+impl<S, T> fmt::Debug for Reconnect<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reconnect")
+            .field("addr", &self.addr)
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+/// Socket errors reported to the application
+#[derive(Debug)]
+struct SocketErrors {
+    policy: SocketErrorPolicy,
+    /// The most recently reported error
+    last: Option<SocketError>,
+    /// Number of errors reported so far
+    generation: u64,
+    reported: Broadcast,
+}
+
+impl SocketErrors {
+    fn new(policy: SocketErrorPolicy) -> Self {
+        Self {
+            policy,
+            last: None,
+            generation: 0,
+            reported: Broadcast::new(),
+        }
+    }
+
+    /// Whether the endpoint carries on after `error`
+    fn tolerates(&self, error: &io::Error) -> bool {
+        is_transient(error) || (self.policy != SocketErrorPolicy::Stop && is_network_change(error))
+    }
+
+    fn transient(&mut self, operation: SocketOperation, error: io::Error) {
+        debug!("failed to {} on socket: {}", operation, error);
+        if self.policy != SocketErrorPolicy::Retry {
+            self.report(SocketError {
+                operation,
+                persistent: false,
+                error: Arc::new(error),
+            });
+        }
+    }
+
+    /// Report an error that stops the endpoint, returning it for the driver to fail with
+    fn persistent(&mut self, operation: SocketOperation, error: io::Error) -> io::Error {
+        let kind = error.kind();
+        let error = SocketError {
+            operation,
+            persistent: true,
+            error: Arc::new(error),
+        };
+        self.report(error.clone());
+        io::Error::new(kind, error)
+    }
+
+    fn report(&mut self, error: SocketError) {
+        self.last = Some(error);
+        self.generation += 1;
+        self.reported.wake();
+    }
+}
+
+/// Number of consecutive sends that must fail due to network changes before the socket is recreated
+const NETWORK_ERROR_THRESHOLD: u32 = 3;
+
+/// Errors from [`Endpoint::connect_first()`] and [`Endpoint::connect_any()`], describing why the
+/// last attempt failed
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ConnectFirstError<A: Address> {
+    /// The connection couldn't be started with the last configuration or candidate
+    #[error(transparent)]
+    Connect(#[from] ConnectError<A>),
+    /// The last connection attempt failed
+    #[error(transparent)]
+    Connection(#[from] ConnectionError),
+}
+
+/// How an endpoint handles socket errors which don't prevent further I/O from succeeding
+///
+/// Such transient errors include ICMP errors concerning earlier datagrams, datagrams refused by a
+/// local firewall, exhausted socket buffers, and network changes. Datagrams which failed to send
+/// are dropped if the endpoint carries on, to be retransmitted by loss recovery if need be. Any
+/// other error is persistent: it stops the endpoint, and is always reported through
+/// [`Endpoint::socket_error()`]. Calls interrupted by a signal are retried regardless.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SocketErrorPolicy {
+    /// Carry on after logging the error
+    Retry,
+    /// Carry on, reporting the error through [`Endpoint::socket_error()`]
+    Surface,
+    /// Treat network changes, e.g. the interface the socket is bound to going down, as persistent
+    ///
+    /// Suits applications which would rather rebind than wait for the network to recover. Other
+    /// transient errors are handled as under `Surface`.
+    Stop,
+}
+
+/// A failed operation on an endpoint's socket, as reported by [`Endpoint::socket_error()`]
+#[derive(Debug, Error, Clone)]
+#[error("failed to {operation} on socket: {error}")]
+pub struct SocketError {
+    /// The operation that failed
+    pub operation: SocketOperation,
+    /// Whether the error stopped the endpoint
+    pub persistent: bool,
+    /// The error reported by the socket
+    pub error: Arc<io::Error>,
+}
+
+/// An operation on an endpoint's socket
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SocketOperation {
+    /// Sending datagrams
+    Send,
+    /// Receiving datagrams
+    Recv,
+}
+
+impl fmt::Display for SocketOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            SocketOperation::Send => "send",
+            SocketOperation::Recv => "receive",
+        })
+    }
+}
@@ -0,0 +1,399 @@
+//! Implementations of the [`h3`](::h3) crate's QUIC transport traits
+//!
+//! Enabled by the `h3` feature. An established [`NewConnection`] implements
+//! `h3::quic::Connection`, so it can be passed directly to `h3::server::Connection::new()` or
+//! `h3::client::new()` to run HTTP/3 over it. Incoming unidirectional streams and both halves of
+//! incoming bidirectional streams must not be consumed elsewhere while HTTP/3 is in charge of the
+//! connection.
+
+use std::{
+    convert::TryFrom,
+    fmt,
+    task::{Context, Poll},
+};
+
+use ::h3::quic::{self, StreamId as H3StreamId, WriteBuf};
+use bytes::{Buf, Bytes};
+use futures::{ready, FutureExt, StreamExt};
+use thiserror::Error;
+
+use crate::{
+    connection::{Connection, NewConnection},
+    recv_stream::{ReadError, RecvStream},
+    send_stream::WriteError,
+    transport::Socket,
+    StreamId, VarInt,
+};
+
+impl<B, S, T> quic::Connection<B> for NewConnection<S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type BidiStream = BidiStream<B, S, T>;
+    type SendStream = SendStream<B, S, T>;
+    type RecvStream = RecvStream<S, T>;
+    type OpenStreams = Connection<S, T>;
+    type Error = ConnectionError;
+
+    fn poll_accept_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Self::RecvStream>, Self::Error>> {
+        let stream = ready!(self.uni_streams.poll_next_unpin(cx)).transpose()?;
+        Poll::Ready(Ok(stream))
+    }
+
+    fn poll_accept_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<Self::BidiStream>, Self::Error>> {
+        let stream = ready!(self.bi_streams.poll_next_unpin(cx)).transpose()?;
+        Poll::Ready(Ok(stream.map(|(send, recv)| BidiStream::new(send, recv))))
+    }
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        quic::OpenStreams::poll_open_bidi(&mut self.connection, cx)
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        quic::OpenStreams::poll_open_send(&mut self.connection, cx)
+    }
+
+    fn opener(&self) -> Self::OpenStreams {
+        self.connection.clone()
+    }
+
+    fn close(&mut self, code: ::h3::error::Code, reason: &[u8]) {
+        quic::OpenStreams::<B>::close(&mut self.connection, code, reason)
+    }
+}
+
+impl<B, S, T> quic::OpenStreams<B> for Connection<S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type BidiStream = BidiStream<B, S, T>;
+    type SendStream = SendStream<B, S, T>;
+    type RecvStream = RecvStream<S, T>;
+    type Error = ConnectionError;
+
+    fn poll_open_bidi(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::BidiStream, Self::Error>> {
+        let (send, recv) = ready!(self.open_bi().poll_unpin(cx))?;
+        Poll::Ready(Ok(BidiStream::new(send, recv)))
+    }
+
+    fn poll_open_send(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::SendStream, Self::Error>> {
+        let send = ready!(self.open_uni().poll_unpin(cx))?;
+        Poll::Ready(Ok(SendStream::new(send)))
+    }
+
+    fn close(&mut self, code: ::h3::error::Code, reason: &[u8]) {
+        Connection::close(self, varint(code.value()), reason)
+    }
+}
+
+impl<S, T> quic::RecvStream for RecvStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Buf = Bytes;
+    type Error = ReadError;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        let chunk = ready!(self.poll_read_chunk(cx, usize::MAX, true))?;
+        Poll::Ready(Ok(chunk.map(|x| x.bytes)))
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        // The stream may already have been finished or reset, in which case there's nothing to stop
+        let _ = self.stop(varint(error_code));
+    }
+}
+
+/// The sending half of a stream, adapted to `h3::quic::SendStream`
+///
+/// Holds on to the data passed to `send_data()` until it's written by `poll_ready()`.
+pub struct SendStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    stream: crate::send_stream::SendStream<S, T>,
+    writing: Option<WriteBuf<B>>,
+}
+
+impl<B, S, T> SendStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Adapt `stream` for use with `h3`
+    pub fn new(stream: crate::send_stream::SendStream<S, T>) -> Self {
+        Self {
+            stream,
+            writing: None,
+        }
+    }
+
+    /// Recover the underlying stream, discarding any data which hasn't been written yet
+    pub fn into_inner(self) -> crate::send_stream::SendStream<S, T> {
+        self.stream
+    }
+}
+
+impl<B, S, T> fmt::Debug for SendStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendStream")
+            .field("id", &self.stream.id())
+            .field("writing", &self.writing.as_ref().map(|x| x.remaining()))
+            .finish()
+    }
+}
+
+impl<B, S, T> quic::SendStream<B> for SendStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Error = SendStreamError;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(ref mut data) = self.writing {
+            while data.has_remaining() {
+                let n = ready!(self.stream.execute_poll(cx, |s| s.write(data.chunk())))?;
+                data.advance(n);
+            }
+        }
+        self.writing = None;
+        Poll::Ready(Ok(()))
+    }
+
+    fn send_data<D: Into<WriteBuf<B>>>(&mut self, data: D) -> Result<(), Self::Error> {
+        if self.writing.is_some() {
+            return Err(SendStreamError::NotReady);
+        }
+        self.writing = Some(data.into());
+        Ok(())
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.stream.poll_finish(cx).map_err(Into::into)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        // The stream may already have been finished or reset, in which case there's nothing to do
+        let _ = self.stream.reset(varint(reset_code));
+    }
+
+    fn id(&self) -> H3StreamId {
+        h3_stream_id(self.stream.id())
+    }
+}
+
+/// A bidirectional stream, adapted to `h3::quic::BidiStream`
+#[derive(Debug)]
+pub struct BidiStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    send: SendStream<B, S, T>,
+    recv: RecvStream<S, T>,
+}
+
+impl<B, S, T> BidiStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// Adapt the two halves of a bidirectional stream for use with `h3`
+    pub fn new(send: crate::send_stream::SendStream<S, T>, recv: RecvStream<S, T>) -> Self {
+        Self {
+            send: SendStream::new(send),
+            recv,
+        }
+    }
+}
+
+impl<B, S, T> quic::BidiStream<B> for BidiStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type SendStream = SendStream<B, S, T>;
+    type RecvStream = RecvStream<S, T>;
+
+    fn split(self) -> (Self::SendStream, Self::RecvStream) {
+        (self.send, self.recv)
+    }
+}
+
+impl<B, S, T> quic::SendStream<B> for BidiStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Error = SendStreamError;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.send.poll_ready(cx)
+    }
+
+    fn send_data<D: Into<WriteBuf<B>>>(&mut self, data: D) -> Result<(), Self::Error> {
+        self.send.send_data(data)
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.send.poll_finish(cx)
+    }
+
+    fn reset(&mut self, reset_code: u64) {
+        self.send.reset(reset_code)
+    }
+
+    fn id(&self) -> H3StreamId {
+        self.send.id()
+    }
+}
+
+impl<B, S, T> quic::RecvStream for BidiStream<B, S, T>
+where
+    B: Buf,
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Buf = Bytes;
+    type Error = ReadError;
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Self::Buf>, Self::Error>> {
+        self.recv.poll_data(cx)
+    }
+
+    fn stop_sending(&mut self, error_code: u64) {
+        self.recv.stop_sending(error_code)
+    }
+}
+
+/// Reasons why a connection might be lost, as reported through the `h3` traits
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error(transparent)]
+pub struct ConnectionError(#[from] pub proto::ConnectionError);
+
+impl quic::Error for ConnectionError {
+    fn is_timeout(&self) -> bool {
+        is_timeout(&self.0)
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        application_error_code(&self.0)
+    }
+}
+
+/// Errors that arise from writing to a stream through the `h3` traits
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SendStreamError {
+    /// Writing to the stream failed
+    #[error(transparent)]
+    Write(#[from] WriteError),
+    /// Data was sent before `poll_ready()` finished writing out the previous data
+    #[error("stream not ready")]
+    NotReady,
+}
+
+impl quic::Error for SendStreamError {
+    fn is_timeout(&self) -> bool {
+        match self {
+            SendStreamError::Write(e) => e.is_timeout(),
+            SendStreamError::NotReady => false,
+        }
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        match self {
+            SendStreamError::Write(e) => e.err_code(),
+            SendStreamError::NotReady => None,
+        }
+    }
+}
+
+impl quic::Error for WriteError {
+    fn is_timeout(&self) -> bool {
+        matches!(self, WriteError::ConnectionClosed(e) if is_timeout(e))
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        match self {
+            WriteError::Stopped(code) => Some(code.into_inner()),
+            WriteError::ConnectionClosed(e) => application_error_code(e),
+            _ => None,
+        }
+    }
+}
+
+impl quic::Error for ReadError {
+    fn is_timeout(&self) -> bool {
+        matches!(self, ReadError::ConnectionClosed(e) if is_timeout(e))
+    }
+
+    fn err_code(&self) -> Option<u64> {
+        match self {
+            ReadError::Reset(code) => Some(code.into_inner()),
+            ReadError::ConnectionClosed(e) => application_error_code(e),
+            _ => None,
+        }
+    }
+}
+
+fn is_timeout(e: &proto::ConnectionError) -> bool {
+    matches!(
+        e,
+        proto::ConnectionError::TimedOut
+            | proto::ConnectionError::HandshakeTimedOut(_)
+            | proto::ConnectionError::UdpBlocked
+    )
+}
+
+fn application_error_code(e: &proto::ConnectionError) -> Option<u64> {
+    match e {
+        proto::ConnectionError::ApplicationClosed(close) => Some(close.error_code.into_inner()),
+        _ => None,
+    }
+}
+
+fn h3_stream_id(id: StreamId) -> H3StreamId {
+    H3StreamId::try_from(VarInt::from(id).into_inner()).expect("stream IDs are valid varints")
+}
+
+/// Convert an error code passed in by `h3`, which only uses codes that fit in a varint
+fn varint(code: u64) -> VarInt {
+    VarInt::from_u64(code).expect("error code out of range")
+}
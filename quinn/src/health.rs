@@ -0,0 +1,45 @@
+use futures::StreamExt;
+use tracing::debug;
+
+use crate::{
+    connection::{Connecting, NewConnection},
+    endpoint::Spawner,
+    transport::Socket,
+};
+
+/// Answer a connection accepted for health checking
+///
+/// Every bidirectional stream the peer opens is echoed back once the peer finishes it, allowing
+/// the peer to measure the round trip time through the endpoint without involving the
+/// application. See [`EndpointBuilder::health_check()`](crate::generic::EndpointBuilder::health_check).
+pub(crate) async fn respond<S, T>(connecting: Connecting<S, T>, spawner: Spawner)
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    let NewConnection { mut bi_streams, .. } = match connecting.await {
+        Ok(x) => x,
+        Err(e) => {
+            debug!("health check handshake failed: {}", e);
+            return;
+        }
+    };
+    while let Some(Ok((mut send, recv))) = bi_streams.next().await {
+        spawner.spawn(async move {
+            let data = match recv.read_to_end(MAX_ECHO_SIZE).await {
+                Ok(x) => x,
+                Err(e) => {
+                    debug!("health check stream failed: {}", e);
+                    return;
+                }
+            };
+            if send.write_all(&data).await.is_ok() {
+                let _ = send.finish().await;
+            }
+        });
+    }
+}
+
+/// Largest amount of data echoed back on a stream, so health checks can't be used for
+/// amplification or to exhaust memory
+const MAX_ECHO_SIZE: usize = 1024;
@@ -0,0 +1,87 @@
+//! Utilities for bridging QUIC streams with other I/O objects
+
+use std::io;
+
+use futures::future;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{bi_stream::BiStream, transport::Socket, VarInt};
+
+/// Copy data in both directions between a bidirectional QUIC stream and another I/O object, such
+/// as a `TcpStream`
+///
+/// Unlike `tokio::io::copy_bidirectional`, half-closes are propagated faithfully in both
+/// directions: when the peer finishes its side of `stream`, `other` is shut down for writing, and
+/// when reading from `other` reaches end of file, `stream` is finished. Each direction keeps
+/// running until it has itself reached its end.
+///
+/// If the peer resets `stream`, or reading from or writing to `other` fails, an error is returned
+/// without shutting down `other`, so that dropping it closes the connection abruptly rather than
+/// signaling a clean end of data. Failures on `other` in turn reset or stop `stream` with error
+/// code 0, so the peer can distinguish them from a clean finish.
+///
+/// Returns the number of bytes copied from `stream` to `other` and from `other` to `stream`,
+/// respectively.
+pub async fn copy_bidirectional<S, T, IO>(
+    stream: &mut BiStream<S, T>,
+    other: &mut IO,
+) -> io::Result<(u64, u64)>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    copy_bidirectional_with_code(stream, other, VarInt::from_u32(0)).await
+}
+
+/// Like `copy_bidirectional`, but using `error_code` to reset or stop `stream` when `other` fails
+pub(crate) async fn copy_bidirectional_with_code<S, T, IO>(
+    stream: &mut BiStream<S, T>,
+    other: &mut IO,
+    error_code: VarInt,
+) -> io::Result<(u64, u64)>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+    IO: AsyncRead + AsyncWrite + Unpin,
+{
+    let BiStream { send, recv } = stream;
+    let (mut reader, mut writer) = tokio::io::split(other);
+
+    let incoming = async {
+        let mut copied = 0;
+        while let Some(chunk) = recv.read_chunk(usize::max_value(), true).await? {
+            if let Err(e) = writer.write_all(&chunk.bytes).await {
+                let _ = recv.stop(error_code);
+                return Err(e);
+            }
+            copied += chunk.bytes.len() as u64;
+        }
+        writer.shutdown().await?;
+        Ok(copied)
+    };
+
+    let outgoing = async {
+        let mut copied = 0;
+        let mut buf = vec![0; BUF_SIZE];
+        loop {
+            let n = match reader.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    let _ = send.reset(error_code);
+                    return Err(e);
+                }
+            };
+            send.write_all(&buf[..n]).await?;
+            copied += n as u64;
+        }
+        send.finish().await?;
+        Ok(copied)
+    };
+
+    future::try_join(incoming, outgoing).await
+}
+
+/// Size of the buffer used to read from the non-QUIC side
+const BUF_SIZE: usize = 8 * 1024;
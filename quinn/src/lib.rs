@@ -50,39 +50,98 @@ let (endpoint, _) = builder.bind(&\"[::]:0\".parse().unwrap()).unwrap();
 //! encryption alone.
 #![warn(missing_docs)]
 
+mod anti_replay;
+mod bi_stream;
 mod broadcast;
+mod buffer_pool;
 mod builders;
+mod clock;
 mod connection;
+#[cfg(feature = "driver_counters")]
+mod counters;
+#[cfg(feature = "debug_counters")]
+mod debug_counters;
+mod dyn_stream;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
 mod endpoint;
+#[cfg(feature = "h3")]
+pub mod h3;
+mod health;
+pub mod io;
+mod memory;
 mod mutex;
 mod platform;
+pub mod proxy;
+mod reconnect;
 mod recv_stream;
+pub mod resume;
+#[cfg(feature = "runtime-tokio")]
+mod router;
+mod runtime;
 mod send_stream;
+#[cfg(feature = "tower")]
+mod serve;
+mod shaper;
+#[cfg(all(unix, feature = "systemd"))]
+mod systemd;
+mod unwind;
 
 pub use proto::{
-    crypto, ApplicationClose, Certificate, CertificateChain, Chunk, ConfigError, ConnectError,
-    ConnectionClose, ConnectionError, ParseError, PrivateKey, StreamId, Transmit, TransportConfig,
-    VarInt,
+    crypto, error_codes, Admission, AdmissionController, ApplicationClose, Certificate,
+    CertificateChain, Chunk, Coalescing, ConfigError, ConnectError, ConnectionClose,
+    ConnectionError, ConnectionQuota, CreditPolicy, DatagramTransform, DrainedStream, EarlyData,
+    EndpointLoad, EndpointStats, HandshakeFailure, HandshakeFailureStats, HandshakePhase,
+    HandshakeSummary, ListenerFilter, MemoryRttCache, MtuDiscoveryConfig, PaddingPolicy,
+    ParseError, PowerMode, PrivateKey, RecvStreamStats, Reliability, RttCache, SendStreamStats,
+    StatelessReset, StreamId, Tap, TapDirection, Transmit, TransportConfig, TransportProfile,
+    UnknownErrorCode, VarInt,
 };
 
 pub use crate::{
+    anti_replay::{AntiReplay, MemoryAntiReplay},
     builders::EndpointError,
-    connection::{SendDatagramError, ZeroRttAccepted},
-    recv_stream::{ReadError, ReadExactError, ReadToEndError},
-    send_stream::{StoppedError, WriteError},
+    clock::{AsyncTimer, Clock, ThreadClock},
+    connection::{PriorityClass, SendDatagramError, SpawnedHandlers, ZeroRttAccepted},
+    dyn_stream::{DynRecvStream, DynSendStream},
+    endpoint::{ConnectFirstError, SocketError, SocketErrorPolicy, SocketOperation},
+    reconnect::ReconnectError,
+    recv_stream::{ReadError, ReadExactError, ReadToEndError, ReadTrailerError, TryReadError},
+    runtime::{ExecutorRuntime, Runtime},
+    send_stream::{StoppedError, TryWriteError, WriteError},
+    unwind::DriverPanic,
 };
 
+#[cfg(feature = "runtime-tokio")]
+pub use crate::{clock::TokioClock, runtime::TokioRuntime};
+
+#[cfg(feature = "debug_counters")]
+pub use crate::debug_counters::DebugCounters;
+
+#[cfg(feature = "driver_counters")]
+pub use crate::counters::DriverCounters;
+
+#[cfg(feature = "tower")]
+pub use crate::serve::{serve, ServeError};
+
 /// Types that are generic over the crypto protocol implementation
 pub mod generic {
+    #[cfg(feature = "runtime-tokio")]
+    pub use crate::router::{RoutedStream, StreamRouter};
     pub use crate::{
+        bi_stream::BiStream,
         builders::{ClientConfigBuilder, EndpointBuilder, ServerConfigBuilder},
         connection::{
-            Connecting, Connection, Datagrams, IncomingBiStreams, IncomingUniStreams,
-            NewConnection, OpenBi, OpenUni,
+            BlackHoleDetected, Connecting, Connection, Datagrams, IdleTimeoutWarning,
+            IncomingBiStreams, IncomingUniStreams, NewConnection, OpenBi, OpenUni, PathMtuChanged,
+            WeakConnection,
+        },
+        endpoint::{Endpoint, Incoming, Listener, StatsInterval},
+        reconnect::ReconnectingConnection,
+        recv_stream::{
+            IntoChunks, Read, ReadChunk, ReadChunks, ReadExact, ReadToEnd, Readable, RecvStream,
         },
-        endpoint::{Endpoint, Incoming},
-        recv_stream::{Read, ReadChunk, ReadChunks, ReadExact, ReadToEnd, RecvStream},
-        send_stream::SendStream,
+        send_stream::{SendStream, Writable},
     };
     pub use proto::generic::{ClientConfig, ServerConfig};
 }
@@ -90,30 +149,71 @@ pub mod generic {
 /// Traits and implementations for underlying connection on which QUIC packets transmit.
 pub mod transport {
     use crate::platform::SocketCapabilities;
-    pub use crate::platform::{RecvMeta, UdpSocket};
+    pub use crate::{
+        memory::{MemoryNetwork, MemorySocket},
+        platform::{PacketTooBig, RecvMeta, UdpSocket},
+    };
     use proto::Transmit;
     use std::{
         io::{IoSliceMut, Result},
-        net::SocketAddr,
         task::{Context, Poll},
     };
 
     /// A socket that abstracts the underlying connection
     pub trait Socket: Send + 'static {
+        /// How peers are addressed on the underlying connection
+        ///
+        /// `SocketAddr` for UDP. Transports with their own addressing, e.g. overlay networks, can
+        /// use their own address type instead of mapping their addresses onto IP.
+        type Address: proto::Address;
+
         /// Poll the underlying connection to send `Transmit`, return the number of successfully transmitted `Transmit`.
-        fn poll_send(&self, cx: &mut Context, transmits: &mut [Transmit]) -> Poll<Result<usize>>;
+        fn poll_send(
+            &self,
+            cx: &mut Context,
+            transmits: &mut [Transmit<Self::Address>],
+        ) -> Poll<Result<usize>>;
 
         /// Poll the underlying connection to receive, return the number of received bufs.
         fn poll_recv(
             &self,
             cx: &mut Context,
             bufs: &mut [IoSliceMut<'_>],
-            meta: &mut [RecvMeta],
+            meta: &mut [RecvMeta<Self::Address>],
         ) -> Poll<Result<usize>>;
 
-        /// The socket address of the local endpoint, return an arbitrary port with the IP address
-        /// if the connection doesn't support socket address (e.g. ICMP)
-        fn local_addr(&self) -> Result<SocketAddr>;
+        /// The address of the local endpoint
+        fn local_addr(&self) -> Result<Self::Address>;
+
+        /// Convert `remote` into the form datagrams must be sent to it with from this socket
+        ///
+        /// Returns `None` if `remote` can't be reached from this socket, e.g. an IPv6 address
+        /// from an IPv4 socket. Defaults to using `remote` as is.
+        fn map_remote(&self, remote: Self::Address) -> Option<Self::Address> {
+            Some(remote)
+        }
+
+        /// Take the next report of a datagram that was dropped for exceeding the path MTU, if any
+        ///
+        /// Reports are collected while sending and receiving, e.g. from ICMP Packet Too Big
+        /// messages. Defaults to never reporting anything, for sockets which can't receive them.
+        fn take_packet_too_big(&self) -> Option<PacketTooBig<Self::Address>> {
+            None
+        }
+
+        /// Create a fresh socket of the same kind, e.g. after a network change broke this one
+        ///
+        /// Used by endpoints configured to recreate their socket on network errors. Defaults to
+        /// failing, for sockets which can't be recreated.
+        fn recreate(&self) -> Result<Self>
+        where
+            Self: Sized,
+        {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "socket can't be recreated",
+            ))
+        }
 
         /// Returns the platforms (UDP) socket capabilities. Default to 1 for max_gso_segments.
         fn caps() -> SocketCapabilities {
@@ -129,6 +229,8 @@ mod rustls_impls {
     use crate::{generic, platform::UdpSocket};
     use proto::crypto::rustls::TlsSession;
 
+    /// A `BiStream` using rustls for the cryptography protocol
+    pub type BiStream = generic::BiStream<TlsSession, UdpSocket>;
     /// A `ClientConfig` using rustls for the cryptography protocol
     pub type ClientConfig = generic::ClientConfig<TlsSession>;
     /// A `ServerConfig` using rustls for the cryptography protocol
@@ -141,12 +243,16 @@ mod rustls_impls {
     /// A `ServerConfigBuilder` using rustls for the cryptography protocol
     pub type ServerConfigBuilder = generic::ServerConfigBuilder<TlsSession>;
 
+    /// A `BlackHoleDetected` using rustls for the cryptography protocol
+    pub type BlackHoleDetected = generic::BlackHoleDetected<TlsSession, UdpSocket>;
     /// A `Connecting` using rustls for the cryptography protocol
     pub type Connecting = generic::Connecting<TlsSession, UdpSocket>;
     /// A `Connection` using rustls for the cryptography protocol
     pub type Connection = generic::Connection<TlsSession, UdpSocket>;
     /// A `Datagrams` using rustls for the cryptography protocol
     pub type Datagrams = generic::Datagrams<TlsSession, UdpSocket>;
+    /// An `IdleTimeoutWarning` using rustls for the cryptography protocol
+    pub type IdleTimeoutWarning = generic::IdleTimeoutWarning<TlsSession, UdpSocket>;
     /// An `IncomingBiStreams` using rustls for the cryptography protocol
     pub type IncomingBiStreams = generic::IncomingBiStreams<TlsSession, UdpSocket>;
     /// An `IncomingUniStreams` using rustls for the cryptography protocol
@@ -157,12 +263,24 @@ mod rustls_impls {
     pub type OpenBi = generic::OpenBi<TlsSession, UdpSocket>;
     /// An `OpenUni` using rustls for the cryptography protocol
     pub type OpenUni = generic::OpenUni<TlsSession, UdpSocket>;
+    /// A `PathMtuChanged` using rustls for the cryptography protocol
+    pub type PathMtuChanged = generic::PathMtuChanged<TlsSession, UdpSocket>;
+    /// A `WeakConnection` using rustls for the cryptography protocol
+    pub type WeakConnection = generic::WeakConnection<TlsSession, UdpSocket>;
+    /// A `ReconnectingConnection` using rustls for the cryptography protocol
+    pub type ReconnectingConnection = generic::ReconnectingConnection<TlsSession, UdpSocket>;
 
     /// An `Endpoint` using rustls for the cryptography protocol and UDP socket for underlying connection.
     pub type Endpoint = generic::Endpoint<TlsSession, UdpSocket>;
     /// An `Incoming` using rustls for the cryptography protocol and UDP socket for underlying connection.
     pub type Incoming = generic::Incoming<TlsSession, UdpSocket>;
+    /// A `Listener` using rustls for the cryptography protocol and UDP socket for underlying connection.
+    pub type Listener = generic::Listener<TlsSession, UdpSocket>;
+    /// A `StatsInterval` using rustls for the cryptography protocol and UDP socket for underlying connection.
+    pub type StatsInterval = generic::StatsInterval<TlsSession, UdpSocket>;
 
+    /// An `IntoChunks` using rustls for the cryptography protocol
+    pub type IntoChunks = generic::IntoChunks<TlsSession, UdpSocket>;
     /// A `Read` using rustls for the cryptography protocol
     pub type Read<'a> = generic::Read<'a, TlsSession, UdpSocket>;
     /// A `ReadExact` using rustls for the cryptography protocol
@@ -173,27 +291,42 @@ mod rustls_impls {
     pub type RecvStream = generic::RecvStream<TlsSession, UdpSocket>;
     /// A `SendStream` using rustls for the cryptography protocol
     pub type SendStream = generic::SendStream<TlsSession, UdpSocket>;
+
+    /// A `RoutedStream` using rustls for the cryptography protocol
+    #[cfg(feature = "runtime-tokio")]
+    pub type RoutedStream = generic::RoutedStream<TlsSession, UdpSocket>;
+    /// A `StreamRouter` using rustls for the cryptography protocol
+    #[cfg(feature = "runtime-tokio")]
+    pub type StreamRouter = generic::StreamRouter<TlsSession, UdpSocket>;
 }
 
 #[cfg(feature = "rustls")]
 pub use rustls_impls::*;
 
+/// The rustls version configurations passed to `with_rustls_config` must be built with
+#[cfg(feature = "rustls")]
+pub use rustls;
+
 #[cfg(test)]
 mod tests;
 
 #[derive(Debug)]
-enum ConnectionEvent {
+enum ConnectionEvent<A> {
     Close {
         error_code: VarInt,
         reason: bytes::Bytes,
     },
-    Proto(proto::ConnectionEvent),
+    Proto(proto::ConnectionEvent<A>),
+    PacketTooBig(transport::PacketTooBig<A>),
+    SocketRecreated,
+    Pause,
+    Resume,
 }
 
 #[derive(Debug)]
-enum EndpointEvent {
-    Proto(proto::EndpointEvent),
-    Transmit(proto::Transmit),
+enum EndpointEvent<A> {
+    Proto(proto::EndpointEvent<A>),
+    Transmit(proto::Transmit<A>, PriorityClass),
 }
 
 /// Maximum number of send/recv calls to make before moving on to other processing
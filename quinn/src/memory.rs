@@ -0,0 +1,333 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, IoSliceMut},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use proto::{EcnCodepoint, Transmit};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    clock::{AsyncTimer, Clock},
+    platform::RecvMeta,
+    transport::Socket,
+};
+
+/// A simulated network for [`MemorySocket`]s to exchange datagrams over, within the process
+///
+/// Endpoints bound to sockets on the same network can talk to each other as they would over UDP,
+/// without touching the OS network stack. Datagrams are delivered to the socket bound to exactly
+/// their destination address, and dropped if there is none.
+///
+/// By default datagrams arrive immediately and in order. Network conditions can be degraded with
+/// [`latency()`](Self::latency), [`jitter()`](Self::jitter), [`reorder()`](Self::reorder) and
+/// [`loss()`](Self::loss), also while endpoints are running, e.g. to simulate an outage. The random
+/// decisions involved are drawn from a generator seeded with [`seed()`](Self::seed), so the same
+/// sequence of datagrams meets the same fate on every run.
+///
+/// Cloning a `MemoryNetwork` yields another handle to the same network.
+#[derive(Debug, Clone)]
+pub struct MemoryNetwork {
+    state: Arc<Mutex<NetworkState>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MemoryNetwork {
+    /// Create an empty network, timing deliveries with [`TokioClock`](crate::TokioClock)
+    #[cfg(feature = "runtime-tokio")]
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(crate::TokioClock))
+    }
+
+    /// Create an empty network, timing deliveries with `clock`
+    ///
+    /// Should be the clock of the endpoints using the network, so that simulated latency follows
+    /// the same notion of time as their timers.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(NetworkState {
+                sockets: HashMap::new(),
+                latency: Duration::from_secs(0),
+                jitter: Duration::from_secs(0),
+                reorder: 0.0,
+                loss: 0.0,
+                rng: StdRng::seed_from_u64(0),
+                next_seq: 0,
+                next_port: EPHEMERAL_PORTS_START,
+            })),
+            clock,
+        }
+    }
+
+    /// Bind a socket to `addr`
+    ///
+    /// If the port of `addr` is 0, a free one is picked. Fails with
+    /// [`AddrInUse`](io::ErrorKind::AddrInUse) if another socket on the network is bound to `addr`.
+    /// The address is released once the socket is dropped.
+    pub fn bind(&self, mut addr: SocketAddr) -> io::Result<MemorySocket> {
+        let mut state = self.state.lock().unwrap();
+        if addr.port() == 0 {
+            addr.set_port(state.free_port(addr)?);
+        } else if state.sockets.contains_key(&addr) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is already bound", addr),
+            ));
+        }
+        let inbox = Arc::new(Mutex::new(Inbox::default()));
+        state.sockets.insert(addr, inbox.clone());
+        Ok(MemorySocket {
+            addr,
+            network: self.clone(),
+            inbox,
+            timer: Mutex::new(None),
+        })
+    }
+
+    /// Delay every datagram by `latency` on its way to its destination
+    ///
+    /// Defaults to 0.
+    pub fn latency(&self, latency: Duration) -> &Self {
+        self.state.lock().unwrap().latency = latency;
+        self
+    }
+
+    /// Delay every datagram by up to `jitter` on top of the [`latency()`](Self::latency)
+    ///
+    /// The extra delay is drawn uniformly at random for each datagram, so datagrams sent less
+    /// than `jitter` apart can overtake each other. Defaults to 0.
+    pub fn jitter(&self, jitter: Duration) -> &Self {
+        self.state.lock().unwrap().jitter = jitter;
+        self
+    }
+
+    /// Fraction of datagrams held back behind those sent after them
+    ///
+    /// Reordered datagrams are delayed by another [`latency()`](Self::latency), so reordering
+    /// requires a non-zero latency. Defaults to 0.
+    ///
+    /// # Panics
+    ///
+    /// If `probability` is not between 0 and 1.
+    pub fn reorder(&self, probability: f64) -> &Self {
+        assert!((0.0..=1.0).contains(&probability));
+        self.state.lock().unwrap().reorder = probability;
+        self
+    }
+
+    /// Fraction of datagrams dropped at random
+    ///
+    /// Defaults to 0.
+    ///
+    /// # Panics
+    ///
+    /// If `probability` is not between 0 and 1.
+    pub fn loss(&self, probability: f64) -> &Self {
+        assert!((0.0..=1.0).contains(&probability));
+        self.state.lock().unwrap().loss = probability;
+        self
+    }
+
+    /// Reset the generator deciding the fate of datagrams to a state derived from `seed`
+    ///
+    /// Defaults to a seed of 0.
+    pub fn seed(&self, seed: u64) -> &Self {
+        self.state.lock().unwrap().rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl Default for MemoryNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+struct NetworkState {
+    sockets: HashMap<SocketAddr, Arc<Mutex<Inbox>>>,
+    latency: Duration,
+    jitter: Duration,
+    reorder: f64,
+    loss: f64,
+    rng: StdRng,
+    /// Orders datagrams due at the same time by when they were sent
+    next_seq: u64,
+    next_port: u16,
+}
+
+impl NetworkState {
+    fn free_port(&mut self, mut addr: SocketAddr) -> io::Result<u16> {
+        for _ in EPHEMERAL_PORTS_START..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORTS_START);
+            addr.set_port(port);
+            if !self.sockets.contains_key(&addr) {
+                return Ok(port);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            "no free ephemeral ports",
+        ))
+    }
+
+    /// When a datagram sent `now` arrives, or `None` if it's lost
+    fn arrival(&mut self, now: Instant) -> Option<Instant> {
+        if self.rng.gen_bool(self.loss) {
+            return None;
+        }
+        let mut delay = self.latency + self.jitter.mul_f64(self.rng.gen());
+        if self.rng.gen_bool(self.reorder) {
+            delay += self.latency;
+        }
+        Some(now + delay)
+    }
+}
+
+/// Datagrams on their way to a socket
+#[derive(Debug, Default)]
+struct Inbox {
+    /// By arrival time, then order of sending
+    queue: BTreeMap<(Instant, u64), Datagram>,
+    /// Task to wake when a datagram is queued
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+struct Datagram {
+    source: SocketAddr,
+    contents: Vec<u8>,
+    ecn: Option<EcnCodepoint>,
+}
+
+/// A [`Socket`] on a [`MemoryNetwork`]
+///
+/// Created with [`MemoryNetwork::bind()`], and passed to
+/// [`EndpointBuilder::with_socket()`](crate::generic::EndpointBuilder::with_socket).
+pub struct MemorySocket {
+    addr: SocketAddr,
+    network: MemoryNetwork,
+    inbox: Arc<Mutex<Inbox>>,
+    /// Fires when the next datagram in flight to this socket arrives
+    timer: Mutex<Option<Pin<Box<dyn AsyncTimer>>>>,
+}
+
+impl Socket for MemorySocket {
+    type Address = SocketAddr;
+
+    fn poll_send(
+        &self,
+        _: &mut Context,
+        transmits: &mut [Transmit<SocketAddr>],
+    ) -> Poll<io::Result<usize>> {
+        let now = self.network.clock.now();
+        let mut state = self.network.state.lock().unwrap();
+        for transmit in transmits.iter() {
+            let inbox = match state.sockets.get(&transmit.destination) {
+                Some(x) => x.clone(),
+                None => continue,
+            };
+            let segment_size = transmit.segment_size.unwrap_or(transmit.contents.len());
+            for contents in transmit.contents.chunks(segment_size.max(1)) {
+                let arrival = match state.arrival(now) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let seq = state.next_seq;
+                state.next_seq += 1;
+                let mut inbox = inbox.lock().unwrap();
+                inbox.queue.insert(
+                    (arrival, seq),
+                    Datagram {
+                        source: self.addr,
+                        contents: contents.to_vec(),
+                        ecn: transmit.ecn,
+                    },
+                );
+                if let Some(waker) = inbox.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+        Poll::Ready(Ok(transmits.len()))
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta<SocketAddr>],
+    ) -> Poll<io::Result<usize>> {
+        let now = self.network.clock.now();
+        let mut inbox = self.inbox.lock().unwrap();
+        let mut received = 0;
+        while received < bufs.len() {
+            let key = match inbox.queue.keys().next() {
+                Some(&key) if key.0 <= now => key,
+                _ => break,
+            };
+            let datagram = inbox.queue.remove(&key).unwrap();
+            // Truncated to fit, like UDP
+            let len = datagram.contents.len().min(bufs[received].len());
+            bufs[received][..len].copy_from_slice(&datagram.contents[..len]);
+            meta[received] = RecvMeta {
+                addr: datagram.source,
+                len,
+                ecn: datagram.ecn,
+                dst_ip: None,
+            };
+            received += 1;
+        }
+        if received > 0 {
+            return Poll::Ready(Ok(received));
+        }
+
+        inbox.waker = Some(cx.waker().clone());
+        let next = inbox.queue.keys().next().map(|&(arrival, _)| arrival);
+        drop(inbox);
+        if let Some(arrival) = next {
+            let mut timer = self.timer.lock().unwrap();
+            match *timer {
+                Some(ref mut timer) => timer.as_mut().reset(arrival),
+                None => *timer = Some(self.network.clock.new_timer(arrival)),
+            }
+            if timer.as_mut().unwrap().as_mut().poll(cx).is_ready() {
+                cx.waker().wake_by_ref();
+            }
+        }
+        Poll::Pending
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
+
+impl Drop for MemorySocket {
+    fn drop(&mut self) {
+        self.network
+            .state
+            .lock()
+            .unwrap()
+            .sockets
+            .remove(&self.addr);
+    }
+}
+
+impl fmt::Debug for MemorySocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemorySocket")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+/// First port handed out to sockets bound to port 0, as in the IANA ephemeral range
+const EPHEMERAL_PORTS_START: u16 = 49152;
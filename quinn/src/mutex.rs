@@ -1,6 +1,7 @@
 use std::{
     fmt::Debug,
     ops::{Deref, DerefMut},
+    sync::PoisonError,
 };
 
 #[cfg(feature = "lock_tracking")]
@@ -45,14 +46,16 @@ mod tracking {
         /// The purpose will be recorded in the list of last lock owners
         pub fn lock(&self, purpose: &'static str) -> MutexGuard<T> {
             let now = Instant::now();
-            let guard = self.inner.lock().unwrap();
+            // Poisoning is ignored, so that a driver which panicked while holding the lock leaves the
+            // state it guards accessible to handles, e.g. to report that a connection was abandoned
+            let guard = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
 
             let lock_time = Instant::now();
             let elapsed = lock_time.duration_since(now);
 
             if elapsed > Duration::from_millis(1) {
                 warn!(
-                    "Locking the connection for {} took {:?}. Last owners: {:?}",
+                    "Locking for {} took {:?}. Last owners: {:?}",
                     purpose, elapsed, guard.last_lock_owner
                 );
             }
@@ -80,10 +83,7 @@ mod tracking {
             let duration = self.start_time.elapsed();
 
             if duration > Duration::from_millis(1) {
-                warn!(
-                    "Utilizing the connection for {} took {:?}",
-                    self.purpose, duration
-                );
+                warn!("Holding the lock for {} took {:?}", self.purpose, duration);
             }
 
             self.guard
@@ -134,8 +134,10 @@ mod non_tracking {
         ///
         /// The purpose will be recorded in the list of last lock owners
         pub fn lock(&self, _purpose: &'static str) -> MutexGuard<T> {
+            // Poisoning is ignored, so that a driver which panicked while holding the lock leaves the
+            // state it guards accessible to handles, e.g. to report that a connection was abandoned
             MutexGuard {
-                guard: self.inner.lock().unwrap(),
+                guard: self.inner.lock().unwrap_or_else(PoisonError::into_inner),
             }
         }
     }
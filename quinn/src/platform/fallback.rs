@@ -33,7 +33,16 @@ impl TryFrom<std::net::UdpSocket> for UdpSocket {
     }
 }
 
+#[cfg(windows)]
+impl std::os::windows::io::AsRawSocket for UdpSocket {
+    fn as_raw_socket(&self) -> std::os::windows::io::RawSocket {
+        self.io.as_raw_socket()
+    }
+}
+
 impl Socket for UdpSocket {
+    type Address = SocketAddr;
+
     fn poll_send(
         &self,
         cx: &mut Context,
@@ -80,6 +89,15 @@ impl Socket for UdpSocket {
     fn local_addr(&self) -> io::Result<SocketAddr> {
         self.io.local_addr()
     }
+
+    fn map_remote(&self, remote: SocketAddr) -> Option<SocketAddr> {
+        super::map_remote(self.local_addr().ok()?, remote)
+    }
+
+    fn recreate(&self) -> io::Result<Self> {
+        let addr = super::recreate_addr(self.local_addr()?);
+        UdpSocket::try_from(std::net::UdpSocket::bind(addr)?)
+    }
 }
 
 pub const BATCH_SIZE: usize = 1;
@@ -1,7 +1,7 @@
 //! Uniform interface to send/recv UDP packets with ECN information.
 use std::{
     io::{self, IoSliceMut},
-    net::{IpAddr, Ipv6Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
 };
 
 use proto::{EcnCodepoint, Transmit};
@@ -39,9 +39,9 @@ pub struct SocketCapabilities {
 
 /// Meta information regarding the received buffer
 #[derive(Debug, Copy, Clone)]
-pub struct RecvMeta {
+pub struct RecvMeta<A = SocketAddr> {
     /// The remote address where the buffer came from
-    pub addr: SocketAddr,
+    pub addr: A,
     /// The length of the buffer
     pub len: usize,
     /// The ECN bit
@@ -50,6 +50,80 @@ pub struct RecvMeta {
     pub dst_ip: Option<IpAddr>,
 }
 
+/// Report that a datagram was dropped for exceeding the maximum transmission unit of its path
+#[derive(Debug, Copy, Clone)]
+pub struct PacketTooBig<A = SocketAddr> {
+    /// The address the datagram was sent to
+    pub destination: A,
+    /// The largest UDP payload the path is reported to support
+    pub mtu: u16,
+}
+
+/// Address to bind a replacement for a socket bound to `local`
+///
+/// The original address may have become unusable, so only its family is preserved.
+fn recreate_addr(local: SocketAddr) -> SocketAddr {
+    let ip = match local {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    SocketAddr::new(ip, 0)
+}
+
+/// Express `remote` in the form datagrams must be sent to it with from a socket bound to `local`
+///
+/// IPv6 sockets reach IPv4 peers through IPv4-mapped addresses, while IPv4 sockets can't reach
+/// IPv6 peers at all.
+fn map_remote(local: SocketAddr, remote: SocketAddr) -> Option<SocketAddr> {
+    match (local, remote) {
+        (SocketAddr::V4(_), SocketAddr::V6(_)) => None,
+        (SocketAddr::V6(_), SocketAddr::V4(x)) => Some(SocketAddr::V6(SocketAddrV6::new(
+            x.ip().to_ipv6_mapped(),
+            x.port(),
+            0,
+            0,
+        ))),
+        _ => Some(remote),
+    }
+}
+
+/// Whether a send failed because the network configuration changed underneath the socket
+pub(crate) fn is_network_change(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    const CODES: &[i32] = &[
+        libc::ENETUNREACH,
+        libc::ENETDOWN,
+        libc::EHOSTUNREACH,
+        libc::EADDRNOTAVAIL,
+    ];
+    // WSAENETUNREACH, WSAENETDOWN, WSAEHOSTUNREACH, WSAEADDRNOTAVAIL
+    #[cfg(windows)]
+    const CODES: &[i32] = &[10051, 10050, 10065, 10049];
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+    e.raw_os_error().map_or(false, |code| CODES.contains(&code))
+}
+
+/// Whether a socket operation failed for reasons that don't prevent further operations succeeding
+///
+/// Covers errors raised by ICMP messages concerning earlier datagrams, datagrams refused by local
+/// firewalls, and exhausted socket buffers. Network changes and interrupted calls are told apart by
+/// `is_network_change` and `io::ErrorKind::Interrupted` respectively.
+pub(crate) fn is_transient(e: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    #[cfg(unix)]
+    const CODES: &[i32] = &[libc::ENOBUFS, libc::ENOMEM];
+    // WSAENOBUFS
+    #[cfg(windows)]
+    const CODES: &[i32] = &[10055];
+    #[cfg(not(any(unix, windows)))]
+    const CODES: &[i32] = &[];
+    match e.kind() {
+        ConnectionReset | ConnectionRefused | PermissionDenied => true,
+        _ => e.raw_os_error().map_or(false, |code| CODES.contains(&code)),
+    }
+}
+
 impl Default for RecvMeta {
     /// Constructs a value with arbitrary fields, intended to be overwritten
     fn default() -> Self {
@@ -1,22 +1,25 @@
 use std::{
+    collections::VecDeque,
     convert::TryFrom,
     io,
     io::IoSliceMut,
     mem::{self, MaybeUninit},
-    net::{IpAddr, SocketAddr},
-    os::unix::io::AsRawFd,
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    os::unix::io::{AsRawFd, RawFd},
     ptr,
+    sync::Mutex,
     task::{Context, Poll},
 };
 
 use futures::ready;
+use fxhash::FxHashMap;
 use lazy_static::lazy_static;
 use proto::{EcnCodepoint, Transmit};
 use tokio::io::unix::AsyncFd;
 
 use crate::transport::Socket;
 
-use super::{cmsg, RecvMeta, SocketCapabilities};
+use super::{cmsg, PacketTooBig, RecvMeta, SocketCapabilities};
 
 #[cfg(target_os = "freebsd")]
 type IpTosTy = libc::c_uchar;
@@ -30,6 +33,8 @@ type IpTosTy = libc::c_int;
 #[derive(Debug)]
 pub struct UdpSocket {
     io: AsyncFd<mio::net::UdpSocket>,
+    flow_labels: FlowLabels,
+    packet_too_big: PacketTooBigReports,
 }
 
 impl TryFrom<std::net::UdpSocket> for UdpSocket {
@@ -41,11 +46,21 @@ impl TryFrom<std::net::UdpSocket> for UdpSocket {
         init(&io)?;
         Ok(UdpSocket {
             io: AsyncFd::new(io)?,
+            flow_labels: FlowLabels::default(),
+            packet_too_big: PacketTooBigReports::default(),
         })
     }
 }
 
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.io.get_ref().as_raw_fd()
+    }
+}
+
 impl Socket for UdpSocket {
+    type Address = SocketAddr;
+
     fn poll_send(
         &self,
         cx: &mut Context,
@@ -53,7 +68,14 @@ impl Socket for UdpSocket {
     ) -> Poll<Result<usize, io::Error>> {
         loop {
             let mut guard = ready!(self.io.poll_write_ready(cx))?;
-            if let Ok(res) = guard.try_io(|io| send(io.get_ref(), transmits)) {
+            if let Ok(res) = guard.try_io(|io| {
+                send(
+                    io.get_ref(),
+                    &self.flow_labels,
+                    &self.packet_too_big,
+                    transmits,
+                )
+            }) {
                 return Poll::Ready(res);
             }
         }
@@ -68,7 +90,8 @@ impl Socket for UdpSocket {
         debug_assert!(!bufs.is_empty());
         loop {
             let mut guard = ready!(self.io.poll_read_ready(cx))?;
-            if let Ok(res) = guard.try_io(|io| recv(io.get_ref(), bufs, meta)) {
+            if let Ok(res) = guard.try_io(|io| recv(io.get_ref(), &self.packet_too_big, bufs, meta))
+            {
                 return Poll::Ready(res);
             }
         }
@@ -78,6 +101,19 @@ impl Socket for UdpSocket {
         self.io.get_ref().local_addr()
     }
 
+    fn take_packet_too_big(&self) -> Option<PacketTooBig> {
+        self.packet_too_big.0.lock().unwrap().pop_front()
+    }
+
+    fn map_remote(&self, remote: SocketAddr) -> Option<SocketAddr> {
+        super::map_remote(self.local_addr().ok()?, remote)
+    }
+
+    fn recreate(&self) -> io::Result<Self> {
+        let addr = super::recreate_addr(self.local_addr()?);
+        UdpSocket::try_from(std::net::UdpSocket::bind(addr)?)
+    }
+
     fn caps() -> SocketCapabilities {
         caps()
     }
@@ -120,6 +156,10 @@ fn init(io: &mio::net::UdpSocket) -> io::Result<()> {
     }
     #[cfg(target_os = "linux")]
     {
+        // Queue ICMP errors, and in particular Packet Too Big messages, for `error_queue::recv`,
+        // and wake readers when they arrive. Without them, the path MTU is still found by probing.
+        error_queue::enable(io, &addr);
+
         if addr.is_ipv4() {
             let rc = unsafe {
                 libc::setsockopt(
@@ -191,11 +231,364 @@ fn init(io: &mio::net::UdpSocket) -> io::Result<()> {
             return Err(io::Error::last_os_error());
         }
     }
+    #[cfg(target_os = "linux")]
+    {
+        if addr.is_ipv6() {
+            // Take flow labels from the destination address of outgoing packets. Without this,
+            // packets are still sent, just without their flow labels.
+            flow_label::enable(io);
+        }
+    }
     Ok(())
 }
 
+/// IPv6 flow labels which may be set on outgoing packets
+///
+/// Linux only allows flow labels to be set on packets if the socket holds a lease on them, which we
+/// acquire the first time a label is used for a given destination. Leases are released again,
+/// oldest first, once more than `MAX_FLOW_LABEL_LEASES` label and destination pairs are in use.
+#[derive(Debug, Default)]
+struct FlowLabels(Mutex<FlowLabelLeases>);
+
+#[derive(Debug, Default)]
+struct FlowLabelLeases {
+    /// Whether a lease could be acquired for each label and destination
+    leased: FxHashMap<(u32, Ipv6Addr), bool>,
+    /// Keys of `leased` in the order they were added
+    order: VecDeque<(u32, Ipv6Addr)>,
+}
+
+impl FlowLabels {
+    /// Destination address to send `transmit` to, carrying its flow label if possible
+    fn destination(&self, io: &mio::net::UdpSocket, transmit: &Transmit) -> SocketAddr {
+        let (label, addr) = match (transmit.flow_label, transmit.destination) {
+            // IPv4-mapped destinations are sent over IPv4, which has no flow label
+            (Some(label), SocketAddr::V6(addr)) if !is_ipv4_mapped(addr.ip()) => (label, addr),
+            _ => return transmit.destination,
+        };
+        let key = (label, *addr.ip());
+        let mut leases = self.0.lock().unwrap();
+        let leased = match leases.leased.get(&key) {
+            Some(&x) => x,
+            None => {
+                if leases.order.len() == MAX_FLOW_LABEL_LEASES {
+                    let oldest = leases.order.pop_front().unwrap();
+                    if leases.leased.remove(&oldest) == Some(true) {
+                        flow_label::release(io, oldest.0);
+                    }
+                }
+                let leased = flow_label::lease(io, label, addr.ip());
+                leases.leased.insert(key, leased);
+                leases.order.push_back(key);
+                leased
+            }
+        };
+        if !leased {
+            return transmit.destination;
+        }
+        // `SocketAddrV6` passes flow information through to `sin6_flowinfo` unchanged, so it must
+        // be in network byte order
+        SocketAddrV6::new(*addr.ip(), addr.port(), label.to_be(), addr.scope_id()).into()
+    }
+}
+
+/// Bounds the number of flow label leases held by a socket, and the memory used to track them
+const MAX_FLOW_LABEL_LEASES: usize = 1024;
+
+fn is_ipv4_mapped(ip: &Ipv6Addr) -> bool {
+    ip.segments()[..6] == [0, 0, 0, 0, 0, 0xffff]
+}
+
+#[cfg(target_os = "linux")]
+mod flow_label {
+    use std::{io, mem, net::Ipv6Addr, os::unix::io::AsRawFd};
+
+    use tracing::debug;
+
+    const IPV6_FLOWLABEL_MGR: libc::c_int = 32;
+    const IPV6_FLOWINFO_SEND: libc::c_int = 33;
+    const IPV6_FL_A_GET: u8 = 0;
+    const IPV6_FL_A_PUT: u8 = 1;
+    const IPV6_FL_S_ANY: u8 = 255;
+    const IPV6_FL_F_CREATE: u16 = 1;
+
+    #[repr(C)]
+    struct in6_flowlabel_req {
+        flr_dst: libc::in6_addr,
+        flr_label: u32,
+        flr_action: u8,
+        flr_share: u8,
+        flr_flags: u16,
+        flr_expires: u16,
+        flr_linger: u16,
+        __flr_pad: u32,
+    }
+
+    /// Take flow labels for packets sent from `io` from their destination address, if supported
+    pub fn enable(io: &mio::net::UdpSocket) {
+        let on: libc::c_int = 1;
+        let rc = unsafe {
+            libc::setsockopt(
+                io.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                IPV6_FLOWINFO_SEND,
+                &on as *const _ as _,
+                mem::size_of_val(&on) as _,
+            )
+        };
+        if rc == -1 {
+            debug!(
+                "failed to enable sending flow labels: {}",
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    /// Acquire a lease on `label` for packets sent from `io` to `dst`, returning whether it
+    /// succeeded
+    pub fn lease(io: &mio::net::UdpSocket, label: u32, dst: &Ipv6Addr) -> bool {
+        let rc = manage(io, label, dst, IPV6_FL_A_GET, IPV6_FL_F_CREATE);
+        if rc == -1 {
+            debug!(
+                "failed to lease flow label {:#x}: {}",
+                label,
+                io::Error::last_os_error()
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Release a lease on `label` acquired by [`lease()`]
+    pub fn release(io: &mio::net::UdpSocket, label: u32) {
+        let rc = manage(io, label, &Ipv6Addr::UNSPECIFIED, IPV6_FL_A_PUT, 0);
+        if rc == -1 {
+            debug!(
+                "failed to release flow label {:#x}: {}",
+                label,
+                io::Error::last_os_error()
+            );
+        }
+    }
+
+    fn manage(
+        io: &mio::net::UdpSocket,
+        label: u32,
+        dst: &Ipv6Addr,
+        action: u8,
+        flags: u16,
+    ) -> libc::c_int {
+        let req = in6_flowlabel_req {
+            flr_dst: libc::in6_addr {
+                s6_addr: dst.octets(),
+            },
+            flr_label: label.to_be(),
+            flr_action: action,
+            flr_share: IPV6_FL_S_ANY,
+            flr_flags: flags,
+            flr_expires: 0,
+            flr_linger: 0,
+            __flr_pad: 0,
+        };
+        unsafe {
+            libc::setsockopt(
+                io.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                IPV6_FLOWLABEL_MGR,
+                &req as *const _ as _,
+                mem::size_of_val(&req) as _,
+            )
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod flow_label {
+    use std::net::Ipv6Addr;
+
+    pub fn lease(_: &mio::net::UdpSocket, _: u32, _: &Ipv6Addr) -> bool {
+        false
+    }
+
+    pub fn release(_: &mio::net::UdpSocket, _: u32) {}
+}
+
+/// Reports of datagrams dropped for exceeding the path MTU, awaiting collection by the endpoint
+#[derive(Debug, Default)]
+struct PacketTooBigReports(Mutex<VecDeque<PacketTooBig>>);
+
+impl PacketTooBigReports {
+    /// Read all errors queued on `io`, returning whether there were any
+    fn drain(&self, io: &mio::net::UdpSocket) -> bool {
+        let mut drained = false;
+        while let Some(error) = error_queue::recv(io) {
+            drained = true;
+            if let Some(report) = error {
+                let mut reports = self.0.lock().unwrap();
+                if reports.len() == MAX_PACKET_TOO_BIG_REPORTS {
+                    reports.pop_front();
+                }
+                reports.push_back(report);
+            }
+        }
+        drained
+    }
+}
+
+/// Bounds the memory used by reports if the endpoint falls behind
+const MAX_PACKET_TOO_BIG_REPORTS: usize = 64;
+
+#[cfg(target_os = "linux")]
+mod error_queue {
+    use std::{cmp, io, mem, net::SocketAddr, os::unix::io::AsRawFd, ptr};
+
+    use tracing::debug;
+
+    use super::{cmsg, is_ipv4_mapped, PacketTooBig, CMSG_LEN};
+
+    const SO_SELECT_ERR_QUEUE: libc::c_int = 45;
+    const SO_EE_ORIGIN_LOCAL: u8 = 1;
+    const SO_EE_ORIGIN_ICMP: u8 = 2;
+    const SO_EE_ORIGIN_ICMP6: u8 = 3;
+
+    #[repr(C)]
+    struct sock_extended_err {
+        ee_errno: u32,
+        ee_origin: u8,
+        ee_type: u8,
+        ee_code: u8,
+        ee_pad: u8,
+        ee_info: u32,
+        ee_data: u32,
+    }
+
+    /// Ask the kernel to queue errors on `io`, bound to `addr`, if it supports doing so
+    pub fn enable(io: &mio::net::UdpSocket, addr: &SocketAddr) {
+        if let Err(e) = try_enable(io, addr) {
+            debug!("failed to enable the error queue: {}", e);
+        }
+    }
+
+    fn try_enable(io: &mio::net::UdpSocket, addr: &SocketAddr) -> io::Result<()> {
+        let on: libc::c_int = 1;
+        let rc = unsafe {
+            libc::setsockopt(
+                io.as_raw_fd(),
+                libc::SOL_SOCKET,
+                SO_SELECT_ERR_QUEUE,
+                &on as *const _ as _,
+                mem::size_of_val(&on) as _,
+            )
+        };
+        if rc == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if addr.is_ipv4() || !io.only_v6()? {
+            let rc = unsafe {
+                libc::setsockopt(
+                    io.as_raw_fd(),
+                    libc::IPPROTO_IP,
+                    libc::IP_RECVERR,
+                    &on as *const _ as _,
+                    mem::size_of_val(&on) as _,
+                )
+            };
+            if rc == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if addr.is_ipv6() {
+            let rc = unsafe {
+                libc::setsockopt(
+                    io.as_raw_fd(),
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_RECVERR,
+                    &on as *const _ as _,
+                    mem::size_of_val(&on) as _,
+                )
+            };
+            if rc == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Dequeue an error from `io`, returning `None` if there are none and `Some(None)` if it
+    /// doesn't concern the path MTU
+    pub fn recv(io: &mio::net::UdpSocket) -> Option<Option<PacketTooBig>> {
+        let mut name = unsafe { mem::zeroed::<libc::sockaddr_storage>() };
+        let mut ctrl = cmsg::Aligned([0u8; CMSG_LEN]);
+        let mut hdr = unsafe { mem::zeroed::<libc::msghdr>() };
+        hdr.msg_name = &mut name as *mut _ as _;
+        hdr.msg_namelen = mem::size_of_val(&name) as _;
+        hdr.msg_control = ctrl.0.as_mut_ptr() as _;
+        hdr.msg_controllen = CMSG_LEN as _;
+        loop {
+            let n = unsafe { libc::recvmsg(io.as_raw_fd(), &mut hdr, libc::MSG_ERRQUEUE) };
+            if n != -1 {
+                break;
+            }
+            if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                return None;
+            }
+        }
+
+        // The original destination of the datagram which caused the error
+        let destination = match unsafe { socket2::SockAddr::new(name, hdr.msg_namelen) }.as_socket()
+        {
+            Some(x) => x,
+            None => return Some(None),
+        };
+        for cmsg in unsafe { cmsg::Iter::new(&hdr) } {
+            match (cmsg.cmsg_level, cmsg.cmsg_type) {
+                (libc::IPPROTO_IP, libc::IP_RECVERR) | (libc::IPPROTO_IPV6, libc::IPV6_RECVERR) => {
+                }
+                _ => continue,
+            }
+            // Followed by the address of the node which reported the error, so the payload is
+            // larger than `cmsg::decode` expects
+            let err =
+                unsafe { ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const sock_extended_err) };
+            let origin_ok = matches!(
+                err.ee_origin,
+                SO_EE_ORIGIN_LOCAL | SO_EE_ORIGIN_ICMP | SO_EE_ORIGIN_ICMP6
+            );
+            if !origin_ok || err.ee_errno != libc::EMSGSIZE as u32 {
+                continue;
+            }
+            // `ee_info` is the MTU at the IP layer, which includes the IP and UDP headers
+            let headers = match destination {
+                SocketAddr::V6(addr) if !is_ipv4_mapped(addr.ip()) => 40 + 8,
+                _ => 20 + 8,
+            };
+            let mtu = cmp::min(err.ee_info.saturating_sub(headers), u16::MAX.into()) as u16;
+            return Some(Some(PacketTooBig { destination, mtu }));
+        }
+        Some(None)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod error_queue {
+    use std::net::SocketAddr;
+
+    use super::PacketTooBig;
+
+    pub fn enable(_: &mio::net::UdpSocket, _: &SocketAddr) {}
+
+    pub fn recv(_: &mio::net::UdpSocket) -> Option<Option<PacketTooBig>> {
+        None
+    }
+}
+
 #[cfg(not(any(target_os = "macos", target_os = "ios")))]
-fn send(io: &mio::net::UdpSocket, transmits: &[Transmit]) -> io::Result<usize> {
+fn send(
+    io: &mio::net::UdpSocket,
+    flow_labels: &FlowLabels,
+    packet_too_big: &PacketTooBigReports,
+    transmits: &[Transmit],
+) -> io::Result<usize> {
     let mut msgs: [libc::mmsghdr; BATCH_SIZE] = unsafe { mem::zeroed() };
     let mut iovecs: [libc::iovec; BATCH_SIZE] = unsafe { mem::zeroed() };
     let mut cmsgs = [cmsg::Aligned([0u8; CMSG_LEN]); BATCH_SIZE];
@@ -211,7 +604,7 @@ fn send(io: &mio::net::UdpSocket, transmits: &[Transmit]) -> io::Result<usize> {
         let dst_addr = unsafe {
             std::ptr::write(
                 addrs[i].as_mut_ptr(),
-                socket2::SockAddr::from(transmit.destination),
+                socket2::SockAddr::from(flow_labels.destination(io, transmit)),
             );
             &*addrs[i].as_ptr()
         };
@@ -237,6 +630,18 @@ fn send(io: &mio::net::UdpSocket, transmits: &[Transmit]) -> io::Result<usize> {
             if e.kind() == io::ErrorKind::Interrupted {
                 continue;
             }
+            if e.raw_os_error() == Some(libc::EMSGSIZE) {
+                // The first datagram exceeds a known path MTU, or an earlier datagram was
+                // reported to. Drop it like the network would have, and collect the report so
+                // that future datagrams are sized appropriately.
+                packet_too_big.drain(io);
+                return Ok(1);
+            }
+            // Errors reported by ICMP are raised by the next operation on the socket, but don't
+            // concern it
+            if packet_too_big.drain(io) {
+                continue;
+            }
             return Err(e);
         }
         return Ok(n as usize);
@@ -244,13 +649,18 @@ fn send(io: &mio::net::UdpSocket, transmits: &[Transmit]) -> io::Result<usize> {
 }
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
-fn send(io: &mio::net::UdpSocket, transmits: &[Transmit]) -> io::Result<usize> {
+fn send(
+    io: &mio::net::UdpSocket,
+    flow_labels: &FlowLabels,
+    _packet_too_big: &PacketTooBigReports,
+    transmits: &[Transmit],
+) -> io::Result<usize> {
     let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
     let mut iov: libc::iovec = unsafe { mem::zeroed() };
     let mut ctrl = cmsg::Aligned([0u8; CMSG_LEN]);
     let mut sent = 0;
     while sent < transmits.len() {
-        let addr = socket2::SockAddr::from(transmits[sent].destination);
+        let addr = socket2::SockAddr::from(flow_labels.destination(io, &transmits[sent]));
         prepare_msg(&transmits[sent], &addr, &mut hdr, &mut iov, &mut ctrl);
         let n = unsafe { libc::sendmsg(io.as_raw_fd(), &hdr, 0) };
         if n == -1 {
@@ -275,6 +685,7 @@ fn send(io: &mio::net::UdpSocket, transmits: &[Transmit]) -> io::Result<usize> {
 #[cfg(not(any(target_os = "macos", target_os = "ios")))]
 fn recv(
     io: &mio::net::UdpSocket,
+    packet_too_big: &PacketTooBigReports,
     bufs: &mut [IoSliceMut<'_>],
     meta: &mut [RecvMeta],
 ) -> io::Result<usize> {
@@ -305,6 +716,12 @@ fn recv(
             if e.kind() == io::ErrorKind::Interrupted {
                 continue;
             }
+            // Errors reported by ICMP are raised by the next operation on the socket, and also
+            // make it readable; either way, there may be datagrams to receive once they're
+            // collected
+            if packet_too_big.drain(io) {
+                continue;
+            }
             return Err(e);
         }
         break n;
@@ -318,6 +735,7 @@ fn recv(
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 fn recv(
     io: &mio::net::UdpSocket,
+    _packet_too_big: &PacketTooBigReports,
     bufs: &mut [IoSliceMut<'_>],
     meta: &mut [RecvMeta],
 ) -> io::Result<usize> {
@@ -375,10 +793,12 @@ fn prepare_msg(
     hdr.msg_controllen = CMSG_LEN as _;
     let mut encoder = unsafe { cmsg::Encoder::new(hdr) };
     let ecn = transmit.ecn.map_or(0, |x| x as libc::c_int);
+    let dscp = transmit.dscp.map_or(0, |x| libc::c_int::from(x & 0x3f));
+    let tos = dscp << 2 | ecn;
     if transmit.destination.is_ipv4() {
-        encoder.push(libc::IPPROTO_IP, libc::IP_TOS, ecn as IpTosTy);
+        encoder.push(libc::IPPROTO_IP, libc::IP_TOS, tos as IpTosTy);
     } else {
-        encoder.push(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, ecn);
+        encoder.push(libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos);
     }
 
     if let Some(segment_size) = transmit.segment_size {
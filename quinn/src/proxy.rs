@@ -0,0 +1,92 @@
+//! Building blocks for tunneling TCP connections over QUIC
+//!
+//! Each TCP connection is carried by its own bidirectional stream, opened by the side that
+//! accepted the TCP connection using [`tcp_to_quic()`] and served by the other side using
+//! [`quic_to_tcp()`]. Data is copied as by [`io::copy_bidirectional()`], with half-closes
+//! propagated in both directions.
+//!
+//! Abrupt terminations are mapped as follows, so that neither side mistakes a failure for a clean
+//! end of data:
+//!
+//! - If a TCP connection fails, e.g. because it was reset, its stream is reset and stopped with
+//!   [`TCP_RESET`].
+//! - If the peer resets or stops the stream, with any error code, the TCP connection is reset by
+//!   closing it with a zero linger timeout.
+//! - If [`quic_to_tcp()`] can't connect to its target, the stream is reset and stopped with
+//!   [`CONNECT_FAILED`].
+//!
+//! [`io::copy_bidirectional()`]: crate::io::copy_bidirectional
+
+use std::{io, net::SocketAddr, time::Duration};
+
+use socket2::SockRef;
+use tokio::net::TcpStream;
+use tracing::debug;
+
+use crate::{
+    bi_stream::BiStream, connection::Connection, io::copy_bidirectional_with_code,
+    transport::Socket, VarInt,
+};
+
+/// Stream error code signaling that the TCP connection carried by the stream failed
+pub const TCP_RESET: VarInt = VarInt::from_u32(1);
+
+/// Stream error code signaling that the TCP connection to be carried by the stream couldn't be
+/// established
+pub const CONNECT_FAILED: VarInt = VarInt::from_u32(2);
+
+/// Carry an accepted TCP connection over a new bidirectional stream on `connection`
+///
+/// Completes once both directions have been closed, returning the number of bytes copied from
+/// the stream to `tcp` and from `tcp` to the stream, respectively.
+pub async fn tcp_to_quic<S, T>(
+    connection: &Connection<S, T>,
+    tcp: TcpStream,
+) -> io::Result<(u64, u64)>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    let (send, recv) = connection.open_bi().await?;
+    splice(BiStream::new(send, recv), tcp).await
+}
+
+/// Carry the data of a bidirectional stream opened by the peer over a new TCP connection to
+/// `target`
+///
+/// Completes once both directions have been closed, returning the number of bytes copied from
+/// the stream to the TCP connection and from the TCP connection to the stream, respectively.
+pub async fn quic_to_tcp<S, T>(
+    mut stream: BiStream<S, T>,
+    target: SocketAddr,
+) -> io::Result<(u64, u64)>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    let tcp = match TcpStream::connect(target).await {
+        Ok(x) => x,
+        Err(e) => {
+            debug!(%target, "failed to connect: {}", e);
+            let _ = stream.send.reset(CONNECT_FAILED);
+            let _ = stream.recv.stop(CONNECT_FAILED);
+            return Err(e);
+        }
+    };
+    splice(stream, tcp).await
+}
+
+/// Copy data between `stream` and `tcp`, resetting `tcp` if either side fails
+async fn splice<S, T>(mut stream: BiStream<S, T>, mut tcp: TcpStream) -> io::Result<(u64, u64)>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    let result = copy_bidirectional_with_code(&mut stream, &mut tcp, TCP_RESET).await;
+    if let Err(ref e) = result {
+        debug!("proxied connection failed: {}", e);
+        // Closing the socket with a zero linger timeout sends a RST, without blocking
+        let _ = SockRef::from(&tcp).set_linger(Some(Duration::from_secs(0)));
+    }
+    result
+}
@@ -0,0 +1,175 @@
+use std::{fmt, future::Future, io};
+
+use futures::{future::BoxFuture, lock::Mutex, FutureExt};
+use proto::{generic::ClientConfig, Address, ConnectError, ConnectionError};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::{
+    connection::Connection,
+    endpoint::{Endpoint, Reconnect},
+    recv_stream::RecvStream,
+    send_stream::SendStream,
+    transport::Socket,
+};
+
+/// A client connection which is transparently replaced by a new one once lost
+///
+/// The server is dialed on first use, and again, with the same configuration, whenever the
+/// connection in use has been closed or lost by the time another one is needed. Concurrent callers
+/// share a single attempt. Streams always belong to a single connection, so those open when the
+/// connection is lost fail as usual; [`request()`] repeats idempotent requests interrupted this
+/// way, and hooks registered with [`on_reconnect()`] can replay whatever state the application had
+/// established on the old connection.
+///
+/// Only the connection handle is kept, so streams and datagrams sent by the server are ignored.
+/// Dialing fails once every handle to the endpoint has been dropped.
+///
+/// [`request()`]: ReconnectingConnection::request
+/// [`on_reconnect()`]: ReconnectingConnection::on_reconnect
+pub struct ReconnectingConnection<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    reconnect: Reconnect<S, T>,
+    current: Mutex<Option<Connection<S, T>>>,
+    hooks: Vec<Hook<S, T>>,
+}
+
+type Hook<S, T> = Box<dyn Fn(Connection<S, T>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+impl<S, T> ReconnectingConnection<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    /// Connect to `server_name` at `addr` through `endpoint`, once first needed
+    ///
+    /// Uses the endpoint's default client configuration as of now. See [`Endpoint::connect()`].
+    pub fn new(endpoint: &Endpoint<S, T>, addr: &T::Address, server_name: &str) -> Self {
+        Self::with_config(
+            endpoint,
+            endpoint.default_client_config(),
+            addr,
+            server_name,
+        )
+    }
+
+    /// Connect to `server_name` at `addr` through `endpoint` with `config`, once first needed
+    pub fn with_config(
+        endpoint: &Endpoint<S, T>,
+        config: ClientConfig<S>,
+        addr: &T::Address,
+        server_name: &str,
+    ) -> Self {
+        Self {
+            reconnect: Reconnect::new(endpoint, config, addr, server_name),
+            current: Mutex::new(None),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Run `hook` on every connection that replaces a lost one, before it's handed out
+    ///
+    /// Lets applications replay requests that are safe to repeat, or re-establish session state,
+    /// ahead of any new streams. Hooks run in the order they were registered in, and must use the
+    /// connection they're passed rather than calling back into this `ReconnectingConnection`.
+    pub fn on_reconnect<F, Fut>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(Connection<S, T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.hooks.push(Box::new(move |conn| hook(conn).boxed()));
+        self
+    }
+
+    /// The current connection, dialing the server first if there's none or it's been lost
+    pub async fn connection(&self) -> Result<Connection<S, T>, ReconnectError<T::Address>> {
+        let mut current = self.current.lock().await;
+        if let Some(ref conn) = *current {
+            if !conn.is_closed() {
+                return Ok(conn.clone());
+            }
+        }
+        let replacing = current.is_some();
+        let conn = self.reconnect.start()?.await?.connection;
+        if replacing {
+            debug!("reconnected to {}", conn.remote_address());
+            for hook in &self.hooks {
+                hook(conn.clone()).await;
+            }
+        }
+        *current = Some(conn.clone());
+        Ok(conn)
+    }
+
+    /// Open a unidirectional stream on the current connection, dialing the server if need be
+    pub async fn open_uni(&self) -> Result<SendStream<S, T>, ReconnectError<T::Address>> {
+        Ok(self.connection().await?.open_uni().await?)
+    }
+
+    /// Open a bidirectional stream on the current connection, dialing the server if need be
+    pub async fn open_bi(
+        &self,
+    ) -> Result<(SendStream<S, T>, RecvStream<S, T>), ReconnectError<T::Address>> {
+        Ok(self.connection().await?.open_bi().await?)
+    }
+
+    /// Perform an idempotent request, repeating it once on a new connection if its connection is
+    /// lost before it completes
+    ///
+    /// `request` is passed the connection to perform the request on. Its failure is only retried
+    /// if the connection was closed or lost by then; otherwise, or if the second attempt fails as
+    /// well, its error is returned.
+    pub async fn request<F, Fut, R, E>(&self, mut request: F) -> Result<R, E>
+    where
+        F: FnMut(Connection<S, T>) -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        E: From<ReconnectError<T::Address>>,
+    {
+        let conn = self.connection().await?;
+        match request(conn.clone()).await {
+            Err(_) if conn.is_closed() => {
+                debug!("connection lost during request, retrying");
+                request(self.connection().await?).await
+            }
+            result => result,
+        }
+    }
+}
+
+impl<S, T> fmt::Debug for ReconnectingConnection<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingConnection")
+            .field("reconnect", &self.reconnect)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+/// Errors from dialing the server for a [`ReconnectingConnection`], or using the connection
+///
+/// [`ReconnectingConnection`]: crate::generic::ReconnectingConnection
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ReconnectError<A: Address> {
+    /// The connection couldn't be started
+    #[error(transparent)]
+    Connect(#[from] ConnectError<A>),
+    /// The connection failed or was lost
+    #[error(transparent)]
+    Connection(#[from] ConnectionError),
+}
+
+impl<A: Address> From<ReconnectError<A>> for io::Error {
+    fn from(x: ReconnectError<A>) -> Self {
+        match x {
+            ReconnectError::Connect(e) => io::Error::new(io::ErrorKind::Other, e),
+            ReconnectError::Connection(e) => e.into(),
+        }
+    }
+}
@@ -6,11 +6,13 @@ use std::{
 };
 
 use bytes::Bytes;
-use futures::{io::AsyncRead, ready};
-use proto::{Chunk, Chunks, ConnectionError, ReadableError, StreamId};
+use futures::{io::AsyncRead, ready, FutureExt};
+use proto::{Chunk, Chunks, ConnectionError, ReadableError, RecvStreamStats, StreamId};
 use thiserror::Error;
 use tokio::io::ReadBuf;
 
+#[cfg(feature = "debug_counters")]
+use crate::debug_counters::{Kind, Tracked};
 use crate::{connection::ConnectionRef, transport::Socket, VarInt};
 
 /// A stream that can only be used to receive data
@@ -35,6 +37,10 @@ where
     is_0rtt: bool,
     all_data_read: bool,
     reset: Option<VarInt>,
+    /// Final statistics, once the end of the stream has been read
+    stats: Option<RecvStreamStats>,
+    #[cfg(feature = "debug_counters")]
+    _tracked: Option<Tracked>,
 }
 
 impl<S, T> RecvStream<S, T>
@@ -43,12 +49,21 @@ where
     T: Socket,
 {
     pub(crate) fn new(conn: ConnectionRef<S, T>, stream: StreamId, is_0rtt: bool) -> Self {
+        #[cfg(feature = "debug_counters")]
+        let tracked = conn
+            .lock("RecvStream::new")
+            .tracked
+            .as_ref()
+            .map(|x| x.sibling(Kind::Stream));
         Self {
             conn,
             stream,
             is_0rtt,
             all_data_read: false,
             reset: None,
+            stats: None,
+            #[cfg(feature = "debug_counters")]
+            _tracked: tracked,
         }
     }
 
@@ -84,7 +99,51 @@ where
         }
     }
 
-    fn poll_read(
+    /// Wait until the stream can be read from without blocking
+    ///
+    /// Completes once an ordered read would yield data, the end of the stream, or an error, without
+    /// consuming anything, like tokio's `TcpStream::readable()`. Lets applications that manage
+    /// their own buffers wait for data before committing a buffer to it.
+    pub fn readable(&mut self) -> Readable<'_, S, T> {
+        Readable { stream: self }
+    }
+
+    /// Foundation of [`readable()`](Self::readable), for use in manually implemented futures
+    pub fn poll_readable(&mut self, cx: &mut Context) -> Poll<Result<(), ReadError>> {
+        if self.all_data_read || self.reset.is_some() {
+            return Poll::Ready(Ok(()));
+        }
+        let mut conn = self.conn.lock("RecvStream::poll_readable");
+        if self.is_0rtt {
+            conn.check_0rtt().map_err(|()| ReadError::ZeroRttRejected)?;
+        }
+        match conn.inner.recv_stream(self.stream).is_readable() {
+            // Reads report unknown streams themselves
+            Ok(true) | Err(_) => return Poll::Ready(Ok(())),
+            Ok(false) => {}
+        }
+        if let Some(ref x) = conn.error {
+            return Poll::Ready(Err(ReadError::ConnectionClosed(x.clone())));
+        }
+        conn.blocked_readers.insert(self.stream, cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Read data contiguously from the stream without waiting
+    ///
+    /// Like [`read()`](Self::read), but fails with [`TryReadError::Blocked`] rather than waiting
+    /// if no data is available yet. For poll-based state machines, typically paired with
+    /// [`readable()`](Self::readable) to learn when to try again.
+    ///
+    /// [`TryReadError::Blocked`]: crate::TryReadError::Blocked
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<Option<usize>, TryReadError> {
+        match self.read(buf).now_or_never() {
+            Some(x) => Ok(x?),
+            None => Err(TryReadError::Blocked),
+        }
+    }
+
+    pub(crate) fn poll_read(
         &mut self,
         cx: &mut Context,
         buf: &mut ReadBuf<'_>,
@@ -132,8 +191,20 @@ where
         }
     }
 
+    /// Convert into a stream of the remaining data, in order
+    ///
+    /// Equivalent to calling [`read_chunk()`](Self::read_chunk) in a loop with `ordered` set, but
+    /// owns the `RecvStream`, so it can be moved into another task or combined with stream
+    /// adapters. Ends once the stream is finished, or after yielding an error.
+    pub fn into_chunks(self) -> IntoChunks<S, T> {
+        IntoChunks {
+            stream: self,
+            done: false,
+        }
+    }
+
     /// Foundation of [`read_chunk()`]: RecvStream::read_chunk
-    fn poll_read_chunk(
+    pub(crate) fn poll_read_chunk(
         &mut self,
         cx: &mut Context,
         max_length: usize,
@@ -206,6 +277,36 @@ where
         }
     }
 
+    /// Read all remaining data, separating out a trailer written by
+    /// [`SendStream::finish_with()`]
+    ///
+    /// Yields the data preceding the trailer, and the trailer itself. Fails with
+    /// [`ReadTrailerError::MissingTrailer`] if the stream doesn't end in a trailer. `size_limit`
+    /// applies to the stream as a whole, including the trailer. See
+    /// [`read_to_end()`](Self::read_to_end) for details.
+    ///
+    /// [`SendStream::finish_with()`]: crate::generic::SendStream::finish_with
+    /// [`ReadTrailerError::MissingTrailer`]: crate::ReadTrailerError::MissingTrailer
+    pub async fn read_to_end_with_trailer(
+        self,
+        size_limit: usize,
+    ) -> Result<(Vec<u8>, Bytes), ReadTrailerError> {
+        let mut data = self.read_to_end(size_limit).await?;
+        if data.len() < TRAILER_LEN_SIZE {
+            return Err(ReadTrailerError::MissingTrailer);
+        }
+        let mut len = [0; TRAILER_LEN_SIZE];
+        len.copy_from_slice(&data[data.len() - TRAILER_LEN_SIZE..]);
+        let len = u32::from_be_bytes(len) as usize;
+        let body_len = match (data.len() - TRAILER_LEN_SIZE).checked_sub(len) {
+            Some(x) => x,
+            None => return Err(ReadTrailerError::MissingTrailer),
+        };
+        let mut trailer = data.split_off(body_len);
+        trailer.truncate(len);
+        Ok((data, trailer.into()))
+    }
+
     /// Stop accepting data
     ///
     /// Discards unread data and notifies the peer to stop transmitting. Once stopped, further
@@ -221,6 +322,34 @@ where
         Ok(())
     }
 
+    /// Statistics about the stream
+    ///
+    /// Remains available once the end of the stream has been read, yielding the stream's final
+    /// statistics.
+    pub fn stats(&self) -> Result<RecvStreamStats, UnknownStream> {
+        if let Some(stats) = self.stats {
+            return Ok(stats);
+        }
+        let mut conn = self.conn.lock("RecvStream::stats");
+        Ok(conn.inner.recv_stream(self.stream).stats()?)
+    }
+
+    /// Allow the peer to send `bytes` more data on this stream
+    ///
+    /// Only has an effect on connections using [`CreditPolicy::Manual`], where this is the only
+    /// way for stream-level flow control credit to be returned to the peer.
+    ///
+    /// [`CreditPolicy::Manual`]: crate::CreditPolicy::Manual
+    pub fn credit(&mut self, bytes: u64) -> Result<(), UnknownStream> {
+        let mut conn = self.conn.lock("RecvStream::credit");
+        if self.is_0rtt && conn.check_0rtt().is_err() {
+            return Ok(());
+        }
+        conn.inner.recv_stream(self.stream).credit(bytes)?;
+        conn.wake();
+        Ok(())
+    }
+
     /// Check if this stream has been opened during 0-RTT.
     ///
     /// In which case any non-idempotent request should be considered dangerous at the application
@@ -264,9 +393,13 @@ where
         let status = match self.reset.take() {
             Some(code) => ReadStatus::Failed(None, Reset(code)),
             None => {
+                let now = conn.clock.now();
                 let mut recv = conn.inner.recv_stream(self.stream);
-                let mut chunks = recv.read(ordered)?;
+                let mut chunks = recv.read_at(ordered, now)?;
                 let status = read_fn(&mut chunks);
+                if let ReadStatus::Finished(_) = status {
+                    self.stats = chunks.stats();
+                }
                 if chunks.finalize().should_transmit() {
                     conn.wake();
                 }
@@ -383,6 +516,24 @@ pub enum ReadToEndError {
     TooLong,
 }
 
+/// Error from [`RecvStream::read_to_end_with_trailer()`]
+///
+/// [`RecvStream::read_to_end_with_trailer()`]: crate::generic::RecvStream::read_to_end_with_trailer
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ReadTrailerError {
+    /// An error occurred while reading the stream
+    #[error("{0}")]
+    ReadToEnd(#[from] ReadToEndError),
+    /// The stream doesn't end with a trailer as written by [`SendStream::finish_with()`]
+    ///
+    /// [`SendStream::finish_with()`]: crate::generic::SendStream::finish_with
+    #[error("missing trailer")]
+    MissingTrailer,
+}
+
+/// Size of the length suffix following a trailer
+const TRAILER_LEN_SIZE: usize = 4;
+
 impl<S, T> AsyncRead for RecvStream<S, T>
 where
     S: proto::crypto::Session,
@@ -483,6 +634,26 @@ impl From<ReadError> for io::Error {
     }
 }
 
+/// Errors that arise from reading from a stream without waiting
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum TryReadError {
+    /// No data is available to read yet
+    #[error("blocked")]
+    Blocked,
+    /// A read error occurred
+    #[error("{0}")]
+    Read(#[from] ReadError),
+}
+
+impl From<TryReadError> for io::Error {
+    fn from(x: TryReadError) -> Self {
+        match x {
+            TryReadError::Blocked => io::Error::new(io::ErrorKind::WouldBlock, x),
+            TryReadError::Read(e) => e.into(),
+        }
+    }
+}
+
 /// Future produced by [`RecvStream::read()`].
 ///
 /// [`RecvStream::read()`]: crate::generic::RecvStream::read
@@ -581,6 +752,36 @@ where
     }
 }
 
+/// Stream produced by [`RecvStream::into_chunks()`].
+///
+/// [`RecvStream::into_chunks()`]: crate::generic::RecvStream::into_chunks
+#[derive(Debug)]
+pub struct IntoChunks<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    stream: RecvStream<S, T>,
+    done: bool,
+}
+
+impl<S, T> futures::Stream for IntoChunks<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Item = Result<Chunk, ReadError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let result = ready!(self.stream.poll_read_chunk(cx, usize::MAX, true)).transpose();
+        self.done = !matches!(result, Some(Ok(_)));
+        Poll::Ready(result)
+    }
+}
+
 /// Future produced by [`RecvStream::read_chunks()`].
 ///
 /// [`RecvStream::read_chunks()`]: crate::generic::RecvStream::read_chunks
@@ -605,6 +806,28 @@ where
     }
 }
 
+/// Future produced by [`RecvStream::readable()`].
+///
+/// [`RecvStream::readable()`]: crate::generic::RecvStream::readable
+pub struct Readable<'a, S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    stream: &'a mut RecvStream<S, T>,
+}
+
+impl<'a, S, T> Future for Readable<'a, S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Output = Result<(), ReadError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().stream.poll_readable(cx)
+    }
+}
+
 /// Error indicating that a stream has already been finished or reset
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
 #[error("unknown stream")]
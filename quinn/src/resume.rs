@@ -0,0 +1,272 @@
+//! Transfers of large objects which can be resumed after a connection is lost
+//!
+//! A transfer moves the contents of a seekable source, identified by an application-chosen id, to
+//! a sink on the peer over a bidirectional stream opened by the sender with [`send()`]. The
+//! receiver passes incoming bidirectional streams to [`accept()`], decides where the object
+//! identified by [`IncomingTransfer::id()`] should be stored, and completes the transfer with
+//! [`IncomingTransfer::receive()`].
+//!
+//! If the connection is lost partway through, the application simply starts the same transfer
+//! again once it has reconnected. The receiver reports how much of the object its sink already
+//! holds, and only the remainder is sent.
+//!
+//! Both sides compute a digest of the object. Before resuming, the sender checks the digest of the
+//! prefix held by the receiver against its own source, and starts over from the beginning if they
+//! differ, e.g. because the source changed in the meantime. Once all data has been sent, the
+//! receiver checks the digest of the entire object, resetting and stopping the stream with
+//! [`INTEGRITY_FAILURE`] if it doesn't match, or finishing its side of the stream to confirm the
+//! transfer otherwise. The digest is not cryptographically secure: it guards against diverging
+//! copies, while data in transit is already protected by QUIC itself.
+//!
+//! All integers on the wire are 64 bits wide and big-endian:
+//!
+//! 1. The sender sends the transfer's id and the size of the object.
+//! 2. The receiver replies with the number of bytes it holds and their digest.
+//! 3. The sender sends the offset it resumes from, which is either zero or the number of bytes the
+//!    receiver holds, followed by the object's data from that offset on, and finally the digest of
+//!    the entire object.
+
+use std::io::{self, Cursor, SeekFrom};
+
+use futures::{future::BoxFuture, FutureExt};
+#[cfg(feature = "runtime-tokio")]
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tracing::debug;
+
+use crate::{
+    bi_stream::BiStream, connection::Connection, recv_stream::ReadError, transport::Socket, VarInt,
+};
+
+/// Stream error code signaling that the received object didn't match the sender's digest
+pub const INTEGRITY_FAILURE: VarInt = VarInt::from_u32(1);
+
+/// Transfer the contents of `source`, identified by `id`, over a new bidirectional stream on
+/// `connection`
+///
+/// Data the peer already holds from an earlier attempt at the same transfer is skipped. Completes
+/// once the peer has confirmed the integrity of the entire object, returning the number of bytes
+/// of the object which were actually transmitted.
+pub async fn send<S, T, R>(
+    connection: &Connection<S, T>,
+    id: u64,
+    source: &mut R,
+) -> io::Result<u64>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let size = source.seek(SeekFrom::End(0)).await?;
+    send.write_all(&encode(id, size)).await?;
+
+    let held = recv.read_u64().await?;
+    let peer_digest = recv.read_u64().await?;
+    if held > size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "peer holds more data than the object contains",
+        ));
+    }
+    source.seek(SeekFrom::Start(0)).await?;
+    let mut digest = Digest::new();
+    digest.update_from(&mut *source, held).await?;
+    let offset = if digest.get() == peer_digest {
+        held
+    } else {
+        debug!(id, held, "peer's data doesn't match, starting over");
+        source.seek(SeekFrom::Start(0)).await?;
+        digest = Digest::new();
+        0
+    };
+    send.write_all(&offset.to_be_bytes()).await?;
+
+    let mut buf = vec![0; BUF_SIZE];
+    let mut remaining = size - offset;
+    while remaining > 0 {
+        let n = source
+            .read(&mut buf[..remaining.min(BUF_SIZE as u64) as usize])
+            .await?;
+        if n == 0 {
+            let _ = send.reset(VarInt::from_u32(0));
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        send.write_all(&buf[..n]).await?;
+        digest.update(&buf[..n]);
+        remaining -= n as u64;
+    }
+    send.write_all(&digest.get().to_be_bytes()).await?;
+    send.finish().await?;
+
+    match recv.read_chunk(1, true).await {
+        Ok(None) => Ok(size - offset),
+        Ok(Some(_)) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected data from peer",
+        )),
+        Err(ReadError::Reset(code)) if code == INTEGRITY_FAILURE => Err(integrity_failure()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read the header of a transfer from a bidirectional stream opened by the peer
+pub async fn accept<S, T>(mut stream: BiStream<S, T>) -> io::Result<IncomingTransfer<S, T>>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    let id = stream.recv.read_u64().await?;
+    let size = stream.recv.read_u64().await?;
+    Ok(IncomingTransfer { stream, id, size })
+}
+
+/// A transfer started by the peer, obtained from [`accept()`]
+#[derive(Debug)]
+pub struct IncomingTransfer<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    stream: BiStream<S, T>,
+    id: u64,
+    size: u64,
+}
+
+impl<S, T> IncomingTransfer<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// The id the sender chose for the transfer
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The size of the object being transferred, in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Complete the transfer into `sink`
+    ///
+    /// `sink` should be empty or hold the data written to it by an earlier attempt at the same
+    /// transfer, which is kept if it still matches the sender's object. Anything else it holds is
+    /// discarded, so that it ends up holding exactly the object. Returns the number of bytes of the
+    /// object which were actually received.
+    pub async fn receive<W: Sink>(self, sink: &mut W) -> io::Result<u64> {
+        let BiStream { mut send, mut recv } = self.stream;
+        let held = sink.seek(SeekFrom::End(0)).await?.min(self.size);
+        sink.seek(SeekFrom::Start(0)).await?;
+        let mut digest = Digest::new();
+        digest.update_from(&mut *sink, held).await?;
+        send.write_all(&encode(held, digest.get())).await?;
+
+        let offset = recv.read_u64().await?;
+        if offset == 0 {
+            digest = Digest::new();
+        } else if offset != held {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "peer resumed from an unexpected offset",
+            ));
+        }
+        debug!(id = self.id, offset, size = self.size, "receiving");
+        sink.set_len(offset).await?;
+        sink.seek(SeekFrom::Start(offset)).await?;
+
+        let mut remaining = self.size - offset;
+        while remaining > 0 {
+            let max_length = remaining.min(usize::max_value() as u64) as usize;
+            let chunk = match recv.read_chunk(max_length, true).await? {
+                Some(x) => x,
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+            sink.write_all(&chunk.bytes).await?;
+            digest.update(&chunk.bytes);
+            remaining -= chunk.bytes.len() as u64;
+        }
+        sink.flush().await?;
+
+        if recv.read_u64().await? != digest.get() {
+            let _ = recv.stop(INTEGRITY_FAILURE);
+            let _ = send.reset(INTEGRITY_FAILURE);
+            return Err(integrity_failure());
+        }
+        send.finish().await?;
+        Ok(self.size - offset)
+    }
+}
+
+/// Storage an object is received into with [`IncomingTransfer::receive()`]
+pub trait Sink: AsyncRead + AsyncWrite + AsyncSeek + Unpin {
+    /// Truncate or extend the storage to `len` bytes
+    fn set_len(&mut self, len: u64) -> BoxFuture<'_, io::Result<()>>;
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl Sink for File {
+    fn set_len(&mut self, len: u64) -> BoxFuture<'_, io::Result<()>> {
+        File::set_len(self, len).boxed()
+    }
+}
+
+impl Sink for Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> BoxFuture<'_, io::Result<()>> {
+        self.get_mut().resize(len as usize, 0);
+        futures::future::ok(()).boxed()
+    }
+}
+
+fn encode(a: u64, b: u64) -> [u8; 16] {
+    let mut buf = [0; 16];
+    buf[..8].copy_from_slice(&a.to_be_bytes());
+    buf[8..].copy_from_slice(&b.to_be_bytes());
+    buf
+}
+
+fn integrity_failure() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "transferred object failed integrity check",
+    )
+}
+
+/// 64-bit FNV-1a
+struct Digest(u64);
+
+impl Digest {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Digest the next `len` bytes of `reader`
+    async fn update_from<R: AsyncRead + Unpin>(&mut self, reader: R, len: u64) -> io::Result<()> {
+        let mut reader = reader.take(len);
+        let mut buf = vec![0; BUF_SIZE];
+        let mut read = 0;
+        while read < len {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.update(&buf[..n]);
+            read += n as u64;
+        }
+        Ok(())
+    }
+
+    fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Size of the buffer used to read from sources and sinks
+const BUF_SIZE: usize = 64 * 1024;
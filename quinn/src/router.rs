@@ -0,0 +1,198 @@
+use std::{fmt, future::Future, sync::Arc};
+
+use bytes::{Bytes, BytesMut};
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt, TryStreamExt};
+use tracing::debug;
+
+use crate::{
+    connection::{IncomingBiStreams, IncomingUniStreams},
+    recv_stream::{ReadError, RecvStream},
+    send_stream::SendStream,
+    transport::Socket,
+    ConnectionError, VarInt,
+};
+
+/// Dispatches streams opened by the peer to handlers according to their first bytes
+///
+/// Multi-protocol servers typically start every stream with a short tag identifying what the
+/// stream carries. A `StreamRouter` reads up to `prefix_len` bytes from the start of each
+/// incoming stream and passes them, along with the stream itself, to the first handler whose
+/// pattern the prefix starts with. The stream's remaining data is left for the handler to read.
+///
+/// Streams matching no pattern are passed to the fallback handler if one was set, and are
+/// otherwise stopped and reset with error code 0.
+pub struct StreamRouter<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    prefix_len: usize,
+    routes: Vec<(Bytes, Handler<S, T>)>,
+    fallback: Option<Handler<S, T>>,
+}
+
+type Handler<S, T> = Box<dyn Fn(Bytes, RoutedStream<S, T>) -> BoxFuture<'static, ()> + Send + Sync>;
+
+impl<S, T> StreamRouter<S, T>
+where
+    S: proto::crypto::Session + 'static,
+    T: Socket,
+{
+    /// Create a router which reads up to `prefix_len` bytes from each incoming stream
+    pub fn new(prefix_len: usize) -> Self {
+        Self {
+            prefix_len,
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Pass streams whose prefix starts with `pattern` to `handler`
+    ///
+    /// Patterns are tried in the order they were registered in.
+    ///
+    /// # Panics
+    ///
+    /// If `pattern` is longer than the router's `prefix_len`, as it could never match.
+    pub fn route<F, Fut>(&mut self, pattern: impl Into<Bytes>, handler: F) -> &mut Self
+    where
+        F: Fn(Bytes, RoutedStream<S, T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let pattern = pattern.into();
+        assert!(
+            pattern.len() <= self.prefix_len,
+            "pattern longer than prefix_len"
+        );
+        self.routes.push((
+            pattern,
+            Box::new(move |prefix, stream| handler(prefix, stream).boxed()),
+        ));
+        self
+    }
+
+    /// Pass streams which match no pattern to `handler`
+    pub fn fallback<F, Fut>(&mut self, handler: F) -> &mut Self
+    where
+        F: Fn(Bytes, RoutedStream<S, T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.fallback = Some(Box::new(move |prefix, stream| {
+            handler(prefix, stream).boxed()
+        }));
+        self
+    }
+
+    /// Dispatch every stream the peer opens until the connection is closed
+    ///
+    /// Each stream is read from and handled on its own task, so slow streams don't hold up
+    /// others. Completes once the connection is closed, successfully only if it was closed
+    /// locally.
+    ///
+    /// # Panics
+    ///
+    /// When called outside the context of a Tokio runtime.
+    pub async fn run(
+        self,
+        uni_streams: IncomingUniStreams<S, T>,
+        bi_streams: IncomingBiStreams<S, T>,
+    ) -> Result<(), ConnectionError> {
+        let router = Arc::new(self);
+        let mut incoming = stream::select(
+            uni_streams.map_ok(RoutedStream::Uni),
+            bi_streams.map_ok(|(send, recv)| RoutedStream::Bi(send, recv)),
+        );
+        while let Some(stream) = incoming.next().await {
+            let stream = stream?;
+            let router = router.clone();
+            tokio::spawn(async move { router.dispatch(stream).await });
+        }
+        Ok(())
+    }
+
+    async fn dispatch(&self, mut stream: RoutedStream<S, T>) {
+        let prefix = match read_prefix(stream.recv(), self.prefix_len).await {
+            Ok(x) => x,
+            Err(e) => {
+                debug!("failed to read stream prefix: {}", e);
+                return;
+            }
+        };
+        let handler = self
+            .routes
+            .iter()
+            .find(|(pattern, _)| prefix.starts_with(pattern))
+            .map(|(_, handler)| handler)
+            .or(self.fallback.as_ref());
+        match handler {
+            Some(handler) => handler(prefix, stream).await,
+            None => {
+                debug!(?prefix, "no route for stream");
+                let code = VarInt::from_u32(0);
+                if let RoutedStream::Bi(ref mut send, _) = stream {
+                    let _ = send.reset(code);
+                }
+                let _ = stream.recv().stop(code);
+            }
+        }
+    }
+}
+
+impl<S, T> fmt::Debug for StreamRouter<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamRouter")
+            .field("prefix_len", &self.prefix_len)
+            .field(
+                "routes",
+                &self.routes.iter().map(|(x, _)| x).collect::<Vec<_>>(),
+            )
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// A stream opened by the peer, as passed to a `StreamRouter`'s handlers
+#[derive(Debug)]
+pub enum RoutedStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// A unidirectional stream
+    Uni(RecvStream<S, T>),
+    /// A bidirectional stream
+    Bi(SendStream<S, T>, RecvStream<S, T>),
+}
+
+impl<S, T> RoutedStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    /// The receiving half of the stream
+    pub fn recv(&mut self) -> &mut RecvStream<S, T> {
+        match self {
+            RoutedStream::Uni(recv) | RoutedStream::Bi(_, recv) => recv,
+        }
+    }
+}
+
+/// Read up to `len` bytes, or fewer if the stream finishes first
+async fn read_prefix<S, T>(recv: &mut RecvStream<S, T>, len: usize) -> Result<Bytes, ReadError>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    let mut prefix = BytesMut::with_capacity(len);
+    while prefix.len() < len {
+        match recv.read_chunk(len - prefix.len(), true).await? {
+            Some(chunk) => prefix.extend_from_slice(&chunk.bytes),
+            None => break,
+        }
+    }
+    Ok(prefix.freeze())
+}
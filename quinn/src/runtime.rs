@@ -0,0 +1,116 @@
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use futures::task::{FutureObj, Spawn};
+#[cfg(feature = "runtime-tokio")]
+use tokio::runtime::Handle;
+use tracing::error;
+
+use crate::clock::Clock;
+#[cfg(feature = "runtime-tokio")]
+use crate::clock::TokioClock;
+
+/// An async runtime for an endpoint and its connections to run on
+///
+/// Supplies the tasks driving an endpoint and its connections with somewhere to run, and the clock
+/// their timers are constructed from. Sockets are passed separately, through
+/// [`EndpointBuilder::with_socket()`]; the built-in [`UdpSocket`] relies on tokio's I/O driver, so
+/// endpoints on other runtimes need a [`Socket`] of their own.
+///
+/// See [`EndpointBuilder::runtime()`].
+///
+/// [`EndpointBuilder::with_socket()`]: crate::generic::EndpointBuilder::with_socket
+/// [`EndpointBuilder::runtime()`]: crate::generic::EndpointBuilder::runtime
+/// [`UdpSocket`]: crate::transport::UdpSocket
+/// [`Socket`]: crate::transport::Socket
+pub trait Runtime: Send + Sync + fmt::Debug + 'static {
+    /// Run `future` to completion in the background
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>);
+
+    /// The clock to construct timers from
+    fn clock(&self) -> Arc<dyn Clock>;
+}
+
+/// The default [`Runtime`], spawning tasks on the current tokio runtime
+///
+/// Timers are driven by [`TokioClock`].
+#[cfg(feature = "runtime-tokio")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "runtime-tokio")]
+impl Runtime for TokioRuntime {
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        tokio::spawn(future);
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::new(TokioClock)
+    }
+}
+
+/// Spawns tasks on a specific tokio runtime, rather than the current one
+#[cfg(feature = "runtime-tokio")]
+#[derive(Debug, Clone)]
+pub(crate) struct HandleRuntime(pub(crate) Handle);
+
+#[cfg(feature = "runtime-tokio")]
+impl Runtime for HandleRuntime {
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        self.0.spawn(future);
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::new(TokioClock)
+    }
+}
+
+/// The runtime of endpoints which aren't given one, if any
+pub(crate) fn default_runtime() -> Option<Arc<dyn Runtime>> {
+    #[cfg(feature = "runtime-tokio")]
+    return Some(Arc::new(TokioRuntime));
+    #[cfg(not(feature = "runtime-tokio"))]
+    None
+}
+
+/// A [`Runtime`] spawning tasks onto any executor implementing [`Spawn`]
+///
+/// Such as `futures::executor::ThreadPool`. Executors don't come with timers, so a clock has to be
+/// supplied as well, e.g. [`ThreadClock`](crate::ThreadClock).
+pub struct ExecutorRuntime<E> {
+    executor: E,
+    clock: Arc<dyn Clock>,
+}
+
+impl<E> ExecutorRuntime<E>
+where
+    E: Spawn + Send + Sync + 'static,
+{
+    /// Spawn tasks onto `executor`, with timers constructed from `clock`
+    pub fn new(executor: E, clock: Arc<dyn Clock>) -> Self {
+        Self { executor, clock }
+    }
+}
+
+impl<E> Runtime for ExecutorRuntime<E>
+where
+    E: Spawn + Send + Sync + 'static,
+{
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        if let Err(e) = self.executor.spawn_obj(FutureObj::new(future)) {
+            error!("failed to spawn task: {}", e);
+        }
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+}
+
+impl<E> fmt::Debug for ExecutorRuntime<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExecutorRuntime")
+            .field("executor", &"[ opaque ]")
+            .field("clock", &self.clock)
+            .finish()
+    }
+}
@@ -1,22 +1,35 @@
 use std::{
+    convert::TryFrom,
     future::Future,
-    io,
+    io, mem,
     pin::Pin,
     task::{Context, Poll},
+    time::Instant,
 };
 
 use bytes::Bytes;
-use futures::{channel::oneshot, io::AsyncWrite, ready, FutureExt};
-use proto::{ConnectionError, FinishError, StreamId, Written};
+use futures::{channel::oneshot, io::AsyncWrite, ready, FutureExt, Sink};
+use proto::{
+    Coalescing, ConnectionError, FinishError, Reliability, SendStreamStats, StreamId, Written,
+};
 use thiserror::Error;
 
-use crate::{connection::ConnectionRef, recv_stream::UnknownStream, transport::Socket, VarInt};
+#[cfg(feature = "debug_counters")]
+use crate::debug_counters::{Kind, Tracked};
+use crate::{
+    clock::AsyncTimer, connection::ConnectionRef, recv_stream::UnknownStream, transport::Socket,
+    VarInt,
+};
 
 /// A stream that can only be used to send data
 ///
 /// If dropped, streams that haven't been explicitly [`reset()`] will continue to (re)transmit
 /// previously written data until it has been fully acknowledged or the connection is closed.
 ///
+/// Also usable as a [`Sink`] of chunks, for use with stream combinators and codecs. Flow control
+/// applies backpressure by delaying readiness until the previous chunk has been written. Data
+/// given to the sink that hasn't been flushed yet is lost if the stream is dropped.
+///
 /// [`reset()`]: SendStream::reset
 #[derive(Debug)]
 pub struct SendStream<S, T>
@@ -27,7 +40,13 @@ where
     conn: ConnectionRef<S, T>,
     stream: StreamId,
     is_0rtt: bool,
-    finishing: Option<oneshot::Receiver<Option<WriteError>>>,
+    finishing: Option<oneshot::Receiver<Result<SendStreamStats, WriteError>>>,
+    /// Final statistics, once the stream has been finished
+    stats: Option<SendStreamStats>,
+    /// Data passed to `Sink::start_send` that hasn't been written yet
+    sink_pending: Bytes,
+    #[cfg(feature = "debug_counters")]
+    _tracked: Option<Tracked>,
 }
 
 impl<S, T> SendStream<S, T>
@@ -36,11 +55,21 @@ where
     T: Socket,
 {
     pub(crate) fn new(conn: ConnectionRef<S, T>, stream: StreamId, is_0rtt: bool) -> Self {
+        #[cfg(feature = "debug_counters")]
+        let tracked = conn
+            .lock("SendStream::new")
+            .tracked
+            .as_ref()
+            .map(|x| x.sibling(Kind::Stream));
         Self {
             conn,
             stream,
             is_0rtt,
             finishing: None,
+            stats: None,
+            sink_pending: Bytes::new(),
+            #[cfg(feature = "debug_counters")]
+            _tracked: tracked,
         }
     }
 
@@ -57,6 +86,25 @@ where
         WriteAll { stream: self, buf }
     }
 
+    /// Write an entire buffer to the stream, or reset the stream if that can't be done by
+    /// `deadline`
+    ///
+    /// Bounds the time a message may spend held back by flow or congestion control. If the
+    /// deadline passes first, the stream is reset with error code 0, so the peer doesn't wait for
+    /// the rest of the message, and this fails with [`WriteError::TimedOut`].
+    pub fn write_with_deadline<'a>(
+        &'a mut self,
+        buf: &'a [u8],
+        deadline: Instant,
+    ) -> WriteWithDeadline<'a, S, T> {
+        WriteWithDeadline {
+            stream: self,
+            buf,
+            deadline,
+            timer: None,
+        }
+    }
+
     /// Write chunks to the stream
     ///
     /// Yields the number of bytes and chunks written on success.
@@ -83,7 +131,55 @@ where
         }
     }
 
-    fn execute_poll<F, R>(&mut self, cx: &mut Context, write_fn: F) -> Poll<Result<R, WriteError>>
+    /// Wait until the stream can be written to without blocking
+    ///
+    /// Completes once flow control permits writing at least one byte, or a write would fail,
+    /// without writing anything, like tokio's `TcpStream::writable()`. Lets applications that
+    /// manage their own buffers defer producing data until it can be sent.
+    pub fn writable(&mut self) -> Writable<'_, S, T> {
+        Writable { stream: self }
+    }
+
+    /// Foundation of [`writable()`](Self::writable), for use in manually implemented futures
+    pub fn poll_writable(&mut self, cx: &mut Context) -> Poll<Result<(), WriteError>> {
+        let mut conn = self.conn.lock("SendStream::poll_writable");
+        if self.is_0rtt {
+            conn.check_0rtt()
+                .map_err(|()| WriteError::ZeroRttRejected)?;
+        }
+        if let Some(ref x) = conn.error {
+            return Poll::Ready(Err(WriteError::ConnectionClosed(x.clone())));
+        }
+        match conn.inner.send_stream(self.stream).is_writable() {
+            Ok(true) => Poll::Ready(Ok(())),
+            Ok(false) => {
+                conn.blocked_writers.insert(self.stream, cx.waker().clone());
+                Poll::Pending
+            }
+            Err(_) => Poll::Ready(Err(WriteError::UnknownStream)),
+        }
+    }
+
+    /// Write bytes to the stream without waiting
+    ///
+    /// Like [`write()`](Self::write), but fails with [`TryWriteError::Blocked`] rather than
+    /// waiting if flow or congestion control doesn't permit writing anything yet. For poll-based
+    /// state machines, typically paired with [`writable()`](Self::writable) to learn when to try
+    /// again.
+    ///
+    /// [`TryWriteError::Blocked`]: crate::TryWriteError::Blocked
+    pub fn try_write(&mut self, buf: &[u8]) -> Result<usize, TryWriteError> {
+        match self.write(buf).now_or_never() {
+            Some(x) => Ok(x?),
+            None => Err(TryWriteError::Blocked),
+        }
+    }
+
+    pub(crate) fn execute_poll<F, R>(
+        &mut self,
+        cx: &mut Context,
+        write_fn: F,
+    ) -> Poll<Result<R, WriteError>>
     where
         F: FnOnce(&mut proto::SendStream) -> Result<R, proto::WriteError>,
     {
@@ -115,6 +211,17 @@ where
         Poll::Ready(Ok(result))
     }
 
+    /// Write any data passed to `Sink::start_send` that hasn't been written yet
+    fn poll_sink_pending(&mut self, cx: &mut Context) -> Poll<Result<(), WriteError>> {
+        while !self.sink_pending.is_empty() {
+            let mut bufs = [mem::take(&mut self.sink_pending)];
+            let result = self.execute_poll(cx, |s| s.write_chunks(&mut bufs));
+            self.sink_pending = mem::take(&mut bufs[0]);
+            ready!(result)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
     /// Shut down the send stream gracefully.
     ///
     /// No new data may be written after calling this method. Completes when the peer has
@@ -123,6 +230,25 @@ where
         Finish { stream: self }
     }
 
+    /// Write `trailer` as the final data on the stream and shut it down gracefully
+    ///
+    /// The trailer is followed by its length, so the peer can separate it from the rest of the
+    /// stream using [`RecvStream::read_to_end_with_trailer()`]. Useful for final metadata that is
+    /// only known once the body has been written, such as RPC status codes. Completes as
+    /// [`finish()`](Self::finish) does.
+    ///
+    /// # Panics
+    ///
+    /// If `trailer` is longer than `u32::MAX` bytes.
+    ///
+    /// [`RecvStream::read_to_end_with_trailer()`]: crate::generic::RecvStream::read_to_end_with_trailer
+    pub async fn finish_with(&mut self, trailer: Bytes) -> Result<(), WriteError> {
+        let len = u32::try_from(trailer.len()).expect("trailer too long");
+        let len = Bytes::copy_from_slice(&len.to_be_bytes());
+        self.write_all_chunks(&mut [trailer, len]).await?;
+        self.finish().await
+    }
+
     #[doc(hidden)]
     pub fn poll_finish(&mut self, cx: &mut Context) -> Poll<Result<(), WriteError>> {
         let mut conn = self.conn.lock("poll_finish");
@@ -150,8 +276,11 @@ where
             .poll_unpin(cx)
             .map(|x| x.unwrap())
         {
-            Poll::Ready(None) => Poll::Ready(Ok(())),
-            Poll::Ready(Some(e)) => Poll::Ready(Err(e)),
+            Poll::Ready(Ok(stats)) => {
+                self.stats = Some(stats);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
             Poll::Pending => {
                 // To ensure that finished streams can be detected even after the connection is
                 // closed, we must only check for connection errors after determining that the
@@ -194,12 +323,55 @@ where
         Ok(())
     }
 
+    /// Set how long data may go unacknowledged before the stream is abandoned
+    ///
+    /// Streams are reliable by default. With [`Reliability::Deadline`], the stream is reset with
+    /// error code 0 once any data written to it goes unacknowledged for the given duration,
+    /// providing partially reliable delivery of data that's worthless once stale, such as media
+    /// frames. Writes and finishing then fail with [`WriteError::UnknownStream`].
+    ///
+    /// [`Reliability::Deadline`]: crate::Reliability::Deadline
+    pub fn set_reliability(&self, reliability: Reliability) -> Result<(), UnknownStream> {
+        let mut conn = self.conn.lock("SendStream::set_reliability");
+        conn.inner
+            .send_stream(self.stream)
+            .set_reliability(reliability)?;
+        conn.wake();
+        Ok(())
+    }
+
+    /// Set whether small writes may be held back so they can share packets
+    ///
+    /// Off by default. See [`Coalescing`] for details.
+    ///
+    /// [`Coalescing`]: crate::Coalescing
+    pub fn set_coalescing(&self, coalescing: Option<Coalescing>) -> Result<(), UnknownStream> {
+        let mut conn = self.conn.lock("SendStream::set_coalescing");
+        conn.inner
+            .send_stream(self.stream)
+            .set_coalescing(coalescing)?;
+        conn.wake();
+        Ok(())
+    }
+
     /// Get the priority of the send stream
     pub fn priority(&self) -> Result<i32, UnknownStream> {
         let mut conn = self.conn.lock("SendStream::priority");
         Ok(conn.inner.send_stream(self.stream).priority()?)
     }
 
+    /// Statistics about the stream
+    ///
+    /// Remains available once [`finish()`](Self::finish) has completed, yielding the stream's final
+    /// statistics.
+    pub fn stats(&self) -> Result<SendStreamStats, UnknownStream> {
+        if let Some(stats) = self.stats {
+            return Ok(stats);
+        }
+        let mut conn = self.conn.lock("SendStream::stats");
+        Ok(conn.inner.send_stream(self.stream).stats()?)
+    }
+
     /// Completes if/when the peer stops the stream, yielding the error code
     pub fn stopped(&mut self) -> Stopped<'_, S, T> {
         Stopped { stream: self }
@@ -248,6 +420,37 @@ where
     }
 }
 
+impl<S, T> Sink<Bytes> for SendStream<S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Error = WriteError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_sink_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        debug_assert!(
+            self.sink_pending.is_empty(),
+            "start_send called before ready"
+        );
+        self.get_mut().sink_pending = item;
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_sink_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_sink_pending(cx))?;
+        this.poll_finish(cx)
+    }
+}
+
 impl<S, T> tokio::io::AsyncWrite for SendStream<S, T>
 where
     S: proto::crypto::Session,
@@ -393,6 +596,55 @@ where
     }
 }
 
+/// Future produced by [`SendStream::write_with_deadline()`].
+///
+/// [`SendStream::write_with_deadline()`]: crate::generic::SendStream::write_with_deadline
+pub struct WriteWithDeadline<'a, S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    stream: &'a mut SendStream<S, T>,
+    buf: &'a [u8],
+    deadline: Instant,
+    timer: Option<Pin<Box<dyn AsyncTimer>>>,
+}
+
+impl<'a, S, T> Future for WriteWithDeadline<'a, S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Output = Result<(), WriteError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            if this.buf.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            let buf = this.buf;
+            match this.stream.execute_poll(cx, |s| s.write(buf)) {
+                Poll::Ready(result) => this.buf = &this.buf[result?..],
+                Poll::Pending => break,
+            }
+        }
+
+        let stream = &mut *this.stream;
+        let deadline = this.deadline;
+        let timer = this.timer.get_or_insert_with(|| {
+            stream
+                .conn
+                .lock("WriteWithDeadline::poll")
+                .clock
+                .new_timer(deadline)
+        });
+        ready!(timer.as_mut().poll(cx));
+        // The stream may have been reset or finished already, in which case the peer isn't waiting
+        let _ = stream.reset(VarInt::from_u32(0));
+        Poll::Ready(Err(WriteError::TimedOut))
+    }
+}
+
 /// Future produced by [`SendStream::write_chunks()`].
 ///
 /// [`SendStream::write_chunks()`]: crate::generic::SendStream::write_chunks
@@ -480,6 +732,28 @@ where
     }
 }
 
+/// Future produced by [`SendStream::writable()`].
+///
+/// [`SendStream::writable()`]: crate::generic::SendStream::writable
+pub struct Writable<'a, S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    stream: &'a mut SendStream<S, T>,
+}
+
+impl<'a, S, T> Future for Writable<'a, S, T>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+{
+    type Output = Result<(), WriteError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().stream.poll_writable(cx)
+    }
+}
+
 /// Errors that arise from writing to a stream
 #[derive(Debug, Error, Clone, PartialEq, Eq)]
 pub enum WriteError {
@@ -502,6 +776,24 @@ pub enum WriteError {
     /// [`Connecting::into_0rtt()`]: crate::generic::Connecting::into_0rtt()
     #[error("0-RTT rejected")]
     ZeroRttRejected,
+    /// The data couldn't be written before the deadline, so the stream was reset
+    ///
+    /// Only returned by [`SendStream::write_with_deadline()`].
+    ///
+    /// [`SendStream::write_with_deadline()`]: crate::generic::SendStream::write_with_deadline
+    #[error("deadline exceeded")]
+    TimedOut,
+}
+
+/// Errors that arise from writing to a stream without waiting
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum TryWriteError {
+    /// Nothing could be written without waiting
+    #[error("blocked")]
+    Blocked,
+    /// A write error occurred
+    #[error("{0}")]
+    Write(#[from] WriteError),
 }
 
 /// Errors that arise while monitoring for a send stream stop from the peer
@@ -529,7 +821,17 @@ impl From<WriteError> for io::Error {
         let kind = match x {
             Stopped(_) | ZeroRttRejected => io::ErrorKind::ConnectionReset,
             ConnectionClosed(_) | UnknownStream => io::ErrorKind::NotConnected,
+            TimedOut => io::ErrorKind::TimedOut,
         };
         io::Error::new(kind, x)
     }
 }
+
+impl From<TryWriteError> for io::Error {
+    fn from(x: TryWriteError) -> Self {
+        match x {
+            TryWriteError::Blocked => io::Error::new(io::ErrorKind::WouldBlock, x),
+            TryWriteError::Write(e) => e.into(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    fmt,
+    future::Future,
+    task::{Context, Poll},
+};
+
+use futures::{future, stream::FuturesUnordered, FutureExt, StreamExt};
+use thiserror::Error;
+use tower_service::Service;
+use tracing::debug;
+
+use crate::{
+    connection::IncomingBiStreams, recv_stream::RecvStream, send_stream::SendStream,
+    transport::Socket, ConnectionError,
+};
+
+/// Drive `service` with every bidirectional stream opened by the peer
+///
+/// Each incoming stream is passed to `service` as a `(SendStream, RecvStream)` request, and the
+/// resulting future is polled to completion alongside the accept loop. At most `max_concurrent`
+/// requests are in flight at a time; further streams are left unaccepted, which holds back the
+/// peer's stream credit, until one of them completes. Errors returned by individual requests are
+/// logged and otherwise ignored.
+///
+/// Once `shutdown` completes, or the peer stops opening streams, no further streams are accepted
+/// and the returned future completes after all in-flight requests have finished.
+///
+/// # Panics
+///
+/// If `max_concurrent` is zero.
+pub async fn serve<S, T, Svc, F>(
+    mut streams: IncomingBiStreams<S, T>,
+    mut service: Svc,
+    max_concurrent: usize,
+    shutdown: F,
+) -> Result<(), ServeError<Svc::Error>>
+where
+    S: proto::crypto::Session,
+    T: Socket,
+    Svc: Service<(SendStream<S, T>, RecvStream<S, T>)>,
+    Svc::Error: fmt::Display,
+    F: Future<Output = ()>,
+{
+    assert!(max_concurrent > 0, "max_concurrent must be nonzero");
+    let shutdown = shutdown.fuse();
+    futures::pin_mut!(shutdown);
+    let mut in_flight = FuturesUnordered::new();
+    let mut accepting = true;
+
+    future::poll_fn(|cx: &mut Context| loop {
+        while let Poll::Ready(Some(result)) = in_flight.poll_next_unpin(cx) {
+            if let Err(e) = result {
+                debug!("request failed: {}", e);
+            }
+        }
+
+        if accepting && shutdown.as_mut().poll(cx).is_ready() {
+            debug!("shutting down; {} requests in flight", in_flight.len());
+            accepting = false;
+        }
+        if !accepting {
+            return if in_flight.is_empty() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            };
+        }
+        if in_flight.len() >= max_concurrent {
+            return Poll::Pending;
+        }
+
+        match service.poll_ready(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(ServeError::Service(e))),
+            Poll::Pending => return Poll::Pending,
+        }
+        match streams.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(request))) => in_flight.push(service.call(request)),
+            Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(ServeError::Connection(e))),
+            Poll::Ready(None) => accepting = false,
+            Poll::Pending => return Poll::Pending,
+        }
+    })
+    .await
+}
+
+/// Errors that cause [`serve()`] to stop
+#[derive(Debug, Error, Clone)]
+pub enum ServeError<E> {
+    /// The connection was lost
+    #[error(transparent)]
+    Connection(#[from] ConnectionError),
+    /// The service failed to become ready
+    #[error("service failed: {0}")]
+    Service(E),
+}
@@ -0,0 +1,98 @@
+use std::{
+    num::NonZeroU64,
+    pin::Pin,
+    task::Context,
+    time::{Duration, Instant},
+};
+
+use crate::clock::{AsyncTimer, Clock};
+
+/// Token bucket limiting the rate at which the endpoint driver sends data, across all connections
+///
+/// Refills at `rate` bytes per second, holding at most `BURST` worth of tokens. A batch of
+/// transmits is admitted while any tokens remain, so the bucket may run into debt by up to one
+/// transmit, which is paid off before anything else is sent.
+#[derive(Debug)]
+pub(crate) struct Shaper {
+    rate: NonZeroU64,
+    /// Bytes that may be sent immediately, negative while in debt
+    tokens: f64,
+    updated: Instant,
+    timer: Option<Pin<Box<dyn AsyncTimer>>>,
+}
+
+impl Shaper {
+    /// Limit sending to `rate` bytes per second, starting with a full bucket
+    pub(crate) fn new(rate: NonZeroU64, now: Instant) -> Self {
+        let mut this = Self {
+            rate,
+            tokens: 0.0,
+            updated: now,
+            timer: None,
+        };
+        this.tokens = this.capacity();
+        this
+    }
+
+    pub(crate) fn rate(&self) -> NonZeroU64 {
+        self.rate
+    }
+
+    /// Change the rate, keeping the tokens accumulated so far
+    pub(crate) fn set_rate(&mut self, rate: NonZeroU64, now: Instant) {
+        self.refill(now);
+        self.rate = rate;
+        self.tokens = self.tokens.min(self.capacity());
+    }
+
+    /// Number of leading `lens` that may be sent now
+    pub(crate) fn admit(&mut self, now: Instant, lens: impl Iterator<Item = usize>) -> usize {
+        self.refill(now);
+        let mut budget = self.tokens;
+        let mut count = 0;
+        for len in lens {
+            if budget <= 0.0 {
+                break;
+            }
+            budget -= len as f64;
+            count += 1;
+        }
+        count
+    }
+
+    /// Account for `bytes` having been sent
+    pub(crate) fn consume(&mut self, bytes: usize) {
+        self.tokens -= bytes as f64;
+    }
+
+    /// Arrange for the driver to be woken once tokens are available again
+    ///
+    /// Returns whether they already are, in which case the driver should try again immediately.
+    pub(crate) fn poll_ready(&mut self, cx: &mut Context, clock: &dyn Clock) -> bool {
+        let deficit =
+            Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate.get() as f64);
+        let deadline = self.updated + deficit;
+        let timer = match self.timer {
+            Some(ref mut timer) => {
+                timer.as_mut().reset(deadline);
+                timer
+            }
+            None => self.timer.get_or_insert(clock.new_timer(deadline)),
+        };
+        timer.as_mut().poll(cx).is_ready()
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.updated = now;
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.rate.get() as f64).min(self.capacity());
+    }
+
+    fn capacity(&self) -> f64 {
+        self.rate.get() as f64 * BURST.as_secs_f64()
+    }
+}
+
+/// Length of the bursts the bucket permits at the full rate
+const BURST: Duration = Duration::from_millis(10);
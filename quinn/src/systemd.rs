@@ -0,0 +1,96 @@
+//! Support for systemd's socket activation protocol
+//!
+//! See `sd_listen_fds(3)` for details.
+
+use std::{
+    env, io, mem,
+    net::UdpSocket,
+    os::unix::io::{FromRawFd, RawFd},
+    process,
+    sync::Mutex,
+};
+
+use once_cell::sync::Lazy;
+
+/// Take ownership of a datagram socket passed to this process by systemd, if any
+///
+/// If `name` is given, only a socket configured with a matching `FileDescriptorName=` is taken.
+/// Otherwise, the first datagram socket is taken. Each socket is taken at most once.
+pub(crate) fn take_socket(name: Option<&str>) -> io::Result<Option<UdpSocket>> {
+    let names = env::var("LISTEN_FDNAMES").ok();
+    let fds = activated_fds(
+        env::var("LISTEN_PID").ok().as_deref(),
+        env::var("LISTEN_FDS").ok().as_deref(),
+        names.as_deref(),
+        process::id(),
+    );
+    let mut taken = TAKEN.lock().unwrap();
+    for (fd, fd_name) in fds {
+        if taken.contains(&fd) || name.map_or(false, |x| fd_name != Some(x)) {
+            continue;
+        }
+        if !is_datagram_socket(fd)? {
+            continue;
+        }
+        // Sockets passed by systemd aren't close-on-exec, so children would inherit them
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        taken.push(fd);
+        return Ok(Some(unsafe { UdpSocket::from_raw_fd(fd) }));
+    }
+    Ok(None)
+}
+
+/// Determine the file descriptors passed by systemd and their names from its environment
+pub(crate) fn activated_fds<'a>(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&'a str>,
+    pid: u32,
+) -> Vec<(RawFd, Option<&'a str>)> {
+    // The variables may have been inherited from a parent which was activated itself
+    if listen_pid.and_then(|x| x.parse::<u32>().ok()) != Some(pid) {
+        return Vec::new();
+    }
+    let count = match listen_fds.and_then(|x| x.parse::<RawFd>().ok()) {
+        Some(x) => x,
+        None => return Vec::new(),
+    };
+    let mut names = listen_fdnames.map(|x| x.split(':'));
+    (0..count)
+        .map(|i| {
+            let name = names.as_mut().and_then(|x| x.next());
+            (LISTEN_FDS_START + i, name)
+        })
+        .collect()
+}
+
+fn is_datagram_socket(fd: RawFd) -> io::Result<bool> {
+    let mut ty: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            &mut ty as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if rc == -1 {
+        let e = io::Error::last_os_error();
+        // Not a socket at all, e.g. a FIFO
+        if e.raw_os_error() == Some(libc::ENOTSOCK) {
+            return Ok(false);
+        }
+        return Err(e);
+    }
+    Ok(ty == libc::SOCK_DGRAM)
+}
+
+/// File descriptors already taken over by an endpoint
+static TAKEN: Lazy<Mutex<Vec<RawFd>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// The first file descriptor passed by systemd
+const LISTEN_FDS_START: RawFd = 3;
@@ -19,8 +19,8 @@ use tracing_futures::Instrument as _;
 use tracing_subscriber::EnvFilter;
 
 use super::{
-    ClientConfigBuilder, Endpoint, Incoming, NewConnection, RecvStream, SendStream,
-    ServerConfigBuilder, TransportConfig,
+    BiStream, ClientConfigBuilder, Endpoint, EndpointBuilder, Incoming, NewConnection,
+    PriorityClass, RecvStream, SendStream, ServerConfigBuilder, TransportConfig,
 };
 
 #[test]
@@ -54,7 +54,7 @@ fn handshake_timeout() {
             .unwrap()
             .await
         {
-            Err(crate::ConnectionError::TimedOut) => {}
+            Err(crate::ConnectionError::HandshakeTimedOut(crate::HandshakePhase::Initial)) => {}
             Err(e) => panic!("unexpected error: {:?}", e),
             Ok(_) => panic!("unexpected success"),
         }
@@ -181,6 +181,135 @@ fn export_keying_material() {
     });
 }
 
+#[tokio::test]
+async fn dyn_streams() {
+    use crate::{DynRecvStream, DynSendStream};
+
+    // Written as a protocol library would, without knowledge of the endpoint's types
+    async fn echo(mut send: DynSendStream, recv: DynRecvStream) {
+        let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().await.unwrap();
+    }
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    const MSG: &[u8] = b"hello";
+    tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let (send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        echo(send.into(), recv.into()).await;
+    });
+    let conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect")
+        .connection;
+    let (send, recv) = conn.open_bi().await.unwrap();
+    let (mut send, mut recv) = (DynSendStream::from(send), DynRecvStream::from(recv));
+    assert_eq!(send.id(), recv.id());
+    send.write_all(MSG).await.unwrap();
+    send.finish().await.unwrap();
+    let mut buf = [0; MSG.len()];
+    recv.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, MSG);
+    assert_eq!(recv.read(&mut buf).await.unwrap(), None);
+}
+
+#[cfg(feature = "dynamic")]
+#[tokio::test]
+async fn dynamic_endpoint() {
+    use crate::dynamic;
+
+    let _guard = subscribe();
+    let (endpoint, incoming) = endpoint();
+    let endpoint = dynamic::Endpoint::from(endpoint);
+    let mut incoming = dynamic::Incoming::from(incoming);
+
+    const MSG: &[u8] = b"hello";
+    tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let (mut send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().await.unwrap();
+    });
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(MSG).await.unwrap();
+    send.finish().await.unwrap();
+    let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+    assert_eq!(msg, MSG);
+    assert_eq!(endpoint.connection_count(), 1);
+}
+
+#[cfg(feature = "debug_counters")]
+#[tokio::test]
+async fn debug_counters_churn() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    tokio::spawn(async move {
+        while let Some(connecting) = incoming.next().await {
+            tokio::spawn(async move {
+                let mut new_conn = connecting.await.expect("connection");
+                while let Some(Ok((mut send, recv))) = new_conn.bi_streams.next().await {
+                    let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+                    send.write_all(&msg).await.unwrap();
+                    send.finish().await.unwrap();
+                }
+            });
+        }
+    });
+
+    const MSG: &[u8] = b"churn";
+    for _ in 0..32 {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        for _ in 0..4 {
+            let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+            send.write_all(MSG).await.unwrap();
+            send.finish().await.unwrap();
+            let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+            assert_eq!(msg, MSG);
+        }
+        assert_ne!(endpoint.debug_counters().connections, 0);
+        new_conn.connection.close(0u32.into(), b"");
+    }
+    endpoint.wait_idle().await;
+
+    // Connection drivers may still be winding down
+    let mut counters = endpoint.debug_counters();
+    for _ in 0..100 {
+        if counters.connections == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        counters = endpoint.debug_counters();
+    }
+    assert_eq!(counters.connections, 0);
+    assert_eq!(counters.streams, 0);
+    assert_eq!(counters.timers, 0);
+}
+
 #[tokio::test]
 async fn accept_after_close() {
     let _guard = subscribe();
@@ -223,30 +352,2178 @@ async fn accept_after_close() {
         .expect("read_to_end");
     assert_eq!(msg, MSG);
 
-    // But it's still definitely closed.
-    assert!(receiver.connection.open_uni().await.is_err());
+    // But it's still definitely closed.
+    assert!(receiver.connection.open_uni().await.is_err());
+}
+
+#[tokio::test]
+async fn endpoint_connections() {
+    let _guard = subscribe();
+    let (client, _) = endpoint();
+    let mut servers = Vec::new();
+    for _ in 0..2 {
+        let (server, mut incoming) = endpoint();
+        tokio::spawn(async move {
+            // Keep accepted connections open
+            let mut conns = Vec::new();
+            while let Some(conn) = incoming.next().await {
+                conns.push(conn.await);
+            }
+        });
+        servers.push(server);
+    }
+
+    let mut conns = Vec::new();
+    for server in &servers {
+        // Each server has its own certificate, trusted by its own client configuration
+        let config = server.default_client_config();
+        let connecting = client
+            .connect_with(config, &server.local_addr().unwrap(), "localhost")
+            .unwrap();
+        // Handshaking connections are counted, but not handed out
+        assert_eq!(client.connections().len(), conns.len());
+        conns.push(connecting.await.expect("connect").connection);
+    }
+    assert_eq!(client.connection_count(), 2);
+    let mut remotes = client
+        .connections()
+        .iter()
+        .map(|conn| conn.remote_address().unwrap())
+        .collect::<Vec<_>>();
+    remotes.sort();
+    let mut expected = servers
+        .iter()
+        .map(|server| server.local_addr().unwrap())
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(remotes, expected);
+
+    let found = client.find_connection(conns[1].stable_id()).unwrap();
+    assert_eq!(found.remote_address(), conns[1].remote_address());
+
+    conns[0].close(0u32.into(), b"");
+    assert_eq!(client.connections().len(), 1);
+    assert!(client.find_connection(conns[0].stable_id()).is_none());
+
+    // Handles from `connections()` don't keep connections open
+    let weak = client.connections().pop().unwrap();
+    assert_eq!(weak.stable_id(), conns[1].stable_id());
+    drop((found, conns));
+    assert!(weak.upgrade().is_none());
+    assert!(client.connections().is_empty());
+}
+
+#[tokio::test]
+async fn close_connection() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let (client, server) = future::join(
+        endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { incoming.next().await.unwrap().await },
+    )
+    .await;
+    let mut client = client.expect("connect");
+    let server = server.expect("accept");
+
+    assert!(!endpoint.close_connection(usize::max_value(), 0u32.into(), b""));
+    assert!(endpoint.close_connection(server.connection.stable_id(), 7u32.into(), b"kicked"));
+    match client.uni_streams.next().await {
+        Some(Err(crate::ConnectionError::ApplicationClosed(close))) => {
+            assert_eq!(close.error_code, 7u32.into());
+            assert_eq!(&close.reason[..], b"kicked");
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[tokio::test]
+async fn typed_error_codes() {
+    use std::convert::TryFrom;
+
+    crate::error_codes! {
+        enum AppError {
+            Cancelled = 0x10,
+            Shutdown = 0x11,
+        }
+    }
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let (client, server) = future::join(
+        endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { incoming.next().await.unwrap().await },
+    )
+    .await;
+    let mut client = client.expect("connect");
+    let mut server = server.expect("accept");
+
+    let mut send = client.connection.open_uni().await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+    send.reset(AppError::Cancelled.into()).unwrap();
+    let mut recv = server.uni_streams.next().await.unwrap().unwrap();
+    let mut buf = [0; 16];
+    let code = loop {
+        match recv.read(&mut buf).await {
+            Ok(Some(_)) => {}
+            Err(crate::ReadError::Reset(code)) => break code,
+            x => panic!("unexpected result: {:?}", x),
+        }
+    };
+    assert_eq!(AppError::try_from(code), Ok(AppError::Cancelled));
+
+    server.connection.close(AppError::Shutdown.into(), b"");
+    match client.uni_streams.next().await {
+        Some(Err(crate::ConnectionError::ApplicationClosed(close))) => {
+            assert_eq!(AppError::try_from(close.error_code), Ok(AppError::Shutdown));
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[tokio::test]
+async fn set_max_idle_timeout() {
+    let _guard = subscribe();
+    const LIMIT: Duration = Duration::from_millis(200);
+    let (endpoint, mut incoming) = endpoint();
+    let (client, server) = future::join(
+        endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap(),
+        async { incoming.next().await.unwrap().await },
+    )
+    .await;
+    let _client = client.expect("connect");
+    let mut server = server.expect("accept");
+
+    server.connection.set_max_idle_timeout(Some(LIMIT));
+    assert_eq!(server.connection.idle_timeout(), Some(LIMIT));
+    let start = Instant::now();
+    match server.uni_streams.next().await {
+        Some(Err(crate::ConnectionError::TimedOut)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(start.elapsed() < 4 * LIMIT);
+}
+
+#[tokio::test]
+async fn auth_token() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let addr = endpoint.local_addr().unwrap();
+
+    let plain = endpoint.default_client_config();
+    let mut client_config = ClientConfigBuilder::new(plain.clone());
+    client_config.auth_token(b"api-key").unwrap();
+    let with_token = client_config.build();
+
+    for (config, expected) in vec![(with_token, Some(&b"api-key"[..])), (plain, None)] {
+        let client = endpoint.connect_with(config, &addr, "localhost").unwrap();
+        tokio::spawn(client);
+        let mut connecting = incoming.next().await.unwrap();
+        let data = connecting.handshake_data().await.unwrap();
+        assert_eq!(data.auth_token.as_deref(), expected);
+    }
+}
+
+#[tokio::test]
+async fn fair_accept() {
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::transport::{MemoryNetwork, MemorySocket};
+
+    // Distinct source IPs without relying on the platform's loopback addresses
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4433);
+    let (server_config, client_config) = configs();
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.listen(server_config).fair_accept(true);
+    let (_server, mut incoming) = server
+        .with_socket(network.bind(server_addr).unwrap())
+        .unwrap();
+    let client = |ip| {
+        let mut client = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+        client.default_client_config(client_config.clone());
+        let socket = network.bind(SocketAddr::new(ip, 0)).unwrap();
+        client.with_socket(socket).unwrap().0
+    };
+    let noisy_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+    let quiet_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3));
+    let (noisy, quiet) = (client(noisy_ip), client(quiet_ip));
+
+    // Handshakes complete without being accepted, so once they have, each connection is queued
+    let noisy_conns =
+        future::try_join_all((0..3).map(|_| noisy.connect(&server_addr, "localhost").unwrap()))
+            .await
+            .unwrap();
+    let quiet_conn = quiet
+        .connect(&server_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+
+    let mut sources = Vec::new();
+    for _ in 0..4 {
+        sources.push(incoming.next().await.unwrap().remote_address().ip());
+    }
+    assert_eq!(sources, [noisy_ip, quiet_ip, noisy_ip, noisy_ip]);
+    drop((noisy_conns, quiet_conn));
+}
+
+#[tokio::test]
+async fn manual_drivers() {
+    let _guard = subscribe();
+    let mut builder = endpoint_builder();
+    builder.spawn_drivers(false);
+    let (endpoint, mut incoming) = builder
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let exchange = async {
+        let client = endpoint.connect(&addr, "localhost").unwrap();
+        let (client, server) = future::join(client, async {
+            incoming.next().await.unwrap().await.unwrap()
+        })
+        .await;
+        let mut s = client.unwrap().connection.open_uni().await.unwrap();
+        s.write_all(b"hello").await.unwrap();
+        s.finish().await.unwrap();
+        let mut server = server;
+        let stream = server.uni_streams.next().await.unwrap().unwrap();
+        assert_eq!(
+            &stream.read_to_end(usize::max_value()).await.unwrap()[..],
+            b"hello"
+        );
+    };
+    let drive = future::poll_fn(|cx| endpoint.poll_drivers(cx));
+    futures::pin_mut!(exchange);
+    match future::select(exchange, drive).await {
+        future::Either::Left(((), _)) => {}
+        future::Either::Right((result, _)) => panic!("drivers stopped: {:?}", result),
+    }
+}
+
+/// Construct an endpoint suitable for connecting to itself
+fn endpoint() -> (Endpoint, Incoming) {
+    let (x, y) = endpoint_builder()
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    (x, y)
+}
+
+fn endpoint_builder() -> EndpointBuilder {
+    let mut endpoint = Endpoint::builder();
+    let (server_config, client_config) = configs();
+    endpoint.listen(server_config);
+    endpoint.default_client_config(client_config);
+    endpoint
+}
+
+/// A server configuration with a fresh certificate for "localhost", and a client configuration
+/// trusting it
+fn configs() -> (crate::ServerConfig, crate::ClientConfig) {
+    let mut server_config = ServerConfigBuilder::default();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let key = crate::PrivateKey::from_der(&cert.serialize_private_key_der()).unwrap();
+    let cert = crate::Certificate::from_der(&cert.serialize_der().unwrap()).unwrap();
+    let cert_chain = crate::CertificateChain::from_certs(vec![cert.clone()]);
+    server_config.certificate(cert_chain, key).unwrap();
+
+    let mut client_config = ClientConfigBuilder::default();
+    client_config.add_certificate_authority(cert).unwrap();
+    (server_config.build(), client_config.build())
+}
+
+#[tokio::test]
+async fn set_default_client_config() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let addr = endpoint.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Some(conn) = incoming.next().await {
+            tokio::spawn(async move {
+                let _ = conn.await;
+            });
+        }
+    });
+
+    // Without the server's certificate authority, the handshake fails
+    let trusted = endpoint.default_client_config();
+    endpoint.set_default_client_config(crate::ClientConfig::default());
+    assert!(endpoint.connect(&addr, "localhost").unwrap().await.is_err());
+
+    // Changes made through any handle apply to all of them
+    endpoint.clone().set_default_client_config(trusted);
+    endpoint.connect(&addr, "localhost").unwrap().await.unwrap();
+}
+
+#[test]
+fn handshake_runtime() {
+    let _guard = subscribe();
+    let runtime = rt_basic();
+    let handshake_runtime = rt_threaded();
+    let (endpoint, mut incoming) = {
+        let _guard = runtime.enter();
+        let mut builder = endpoint_builder();
+        builder.handshake_runtime(handshake_runtime.handle().clone());
+        builder
+            .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+            .unwrap()
+    };
+
+    const MSG: &[u8] = b"hello";
+    runtime.spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let mut s = new_conn.connection.open_uni().await.unwrap();
+        s.write_all(MSG).await.unwrap();
+        s.finish().await.unwrap();
+    });
+    runtime.block_on(async move {
+        let mut new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        let stream = new_conn
+            .uni_streams
+            .next()
+            .await
+            .expect("incoming streams")
+            .expect("missing stream");
+        let msg = stream.read_to_end(usize::MAX).await.expect("read_to_end");
+        assert_eq!(msg, MSG);
+    });
+}
+
+#[tokio::test]
+async fn max_concurrent_handshakes() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = {
+        let mut builder = endpoint_builder();
+        builder.max_concurrent_handshakes(1);
+        builder
+            .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+            .unwrap()
+    };
+
+    const COUNT: usize = 8;
+    tokio::spawn(async move {
+        let mut conns = Vec::new();
+        for _ in 0..COUNT {
+            let connecting = incoming.next().await.expect("endpoint");
+            conns.push(tokio::spawn(async move {
+                connecting.await.expect("connection")
+            }));
+        }
+        for conn in conns {
+            conn.await.unwrap();
+        }
+    });
+    let addr = endpoint.local_addr().unwrap();
+    let conns =
+        future::join_all((0..COUNT).map(|_| endpoint.connect(&addr, "localhost").unwrap())).await;
+    for conn in conns {
+        conn.expect("connect");
+    }
+}
+
+#[tokio::test]
+async fn packet_marking() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint_builder()
+        .bind(&SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0))
+        .unwrap();
+
+    const MSG: &[u8] = b"marked";
+    tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        new_conn.connection.set_dscp(Some(10));
+        new_conn.connection.set_flow_label(Some(0xbeef));
+        let (mut send, recv) = new_conn
+            .bi_streams
+            .next()
+            .await
+            .expect("incoming streams")
+            .expect("missing stream");
+        let msg = recv.read_to_end(usize::MAX).await.expect("read_to_end");
+        send.write_all(&msg).await.unwrap();
+        send.finish().await.unwrap();
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    new_conn.connection.set_dscp(Some(46));
+    new_conn.connection.set_flow_label(Some(0x12345));
+    let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(MSG).await.unwrap();
+    send.finish().await.unwrap();
+    let msg = recv.read_to_end(usize::MAX).await.expect("read_to_end");
+    assert_eq!(msg, MSG);
+}
+
+#[cfg(feature = "codec")]
+#[tokio::test]
+async fn bi_stream_pair() {
+    use futures::SinkExt;
+    use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let (send, recv) = new_conn
+            .bi_streams
+            .next()
+            .await
+            .expect("incoming streams")
+            .expect("missing stream");
+        let mut framed = Framed::new(BiStream::new(send, recv), LengthDelimitedCodec::new());
+        while let Some(frame) = framed.next().await {
+            framed.send(frame.unwrap().freeze()).await.unwrap();
+        }
+        framed.close().await.unwrap();
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let mut framed = new_conn.connection.open_bi_stream_pair().await.unwrap();
+    for msg in &[&b"hello"[..], b"", b"world"] {
+        framed.send(Bytes::from_static(msg)).await.unwrap();
+        assert_eq!(&framed.next().await.unwrap().unwrap()[..], *msg);
+    }
+    framed.close().await.unwrap();
+    assert!(framed.next().await.is_none());
+}
+
+#[cfg(feature = "h3")]
+#[tokio::test]
+async fn h3_request() {
+    use bytes::Buf;
+
+    const BODY: &[u8] = b"hello over h3";
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    tokio::spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let mut h3_conn = ::h3::server::Connection::<_, Bytes>::new(new_conn)
+            .await
+            .unwrap();
+        let (req, mut stream) = h3_conn.accept().await.unwrap().expect("request");
+        assert_eq!(req.uri().path(), "/");
+        stream
+            .send_response(http::Response::builder().status(200).body(()).unwrap())
+            .await
+            .unwrap();
+        stream.send_data(Bytes::from_static(BODY)).await.unwrap();
+        stream.finish().await.unwrap();
+        // Keep the connection up until the client is done with it
+        let _ = h3_conn.accept().await;
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let (mut driver, mut send_request) = ::h3::client::new(new_conn).await.unwrap();
+    tokio::spawn(async move {
+        let _ = future::poll_fn(|cx| driver.poll_close(cx)).await;
+    });
+
+    let req = http::Request::get("https://localhost/").body(()).unwrap();
+    let mut stream = send_request.send_request(req).await.unwrap();
+    stream.finish().await.unwrap();
+    let resp = stream.recv_response().await.unwrap();
+    assert_eq!(resp.status(), http::StatusCode::OK);
+    let mut body = Vec::new();
+    while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+        body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+    }
+    assert_eq!(body, BODY);
+}
+
+#[cfg(feature = "tower")]
+#[tokio::test]
+async fn serve_tower() {
+    use std::{
+        pin::Pin,
+        sync::atomic::{AtomicUsize, Ordering},
+        task::{Context, Poll},
+    };
+
+    use futures::{channel::oneshot, FutureExt};
+
+    const MAX_CONCURRENT: usize = 2;
+
+    /// Echoes every request, tracking the peak number of concurrent requests
+    #[derive(Default)]
+    struct Echo {
+        active: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl tower_service::Service<(SendStream, RecvStream)> for Echo {
+        type Response = ();
+        type Error = io::Error;
+        type Future = Pin<Box<dyn future::Future<Output = io::Result<()>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, streams: (SendStream, RecvStream)) -> Self::Future {
+            let active = self.active.clone();
+            let peak = self.peak.clone();
+            Box::pin(async move {
+                let n = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(n, Ordering::SeqCst);
+                echo(streams).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            })
+        }
+    }
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let service = Echo::default();
+    let peak = service.peak.clone();
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+    let server = tokio::spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        super::serve(
+            new_conn.bi_streams,
+            service,
+            MAX_CONCURRENT,
+            shutdown_rx.map(|_| ()),
+        )
+        .await
+        .map_err(|e| e.to_string())
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let requests = (0..4u8).map(|i| {
+        let conn = new_conn.connection.clone();
+        async move {
+            let (mut send, recv) = conn.open_bi().await.unwrap();
+            send.write_all(&[i; 64]).await.unwrap();
+            send.finish().await.unwrap();
+            assert_eq!(recv.read_to_end(usize::max_value()).await.unwrap(), [i; 64]);
+        }
+    });
+    future::join_all(requests).await;
+
+    shutdown_tx.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(peak.load(Ordering::SeqCst) <= MAX_CONCURRENT);
+}
+
+#[tokio::test]
+async fn for_each_spawned() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    let server = tokio::spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        new_conn
+            .bi_streams
+            .for_each_spawned(2, |mut send, mut recv| async move {
+                let mut first = [0];
+                recv.read_exact(&mut first).await.unwrap();
+                if first[0] == 0 {
+                    panic!("handler failed");
+                }
+                send.write_all(&first).await.unwrap();
+                echo((send, recv)).await;
+            })
+            .await
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let (mut send, _recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(&[0]).await.unwrap();
+
+    // Other streams are served regardless of the panic
+    let requests = (1..5u8).map(|i| {
+        let conn = new_conn.connection.clone();
+        async move {
+            let (mut send, recv) = conn.open_bi().await.unwrap();
+            send.write_all(&[i; 64]).await.unwrap();
+            send.finish().await.unwrap();
+            assert_eq!(recv.read_to_end(usize::max_value()).await.unwrap(), [i; 64]);
+        }
+    });
+    future::join_all(requests).await;
+
+    new_conn.connection.close(0u32.into(), b"done");
+    match server.await.unwrap() {
+        Err(crate::ConnectionError::ApplicationClosed(_)) => {}
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[tokio::test]
+async fn for_each_spawned_stop() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    let server = tokio::spawn(async move { incoming.next().await.unwrap().await.unwrap() });
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let handlers = server
+        .await
+        .unwrap()
+        .bi_streams
+        .for_each_spawned(1, |send, recv| echo((send, recv)));
+
+    let (mut send, mut recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(b"hi").await.unwrap();
+    let mut echoed = [0; 2];
+    recv.read_exact(&mut echoed).await.unwrap();
+    assert_eq!(&echoed, b"hi");
+
+    // The running handler is left to finish
+    handlers.stop();
+    send.write_all(b"!").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(recv.read_to_end(usize::max_value()).await.unwrap(), b"!");
+    handlers.await.unwrap();
+}
+
+#[tokio::test]
+async fn stream_router() {
+    use crate::generic::RoutedStream;
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let (uni_send, mut uni_recv) = tokio::sync::mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        let new_conn = incoming.next().await.unwrap().await.unwrap();
+        let mut router = crate::StreamRouter::new(4);
+        router
+            .route(&b"echo"[..], |_, stream| async move {
+                if let RoutedStream::Bi(mut send, recv) = stream {
+                    let data = recv.read_to_end(usize::max_value()).await.unwrap();
+                    send.write_all(&data).await.unwrap();
+                    send.finish().await.unwrap();
+                }
+            })
+            .route(&b"log"[..], move |prefix, stream| {
+                let uni_send = uni_send.clone();
+                async move {
+                    if let RoutedStream::Uni(recv) = stream {
+                        let data = recv.read_to_end(usize::max_value()).await.unwrap();
+                        uni_send.send((prefix, data)).unwrap();
+                    }
+                }
+            });
+        let _ = router.run(new_conn.uni_streams, new_conn.bi_streams).await;
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let conn = new_conn.connection;
+
+    let (mut send, recv) = conn.open_bi().await.unwrap();
+    send.write_all(b"echohello").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(
+        recv.read_to_end(usize::max_value()).await.unwrap(),
+        b"hello"
+    );
+
+    let mut send = conn.open_uni().await.unwrap();
+    send.write_all(b"log:message").await.unwrap();
+    send.finish().await.unwrap();
+    let (prefix, data) = uni_recv.recv().await.unwrap();
+    assert_eq!(&prefix[..], b"log:");
+    assert_eq!(data, b"message");
+
+    let (mut send, recv) = conn.open_bi().await.unwrap();
+    send.write_all(b"nope").await.unwrap();
+    match recv.read_to_end(usize::max_value()).await {
+        Err(crate::ReadToEndError::Read(crate::ReadError::Reset(code))) => {
+            assert_eq!(code, 0u32.into())
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[tokio::test]
+async fn copy_bidirectional() {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    // A TCP backend which only responds once it has seen the end of the request
+    let backend = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut tcp, _) = backend.accept().await.unwrap();
+        let mut request = Vec::new();
+        tcp.read_to_end(&mut request).await.unwrap();
+        assert_eq!(request, b"request");
+        tcp.write_all(b"response").await.unwrap();
+    });
+
+    let proxy = tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        let (send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let mut tcp = TcpStream::connect(backend_addr).await.unwrap();
+        crate::io::copy_bidirectional(&mut BiStream::new(send, recv), &mut tcp)
+            .await
+            .unwrap()
+    });
+
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(b"request").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(
+        recv.read_to_end(usize::max_value()).await.unwrap(),
+        b"response"
+    );
+    assert_eq!(proxy.await.unwrap(), (7, 8));
+}
+
+#[tokio::test]
+async fn tcp_proxy() {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+
+    let backend = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let backend_addr = backend.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut tcp, _) = backend.accept().await.unwrap();
+        let mut request = Vec::new();
+        tcp.read_to_end(&mut request).await.unwrap();
+        assert_eq!(request, b"ping");
+        tcp.write_all(b"pong").await.unwrap();
+    });
+    // Nothing listens here once the listener is dropped
+    let closed_addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    // Exit side: the first stream reaches the backend, the second a closed port
+    tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        for &target in &[backend_addr, closed_addr] {
+            let (send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+            let _ = crate::proxy::quic_to_tcp(BiStream::new(send, recv), target).await;
+        }
+        // Keep the connection open until the client is done
+        let _ = new_conn.bi_streams.next().await;
+    });
+
+    // Entry side
+    let new_conn = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+    let front = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let mut tcp = TcpStream::connect(front.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (accepted, _) = front.accept().await.unwrap();
+    let connection = new_conn.connection.clone();
+    let entry = tokio::spawn(async move {
+        crate::proxy::tcp_to_quic(&connection, accepted)
+            .await
+            .unwrap()
+    });
+    tcp.write_all(b"ping").await.unwrap();
+    tcp.shutdown().await.unwrap();
+    let mut response = Vec::new();
+    tcp.read_to_end(&mut response).await.unwrap();
+    assert_eq!(response, b"pong");
+    assert_eq!(entry.await.unwrap(), (4, 4));
+
+    let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(b"ping").await.unwrap();
+    send.finish().await.unwrap();
+    match recv.read_to_end(usize::max_value()).await {
+        Err(crate::ReadToEndError::Read(crate::ReadError::Reset(code))) => {
+            assert_eq!(code, crate::proxy::CONNECT_FAILED)
+        }
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn raw_fd_handover() {
+    let _guard = subscribe();
+    let builder = endpoint_builder();
+    let localhost = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+    let (old, _) = builder.clone().bind(&localhost).unwrap();
+    let addr = old.local_addr().unwrap();
+    let fd = old.into_raw_fd().unwrap();
+
+    let (new, mut incoming) = unsafe { builder.clone().from_raw_fd(fd) }.unwrap();
+    assert_eq!(new.local_addr().unwrap(), addr);
+    tokio::spawn(async move {
+        let _ = incoming.next().await.unwrap().await.unwrap();
+    });
+    let (client, _) = builder.bind(&localhost).unwrap();
+    client.connect(&addr, "localhost").unwrap().await.unwrap();
+}
+
+#[cfg(all(unix, feature = "systemd"))]
+#[test]
+fn systemd_activated_fds() {
+    use crate::systemd::activated_fds;
+
+    let pid = std::process::id();
+    assert_eq!(
+        activated_fds(Some(&pid.to_string()), Some("2"), Some("quic:admin"), pid),
+        [(3, Some("quic")), (4, Some("admin"))]
+    );
+    assert_eq!(
+        activated_fds(Some(&pid.to_string()), Some("1"), None, pid),
+        [(3, None)]
+    );
+    // Inherited from an activated parent
+    assert!(activated_fds(Some(&(pid + 1).to_string()), Some("1"), None, pid).is_empty());
+    assert!(activated_fds(None, Some("1"), None, pid).is_empty());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn recreate_socket() {
+    use std::{
+        convert::TryFrom,
+        io::IoSliceMut,
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll},
+    };
+
+    use futures::channel::mpsc;
+    use proto::{crypto::rustls::TlsSession, Transmit};
+
+    use crate::transport::{RecvMeta, Socket, UdpSocket as QuinnUdpSocket};
+
+    /// A socket whose sends fail as if its network went away while `unreachable` is set
+    #[derive(Debug)]
+    struct FlakySocket {
+        inner: QuinnUdpSocket,
+        unreachable: Arc<AtomicBool>,
+    }
+
+    impl Socket for FlakySocket {
+        type Address = SocketAddr;
+
+        fn poll_send(
+            &self,
+            cx: &mut Context,
+            transmits: &mut [Transmit],
+        ) -> Poll<io::Result<usize>> {
+            if self.unreachable.load(Ordering::Relaxed) {
+                return Poll::Ready(Err(io::Error::from_raw_os_error(libc::ENETUNREACH)));
+            }
+            self.inner.poll_send(cx, transmits)
+        }
+
+        fn poll_recv(
+            &self,
+            cx: &mut Context,
+            bufs: &mut [IoSliceMut<'_>],
+            meta: &mut [RecvMeta],
+        ) -> Poll<io::Result<usize>> {
+            self.inner.poll_recv(cx, bufs, meta)
+        }
+
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            self.inner.local_addr()
+        }
+
+        fn map_remote(&self, remote: SocketAddr) -> Option<SocketAddr> {
+            self.inner.map_remote(remote)
+        }
+
+        fn recreate(&self) -> io::Result<Self> {
+            Ok(Self {
+                inner: self.inner.recreate()?,
+                unreachable: Arc::new(AtomicBool::new(false)),
+            })
+        }
+    }
+
+    let _guard = subscribe();
+    let (server, mut incoming) = endpoint();
+
+    let (msgs_tx, mut msgs) = mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut new_conn = incoming
+            .next()
+            .await
+            .expect("endpoint")
+            .await
+            .expect("connection");
+        while let Some(Ok(stream)) = new_conn.uni_streams.next().await {
+            let msg = stream.read_to_end(usize::max_value()).await.unwrap();
+            msgs_tx.unbounded_send(msg).unwrap();
+        }
+    });
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, FlakySocket>::default();
+    client
+        .default_client_config(server.default_client_config())
+        .recreate_socket_on_network_error(true);
+    let unreachable = Arc::new(AtomicBool::new(false));
+    let socket =
+        std::net::UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let (client, _) = client
+        .with_socket(FlakySocket {
+            inner: QuinnUdpSocket::try_from(socket).unwrap(),
+            unreachable: unreachable.clone(),
+        })
+        .unwrap();
+    let old_addr = client.local_addr().unwrap();
+    let new_conn = client
+        .connect(&server.local_addr().unwrap(), "localhost")
+        .unwrap()
+        .await
+        .expect("connect");
+
+    let mut send = new_conn.connection.open_uni().await.unwrap();
+    send.write_all(b"before").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(msgs.next().await.unwrap(), b"before");
+
+    // Sends fail until the socket is recreated, after which the connection migrates to it
+    let recreated = client.socket_recreated();
+    unreachable.store(true, Ordering::Relaxed);
+    let mut send = new_conn.connection.open_uni().await.unwrap();
+    send.write_all(b"after").await.unwrap();
+    send.finish().await.unwrap();
+    assert_ne!(recreated.await.unwrap(), old_addr);
+    assert_eq!(msgs.next().await.unwrap(), b"after");
+}
+
+/// Nodes on a [`MemoryNetwork`](crate::transport::MemoryNetwork) addressed by number rather than
+/// IP, for endpoints on custom sockets
+mod node {
+    use std::{
+        fmt,
+        io::{self, IoSliceMut},
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        task::{Context, Poll},
+    };
+
+    use futures::ready;
+    use proto::Transmit;
+
+    use crate::transport::{MemoryNetwork, MemorySocket, RecvMeta, Socket};
+
+    /// An address on the network with no notion of IP
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub(super) struct NodeId(pub(super) u32);
+
+    impl NodeId {
+        /// The address of the node's underlying `MemorySocket`
+        fn socket_addr(self) -> SocketAddr {
+            SocketAddr::new(Ipv4Addr::from(self.0).into(), 1)
+        }
+
+        fn from_socket_addr(addr: SocketAddr) -> Self {
+            match addr.ip() {
+                IpAddr::V4(ip) => Self(ip.into()),
+                IpAddr::V6(_) => unreachable!("nodes are bound to IPv4 addresses"),
+            }
+        }
+    }
+
+    impl fmt::Display for NodeId {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "node-{}", self.0)
+        }
+    }
+
+    impl proto::Address for NodeId {
+        fn encode(&self, buf: &mut Vec<u8>) {
+            buf.extend_from_slice(&self.0.to_be_bytes());
+        }
+    }
+
+    #[derive(Debug)]
+    pub(super) struct NodeSocket(MemorySocket);
+
+    impl NodeSocket {
+        pub(super) fn bind(network: &MemoryNetwork, id: u32) -> Self {
+            Self(network.bind(NodeId(id).socket_addr()).unwrap())
+        }
+    }
+
+    impl Socket for NodeSocket {
+        type Address = NodeId;
+
+        fn poll_send(
+            &self,
+            cx: &mut Context,
+            transmits: &mut [Transmit<NodeId>],
+        ) -> Poll<io::Result<usize>> {
+            let mut transmits = transmits
+                .iter()
+                .map(|x| Transmit {
+                    destination: x.destination.socket_addr(),
+                    ecn: x.ecn,
+                    contents: x.contents.clone(),
+                    segment_size: x.segment_size,
+                    src_ip: x.src_ip,
+                    dscp: x.dscp,
+                    flow_label: x.flow_label,
+                })
+                .collect::<Vec<_>>();
+            self.0.poll_send(cx, &mut transmits)
+        }
+
+        fn poll_recv(
+            &self,
+            cx: &mut Context,
+            bufs: &mut [IoSliceMut<'_>],
+            meta: &mut [RecvMeta<NodeId>],
+        ) -> Poll<io::Result<usize>> {
+            let mut socket_meta = vec![RecvMeta::default(); meta.len()];
+            let n = ready!(self.0.poll_recv(cx, bufs, &mut socket_meta))?;
+            for (meta, x) in meta.iter_mut().zip(&socket_meta[..n]) {
+                *meta = RecvMeta {
+                    addr: NodeId::from_socket_addr(x.addr),
+                    len: x.len,
+                    ecn: x.ecn,
+                    dst_ip: x.dst_ip,
+                };
+            }
+            Poll::Ready(Ok(n))
+        }
+
+        fn local_addr(&self) -> io::Result<NodeId> {
+            self.0.local_addr().map(NodeId::from_socket_addr)
+        }
+    }
+}
+
+#[tokio::test]
+async fn custom_address() {
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::transport::MemoryNetwork;
+    use node::{NodeId, NodeSocket};
+
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    let (server_config, client_config) = configs();
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, NodeSocket>::default();
+    server.listen(server_config);
+    let (server, mut incoming) = server.with_socket(NodeSocket::bind(&network, 1)).unwrap();
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, NodeSocket>::default();
+    client.default_client_config(client_config);
+    let (client, _) = client.with_socket(NodeSocket::bind(&network, 2)).unwrap();
+
+    let server_side = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        assert_eq!(new_conn.connection.remote_address(), NodeId(2));
+        let (mut send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().await.unwrap();
+        new_conn
+    };
+    let client_side = async {
+        let new_conn = client
+            .connect(&server.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(new_conn.connection.remote_address(), NodeId(1));
+        let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+        send.write_all(b"hello").await.unwrap();
+        send.finish().await.unwrap();
+        recv.read_to_end(usize::max_value()).await.unwrap()
+    };
+    let (_conn, echoed) = future::join(server_side, client_side).await;
+    assert_eq!(echoed, b"hello");
+}
+
+#[tokio::test]
+async fn memory_network() {
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::transport::{MemoryNetwork, MemorySocket, Socket};
+
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    network
+        .latency(Duration::from_millis(5))
+        .jitter(Duration::from_millis(5))
+        .reorder(0.1)
+        .loss(0.1)
+        .seed(42);
+    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4433);
+    let socket = network.bind(server_addr).unwrap();
+    assert_eq!(
+        network.bind(server_addr).unwrap_err().kind(),
+        io::ErrorKind::AddrInUse
+    );
+
+    let (server_config, client_config) = configs();
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.listen(server_config);
+    let (_server, mut incoming) = server.with_socket(socket).unwrap();
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    client.default_client_config(client_config);
+    let client_socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 0))
+        .unwrap();
+    let client_addr = client_socket.local_addr().unwrap();
+    assert_ne!(client_addr.port(), 0);
+    let (client, _) = client.with_socket(client_socket).unwrap();
+
+    let mut data = vec![0; 64 * 1024];
+    StdRng::seed_from_u64(0).fill_bytes(&mut data);
+    let server_side = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        assert_eq!(new_conn.connection.remote_address(), client_addr);
+        let (mut send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().await.unwrap();
+        new_conn
+    };
+    let client_side = async {
+        let new_conn = client
+            .connect(&server_addr, "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+        send.write_all(&data).await.unwrap();
+        send.finish().await.unwrap();
+        let echoed = recv.read_to_end(usize::max_value()).await.unwrap();
+        (new_conn, echoed)
+    };
+    let (_, (_conn, echoed)) = future::join(server_side, client_side).await;
+    assert_eq!(echoed, data);
+}
+
+#[tokio::test]
+async fn header_compression_shared_remote() {
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::transport::{MemoryNetwork, MemorySocket};
+
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4433);
+    let (mut server_config, mut client_config) = configs();
+    let mut transport = TransportConfig::default();
+    transport.header_compression(true);
+    let transport = Arc::new(transport);
+    server_config.transport = transport.clone();
+    client_config.transport = transport;
+
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.listen(server_config);
+    let (_server, mut incoming) = server
+        .with_socket(network.bind(server_addr).unwrap())
+        .unwrap();
+    tokio::spawn(async move {
+        while let Some(conn) = incoming.next().await {
+            tokio::spawn(async move {
+                let mut new_conn = conn.await.unwrap();
+                while let Some(Ok((mut send, recv))) = new_conn.bi_streams.next().await {
+                    let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+                    send.write_all(&msg).await.unwrap();
+                    send.finish().await.unwrap();
+                }
+            });
+        }
+    });
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    client.default_client_config(client_config);
+    let client_socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 0))
+        .unwrap();
+    let (client, _) = client.with_socket(client_socket).unwrap();
+
+    // Both connections come from the same address, by which compressed packets are attributed
+    let (first, second) = future::join(
+        client.connect(&server_addr, "localhost").unwrap(),
+        client.connect(&server_addr, "localhost").unwrap(),
+    )
+    .await;
+    for conn in &[first.unwrap().connection, second.unwrap().connection] {
+        let (mut send, recv) = conn.open_bi().await.unwrap();
+        send.write_all(b"hello").await.unwrap();
+        send.finish().await.unwrap();
+        assert_eq!(
+            recv.read_to_end(usize::max_value()).await.unwrap(),
+            b"hello"
+        );
+    }
+}
+
+#[tokio::test]
+async fn path_mtu_changed() {
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::transport::{MemoryNetwork, MemorySocket};
+
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4433);
+    let (server_config, client_config) = configs();
+
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.listen(server_config);
+    let (_server, mut incoming) = server
+        .with_socket(network.bind(server_addr).unwrap())
+        .unwrap();
+    tokio::spawn(async move {
+        let _conn = incoming.next().await.unwrap().await.unwrap();
+        future::pending::<()>().await;
+    });
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    client.default_client_config(client_config);
+    let client_socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 0))
+        .unwrap();
+    let (client, _) = client.with_socket(client_socket).unwrap();
+    let conn = client
+        .connect(&server_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap()
+        .connection;
+
+    let initial = conn.max_udp_payload_size();
+    let changed = conn.path_mtu_changed();
+    conn.probe_mtu(initial + 100);
+    let mtu = changed.await.unwrap();
+    assert_eq!(mtu, initial + 100);
+    assert_eq!(conn.max_udp_payload_size(), mtu);
+}
+
+#[test]
+fn executor_runtime() {
+    use futures::executor::{block_on, ThreadPool};
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::{
+        transport::{MemoryNetwork, MemorySocket},
+        Clock, ExecutorRuntime, ThreadClock,
+    };
+
+    // No tokio runtime is running, so anything still spawned on tokio or using its timers panics
+    let _guard = subscribe();
+    let clock: Arc<dyn Clock> = Arc::new(ThreadClock::new().unwrap());
+    let runtime: Arc<dyn crate::Runtime> = Arc::new(ExecutorRuntime::new(
+        ThreadPool::new().unwrap(),
+        clock.clone(),
+    ));
+    let network = MemoryNetwork::with_clock(clock);
+    let (server_config, client_config) = configs();
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.listen(server_config).runtime(runtime.clone());
+    let server_socket = network
+        .bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            4433,
+        ))
+        .unwrap();
+    let (server, mut incoming) = server.with_socket(server_socket).unwrap();
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    client.default_client_config(client_config).runtime(runtime);
+    let client_socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 0))
+        .unwrap();
+    let (client, _) = client.with_socket(client_socket).unwrap();
+
+    let server_side = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let (mut send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let msg = recv.read_to_end(usize::max_value()).await.unwrap();
+        send.write_all(&msg).await.unwrap();
+        send.finish().await.unwrap();
+        new_conn
+    };
+    let client_side = async {
+        let new_conn = client
+            .connect(&server.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+        send.write_all(b"hello").await.unwrap();
+        send.finish().await.unwrap();
+        let echoed = recv.read_to_end(usize::max_value()).await.unwrap();
+        new_conn.connection.close(0u32.into(), b"done");
+        echoed
+    };
+    let (_conn, echoed) = block_on(future::join(server_side, client_side));
+    assert_eq!(echoed, b"hello");
+}
+
+#[tokio::test]
+async fn socket_errors() {
+    use std::{
+        collections::VecDeque,
+        io::IoSliceMut,
+        sync::Mutex,
+        task::{Context, Poll},
+    };
+
+    use proto::{crypto::rustls::TlsSession, Transmit};
+
+    use crate::{
+        transport::{RecvMeta, Socket},
+        SocketErrorPolicy, SocketOperation,
+    };
+
+    /// A socket which fails sends with queued errors, and otherwise discards them
+    #[derive(Debug, Clone, Default)]
+    struct FailingSocket(Arc<Mutex<VecDeque<io::Error>>>);
+
+    impl Socket for FailingSocket {
+        type Address = SocketAddr;
+
+        fn poll_send(
+            &self,
+            _: &mut Context,
+            transmits: &mut [Transmit],
+        ) -> Poll<io::Result<usize>> {
+            match self.0.lock().unwrap().pop_front() {
+                Some(e) => Poll::Ready(Err(e)),
+                None => Poll::Ready(Ok(transmits.len())),
+            }
+        }
+
+        fn poll_recv(
+            &self,
+            _: &mut Context,
+            _: &mut [IoSliceMut<'_>],
+            _: &mut [RecvMeta],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1))
+        }
+    }
+
+    let _guard = subscribe();
+    let socket = FailingSocket::default();
+    let mut endpoint = crate::generic::EndpointBuilder::<TlsSession, FailingSocket>::default();
+    endpoint
+        .default_client_config(ClientConfigBuilder::default().build())
+        .socket_error_policy(SocketErrorPolicy::Surface);
+    let (endpoint, _) = endpoint.with_socket(socket.clone()).unwrap();
+    let server = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 2);
+
+    // Transient errors are reported, and the endpoint carries on; interrupted calls are retried
+    socket.0.lock().unwrap().extend(vec![
+        io::ErrorKind::Interrupted.into(),
+        io::ErrorKind::ConnectionRefused.into(),
+    ]);
+    let error = endpoint.socket_error();
+    let _a = endpoint.connect(&server, "localhost").unwrap();
+    let error = error.await;
+    assert_eq!(error.operation, SocketOperation::Send);
+    assert!(!error.persistent);
+    assert_eq!(error.error.kind(), io::ErrorKind::ConnectionRefused);
+
+    // Anything else stops the endpoint
+    socket
+        .0
+        .lock()
+        .unwrap()
+        .push_back(io::Error::new(io::ErrorKind::Other, "broken"));
+    let error = endpoint.socket_error();
+    let _b = endpoint.connect(&server, "localhost").unwrap();
+    let error = error.await;
+    assert!(error.persistent);
+    assert_eq!(error.error.kind(), io::ErrorKind::Other);
+    // and remains reported
+    assert!(endpoint.socket_error().await.persistent);
+
+    // Network changes can be made to stop the endpoint too
+    let socket = FailingSocket::default();
+    let mut endpoint = crate::generic::EndpointBuilder::<TlsSession, FailingSocket>::default();
+    endpoint
+        .default_client_config(ClientConfigBuilder::default().build())
+        .socket_error_policy(SocketErrorPolicy::Stop);
+    let (endpoint, _) = endpoint.with_socket(socket.clone()).unwrap();
+    socket
+        .0
+        .lock()
+        .unwrap()
+        .push_back(io::Error::from_raw_os_error(libc::ENETDOWN));
+    let error = endpoint.socket_error();
+    let _c = endpoint.connect(&server, "localhost").unwrap();
+    let error = error.await;
+    assert!(error.persistent);
+    assert_eq!(error.error.raw_os_error(), Some(libc::ENETDOWN));
+}
+
+#[tokio::test]
+async fn custom_clock() {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+        time::Instant as StdInstant,
+    };
+
+    use tokio::time::{sleep_until, Sleep};
+
+    use crate::{AsyncTimer, Clock, TokioClock};
+
+    const SPEEDUP: u32 = 100;
+
+    /// A clock running `SPEEDUP` times faster than real time
+    #[derive(Debug, Copy, Clone)]
+    struct FastClock {
+        origin: StdInstant,
+    }
+
+    impl FastClock {
+        fn real(&self, deadline: StdInstant) -> tokio::time::Instant {
+            (self.origin + deadline.saturating_duration_since(self.origin) / SPEEDUP).into()
+        }
+    }
+
+    impl Clock for FastClock {
+        fn now(&self) -> StdInstant {
+            self.origin + (TokioClock.now() - self.origin) * SPEEDUP
+        }
+
+        fn new_timer(&self, deadline: StdInstant) -> Pin<Box<dyn AsyncTimer>> {
+            Box::pin(FastTimer {
+                clock: *self,
+                sleep: Box::pin(sleep_until(self.real(deadline))),
+            })
+        }
+    }
+
+    #[derive(Debug)]
+    struct FastTimer {
+        clock: FastClock,
+        sleep: Pin<Box<Sleep>>,
+    }
+
+    impl AsyncTimer for FastTimer {
+        fn reset(self: Pin<&mut Self>, deadline: StdInstant) {
+            let this = self.get_mut();
+            this.sleep.as_mut().reset(this.clock.real(deadline));
+        }
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+            AsyncTimer::poll(self.get_mut().sleep.as_mut(), cx)
+        }
+    }
+
+    let _guard = subscribe();
+    let mut builder = endpoint_builder();
+    builder.clock(Arc::new(FastClock {
+        origin: TokioClock.now(),
+    }));
+    let (endpoint, mut incoming) = builder
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+
+    let (server, client) = future::join(
+        async { incoming.next().await.unwrap().await.unwrap() },
+        async {
+            endpoint
+                .connect(&endpoint.local_addr().unwrap(), "localhost")
+                .unwrap()
+                .await
+                .unwrap()
+        },
+    )
+    .await;
+
+    // The default 10 second idle timeout elapses in a tenth of a second of real time
+    let start = Instant::now();
+    let (mut server_streams, mut client_streams) = (server.uni_streams, client.uni_streams);
+    let (server_end, client_end) = future::join(server_streams.next(), client_streams.next()).await;
+    assert!(Instant::now() - start < Duration::from_secs(5));
+    for end in [server_end, client_end].iter() {
+        match end {
+            Some(Err(crate::ConnectionError::TimedOut)) => {}
+            x => panic!("unexpected result: {:?}", x),
+        }
+    }
+}
+
+#[tokio::test]
+async fn handshake_deadline() {
+    let _guard = subscribe();
+    let (endpoint, _) = endpoint();
+    // Never responds, so handshakes to it only end when abandoned
+    let silent = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)).unwrap();
+    let silent = silent.local_addr().unwrap();
+
+    let start = Instant::now();
+    let result = endpoint
+        .connect(&silent, "localhost")
+        .unwrap()
+        .with_timeout(Duration::from_millis(200))
+        .await;
+    assert!(Instant::now() - start < Duration::from_secs(5));
+    assert_eq!(
+        result.err(),
+        Some(crate::ConnectionError::HandshakeTimedOut(
+            crate::HandshakePhase::Initial
+        ))
+    );
+
+    let config = endpoint.default_client_config();
+    let result = endpoint
+        .connect_with_deadline(
+            config,
+            &silent,
+            "localhost",
+            std::time::Instant::now() + Duration::from_millis(200),
+        )
+        .unwrap()
+        .await;
+    assert_eq!(
+        result.err(),
+        Some(crate::ConnectionError::HandshakeTimedOut(
+            crate::HandshakePhase::Initial
+        ))
+    );
+}
+
+#[tokio::test]
+async fn connect_first() {
+    let _guard = subscribe();
+    let (server_config, base_client_config) = configs();
+    let mut server_config = ServerConfigBuilder::new(server_config);
+    server_config.protocols(&[b"new"]);
+    let mut server = Endpoint::builder();
+    server.listen(server_config.build());
+    let (server, mut incoming) = server
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let server_addr = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut conns = Vec::new();
+        while let Some(connecting) = incoming.next().await {
+            if let Ok(conn) = connecting.await {
+                conns.push(conn);
+            }
+        }
+    });
+
+    let client_config = |protocol: &[u8]| {
+        let mut config = ClientConfigBuilder::new(base_client_config.clone());
+        config.protocols(&[protocol]);
+        config.build()
+    };
+    let (client, _) = Endpoint::builder()
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+
+    for &parallel in &[false, true] {
+        let new_conn = client
+            .connect_first(
+                vec![client_config(b"old"), client_config(b"new")],
+                &server_addr,
+                "localhost",
+                parallel,
+            )
+            .await
+            .expect("connect");
+        let protocol = new_conn.connection.handshake_data().unwrap().protocol;
+        assert_eq!(protocol.as_deref(), Some(&b"new"[..]));
+    }
+
+    let (index, _) = client
+        .connect_any(
+            vec![
+                (server_addr, client_config(b"old")),
+                (server_addr, client_config(b"new")),
+            ],
+            "localhost",
+            true,
+        )
+        .await
+        .expect("connect");
+    assert_eq!(index, 1);
+
+    match client
+        .connect_first(
+            vec![client_config(b"old"), client_config(b"older")],
+            &server_addr,
+            "localhost",
+            true,
+        )
+        .await
+    {
+        Err(crate::ConnectFirstError::Connection(_)) => {}
+        x => panic!("unexpected result: {:?}", x.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn trailer() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let mut recvd = Vec::new();
+        for _ in 0..3 {
+            let stream = new_conn.uni_streams.next().await.unwrap().unwrap();
+            recvd.push(stream.read_to_end_with_trailer(1024).await);
+        }
+        recvd
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let mut send = new_conn.connection.open_uni().await.unwrap();
+        send.write_all(b"body").await.unwrap();
+        send.finish_with(Bytes::from_static(b"status: ok"))
+            .await
+            .unwrap();
+        let mut send = new_conn.connection.open_uni().await.unwrap();
+        send.finish_with(Bytes::new()).await.unwrap();
+        let mut send = new_conn.connection.open_uni().await.unwrap();
+        send.write_all(b"no").await.unwrap();
+        send.finish().await.unwrap();
+        new_conn
+    };
+    let (recvd, _conn) = future::join(server, client).await;
+    assert_eq!(
+        recvd,
+        vec![
+            Ok((b"body".to_vec(), Bytes::from_static(b"status: ok"))),
+            Ok((Vec::new(), Bytes::new())),
+            Err(crate::ReadTrailerError::MissingTrailer),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn resume_transfer() {
+    use std::io::Cursor;
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let mut data = vec![0; 100_000];
+    StdRng::seed_from_u64(0).fill_bytes(&mut data);
+    let mut changed = data.clone();
+    changed[0] ^= 1;
+    let mut longer = data.clone();
+    longer.extend_from_slice(&[0xAB; 1000]);
+    changed.extend_from_slice(&[0xAB; 1000]);
+
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let mut recvd = Vec::new();
+        // A partial copy of the object and of a different version of it, then stale copies longer
+        // than the object
+        for held in [
+            &data[..40_000],
+            &changed[..40_000],
+            &longer[..],
+            &changed[..],
+        ]
+        .iter()
+        {
+            let (send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+            let transfer = crate::resume::accept(BiStream::new(send, recv))
+                .await
+                .unwrap();
+            assert_eq!(transfer.id(), 7);
+            assert_eq!(transfer.size(), data.len() as u64);
+            let mut sink = Cursor::new(held.to_vec());
+            let received = transfer.receive(&mut sink).await.unwrap();
+            recvd.push((received, sink.into_inner()));
+        }
+        recvd
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let mut sent = Vec::new();
+        for _ in 0..4 {
+            let mut source = Cursor::new(&data[..]);
+            sent.push(
+                crate::resume::send(&new_conn.connection, 7, &mut source)
+                    .await
+                    .unwrap(),
+            );
+        }
+        (sent, new_conn)
+    };
+    let (recvd, (sent, _conn)) = future::join(server, client).await;
+    assert_eq!(sent, vec![60_000, 100_000, 0, 100_000]);
+    assert_eq!(
+        recvd,
+        vec![
+            (60_000, data.clone()),
+            (100_000, data.clone()),
+            (0, data.clone()),
+            (100_000, data.clone())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn into_chunks() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let stream = new_conn.uni_streams.next().await.unwrap().unwrap();
+        // The chunks outlive the task that accepted the stream
+        tokio::spawn(stream.into_chunks().collect::<Vec<_>>())
+            .await
+            .unwrap()
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let mut send = new_conn.connection.open_uni().await.unwrap();
+        send.write_all(b"hello ").await.unwrap();
+        send.write_all(b"world").await.unwrap();
+        send.finish().await.unwrap();
+        new_conn
+    };
+    let (chunks, _conn) = future::join(server, client).await;
+    let mut data = Vec::new();
+    for chunk in chunks {
+        let chunk = chunk.unwrap();
+        assert_eq!(chunk.offset, data.len() as u64);
+        data.extend_from_slice(&chunk.bytes);
+    }
+    assert_eq!(data, b"hello world");
+}
+
+#[tokio::test]
+async fn stream_readiness() {
+    use futures::FutureExt;
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let (mut send, mut recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let mut buf = [0; 4];
+        recv.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        assert!(recv.readable().now_or_never().is_none());
+        send.writable().await.unwrap();
+        send.write_all(b"pong").await.unwrap();
+        recv.readable().await.unwrap();
+        recv.read_to_end(usize::max_value()).await.unwrap()
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let (mut send, mut recv) = new_conn.connection.open_bi().await.unwrap();
+        send.writable().await.unwrap();
+        send.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        recv.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+        send.write_all(b"done").await.unwrap();
+        send.finish().await.unwrap();
+        new_conn
+    };
+    let (data, _conn) = future::join(server, client).await;
+    assert_eq!(data, b"done");
+}
+
+#[tokio::test]
+async fn stream_try_read_write() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let (mut send, mut recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let mut buf = [0; 4];
+        recv.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        // The client sends nothing more until it sees the reply
+        assert_eq!(recv.try_read(&mut buf), Err(crate::TryReadError::Blocked));
+        send.write_all(b"pong").await.unwrap();
+        recv.read_to_end(usize::max_value()).await.unwrap().len()
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let (mut send, mut recv) = new_conn.connection.open_bi().await.unwrap();
+        assert_eq!(send.try_write(b"ping"), Ok(4));
+        let mut buf = [0; 4];
+        recv.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+        // Without yielding, the peer can't extend flow control, so writing eventually blocks
+        let chunk = [0; 64 * 1024];
+        let mut written = 0;
+        loop {
+            match send.try_write(&chunk) {
+                Ok(n) => written += n,
+                Err(crate::TryWriteError::Blocked) => break,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        send.finish().await.unwrap();
+        (written, new_conn)
+    };
+    let (read, (written, _conn)) = future::join(server, client).await;
+    assert_eq!(read, written);
+}
+
+#[tokio::test]
+async fn reconnecting_connection() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let server = async {
+        let mut first = true;
+        while let Some(connecting) = incoming.next().await {
+            let mut new_conn = connecting.await.unwrap();
+            while let Some(Ok((mut send, recv))) = new_conn.bi_streams.next().await {
+                let data = recv.read_to_end(usize::max_value()).await.unwrap();
+                send.write_all(&data).await.unwrap();
+                send.finish().await.unwrap();
+                if first {
+                    // Lose the first connection after its first request
+                    new_conn.connection.close(0u32.into(), b"");
+                    first = false;
+                }
+            }
+        }
+    };
+    let client = async {
+        let reconnects = Arc::new(AtomicUsize::new(0));
+        let mut conn = crate::ReconnectingConnection::new(
+            &endpoint,
+            &endpoint.local_addr().unwrap(),
+            "localhost",
+        );
+        let counter = reconnects.clone();
+        conn.on_reconnect(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            future::ready(())
+        });
+        let echo = |conn: crate::Connection| async move {
+            let (mut send, recv) = conn.open_bi().await?;
+            send.write_all(b"hello").await?;
+            send.finish().await?;
+            Ok::<_, io::Error>(
+                recv.read_to_end(usize::max_value())
+                    .await
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?,
+            )
+        };
+        assert_eq!(conn.request(echo).await.unwrap(), b"hello");
+        assert_eq!(reconnects.load(Ordering::Relaxed), 0);
+        // Either the loss is noticed before the request is made, or the request fails and is
+        // repeated
+        assert_eq!(conn.request(echo).await.unwrap(), b"hello");
+        assert_eq!(reconnects.load(Ordering::Relaxed), 1);
+        endpoint.close(0u32.into(), b"");
+    };
+    future::join(server, client).await;
+}
+
+#[tokio::test]
+async fn send_stream_sink() {
+    use futures::SinkExt;
+
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    // More than the default stream receive window, so flow control applies backpressure
+    const CHUNKS: u8 = 32;
+    const CHUNK_SIZE: usize = 64 * 1024;
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let stream = new_conn.uni_streams.next().await.unwrap().unwrap();
+        stream.read_to_end(usize::max_value()).await.unwrap()
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let mut send = new_conn.connection.open_uni().await.unwrap();
+        let mut chunks =
+            futures::stream::iter((0..CHUNKS).map(|i| Ok(Bytes::from(vec![i; CHUNK_SIZE]))));
+        send.send_all(&mut chunks).await.unwrap();
+        SinkExt::close(&mut send).await.unwrap();
+        new_conn
+    };
+    let (data, _conn) = future::join(server, client).await;
+    let expected = (0..CHUNKS)
+        .flat_map(|i| vec![i; CHUNK_SIZE])
+        .collect::<Vec<_>>();
+    assert_eq!(data, expected);
 }
 
-/// Construct an endpoint suitable for connecting to itself
-fn endpoint() -> (Endpoint, Incoming) {
-    let mut endpoint = Endpoint::builder();
+#[tokio::test]
+async fn stats_interval() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let mut stats = endpoint.stats_interval(Duration::from_millis(10));
+    let (client, server) = future::join(
+        async {
+            endpoint
+                .connect(&endpoint.local_addr().unwrap(), "localhost")
+                .unwrap()
+                .await
+                .unwrap()
+        },
+        async { incoming.next().await.unwrap().await.unwrap() },
+    )
+    .await;
+    // Both sides of the connection are on the same endpoint
+    while stats.next().await.unwrap().handshakes_completed < 2 {}
+    assert_eq!(endpoint.stats().handshakes_completed, 2);
+    drop((client, server));
+}
 
-    let mut server_config = ServerConfigBuilder::default();
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-    let key = crate::PrivateKey::from_der(&cert.serialize_private_key_der()).unwrap();
-    let cert = crate::Certificate::from_der(&cert.serialize_der().unwrap()).unwrap();
-    let cert_chain = crate::CertificateChain::from_certs(vec![cert.clone()]);
-    server_config.certificate(cert_chain, key).unwrap();
-    endpoint.listen(server_config.build());
+#[tokio::test]
+async fn priority_classes() {
+    let _guard = subscribe();
+    let mut builder = endpoint_builder();
+    builder.max_send_rate(std::num::NonZeroU64::new(1_000_000));
+    let (endpoint, mut incoming) = builder
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let mut pairs = Vec::new();
+    for _ in 0..2 {
+        pairs.push(
+            future::join(
+                async {
+                    endpoint
+                        .connect(&endpoint.local_addr().unwrap(), "localhost")
+                        .unwrap()
+                        .await
+                        .unwrap()
+                },
+                async { incoming.next().await.unwrap().await.unwrap() },
+            )
+            .await,
+        );
+    }
+    let (interactive, bulk) = (pairs.pop().unwrap(), pairs.pop().unwrap());
+    bulk.0.connection.set_priority_class(PriorityClass::Bulk);
+    assert_eq!(
+        interactive.0.connection.priority_class(),
+        PriorityClass::Interactive
+    );
 
-    let mut client_config = ClientConfigBuilder::default();
-    client_config.add_certificate_authority(cert).unwrap();
-    endpoint.default_client_config(client_config.build());
+    // Both connections share the endpoint's rate, but the bulk one yields to the interactive one
+    let transfer = |(client, mut server): (NewConnection, NewConnection)| async move {
+        let send = async {
+            let mut send = client.connection.open_uni().await.unwrap();
+            send.write_all(&[0; 256 * 1024]).await.unwrap();
+            send.finish().await.unwrap();
+        };
+        let recv = async {
+            let stream = server.uni_streams.next().await.unwrap().unwrap();
+            stream.read_to_end(usize::max_value()).await.unwrap();
+        };
+        future::join(send, recv).await;
+        Instant::now()
+    };
+    let (bulk_done, interactive_done) = future::join(transfer(bulk), transfer(interactive)).await;
+    assert!(interactive_done < bulk_done);
+}
+
+#[tokio::test]
+async fn write_with_deadline() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let server = async {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let stream = new_conn.uni_streams.next().await.unwrap().unwrap();
+        // Only start reading once the writer has given up
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        match stream.read_to_end(usize::max_value()).await {
+            Err(crate::ReadToEndError::Read(crate::ReadError::Reset(code))) => {
+                assert_eq!(code, crate::VarInt::from_u32(0))
+            }
+            x => panic!("unexpected result: {:?}", x),
+        }
+        new_conn
+    };
+    let client = async {
+        let new_conn = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap()
+            .await
+            .unwrap();
+        let mut send = new_conn.connection.open_uni().await.unwrap();
+        // More than the default stream receive window, so flow control holds the write back
+        let data = vec![0xAB; 4 * 1024 * 1024];
+        let deadline = std::time::Instant::now() + Duration::from_millis(50);
+        assert_eq!(
+            send.write_with_deadline(&data, deadline).await,
+            Err(crate::WriteError::TimedOut)
+        );
+        new_conn
+    };
+    future::join(server, client).await;
+}
 
-    let (x, y) = endpoint
+#[tokio::test]
+async fn listeners() {
+    let _guard = subscribe();
+    let (base_server_config, base_client_config) = configs();
+    let server_config = |protocols: &[&[u8]]| {
+        let mut config = ServerConfigBuilder::new(base_server_config.clone());
+        config.protocols(protocols);
+        config.build()
+    };
+    let client_config = |protocols: &[&[u8]]| {
+        let mut config = ClientConfigBuilder::new(base_client_config.clone());
+        config.protocols(protocols);
+        config.build()
+    };
+    let mut builder = Endpoint::builder();
+    builder.listen(server_config(&[b"fallback"]));
+    let (endpoint, mut incoming) = builder
         .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
         .unwrap();
-    (x, y)
+    let addr = endpoint.local_addr().unwrap();
+    let mut filter = crate::ListenerFilter::default();
+    filter.protocol(b"chat");
+    let mut chat = endpoint.listen(filter, server_config(&[b"chat"]));
+
+    let client = endpoint.connect_with(client_config(&[b"chat"]), &addr, "localhost");
+    let (client, server) = future::join(client.unwrap(), chat.next()).await;
+    let server = server.unwrap().await.unwrap();
+    let protocol = server.connection.handshake_data().unwrap().protocol;
+    assert_eq!(protocol.as_deref(), Some(&b"chat"[..]));
+    drop((client, server));
+
+    let client = endpoint.connect_with(client_config(&[b"fallback"]), &addr, "localhost");
+    let (client, server) = future::join(client.unwrap(), incoming.next()).await;
+    server.unwrap().await.unwrap();
+    client.unwrap();
+
+    // Once the listener is gone, its connections go to the default server configuration
+    drop(chat);
+    let client = endpoint.connect_with(client_config(&[b"chat", b"fallback"]), &addr, "localhost");
+    let (client, server) = future::join(client.unwrap(), incoming.next()).await;
+    let server = server.unwrap().await.unwrap();
+    let protocol = server.connection.handshake_data().unwrap().protocol;
+    assert_eq!(protocol.as_deref(), Some(&b"fallback"[..]));
+    client.unwrap();
 }
 
 #[tokio::test]
@@ -337,6 +2614,103 @@ async fn zero_rtt() {
     endpoint.wait_idle().await;
 }
 
+#[tokio::test]
+async fn zero_rtt_listener_anti_replay() {
+    use futures::FutureExt;
+    use proto::crypto::rustls::TlsSession;
+
+    use crate::{
+        transport::{MemoryNetwork, MemorySocket},
+        AntiReplay,
+    };
+
+    /// Considers every 0-RTT attempt a replay
+    #[derive(Debug)]
+    struct Paranoid;
+
+    impl AntiReplay for Paranoid {
+        fn check(&self, _: &[u8]) -> futures::future::BoxFuture<'static, bool> {
+            future::ready(false).boxed()
+        }
+    }
+
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    // Leaves time for 0-RTT data to arrive well before the handshake completes
+    network.latency(Duration::from_millis(20));
+    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4433);
+    let (server_config, client_config) = configs();
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.anti_replay(Paranoid);
+    let (server, _) = server
+        .with_socket(network.bind(server_addr).unwrap())
+        .unwrap();
+    let mut listener = server.listen(crate::ListenerFilter::default(), server_config);
+
+    const MSG: &[u8] = b"goodbye!";
+    let (handshaken_send, mut handshaken) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        while let Some(conn) = listener.next().await {
+            let (mut new_conn, accepted) = conn.into_0rtt().unwrap_or_else(|_| unreachable!());
+            let accepted = accepted.shared();
+            let connection = new_conn.connection.clone();
+            let handshake = accepted.clone();
+            tokio::spawn(async move {
+                // Sent after the session ticket, which is issued once the handshake completes
+                handshake.await;
+                let mut s = connection.open_uni().await.unwrap();
+                s.write_all(MSG).await.unwrap();
+                s.finish().await.unwrap();
+            });
+            while let Some(Ok(stream)) = new_conn.uni_streams.next().await {
+                let _ = handshaken_send.unbounded_send(accepted.clone().now_or_never().is_some());
+                let msg = stream.read_to_end(usize::max_value()).await.unwrap();
+                assert_eq!(msg, MSG);
+            }
+        }
+    });
+
+    let mut client = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    client.default_client_config(client_config);
+    let client_socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 0))
+        .unwrap();
+    let (client, _) = client.with_socket(client_socket).unwrap();
+
+    let crate::generic::NewConnection {
+        mut uni_streams, ..
+    } = client
+        .connect(&server_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let stream = uni_streams.next().await.unwrap().unwrap();
+    stream.read_to_end(usize::max_value()).await.unwrap();
+    drop(uni_streams);
+    client.wait_idle().await;
+
+    let (
+        crate::generic::NewConnection {
+            connection,
+            mut uni_streams,
+            ..
+        },
+        zero_rtt,
+    ) = client
+        .connect(&server_addr, "localhost")
+        .unwrap()
+        .into_0rtt()
+        .unwrap_or_else(|_| panic!("missing 0-RTT keys"));
+    let mut s = connection.open_uni().await.unwrap();
+    s.write_all(MSG).await.unwrap();
+    s.finish().await.unwrap();
+    assert!(zero_rtt.await);
+    // Streams opened in 0-RTT are withheld until the handshake completes
+    assert_eq!(handshaken.next().await, Some(true));
+    let stream = uni_streams.next().await.unwrap().unwrap();
+    assert_eq!(stream.read_to_end(usize::max_value()).await.unwrap(), MSG);
+}
+
 #[test]
 fn echo_v6() {
     run_echo(EchoArgs {
@@ -563,6 +2937,412 @@ async fn echo((mut send, mut recv): (SendStream, RecvStream)) {
     let _ = send.finish().await;
 }
 
+#[tokio::test]
+async fn cloned_datagrams() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let addr = endpoint.local_addr().unwrap();
+    let (tx, mut rx) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        let new_conn = incoming.next().await.unwrap().await.unwrap();
+        let workers = (0..2)
+            .map(|_| {
+                let mut datagrams = new_conn.datagrams.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    while let Some(Ok(x)) = datagrams.next().await {
+                        tx.unbounded_send(x).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(new_conn.datagrams);
+        future::join_all(workers).await;
+    });
+
+    let new_conn = endpoint.connect(&addr, "localhost").unwrap().await.unwrap();
+    for i in 0..10u8 {
+        new_conn.connection.send_datagram(vec![i].into()).unwrap();
+    }
+    let mut received = Vec::new();
+    for _ in 0..10 {
+        received.push(rx.next().await.unwrap()[0]);
+    }
+    received.sort_unstable();
+    assert_eq!(received, (0..10).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn connection_label() {
+    let _guard = subscribe();
+    let (endpoint, mut incoming) = endpoint();
+    let addr = endpoint.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let new_conn = incoming
+            .next()
+            .await
+            .unwrap()
+            .with_label("tenant-a")
+            .await
+            .unwrap();
+        new_conn.connection.label()
+    });
+    let new_conn = endpoint.connect(&addr, "localhost").unwrap().await.unwrap();
+    assert_eq!(new_conn.connection.label(), None);
+    assert_eq!(server.await.unwrap().as_deref(), Some("tenant-a"));
+}
+
+#[tokio::test]
+async fn health_check() {
+    let _guard = subscribe();
+    let (server_config, client_config) = configs();
+    let mut server_config = ServerConfigBuilder::new(server_config);
+    server_config.protocols(&[b"quinn-health"]);
+
+    let mut endpoint = Endpoint::builder();
+    endpoint.health_check(b"quinn-health", server_config.build());
+    let (server, mut incoming) = endpoint
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let mut client_config = ClientConfigBuilder::new(client_config);
+    client_config.protocols(&[b"quinn-health"]);
+    let (client, _) = endpoint_builder()
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let new_conn = client
+        .connect_with(client_config.build(), &addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap();
+    let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(b"ping").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(recv.read_to_end(usize::max_value()).await.unwrap(), b"ping");
+
+    // The application never sees health checks
+    drop(server);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), incoming.next())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn idle_timeout_warning() {
+    let _guard = subscribe();
+    const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+    const WARNING: Duration = Duration::from_millis(300);
+    let (mut server_config, client_config) = configs();
+    let mut transport_config = TransportConfig::default();
+    transport_config
+        .max_idle_timeout(Some(IDLE_TIMEOUT))
+        .unwrap()
+        .idle_timeout_warning(Some(WARNING));
+    server_config.transport = Arc::new(transport_config);
+
+    let mut endpoint = Endpoint::builder();
+    endpoint.listen(server_config);
+    endpoint.default_client_config(client_config);
+    let (endpoint, mut incoming) = endpoint
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let addr = endpoint.local_addr().unwrap();
+
+    let _client = endpoint.connect(&addr, "localhost").unwrap().await.unwrap();
+    let server = incoming.next().await.unwrap().await.unwrap().connection;
+    let start = Instant::now();
+    server.idle_timeout_warning().await.unwrap();
+    let dt = start.elapsed();
+    assert!(dt < IDLE_TIMEOUT - WARNING + Duration::from_millis(100));
+
+    // Activity defers the timeout, and a new warning follows
+    server
+        .send_datagram(Bytes::from_static(b"still here"))
+        .unwrap();
+    server.idle_timeout_warning().await.unwrap();
+}
+
+#[cfg(feature = "null-crypto")]
+#[tokio::test]
+async fn null_crypto() {
+    use proto::crypto::null::NullSession;
+
+    use crate::{generic, transport::UdpSocket as QuinnUdpSocket};
+
+    let _guard = subscribe();
+    let mut builder = generic::EndpointBuilder::<NullSession, QuinnUdpSocket>::default();
+    builder.listen(generic::ServerConfig::default());
+    builder.default_client_config(generic::ClientConfig::default());
+    let (endpoint, mut incoming) = builder
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut new_conn = incoming.next().await.unwrap().await.unwrap();
+        let (mut send, recv) = new_conn.bi_streams.next().await.unwrap().unwrap();
+        let data = recv.read_to_end(usize::max_value()).await.unwrap();
+        send.write_all(&data).await.unwrap();
+        send.finish().await.unwrap();
+    });
+
+    let new_conn = endpoint.connect(&addr, "localhost").unwrap().await.unwrap();
+    let (mut send, recv) = new_conn.connection.open_bi().await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(
+        recv.read_to_end(usize::max_value()).await.unwrap(),
+        b"hello"
+    );
+}
+
+#[tokio::test]
+async fn driver_panic_contained() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use proto::{
+        congestion::{Controller, ControllerFactory, NewRenoConfig},
+        crypto::rustls::TlsSession,
+    };
+
+    use crate::transport::{MemoryNetwork, MemorySocket};
+
+    /// Delegates to NewReno, but panics on acknowledgements once armed
+    struct Fuse(Box<dyn Controller>, Arc<AtomicBool>);
+
+    impl Controller for Fuse {
+        fn on_ack(
+            &mut self,
+            now: std::time::Instant,
+            sent: std::time::Instant,
+            bytes: u64,
+            app_limited: bool,
+        ) {
+            assert!(!self.1.load(Ordering::Relaxed), "fuse blown");
+            self.0.on_ack(now, sent, bytes, app_limited);
+        }
+
+        fn on_congestion_event(
+            &mut self,
+            now: std::time::Instant,
+            sent: std::time::Instant,
+            persistent: bool,
+        ) {
+            self.0.on_congestion_event(now, sent, persistent);
+        }
+
+        fn window(&self) -> u64 {
+            self.0.window()
+        }
+
+        fn clone_box(&self) -> Box<dyn Controller> {
+            Box::new(Fuse(self.0.clone_box(), self.1.clone()))
+        }
+
+        fn initial_window(&self) -> u64 {
+            self.0.initial_window()
+        }
+    }
+
+    struct FuseFactory(Arc<AtomicBool>);
+
+    impl ControllerFactory for FuseFactory {
+        fn build(&self, now: std::time::Instant) -> Box<dyn Controller> {
+            let inner = Arc::new(NewRenoConfig::default()).build(now);
+            Box::new(Fuse(inner, self.0.clone()))
+        }
+    }
+
+    let _guard = subscribe();
+    let network = MemoryNetwork::new();
+    let server_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4433);
+    let (server_config, client_config) = configs();
+    let mut server = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    server.listen(server_config);
+    let (_server, mut incoming) = server
+        .with_socket(network.bind(server_addr).unwrap())
+        .unwrap();
+    tokio::spawn(async move {
+        while let Some(conn) = incoming.next().await {
+            tokio::spawn(async move {
+                let mut new_conn = conn.await.unwrap();
+                while let Some(Ok((mut send, recv))) = new_conn.bi_streams.next().await {
+                    let data = recv.read_to_end(usize::max_value()).await.unwrap();
+                    send.write_all(&data).await.unwrap();
+                    send.finish().await.unwrap();
+                }
+            });
+        }
+    });
+
+    let (panics_send, mut panics) = futures::channel::mpsc::unbounded();
+    let mut endpoint = crate::generic::EndpointBuilder::<TlsSession, MemorySocket>::default();
+    endpoint.default_client_config(client_config.clone());
+    endpoint.on_driver_panic(move |panic| {
+        let _ = panics_send.unbounded_send(panic.clone());
+    });
+    let client_socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 0))
+        .unwrap();
+    let (endpoint, _) = endpoint.with_socket(client_socket).unwrap();
+
+    let armed = Arc::new(AtomicBool::new(false));
+    let mut fragile_config = client_config;
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(FuseFactory(armed.clone()));
+    fragile_config.transport = Arc::new(transport);
+    let fragile = endpoint
+        .connect_with(fragile_config, &server_addr, "localhost")
+        .unwrap()
+        .with_label("fragile")
+        .await
+        .unwrap()
+        .connection;
+    let sturdy = endpoint
+        .connect(&server_addr, "localhost")
+        .unwrap()
+        .await
+        .unwrap()
+        .connection;
+    let count = endpoint.connection_count();
+
+    // Once its driver panics, the connection fails without affecting the endpoint
+    armed.store(true, Ordering::Relaxed);
+    let (mut send, recv) = fragile.open_bi().await.unwrap();
+    let _ = send.write_all(b"hello").await;
+    let _ = send.finish().await;
+    let panic = panics.next().await.unwrap();
+    assert_eq!(panic.message(), Some("fuse blown"));
+    assert_eq!(panic.label(), Some("fragile"));
+    match recv.read_to_end(usize::max_value()).await {
+        Err(crate::ReadToEndError::Read(crate::ReadError::ConnectionClosed(
+            crate::ConnectionError::TransportError(e),
+        ))) => assert_eq!(e.code, proto::TransportErrorCode::INTERNAL_ERROR),
+        x => panic!("unexpected result: {:?}", x),
+    }
+    assert!(matches!(
+        fragile.open_uni().await,
+        Err(crate::ConnectionError::TransportError(_))
+    ));
+    while endpoint.connection_count() == count {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let (mut send, recv) = sturdy.open_bi().await.unwrap();
+    send.write_all(b"hello").await.unwrap();
+    send.finish().await.unwrap();
+    assert_eq!(
+        recv.read_to_end(usize::max_value()).await.unwrap(),
+        b"hello"
+    );
+}
+
+#[test]
+fn reconnect_on_reset() {
+    let _guard = subscribe();
+    // Endpoints built from the same builder share a reset key, like a server before and after a
+    // restart
+    let builder = endpoint_builder();
+    let runtime = rt_threaded();
+    let (client, _) = {
+        let _guard = runtime.enter();
+        let mut client = builder.clone();
+        client.reconnect_on_reset();
+        client
+            .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+            .unwrap()
+    };
+
+    let server_runtime = rt_threaded();
+    let addr = {
+        let _guard = server_runtime.enter();
+        let (server, mut incoming) = builder
+            .clone()
+            .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        server_runtime.spawn(async move {
+            let _server = server;
+            let mut conns = Vec::new();
+            while let Some(conn) = incoming.next().await {
+                conns.push(conn.await);
+            }
+        });
+        addr
+    };
+    let conn = runtime.block_on(async {
+        client
+            .connect(&addr, "localhost")
+            .unwrap()
+            .await
+            .unwrap()
+            .connection
+    });
+
+    // Lose the server's state without letting it close the connection, then bring it back
+    drop(server_runtime);
+    let (_server, mut incoming) = {
+        let _guard = runtime.enter();
+        builder.bind(&addr).unwrap()
+    };
+    runtime.block_on(async move {
+        tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Some(conn) = incoming.next().await {
+                conns.push(conn.await);
+            }
+        });
+        let mut send = conn.open_uni().await.unwrap();
+        let _ = send.write_all(&[0xAB; 1000]).await;
+        assert!(matches!(
+            send.finish().await,
+            Err(crate::WriteError::ConnectionClosed(
+                crate::ConnectionError::Reset
+            ))
+        ));
+        assert_eq!(conn.stateless_reset().unwrap().remote, addr);
+        let new_conn = conn.reconnected().await.unwrap().await.unwrap();
+        assert_eq!(new_conn.connection.remote_address(), addr);
+        assert!(conn.reconnected().await.is_none());
+    });
+}
+
+#[test]
+fn shaper() {
+    let start = std::time::Instant::now();
+    // 1MB/s, so bursts of 10KB
+    let mut shaper =
+        crate::shaper::Shaper::new(std::num::NonZeroU64::new(1_000_000).unwrap(), start);
+    // The second transmit is admitted even though it exceeds the remaining tokens, the third isn't
+    assert_eq!(shaper.admit(start, [6000, 6000, 6000].iter().copied()), 2);
+    shaper.consume(12_000);
+    assert_eq!(shaper.admit(start, [1000].iter().copied()), 0);
+    // The debt of 2KB is paid off after 2ms
+    let now = start + Duration::from_micros(1990);
+    assert_eq!(shaper.admit(now, [1000].iter().copied()), 0);
+    let now = start + Duration::from_micros(2010);
+    assert_eq!(shaper.admit(now, [1000].iter().copied()), 1);
+    // Tokens never accumulate beyond a burst
+    let now = start + Duration::from_secs(10);
+    assert_eq!(shaper.admit(now, std::iter::repeat(1000)), 10);
+}
+
+#[test]
+fn memory_anti_replay() {
+    use crate::{AntiReplay, MemoryAntiReplay};
+    let record = MemoryAntiReplay::new(2);
+    let check = |id: &[u8]| futures::executor::block_on(record.check(id));
+    assert!(check(b"a"));
+    assert!(!check(b"a"));
+    assert!(check(b"b"));
+    // Evicts the oldest attempt, which can then be replayed undetected
+    assert!(check(b"c"));
+    assert!(!check(b"b"));
+    assert!(check(b"a"));
+}
+
 fn gen_data(size: usize, seed: u64) -> Vec<u8> {
     let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
     let mut buf = vec![0; size];
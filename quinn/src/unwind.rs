@@ -0,0 +1,59 @@
+use std::{any::Any, fmt, sync::Arc};
+
+/// Details of a panic in the task driving a connection
+///
+/// Passed to the hook registered with
+/// [`EndpointBuilder::on_driver_panic()`](crate::generic::EndpointBuilder::on_driver_panic). The
+/// panic is reported by the panic hook as usual before it's caught, so a backtrace is printed
+/// if enabled, e.g. by setting `RUST_BACKTRACE`; it's no longer available afterwards.
+#[derive(Debug, Clone)]
+pub struct DriverPanic {
+    message: Option<String>,
+    label: Option<Arc<str>>,
+}
+
+impl DriverPanic {
+    pub(crate) fn new(payload: &(dyn Any + Send), label: Option<Arc<str>>) -> Self {
+        let message = match payload.downcast_ref::<&'static str>() {
+            Some(x) => Some((*x).into()),
+            None => payload.downcast_ref::<String>().cloned(),
+        };
+        Self { message, label }
+    }
+
+    /// The panic's message, if its payload was a string
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// The [`label`](crate::generic::Connection::label) of the abandoned connection, if any
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+}
+
+impl fmt::Display for DriverPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message().unwrap_or("unknown cause"))
+    }
+}
+
+/// Called with each panic of a connection driver, from the task that panicked
+#[derive(Clone)]
+pub(crate) struct PanicHook(Arc<dyn Fn(&DriverPanic) + Send + Sync>);
+
+impl PanicHook {
+    pub(crate) fn new(hook: impl Fn(&DriverPanic) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    pub(crate) fn call(&self, panic: &DriverPanic) {
+        (self.0)(panic)
+    }
+}
+
+impl fmt::Debug for PanicHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[ opaque ]")
+    }
+}